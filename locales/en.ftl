@@ -0,0 +1,96 @@
+# User-facing messages, in a subset of the Fluent syntax (https://projectfluent.org).
+#
+# To add a translation, copy this file to <language>.ftl (e.g. ja.ftl or pt-BR.ftl) and
+# translate the text after each "=". Placeables like { $time } are filled in by the program
+# and must be kept. Messages missing from a translation fall back to these.
+
+## Errors
+
+no-frame-yet = No frame has been captured at { $time } yet
+no-frame-at-time = No frame was captured at { $time }, frames are captured every { $interval } minutes
+no-fragments = None of the image fragments for { $time } could be downloaded
+too-few-fragments = Only { $downloaded } of the { $total } image fragments for { $time } could be downloaded, and { $required } are needed
+fragments-failed = { $count } image fragments for { $time } failed: { $causes }
+more-fragments-failed = ...and { $count } more
+placeholder-tile = Fragment { $x },{ $y } of { $time } is a "No Image" placeholder
+placeholder-frame = { $count } of the image fragments for { $time } are "No Image" placeholders, the frame isn't finished yet
+backfill-failed = { $count } frames could not be downloaded, run again to retry them
+animated-format-alone = The { $format } format can't be combined with other formats, or set as the wallpaper
+animated-format-needs-frames = The { $format } format is an animation, so needs --frames with at least 2 frames
+lossy-webp-unavailable = Lossy WebP needs the libwebp library, which this build doesn't include (build with the libwebp feature), so leave out --webp-quality for lossless WebP
+satellite-latest-only = Only the latest frame can be downloaded from { $satellite }, so --time, --frames, --animate, --prefer-daylight without --night-source, backfill, frames and recompose are for Himawari only
+recompose-offline = recompose reads only the tile cache, so can't be used with { $option }, which needs the network
+variants-need-size = --variants needs a size to turn each way, from --resize, --preset, --canvas or --auto-fit
+level-unsupported = Level { $level } is not served for { $product }, use one of { $levels }, or give the levels a mirror serves with --levels
+latest-name-has-directory = The latest file name { $name } must not include a directory
+latest-name-bad-extension = The latest file name { $name } must end in .png, .jpeg, .jpg, .webp or .avif
+no-saved-tiles = No saved tiles were found in { $dir }
+invalid-tile-url = { $url } is not a file:// URL of a directory
+replay-missing = No response to { $url } was recorded in { $dir }
+no-addresses = No addresses were found for { $host }
+source-unavailable = The image server seems to be unavailable, { $failed } of the first { $sampled } fragments failed to download
+hook-failed = The hook { $command } failed with { $status }
+ctl-not-running = No instance is running in watch mode on { $endpoint }
+ctl-unknown-command = Unknown control command { $command }
+no-output-dir = No output directory was given, use --output-dir, set output-dir in the config file, or use --wallpaper-only
+no-config-dir = No config directory could be found for this user
+config-not-found = The config file { $path } was not found
+config-invalid = The config file { $path } is not valid: { $error }
+config-unknown-setting = Unknown setting { $name } in the config file
+config-invalid-value = The setting { $name } in the config file has the wrong type of value
+env-invalid-value = The environment variable { $name } has the wrong type of value
+settings-invalid = The settings from the config file and the environment don't fit together: { $error }
+settings-unavailable = This build has no settings window, build it with the gui feature to add one
+schedule-failed = Installing the schedule failed with { $status }
+unschedule-failed = Removing the schedule failed with { $status }
+crop-outside-image = The crop { $crop } lies outside the { $width }x{ $height } image
+not-enough-space = There isn't enough space in { $dir } for the images, about { $needed } is needed and { $free } is free
+
+## Platform integration
+
+command-failed = The command { $command } failed with { $status }
+program-not-found = The program { $program } was not found, is it installed?
+wallpaper-no-backend = The desktop could not be detected, use --wallpaper-backend to choose how to set the wallpaper
+xfce-no-backgrounds = No XFCE desktop backgrounds were found to set
+lockscreen-unsupported = Setting the lock screen image is not supported on this platform
+lockscreen-failed = Failed to set the lock screen image: { $error }
+screen-size-unknown = The size of the screen could not be found
+auto-fit-failed = Not fitting the image to the screen: { $error }
+monitor-unsupported = Choosing the monitor is only supported on Windows, so the wallpaper is set on every monitor
+slideshow-unsupported = The desktop slideshow is only supported on Windows, so the newest frame is set as the wallpaper
+monitor-not-found = There is no monitor { $monitor }, the monitors are numbered 1 to { $count }
+network-drive = The output directory { $dir } is on a network drive, which the wallpaper may fail to be set from
+
+## Notifications
+
+notify-updated-title = Wallpaper updated
+notify-updated-body = Showing the Earth as captured at { $time }
+notify-failed-title = Failed to update the wallpaper
+
+## Screensaver
+
+screensaver-class-failed = Failed to register the screensaver window class
+screensaver-window-failed = Failed to create the screensaver window
+folder-picker-failed = Failed to create the folder picker dialog
+folder-picker-title = Choose the Himawari archive directory
+
+## Settings window
+
+settings-title = Himawari Desktop Updater settings
+settings-output-dir = Output folder
+settings-browse = Browse…
+settings-level = Level
+settings-level-size = { $level } ({ $pixels } × { $pixels } pixels)
+settings-format = Format
+settings-margins = Margins (top, right, bottom, left)
+settings-set-wallpaper = Set as the desktop background
+settings-interval = Update every
+settings-preview = Preview on your { $width } × { $height } screen
+settings-save = Save
+settings-run-now = Run now
+settings-install-schedule = Install schedule
+settings-saved = Saved the settings to { $path }
+settings-running = Updating…
+settings-update-finished = The update finished
+settings-update-failed = The update failed with { $status }, see the log for details
+settings-schedule-installed = The updater will run every { $interval } minutes
@@ -0,0 +1,69 @@
+use image::RgbaImage;
+
+/// Rough statistics describing the contents of a frame
+pub struct FrameStats {
+    /// Percentage of the sunlit disk covered by cloud
+    pub cloud_cover: f32,
+    /// Mean brightness of the disk, from 0 to 1
+    pub mean_brightness: f32,
+}
+
+/// Only every Nth pixel in each direction is sampled
+const SAMPLE_STRIDE: u32 = 4;
+/// The disk does not quite touch the edges of the stitched image
+const DISK_RADIUS_FRACTION: f32 = 0.49;
+/// Pixels darker than this are on the night side of the planet
+const SUNLIT_LUMA: f32 = 24.0;
+/// Cloud tops are bright...
+const CLOUD_LUMA: f32 = 140.0;
+/// ...and close to grey, unlike bright deserts
+const CLOUD_MAX_SATURATION: f32 = 0.2;
+
+/// Estimates cloud cover and brightness for the disk occupying the square
+/// `disk_size` pixels wide at (`disk_x`, `disk_y`) in `image`
+pub fn analyze(image: &RgbaImage, disk_x: u32, disk_y: u32, disk_size: u32) -> FrameStats {
+    let radius = disk_size as f32 * DISK_RADIUS_FRACTION;
+    let centre = disk_size as f32 / 2.0;
+
+    let mut disk_pixels = 0u64;
+    let mut sunlit_pixels = 0u64;
+    let mut cloud_pixels = 0u64;
+    let mut total_luma = 0f64;
+
+    for y in (0..disk_size).step_by(SAMPLE_STRIDE as usize) {
+        for x in (0..disk_size).step_by(SAMPLE_STRIDE as usize) {
+            let (dx, dy) = (x as f32 - centre, y as f32 - centre);
+            if dx * dx + dy * dy > radius * radius {
+                continue;
+            }
+            let [r, g, b, _] = image.get_pixel(disk_x + x, disk_y + y).0;
+            let (r, g, b) = (r as f32, g as f32, b as f32);
+            let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+
+            disk_pixels += 1;
+            total_luma += luma as f64;
+
+            if luma < SUNLIT_LUMA {
+                continue;
+            }
+            sunlit_pixels += 1;
+
+            let max = r.max(g).max(b);
+            let saturation = (max - r.min(g).min(b)) / max;
+            if luma >= CLOUD_LUMA && saturation <= CLOUD_MAX_SATURATION {
+                cloud_pixels += 1;
+            }
+        }
+    }
+
+    FrameStats {
+        cloud_cover: match sunlit_pixels {
+            0 => 0.0,
+            n => (cloud_pixels as f64 * 100.0 / n as f64) as f32,
+        },
+        mean_brightness: match disk_pixels {
+            0 => 0.0,
+            n => (total_luma / n as f64 / 255.0) as f32,
+        },
+    }
+}
@@ -0,0 +1,75 @@
+//! Writing several frames as one looping animation, in the GIF and APNG formats.
+//!
+//! Frames are encoded as they are added, so only one is held in memory at a time.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+
+use crate::error::AppErr;
+use crate::output_format::OutputFormat;
+
+/// How long each frame is shown for
+const FRAME_DELAY_MS: u32 = 120;
+
+/// Trades GIF colour quantization quality for speed, from 1 (slowest) to 30
+const GIF_SPEED: i32 = 10;
+
+pub enum AnimationWriter {
+    Gif(GifEncoder<BufWriter<File>>),
+    Apng(png::Writer<BufWriter<File>>),
+}
+
+impl AnimationWriter {
+    /// Starts an animation of `frames` frames of `width` by `height` pixels, which loops forever
+    pub fn create(
+        path: &Path,
+        output_format: &OutputFormat,
+        width: u32,
+        height: u32,
+        frames: u32,
+    ) -> Result<AnimationWriter, AppErr> {
+        let file = BufWriter::new(File::create(path)?);
+        match output_format {
+            OutputFormat::GIF => {
+                let mut encoder = GifEncoder::new_with_speed(file, GIF_SPEED);
+                encoder.set_repeat(Repeat::Infinite)?;
+                Ok(AnimationWriter::Gif(encoder))
+            }
+            OutputFormat::APNG => {
+                let mut encoder = png::Encoder::new(file, width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                // No number of plays means play forever
+                encoder.set_animated(frames, 0)?;
+                encoder.set_frame_delay(FRAME_DELAY_MS as u16, 1000)?;
+                Ok(AnimationWriter::Apng(encoder.write_header()?))
+            }
+            _ => Err(AppErr::new(format!("Not an animated format: {}", output_format))),
+        }
+    }
+
+    /// Adds the next frame, which must be the size given when the animation was created
+    pub fn add(&mut self, image: RgbaImage) -> Result<(), AppErr> {
+        match self {
+            AnimationWriter::Gif(encoder) => {
+                let delay = Delay::from_numer_denom_ms(FRAME_DELAY_MS, 1);
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+            }
+            AnimationWriter::Apng(writer) => writer.write_image_data(image.as_raw())?,
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), AppErr> {
+        match self {
+            // The GIF trailer is written when the encoder is dropped
+            AnimationWriter::Gif(_) => {}
+            AnimationWriter::Apng(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::checksums;
+use crate::error::{AppErr, ErrorKind};
+use crate::messages::tr;
+use crate::orientation::{Orientation, ORIENTATIONS};
+use crate::output_format::OutputFormat;
+
+const FRAME_FILE_PREFIX: &str = "himawari8_";
+const FRAME_TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+pub struct ArchivedFrame {
+    pub path: PathBuf,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// The file name an archived frame captured at `timestamp` is written to
+pub fn frame_file_name(timestamp: &DateTime<Utc>, output_format: &OutputFormat) -> String {
+    format!(
+        "{}{}.{}",
+        FRAME_FILE_PREFIX,
+        timestamp.format(FRAME_TIMESTAMP_FORMAT),
+        output_format
+    )
+}
+
+/// The file name of frame `number` when archiving with sequence numbers
+pub fn numbered_file_name(number: u64, output_format: &OutputFormat) -> String {
+    format!("frame_{:06}.{}", number, output_format)
+}
+
+/// The file name used when only the latest frame is kept
+pub fn latest_file_name(output_format: &OutputFormat) -> String {
+    format!("{}latest.{}", FRAME_FILE_PREFIX, output_format)
+}
+
+/// The file name the lock screen version of the latest frame is written to
+pub fn lockscreen_file_name(output_format: &OutputFormat) -> String {
+    format!("{}lockscreen.{}", FRAME_FILE_PREFIX, output_format)
+}
+
+/// Checks a name given for the latest file, returning the output format its extension implies
+pub fn latest_file_format(name: &str) -> Result<OutputFormat, AppErr> {
+    if name.contains(['/', '\\']) {
+        return Err(AppErr::of_kind(ErrorKind::Config, tr!("latest-name-has-directory", name = name)));
+    }
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(OutputFormat::from_extension)
+        .ok_or_else(|| AppErr::of_kind(ErrorKind::Config, tr!("latest-name-bad-extension", name = name)))
+}
+
+/// The directory frames from special events (such as the Moon being in view)
+/// are archived in, out of the way of normal retention
+pub fn events_dir(output_dir: &Path) -> PathBuf {
+    output_dir.join("events")
+}
+
+/// The directory the most recent frames are written to as a numbered sequence
+pub fn sequence_dir(output_dir: &Path) -> PathBuf {
+    output_dir.join("sequence")
+}
+
+/// The file name of frame `index` of a sequence, counting from 1 for the oldest frame
+pub fn sequence_file_name(index: u32, output_format: &OutputFormat) -> String {
+    format!("frame_{:03}.{}", index, output_format)
+}
+
+/// Lists the frames of a sequence, so consumers know when each was captured
+#[derive(Serialize, Deserialize)]
+pub struct SequenceManifest {
+    pub frames: Vec<SequenceFrame>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SequenceFrame {
+    pub file: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+fn sequence_manifest_path(output_dir: &Path) -> PathBuf {
+    sequence_dir(output_dir).join("sequence.json")
+}
+
+/// Reads the manifest of the sequence in `output_dir`, if one has been written
+pub fn read_sequence_manifest(output_dir: &Path) -> Option<SequenceManifest> {
+    let file = std::fs::File::open(sequence_manifest_path(output_dir)).ok()?;
+    serde_json::from_reader(file).ok()
+}
+
+pub fn write_sequence_manifest(
+    output_dir: &Path,
+    manifest: &SequenceManifest,
+) -> Result<(), AppErr> {
+    let file = std::fs::File::create(sequence_manifest_path(output_dir))?;
+    serde_json::to_writer_pretty(file, manifest)?;
+    Ok(())
+}
+
+/// Where the untouched original of the frame written to `path` is kept: the same relative
+/// path under a 'raw' directory, saved losslessly
+pub fn raw_path(output_dir: &Path, path: &Path) -> PathBuf {
+    let relative = path
+        .strip_prefix(output_dir)
+        .unwrap_or_else(|_| Path::new(path.file_name().unwrap_or_default()));
+    output_dir
+        .join("raw")
+        .join(relative)
+        .with_extension(OutputFormat::PNG.to_string())
+}
+
+/// The path of the --checksum-sidecars checksum of the frame at `image_path`,
+/// e.g. `himawari8_20221101_120000.jpeg.sha256`
+pub fn checksum_path(image_path: &Path) -> PathBuf {
+    let mut path = image_path.as_os_str().to_owned();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+/// Metadata written alongside an archived frame
+#[derive(Serialize, Deserialize)]
+pub struct Sidecar {
+    pub timestamp: DateTime<Utc>,
+    /// The time asked for with --time, where it differs from the frame's timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_timestamp: Option<DateTime<Utc>>,
+    pub level: u32,
+    /// Percentage of the sunlit disk covered by cloud
+    pub cloud_cover: f32,
+    /// Mean brightness of the disk, from 0 to 1
+    pub mean_brightness: f32,
+}
+
+/// The path of the sidecar for the frame at `image_path`, e.g. `himawari8_20221101_120000.jpeg.json`
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+    let mut path = image_path.as_os_str().to_owned();
+    path.push(".json");
+    PathBuf::from(path)
+}
+
+pub fn write_sidecar(image_path: &Path, sidecar: &Sidecar) -> Result<(), AppErr> {
+    let file = std::fs::File::create(sidecar_path(image_path))?;
+    serde_json::to_writer_pretty(file, sidecar)?;
+    Ok(())
+}
+
+/// The tiles a frame was written without, so --repair can fetch just those later
+#[derive(Serialize, Deserialize)]
+pub struct MissingTiles {
+    pub timestamp: DateTime<Utc>,
+    pub level: u32,
+    /// The (x, y) positions of the tiles left black
+    pub tiles: Vec<(u32, u32)>,
+    /// Where the tiles sit in the written image, if it wasn't resized or cropped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placement: Option<TilePlacement>,
+}
+
+/// The pixel position of the top left tile of a frame in an image, and the size of a tile
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct TilePlacement {
+    pub left: u32,
+    pub top: u32,
+    pub tile_width: u32,
+}
+
+/// The path of the missing tiles manifest for the frame at `image_path`,
+/// e.g. `himawari8_20221101_120000.jpeg.missing.json`
+pub fn missing_tiles_path(image_path: &Path) -> PathBuf {
+    let mut path = image_path.as_os_str().to_owned();
+    path.push(".missing.json");
+    PathBuf::from(path)
+}
+
+/// The path of the --thumbnail preview of the frame at `image_path`, which is always a JPEG
+pub fn thumbnail_path(image_path: &Path) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    image_path.with_file_name(format!("{}_thumb.jpg", stem))
+}
+
+/// The path of the --variants version of the frame at `image_path` for monitors in
+/// `orientation`
+pub fn variant_path(image_path: &Path, orientation: Orientation) -> PathBuf {
+    let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match image_path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, orientation, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, orientation),
+    };
+    image_path.with_file_name(file_name)
+}
+
+/// Reads the missing tiles manifest of the frame at `image_path`, if it was left incomplete
+pub fn read_missing_tiles(image_path: &Path) -> Option<MissingTiles> {
+    let file = std::fs::File::open(missing_tiles_path(image_path)).ok()?;
+    serde_json::from_reader(file).ok()
+}
+
+/// Records the tiles missing from the frame at `image_path`, or removes the record once
+/// there are none
+pub fn write_missing_tiles(image_path: &Path, missing: &MissingTiles) -> Result<(), AppErr> {
+    let path = missing_tiles_path(image_path);
+    if missing.tiles.is_empty() {
+        return match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        };
+    }
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer_pretty(file, missing)?;
+    Ok(())
+}
+
+fn parse_frame_file_name(file_name: &str) -> Option<DateTime<Utc>> {
+    let (stem, ext) = file_name.strip_prefix(FRAME_FILE_PREFIX)?.rsplit_once('.')?;
+    OutputFormat::from_extension(ext)?;
+    Utc.datetime_from_str(stem, FRAME_TIMESTAMP_FORMAT).ok()
+}
+
+/// Lists the frames archived in `output_dir`, newest first
+pub fn list_frames(output_dir: &Path) -> Result<Vec<ArchivedFrame>, AppErr> {
+    let mut frames = Vec::new();
+    for entry in std::fs::read_dir(output_dir)? {
+        let path = entry?.path();
+        let timestamp = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(parse_frame_file_name);
+        if let Some(timestamp) = timestamp {
+            frames.push(ArchivedFrame { path, timestamp });
+        }
+    }
+    frames.sort_by_key(|frame| std::cmp::Reverse(frame.timestamp));
+    Ok(frames)
+}
+
+/// Deletes every archived frame in `output_dir` other than `keep`, and drops them from the
+/// checksum manifest
+pub fn remove_frames_except(output_dir: &Path, keep: &Path) -> Result<(), AppErr> {
+    for frame in list_frames(output_dir)? {
+        if frame.path != keep {
+            std::fs::remove_file(&frame.path)?;
+            // Not every frame has a sidecar, thumbnail or variants, or is missing tiles
+            let _ = std::fs::remove_file(sidecar_path(&frame.path));
+            let _ = std::fs::remove_file(missing_tiles_path(&frame.path));
+            let thumbnail_path = thumbnail_path(&frame.path);
+            let thumbnail_removed = std::fs::remove_file(&thumbnail_path).is_ok();
+            let variant_paths: Vec<_> = ORIENTATIONS
+                .iter()
+                .map(|&orientation| variant_path(&frame.path, orientation))
+                .filter(|path| std::fs::remove_file(path).is_ok())
+                .collect();
+            // Forgetting a file removes its checksum sidecar too
+            let paths = std::iter::once(&frame.path)
+                .chain(thumbnail_removed.then_some(&thumbnail_path))
+                .chain(&variant_paths);
+            for path in paths {
+                if let Err(app_err) = checksums::forget(output_dir, path) {
+                    warn!("Failed to update the checksum manifest: {}", app_err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_frame_file_names() {
+        let timestamp = Utc.ymd(2022, 11, 1).and_hms(3, 20, 0);
+        assert_eq!(parse_frame_file_name("himawari8_20221101_032000.png"), Some(timestamp));
+        assert_eq!(parse_frame_file_name("himawari8_20221101_032000.JPG"), Some(timestamp));
+        assert_eq!(parse_frame_file_name(&frame_file_name(&timestamp, &OutputFormat::WEBP)), Some(timestamp));
+    }
+
+    #[test]
+    fn parse_other_file_names() {
+        assert_eq!(parse_frame_file_name("himawari8_latest.png"), None);
+        assert_eq!(parse_frame_file_name("himawari8_lockscreen.png"), None);
+        assert_eq!(parse_frame_file_name("himawari8_20221101_032000.txt"), None);
+        assert_eq!(parse_frame_file_name("himawari8_20221101_032000.png.json"), None);
+        assert_eq!(parse_frame_file_name("himawari8_20221101_032000_thumb.jpg"), None);
+        assert_eq!(parse_frame_file_name("himawari8_20221101_032000"), None);
+        assert_eq!(parse_frame_file_name("himawari8_20221301_032000.png"), None);
+        assert_eq!(parse_frame_file_name("20221101_032000.png"), None);
+    }
+
+    #[test]
+    fn removed_frames_leave_the_manifest() {
+        let dir = std::env::temp_dir().join(format!("himawari-desktop-updater-archive-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<_> = (0..3)
+            .map(|hour| {
+                let timestamp = Utc.ymd(2022, 11, 1).and_hms(hour, 0, 0);
+                let path = dir.join(frame_file_name(&timestamp, &OutputFormat::PNG));
+                std::fs::write(&path, [hour as u8]).unwrap();
+                checksums::record(&dir, &path, true).unwrap();
+                path
+            })
+            .collect();
+
+        remove_frames_except(&dir, &paths[2]).unwrap();
+        assert!(!paths[0].exists() && !checksum_path(&paths[0]).exists());
+        assert!(paths[2].exists() && checksum_path(&paths[2]).exists());
+        let report = checksums::verify(&dir).unwrap();
+        assert_eq!(report.ok, 1);
+        assert!(report.missing.is_empty() && report.corrupt.is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
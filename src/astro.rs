@@ -0,0 +1,101 @@
+//! Low precision ephemerides, good to a few tenths of a degree.
+
+use chrono::prelude::*;
+
+use crate::geo;
+
+const J2000: f64 = 2451545.0;
+
+/// Days since the J2000.0 epoch
+fn days_since_j2000(time: &DateTime<Utc>) -> f64 {
+    let julian_day = time.timestamp() as f64 / 86400.0 + 2440587.5;
+    julian_day - J2000
+}
+
+fn sin_deg(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos_deg(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
+
+/// Converts ecliptic coordinates to Earth-fixed cartesian coordinates
+fn ecliptic_to_earth_fixed(d: f64, longitude: f64, latitude: f64, distance: f64) -> [f64; 3] {
+    // Obliquity of the ecliptic
+    let e = 23.439 - 0.000_000_4 * d;
+    // Equatorial (Earth-centred inertial) coordinates
+    let x = distance * cos_deg(latitude) * cos_deg(longitude);
+    let y = distance
+        * (cos_deg(e) * cos_deg(latitude) * sin_deg(longitude) - sin_deg(e) * sin_deg(latitude));
+    let z = distance
+        * (sin_deg(e) * cos_deg(latitude) * sin_deg(longitude) + cos_deg(e) * sin_deg(latitude));
+    // Rotate by Greenwich mean sidereal time
+    let gmst = 280.460_618_37 + 360.985_647_366_29 * d;
+    [
+        x * cos_deg(gmst) + y * sin_deg(gmst),
+        -x * sin_deg(gmst) + y * cos_deg(gmst),
+        z,
+    ]
+}
+
+/// The position of the Moon in Earth-fixed coordinates, in km
+pub fn moon_position(time: &DateTime<Utc>) -> [f64; 3] {
+    let d = days_since_j2000(time);
+    // Mean longitude, elongation and anomaly of the Moon, anomaly of the Sun,
+    // and the Moon's argument of latitude
+    let l = 218.316 + 13.176_396 * d;
+    let e = 297.850 + 12.190_749 * d;
+    let m = 134.963 + 13.064_993 * d;
+    let sun_m = 357.529 + 0.985_600_28 * d;
+    let f = 93.272 + 13.229_350 * d;
+
+    let longitude = l + 6.289 * sin_deg(m) + 1.274 * sin_deg(2.0 * e - m)
+        + 0.658 * sin_deg(2.0 * e)
+        + 0.214 * sin_deg(2.0 * m)
+        - 0.186 * sin_deg(sun_m)
+        - 0.114 * sin_deg(2.0 * f);
+    let latitude = 5.128 * sin_deg(f)
+        + 0.281 * sin_deg(m + f)
+        + 0.278 * sin_deg(m - f)
+        + 0.173 * sin_deg(2.0 * e - f);
+    let distance = 385_001.0
+        - 20_905.0 * cos_deg(m)
+        - 3_699.0 * cos_deg(2.0 * e - m)
+        - 2_956.0 * cos_deg(2.0 * e)
+        - 570.0 * cos_deg(2.0 * m);
+
+    ecliptic_to_earth_fixed(d, longitude, latitude, distance)
+}
+
+/// The position of the Sun in Earth-fixed coordinates, in km
+pub fn sun_position(time: &DateTime<Utc>) -> [f64; 3] {
+    let d = days_since_j2000(time);
+    // Mean longitude and anomaly of the Sun
+    let l = 280.460 + 0.985_647_4 * d;
+    let g = 357.528 + 0.985_600_3 * d;
+
+    let longitude = l + 1.915 * sin_deg(g) + 0.020 * sin_deg(2.0 * g);
+    let distance = (1.000_14 - 0.016_71 * cos_deg(g) - 0.000_14 * cos_deg(2.0 * g)) * 149_597_870.7;
+
+    ecliptic_to_earth_fixed(d, longitude, 0.0, distance)
+}
+
+/// The elevation of the Sun above the horizon at `latitude`, `longitude` at `time`, in degrees
+pub fn sun_elevation(time: &DateTime<Utc>, latitude: f64, longitude: f64) -> f64 {
+    let sun = sun_position(time);
+    let distance = (sun[0] * sun[0] + sun[1] * sun[1] + sun[2] * sun[2]).sqrt();
+    let up = [
+        cos_deg(latitude) * cos_deg(longitude),
+        cos_deg(latitude) * sin_deg(longitude),
+        sin_deg(latitude),
+    ];
+    let dot = (0..3).map(|i| up[i] * sun[i]).sum::<f64>() / distance;
+    dot.asin().to_degrees()
+}
+
+/// True if the Moon appears in the sky around the Earth in a full disk image
+/// taken from above `satellite_longitude` at `time`
+pub fn moon_in_view(time: &DateTime<Utc>, satellite_longitude: f64) -> bool {
+    geo::sky_position(moon_position(time), satellite_longitude).is_some()
+}
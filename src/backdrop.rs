@@ -0,0 +1,150 @@
+//! Backdrops painted around the disk with `--background`: a star field, or a vertical
+//! gradient, so the margins look like part of the picture rather than letterboxing.
+//!
+//! Everything outside the Earth's limb is painted, the black of space in the tiles as well
+//! as the margins. Each channel keeps the brighter of the image and the backdrop, so the glow
+//! of the atmosphere at the limb is left alone.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use image::{Rgba, RgbaImage};
+
+use crate::colour::Colour;
+use crate::geo;
+use crate::map_layers::Disk;
+
+/// About this many stars are drawn in an area the size of the disk
+const STARS_PER_DISK: f64 = 2000.0;
+
+/// Stars are up to this fraction of the disk's width across, and at least a pixel
+const STAR_SIZE_FRACTION: f64 = 1.0 / 2000.0;
+
+/// The start of the sequence the stars are placed by, the same for every frame so that they
+/// stay still in animations
+const STAR_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backdrop {
+    /// The black of space, and the --background-color of the margins
+    #[default]
+    Black,
+    Starfield,
+    /// From the first colour at the top to the second at the bottom
+    Gradient(Colour, Colour),
+}
+
+#[derive(Clone)]
+pub struct BackdropValueParser;
+
+impl clap::builder::TypedValueParser for BackdropValueParser {
+    type Value = Backdrop;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match Backdrop::try_parse(value.to_string_lossy().as_ref()) {
+            Some(backdrop) => Ok(backdrop),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Use black, starfield or gradient:COLOUR-COLOUR, e.g. gradient:#000010-#203060")),
+        }
+    }
+}
+
+impl Backdrop {
+    pub fn try_parse(input: &str) -> Option<Backdrop> {
+        let input = input.trim();
+        if let Some(colours) = input.strip_prefix("gradient:") {
+            let (top, bottom) = colours.split_once('-')?;
+            return Some(Backdrop::Gradient(Colour::try_parse(top)?, Colour::try_parse(bottom)?));
+        }
+        match input {
+            "black" => Some(Backdrop::Black),
+            "starfield" => Some(Backdrop::Starfield),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Backdrop {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            Backdrop::Black => write!(f, "black"),
+            Backdrop::Starfield => write!(f, "starfield"),
+            Backdrop::Gradient(top, bottom) => write!(f, "gradient:{}-{}", top, bottom),
+        }
+    }
+}
+
+/// Paints `backdrop` around `disk` in `image`
+pub fn paint(image: &mut RgbaImage, disk: Disk, backdrop: Backdrop) {
+    let (top, bottom) = match backdrop {
+        Backdrop::Black => return,
+        Backdrop::Starfield => (Colour::BLACK, Colour::BLACK),
+        Backdrop::Gradient(top, bottom) => (top, bottom),
+    };
+    let centre = (disk.left + disk.size / 2.0, disk.top + disk.size / 2.0);
+    let radius = disk.size * geo::disk_radius();
+    let in_space = |x: f64, y: f64| (x - centre.0).powi(2) + (y - centre.1).powi(2) > radius * radius;
+
+    let height = image.height().max(2) - 1;
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if in_space(x as f64 + 0.5, y as f64 + 0.5) {
+            let t = y as f32 / height as f32;
+            let colour = [0, 1, 2].map(|c| top.0[c] as f32 * (1.0 - t) + bottom.0[c] as f32 * t);
+            lighten(pixel, colour);
+        }
+    }
+
+    if backdrop == Backdrop::Starfield {
+        let (width, height) = image.dimensions();
+        let count = (width as f64 * height as f64 / (disk.size * disk.size) * STARS_PER_DISK) as u32;
+        let max_size = (disk.size * STAR_SIZE_FRACTION).max(1.0);
+        let mut random = Random(STAR_SEED);
+        for _ in 0..count {
+            let (x, y) = (random.sample() * width as f64, random.sample() * height as f64);
+            // Most stars are faint and small, a few bright
+            let brightness = random.sample().powi(3) as f32 * 0.8 + 0.2;
+            let size = max_size * (0.5 + 0.5 * random.sample());
+            if in_space(x, y) {
+                draw_star(image, (x, y), size / 2.0, brightness);
+            }
+        }
+    }
+}
+
+/// Keeps the brighter of each channel of `pixel` and `colour`, making the pixel opaque
+fn lighten(pixel: &mut Rgba<u8>, colour: [f32; 3]) {
+    for (channel, new) in pixel.0.iter_mut().zip(colour) {
+        *channel = (*channel).max(new.round() as u8);
+    }
+    pixel.0[3] = 255;
+}
+
+/// Draws a soft white dot `radius` pixels across around `centre`
+fn draw_star(image: &mut RgbaImage, centre: (f64, f64), radius: f64, brightness: f32) {
+    let reach = radius + 1.0;
+    let min_x = (centre.0 - reach).floor().max(0.0) as u32;
+    let max_x = ((centre.0 + reach).ceil() as u32).min(image.width());
+    let min_y = (centre.1 - reach).floor().max(0.0) as u32;
+    let max_y = ((centre.1 + reach).ceil() as u32).min(image.height());
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let distance = ((x as f64 + 0.5 - centre.0).powi(2) + (y as f64 + 0.5 - centre.1).powi(2)).sqrt();
+            let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0) as f32;
+            if coverage > 0.0 {
+                let level = 255.0 * brightness * coverage;
+                lighten(image.get_pixel_mut(x, y), [level; 3]);
+            }
+        }
+    }
+}
+
+/// A xorshift sequence, which is plenty random for scattering stars
+struct Random(u64);
+
+impl Random {
+    /// The next number in the sequence, from 0 up to 1
+    fn sample(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,100 @@
+//! Downloads every frame of a past day, or of a range of times, to fill gaps in the archive
+//! after an outage.
+
+use chrono::prelude::*;
+use log::{error, info, warn};
+
+use crate::archive;
+use crate::console::{Status, Summary};
+use crate::download::{render_bands, DownloadOptions};
+use crate::frames::{FrameStream, Order, StreamOptions};
+use crate::messages::tr;
+use crate::product::FRAME_INTERVAL_MINUTES;
+
+/// The first and last frame times of `date`, leaving out those still to come
+pub fn day(date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = DateTime::<Utc>::from_utc(date.and_hms(0, 0, 0), Utc);
+    let end = start + chrono::Duration::days(1) - chrono::Duration::minutes(FRAME_INTERVAL_MINUTES);
+    (start, end.min(Utc::now()))
+}
+
+/// Downloads every frame captured from `from` to `to` (by default, the latest frame) which
+/// is not already in the output directory, returning the exit code. Frames which fail are
+/// listed at the end, and retried on the next run.
+pub fn run(options: &DownloadOptions, from: DateTime<Utc>, to: Option<DateTime<Utc>>) -> i32 {
+    let stream_options = StreamOptions {
+        product: options.product.clone(),
+        level: options.output_level.to_level(),
+        from: Some(from),
+        to,
+        order: Order::OldestFirst,
+        retry: options.retry,
+    };
+    if let Err(err) = std::fs::DirBuilder::new().recursive(true).create(&options.output_dir) {
+        error!("{}", err);
+        return 1;
+    }
+    let frames = match FrameStream::new(&*options.tiles, stream_options) {
+        Ok(frames) => frames,
+        Err(app_err) => {
+            error!("{}", app_err);
+            return 1;
+        }
+    };
+    let (mut written, mut skipped) = (0, 0);
+    let mut failures = Vec::new();
+
+    for frame in frames {
+        let timestamp = frame.timestamp;
+        let file_name = archive::frame_file_name(&timestamp, &options.output_format);
+        if options.output_dir.join(&file_name).exists() && !options.force {
+            skipped += 1;
+            continue;
+        }
+
+        info!("Backfilling frame with timestamp {}", timestamp);
+        match render_bands(options, &timestamp, &options.output_dir, &file_name, None) {
+            Ok(()) => written += 1,
+            Err(app_err) => {
+                warn!("Failed to download frame {}: {}", timestamp, app_err);
+                failures.push((timestamp, app_err));
+            }
+        }
+    }
+
+    let to = to.map_or_else(|| "latest".to_string(), |to| to.to_string());
+    info!(
+        "Backfilled {} to {}: {} written, {} already present, {} failed",
+        from,
+        to,
+        written,
+        skipped,
+        failures.len()
+    );
+    for (timestamp, app_err) in &failures {
+        info!("Failed: {}: {}", timestamp, app_err);
+    }
+    let failed = failures.len();
+    let summary = Summary::new()
+        .row("From", from)
+        .row("To", to)
+        .status("Written", written, Status::Good)
+        .row("Already present", skipped)
+        .status("Failed", failed, match failed {
+            0 => Status::Good,
+            _ => Status::Bad,
+        });
+    failures
+        .iter()
+        .fold(summary, |summary, (timestamp, _)| {
+            summary.status("Failed frame", timestamp, Status::Bad)
+        })
+        .print();
+    match failed {
+        0 => 0,
+        _ => {
+            error!("{}", tr!("backfill-failed", count = failed));
+            1
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use crate::product::Product;
+use crate::satellite::Satellite;
+
+/// A spectral band, each served as a separate NICT image product
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Band {
+    /// True colour composite of the visible bands
+    Visible,
+    /// The 10.4µm infrared band, which also shows the night side of the planet
+    Infrared,
+}
+
+#[derive(Clone)]
+pub struct BandsValueParser;
+
+impl clap::builder::TypedValueParser for BandsValueParser {
+    type Value = Vec<Band>;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let mut bands = Vec::new();
+        for name in value.to_string_lossy().split(',') {
+            let band = match name.trim() {
+                "visible" => Band::Visible,
+                "infrared" => Band::Infrared,
+                _ => return Err(Error::raw(ErrorKind::InvalidValue, "Invalid band, use a comma separated list of visible and infrared")),
+            };
+            if !bands.contains(&band) {
+                bands.push(band);
+            }
+        }
+        Ok(bands)
+    }
+}
+
+impl Band {
+    pub fn to_product(self) -> Product {
+        let name = match self {
+            Band::Visible => "D531106",
+            Band::Infrared => "INFRARED_FULL",
+        };
+        Product {
+            satellite: Satellite::Himawari,
+            name: name.to_string(),
+            tile_width: 550,
+            base_url: None,
+            levels: None,
+        }
+    }
+}
+
+impl Display for Band {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            Band::Visible => "visible",
+            Band::Infrared => "infrared",
+        };
+        write!(f, "{}", s)
+    }
+}
@@ -0,0 +1,224 @@
+//! Limits on how hard a frame's tiles are retried when the server is struggling.
+//!
+//! Each failed tile is retried a few times, waiting twice as long before each retry, and
+//! the retries come from a budget shared by the whole frame. If most of the first tiles
+//! fail the frame is abandoned early, instead of sending hundreds more requests which will
+//! fail the same way. A frame missing too many tiles once the retries run out is rejected.
+//!
+//! "No Image" placeholders are failures too, but a server sending them is keeping up fine, so
+//! they aren't retried and don't count towards opening the breaker.
+//!
+//! The breaker keeps how each tile failed in the end, its URL, last status and the requests it
+//! took, and sums them up in one place once the frame is done. They go with the error of a
+//! frame which is rejected, too.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use log::warn;
+
+use crate::error::{AppErr, ErrorKind, TileFailure};
+use crate::messages::tr;
+use crate::min_tiles::MinTiles;
+use crate::placeholder::PlaceholderFrame;
+
+/// How many tiles finish before the breaker judges the failure rate
+const SAMPLE_TILES: u32 = 8;
+
+/// The breaker opens if more than this fraction of the sampled tiles failed
+const MAX_FAILURE_RATE: f64 = 0.5;
+
+/// Retries allowed for every 16 tiles of a frame, with a minimum of 2
+const RETRIES_PER_16_TILES: u32 = 1;
+
+/// How long to wait before the first retry of a tile
+const FIRST_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// How many of a frame's failed tiles are listed under the summary of them
+const LISTED_FAILURES: usize = 10;
+
+#[derive(Clone, Copy)]
+pub struct RetryOptions {
+    /// How many times each tile may be retried
+    pub tile_retries: u32,
+    pub min_tiles: MinTiles,
+}
+
+impl Default for RetryOptions {
+    fn default() -> RetryOptions {
+        RetryOptions {
+            tile_retries: 2,
+            min_tiles: MinTiles::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    finished: u32,
+    /// Tiles which failed, including the placeholders
+    failed: u32,
+    placeholders: u32,
+}
+
+pub struct Breaker {
+    tiles: u32,
+    options: RetryOptions,
+    retries_left: AtomicU32,
+    counts: Mutex<Counts>,
+    /// How the tiles which failed after any retries failed
+    failures: Mutex<Vec<TileFailure>>,
+    /// How many of the sampled tiles failed, once the breaker has opened
+    sampled_failures: AtomicU32,
+    open: AtomicBool,
+}
+
+impl Breaker {
+    /// A breaker for a frame made of `tiles` tiles, retried as `options` say
+    pub fn new(tiles: u32, options: RetryOptions) -> Breaker {
+        Breaker {
+            tiles,
+            options,
+            retries_left: AtomicU32::new((tiles * RETRIES_PER_16_TILES / 16).max(2)),
+            counts: Mutex::new(Counts::default()),
+            failures: Mutex::new(Vec::new()),
+            sampled_failures: AtomicU32::new(0),
+            open: AtomicBool::new(false),
+        }
+    }
+
+    /// Fails if the breaker has opened, and no more tiles should be requested
+    pub fn check(&self) -> Result<(), AppErr> {
+        match self.open.load(Ordering::Relaxed) {
+            true => Err(self.error()),
+            false => Ok(()),
+        }
+    }
+
+    /// Takes a retry from the budget for a tile already retried `retries` times.
+    /// Returns how long to wait before retrying, or None if the tile should be given up on.
+    pub fn take_retry(&self, retries: u32) -> Option<Duration> {
+        if retries >= self.options.tile_retries {
+            return None;
+        }
+        self.retries_left
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                left.checked_sub(1)
+            })
+            .ok()?;
+        Some(FIRST_RETRY_DELAY.saturating_mul(2u32.saturating_pow(retries)))
+    }
+
+    /// Notes that a tile was fetched, or failed with `error` after any retries
+    pub fn finish(&self, error: Option<&AppErr>) {
+        if let Some(app_err) = error {
+            self.failures.lock().unwrap().extend_from_slice(app_err.tile_failures());
+        }
+        self.count(error.is_none());
+    }
+
+    fn count(&self, succeeded: bool) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.finished += 1;
+        if !succeeded {
+            counts.failed += 1;
+        }
+        let failed = counts.failed - counts.placeholders;
+        if counts.finished == SAMPLE_TILES && failed as f64 / counts.finished as f64 > MAX_FAILURE_RATE {
+            self.sampled_failures.store(failed, Ordering::Relaxed);
+            self.open.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Notes that a tile was a "No Image" placeholder, which leaves it missing
+    pub fn finish_placeholder(&self) {
+        self.counts.lock().unwrap().placeholders += 1;
+        self.count(false);
+    }
+
+    /// How many of the tiles were placeholders
+    pub fn placeholders(&self) -> u32 {
+        self.counts.lock().unwrap().placeholders
+    }
+
+    /// The failures of the tiles which failed after any retries
+    pub fn failures(&self) -> Vec<TileFailure> {
+        self.failures.lock().unwrap().clone()
+    }
+
+    /// Logs how the tiles of the frame at `timestamp` which failed went wrong: how many failed
+    /// with each status, then the first few of them
+    pub fn summarise(&self, timestamp: &DateTime<Utc>) {
+        let failures = self.failures.lock().unwrap();
+        if failures.is_empty() {
+            return;
+        }
+        let mut causes: Vec<(Option<u16>, usize)> = Vec::new();
+        for failure in failures.iter() {
+            match causes.iter_mut().find(|(status, _)| *status == failure.status) {
+                Some((_, count)) => *count += 1,
+                None => causes.push((failure.status, 1)),
+            }
+        }
+        let causes = causes
+            .iter()
+            .map(|(status, count)| match status {
+                Some(status) => format!("{} with status {}", count, status),
+                None => format!("{} without a response", count),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        warn!("{}", tr!("fragments-failed", count = failures.len(), time = timestamp, causes = causes));
+        for failure in failures.iter().take(LISTED_FAILURES) {
+            warn!("  {} ({} requests): {}", failure.url, failure.attempts, failure.error);
+        }
+        if failures.len() > LISTED_FAILURES {
+            warn!("  {}", tr!("more-fragments-failed", count = failures.len() - LISTED_FAILURES));
+        }
+    }
+
+    /// Fails if the frame at `timestamp` was abandoned, or is missing too many tiles. Either
+    /// way, the tiles which failed are summed up first.
+    pub fn verify(&self, timestamp: &DateTime<Utc>) -> Result<(), AppErr> {
+        self.summarise(timestamp);
+        self.check()?;
+        let counts = *self.counts.lock().unwrap();
+        let downloaded = counts.finished - counts.failed;
+        let required = self.options.min_tiles.required(self.tiles);
+        // Too few tiles because the frame isn't finished yet, which an earlier one can stand in for
+        if downloaded < required && counts.placeholders > 0 {
+            return Err(AppErr::from(PlaceholderFrame {
+                timestamp: *timestamp,
+                tiles: counts.placeholders,
+            }));
+        }
+        let app_err = match downloaded {
+            0 => AppErr::of_kind(ErrorKind::Network, tr!("no-fragments", time = timestamp)),
+            downloaded if downloaded < required => AppErr::of_kind(ErrorKind::Network, tr!(
+                "too-few-fragments",
+                downloaded = downloaded,
+                total = self.tiles,
+                time = timestamp,
+                required = required
+            )),
+            _ => return Ok(()),
+        };
+        Err(app_err.with_tile_failures(self.failures()))
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// The error for a frame abandoned by the breaker
+    pub fn error(&self) -> AppErr {
+        let app_err = AppErr::of_kind(ErrorKind::Network, tr!(
+            "source-unavailable",
+            failed = self.sampled_failures.load(Ordering::Relaxed),
+            sampled = SAMPLE_TILES
+        ));
+        app_err.with_tile_failures(self.failures())
+    }
+}
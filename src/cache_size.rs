@@ -0,0 +1,45 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// A limit on the size of a cache on disk, in bytes
+#[derive(Clone, Copy)]
+pub struct CacheSize(pub u64);
+
+#[derive(Clone)]
+pub struct CacheSizeValueParser;
+
+impl clap::builder::TypedValueParser for CacheSizeValueParser {
+    type Value = CacheSize;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match CacheSize::try_parse(value.to_string_lossy().as_ref()) {
+            Some(size) => Ok(size),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Invalid size, use a number of bytes or a size like 500M or 2G")),
+        }
+    }
+}
+
+impl CacheSize {
+    pub fn try_parse(input: &str) -> Option<CacheSize> {
+        let input = input.trim().to_ascii_uppercase();
+        let input = input.strip_suffix('B').unwrap_or(&input);
+        let (number, unit) = match input.char_indices().last()? {
+            (i, 'K') => (&input[..i], 1 << 10),
+            (i, 'M') => (&input[..i], 1 << 20),
+            (i, 'G') => (&input[..i], 1 << 30),
+            (i, 'T') => (&input[..i], 1 << 40),
+            _ => (input, 1),
+        };
+        let number = number.trim().parse::<u64>().ok()?;
+        number.checked_mul(unit).map(CacheSize)
+    }
+}
+
+impl Display for CacheSize {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let units = [(1 << 40, "T"), (1 << 30, "G"), (1 << 20, "M"), (1 << 10, "K")];
+        match units.iter().find(|(unit, _)| self.0 >= *unit && self.0.is_multiple_of(*unit)) {
+            Some((unit, suffix)) => write!(f, "{}{}", self.0 / unit, suffix),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
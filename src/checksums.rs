@@ -0,0 +1,193 @@
+//! A `SHA256SUMS` manifest of the frames in the output directory.
+//!
+//! The manifest uses the same format as `sha256sum`, so `sha256sum -c SHA256SUMS`
+//! also works, and lets the `verify` subcommand find frames which have since been
+//! truncated or corrupted.
+//!
+//! With --checksum-sidecars each file's checksum is also written beside it, as
+//! `<file>.sha256` in the same format, so a frame copied elsewhere can be checked on its
+//! own. `verify` checks these too, including any for files the manifest has lost.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::archive;
+use crate::error::AppErr;
+
+const SUMS_FILE_NAME: &str = "SHA256SUMS";
+
+/// Frames of different bands are written concurrently, so updates to the manifest take turns
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+struct Entry {
+    hash: String,
+    /// Path relative to the output directory, separated by '/'
+    name: String,
+}
+
+pub struct VerifyReport {
+    pub ok: usize,
+    pub missing: Vec<PathBuf>,
+    pub corrupt: Vec<PathBuf>,
+}
+
+pub fn hash_file(path: &Path) -> Result<String, AppErr> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn relative_name(output_dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(output_dir).ok()?;
+    let parts = relative
+        .components()
+        .map(|part| part.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+fn read_entries(output_dir: &Path) -> Result<Vec<Entry>, AppErr> {
+    let file = match File::open(output_dir.join(SUMS_FILE_NAME)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Some(entry) = parse_line(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_line(line: &str) -> Option<Entry> {
+    // "<hash>  <name>", or "<hash> *<name>" for files hashed in binary mode
+    let (hash, name) = line.split_once(' ')?;
+    let name = name.strip_prefix(['*', ' ']).unwrap_or(name);
+    Some(Entry {
+        hash: hash.to_string(),
+        name: name.to_string(),
+    })
+}
+
+/// Writes the sidecar of the file at `path`, naming the file without its directory so that
+/// `sha256sum -c` works from beside it
+fn write_sidecar(path: &Path, hash: &str) -> Result<(), AppErr> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    std::fs::write(archive::checksum_path(path), format!("{}  {}\n", hash, file_name))?;
+    Ok(())
+}
+
+/// Adds the files in and under `dir` which have a sidecar, but no entry in `entries`
+fn add_sidecar_entries(output_dir: &Path, dir: &Path, entries: &mut Vec<Entry>) -> Result<(), AppErr> {
+    for dir_entry in std::fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        if path.is_dir() {
+            add_sidecar_entries(output_dir, &path, entries)?;
+            continue;
+        }
+        if path.extension().is_none_or(|ext| ext != "sha256") {
+            continue;
+        }
+        // "<file>.sha256" is beside "<file>"
+        let name = match relative_name(output_dir, &path.with_extension("")) {
+            Some(name) => name,
+            None => continue,
+        };
+        if entries.iter().any(|entry| entry.name == name) {
+            continue;
+        }
+        let line = std::fs::read_to_string(&path)?;
+        match line.lines().next().and_then(parse_line) {
+            Some(Entry { hash, .. }) => entries.push(Entry { hash, name }),
+            None => warn!("{}: not a checksum", path.display()),
+        }
+    }
+    Ok(())
+}
+
+fn write_entries(output_dir: &Path, entries: &[Entry]) -> Result<(), AppErr> {
+    // Write to a temporary file first, so an interrupted update can't truncate the manifest
+    let temp_path = output_dir.join(format!("{}.tmp", SUMS_FILE_NAME));
+    let mut file = File::create(&temp_path)?;
+    for entry in entries {
+        writeln!(file, "{}  {}", entry.hash, entry.name)?;
+    }
+    file.sync_all()?;
+    std::fs::rename(&temp_path, output_dir.join(SUMS_FILE_NAME))?;
+    Ok(())
+}
+
+/// Adds the file at `path` to the manifest in `output_dir`, replacing any previous entry, and
+/// writes its checksum to a sidecar beside it too if `sidecar` is set
+pub fn record(output_dir: &Path, path: &Path, sidecar: bool) -> Result<(), AppErr> {
+    let name = match relative_name(output_dir, path) {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    let hash = hash_file(path)?;
+    if sidecar {
+        write_sidecar(path, &hash)?;
+    }
+
+    let _lock = MANIFEST_LOCK.lock().unwrap();
+    let mut entries = read_entries(output_dir)?;
+    entries.retain(|entry| entry.name != name);
+    entries.push(Entry { hash, name });
+    write_entries(output_dir, &entries)
+}
+
+/// Removes the file at `path` from the manifest in `output_dir`, along with its sidecar
+pub fn forget(output_dir: &Path, path: &Path) -> Result<(), AppErr> {
+    // Not every file has one, if sidecars were turned on later
+    let _ = std::fs::remove_file(archive::checksum_path(path));
+    let name = match relative_name(output_dir, path) {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    let _lock = MANIFEST_LOCK.lock().unwrap();
+    let mut entries = read_entries(output_dir)?;
+    let count = entries.len();
+    entries.retain(|entry| entry.name != name);
+    if entries.len() == count {
+        return Ok(());
+    }
+    write_entries(output_dir, &entries)
+}
+
+/// Checks every file in the manifest in `output_dir`, or with a sidecar, against its
+/// recorded hash
+pub fn verify(output_dir: &Path) -> Result<VerifyReport, AppErr> {
+    let mut entries = read_entries(output_dir)?;
+    add_sidecar_entries(output_dir, output_dir, &mut entries)?;
+    info!("Verifying {} files...", entries.len());
+
+    let mut report = VerifyReport {
+        ok: 0,
+        missing: Vec::new(),
+        corrupt: Vec::new(),
+    };
+    for entry in entries {
+        let path = output_dir.join(&entry.name);
+        match hash_file(&path) {
+            Ok(hash) if hash.eq_ignore_ascii_case(&entry.hash) => report.ok += 1,
+            Ok(_) => {
+                warn!("{}: checksum does not match", path.display());
+                report.corrupt.push(path);
+            }
+            Err(app_err) => {
+                warn!("{}: {}", path.display(), app_err);
+                report.missing.push(path);
+            }
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,62 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use image::Rgba;
+
+/// A colour given on the command line, as #RRGGBB, #RRGGBBAA or a name
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Colour(pub Rgba<u8>);
+
+#[derive(Clone)]
+pub struct ColourValueParser;
+
+impl clap::builder::TypedValueParser for ColourValueParser {
+    type Value = Colour;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match Colour::try_parse(value.to_string_lossy().as_ref()) {
+            Some(colour) => Ok(colour),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Invalid colour, use #RRGGBB, #RRGGBBAA, white, black or grey")),
+        }
+    }
+}
+
+impl Colour {
+    pub const WHITE: Colour = Colour(Rgba([255, 255, 255, 255]));
+    pub const BLACK: Colour = Colour(Rgba([0, 0, 0, 255]));
+    pub const TRANSPARENT: Colour = Colour(Rgba([0, 0, 0, 0]));
+
+    pub fn try_parse(input: &str) -> Option<Colour> {
+        let input = input.trim().to_ascii_lowercase();
+        match input.as_str() {
+            "white" => return Some(Colour::WHITE),
+            "black" => return Some(Colour::BLACK),
+            "grey" | "gray" => return Some(Colour(Rgba([128, 128, 128, 255]))),
+            _ => {}
+        }
+        let hex = input.strip_prefix('#').unwrap_or(&input);
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return None;
+        }
+        let mut channels = [255; 4];
+        for (i, channel) in channels.iter_mut().take(hex.len() / 2).enumerate() {
+            *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(Colour(Rgba(channels)))
+    }
+
+    /// The colour as #RRGGBB, without its alpha, as desktops take a background colour
+    pub fn to_rgb_hex(self) -> String {
+        let [r, g, b, _] = self.0 .0;
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+impl Display for Colour {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let [r, g, b, a] = self.0 .0;
+        match a {
+            255 => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            _ => write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+        }
+    }
+}
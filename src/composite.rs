@@ -0,0 +1,440 @@
+//! Stitching a frame together from its tiles, which are downloaded concurrently.
+
+use chrono::prelude::*;
+use futures::future::FutureExt;
+use futures::stream::{self, StreamExt};
+use image::{load_from_memory_with_format, GenericImage, ImageBuffer, ImageFormat, RgbaImage};
+use log::{debug, warn};
+
+use crate::breaker::{Breaker, RetryOptions};
+use crate::colour::Colour;
+use crate::error::AppErr;
+use crate::frames::FrameHandle;
+use crate::margins::Margins;
+use crate::metrics::Metrics;
+use crate::output_level::OutputLevel;
+use crate::placeholder::{self, PlaceholderTile};
+use crate::product::Product;
+use crate::progress::Progress;
+use crate::resize;
+use crate::resize_mode::ResizeMode;
+use crate::size::Size;
+use crate::tiles::ImageSource;
+
+/// How an image is made from a frame, for programs which want the picture without the
+/// archiving and post-processing the updater does
+pub struct CompositeOptions {
+    pub product: Product,
+    pub level: OutputLevel,
+    pub margins: Margins,
+    /// Optional size to scale the image down to, as `resize_mode` says
+    pub resize: Option<Size>,
+    pub resize_mode: ResizeMode,
+    /// Optional colour to fill the margins with, which are otherwise transparent
+    pub background: Option<Colour>,
+    /// How the tiles are retried, and how many are needed
+    pub retry: RetryOptions,
+}
+
+/// Downloads the frame at `timestamp` from `source` and makes an image of it as `options` say
+pub fn composite(
+    source: &dyn ImageSource,
+    timestamp: &DateTime<Utc>,
+    options: &CompositeOptions,
+) -> Result<RgbaImage, AppErr> {
+    let level = options.level.to_level();
+    let frame = FrameHandle::new(source, options.product.clone(), level, *timestamp)
+        .with_background(options.background.unwrap_or(Colour::TRANSPARENT))
+        .with_retry(options.retry);
+    let margins = &options.margins;
+    let disk = options.product.tile_width * level;
+    let (width, height) = (
+        margins.left + disk + margins.right,
+        margins.top + disk + margins.bottom,
+    );
+    let reduce_factor = options
+        .resize
+        .as_ref()
+        .and_then(|size| resize::box_factor(width, height, size, options.resize_mode));
+    let image = match reduce_factor {
+        Some(factor) => frame.fetch_reduced(margins, factor)?,
+        None => frame.fetch(margins)?,
+    };
+    Ok(match options.resize {
+        Some(ref size) => {
+            let background = options.background.unwrap_or(Colour::BLACK);
+            resize::resize(image, size, options.resize_mode, background.0)
+        }
+        None => image,
+    })
+}
+
+/// A frame stitched together from its tiles
+pub struct Stitched {
+    pub image: RgbaImage,
+    /// The (x, y) positions of the tiles which failed to download, left as the background
+    pub missing: Vec<(u32, u32)>,
+    /// How many of the missing tiles were "No Image" placeholders
+    pub placeholders: u32,
+}
+
+/// A frame written out as its tiles arrived, without ever being held whole
+pub struct Streamed {
+    /// The (x, y) positions of the tiles which failed to download, left as the background
+    pub missing: Vec<(u32, u32)>,
+    /// How many of the missing tiles were "No Image" placeholders
+    pub placeholders: u32,
+}
+
+/// The (x, y) positions of `positions` which aren't among the `downloaded` ones
+fn missing_positions(positions: &[(u32, u32)], downloaded: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    positions
+        .iter()
+        .filter(|position| !downloaded.contains(position))
+        .copied()
+        .collect()
+}
+
+/// Downloads every fragment of the frame at `timestamp` and stitches them together,
+/// surrounded by `margins` filled with `background`, retrying failed fragments as `retry` says
+/// and counting them in `metrics`
+#[allow(clippy::too_many_arguments)]
+pub fn download_composite(
+    tiles: &dyn ImageSource,
+    product: &Product,
+    level: u32,
+    timestamp: &DateTime<Utc>,
+    margins: &Margins,
+    background: Colour,
+    retry: RetryOptions,
+    metrics: &Metrics,
+) -> Result<Stitched, AppErr> {
+    let width = product.tile_width;
+
+    // For each (x, y) position in a level*level image...
+    let chunk_positions: Vec<_> = (0..level)
+        .flat_map(|y| (0..level).map(move |x| (x, y)))
+        .collect();
+
+    let w = margins.left + (width * level) + margins.right;
+    let h = margins.top + (width * level) + margins.bottom;
+    let mut buf = ImageBuffer::from_pixel(w, h, background.0);
+
+    // Copy each chunk in as it arrives, so only those still being decoded are held apart
+    // from the image
+    let breaker = Breaker::new(level * level, retry);
+    let progress = Progress::new(level * level);
+    let mut downloaded = Vec::new();
+    for_each_chunk(tiles, product, level, timestamp, chunk_positions.clone(), &breaker, &progress, metrics, |x, y, chunk| {
+        buf.copy_from(&chunk, margins.left + (x * width), margins.top + (y * width))?;
+        downloaded.push((x, y));
+        Ok(())
+    })?;
+    drop(progress);
+    breaker.verify(timestamp)?;
+    let missing = missing_positions(&chunk_positions, &downloaded);
+
+    Ok(Stitched {
+        image: buf,
+        missing,
+        placeholders: breaker.placeholders(),
+    })
+}
+
+/// Like download_composite, but reduces the image by `factor` one row of chunks at a time,
+/// so that the whole image is never held at full size
+#[allow(clippy::too_many_arguments)]
+pub fn download_composite_reduced(
+    tiles: &dyn ImageSource,
+    product: &Product,
+    level: u32,
+    timestamp: &DateTime<Utc>,
+    margins: &Margins,
+    background: Colour,
+    retry: RetryOptions,
+    metrics: &Metrics,
+    factor: u32,
+) -> Result<Stitched, AppErr> {
+    let width = product.tile_width;
+    let w = margins.left + (width * level) + margins.right;
+    let h = margins.top + (width * level) + margins.bottom;
+
+    let breaker = Breaker::new(level * level, retry);
+    let progress = Progress::new(level * level);
+    let mut missing = Vec::new();
+    let mut reducer = resize::BandReducer::new(w, h, factor);
+    reducer.push_blank(margins.top, background.0);
+    for y in 0..level {
+        let band = download_band(tiles, product, level, timestamp, y, margins, background, &breaker, &progress, metrics, &mut missing)?;
+        reducer.push(&band);
+    }
+    reducer.push_blank(margins.bottom, background.0);
+
+    drop(progress);
+    breaker.verify(timestamp)?;
+    Ok(Stitched {
+        image: reducer.finish(),
+        missing,
+        placeholders: breaker.placeholders(),
+    })
+}
+
+/// Like download_composite, but hands the image to `write` from top to bottom, a row of
+/// chunks (or a margin) at a time, as each row arrives. Only a row of chunks is ever held
+/// in memory, however large the level.
+#[allow(clippy::too_many_arguments)]
+pub fn download_composite_streamed<F>(
+    tiles: &dyn ImageSource,
+    product: &Product,
+    level: u32,
+    timestamp: &DateTime<Utc>,
+    margins: &Margins,
+    background: Colour,
+    retry: RetryOptions,
+    metrics: &Metrics,
+    mut write: F,
+) -> Result<Streamed, AppErr>
+where
+    F: FnMut(&RgbaImage) -> Result<(), AppErr>,
+{
+    let w = margins.left + (product.tile_width * level) + margins.right;
+
+    let breaker = Breaker::new(level * level, retry);
+    let progress = Progress::new(level * level);
+    let mut missing = Vec::new();
+    if margins.top > 0 {
+        write(&ImageBuffer::from_pixel(w, margins.top, background.0))?;
+    }
+    for y in 0..level {
+        let band = download_band(tiles, product, level, timestamp, y, margins, background, &breaker, &progress, metrics, &mut missing)?;
+        write(&band)?;
+    }
+    if margins.bottom > 0 {
+        write(&ImageBuffer::from_pixel(w, margins.bottom, background.0))?;
+    }
+
+    drop(progress);
+    breaker.verify(timestamp)?;
+    Ok(Streamed {
+        missing,
+        placeholders: breaker.placeholders(),
+    })
+}
+
+/// Downloads row `y` of the chunks of the frame at `timestamp`, as a band of the image as wide
+/// as `margins` make it. Adds the positions of the chunks which failed to `missing`.
+#[allow(clippy::too_many_arguments)]
+fn download_band(
+    tiles: &dyn ImageSource,
+    product: &Product,
+    level: u32,
+    timestamp: &DateTime<Utc>,
+    y: u32,
+    margins: &Margins,
+    background: Colour,
+    breaker: &Breaker,
+    progress: &Progress,
+    metrics: &Metrics,
+    missing: &mut Vec<(u32, u32)>,
+) -> Result<RgbaImage, AppErr> {
+    let width = product.tile_width;
+    let w = margins.left + (width * level) + margins.right;
+    let mut band = ImageBuffer::from_pixel(w, width, background.0);
+    let chunk_positions: Vec<_> = (0..level).map(|x| (x, y)).collect();
+    let mut downloaded = Vec::new();
+    for_each_chunk(tiles, product, level, timestamp, chunk_positions.clone(), breaker, progress, metrics, |x, _, chunk| {
+        band.copy_from(&chunk, margins.left + (x * width), 0)?;
+        downloaded.push((x, y));
+        Ok(())
+    })?;
+    breaker.check()?;
+    missing.extend(missing_positions(&chunk_positions, &downloaded));
+    Ok(band)
+}
+
+/// Downloads just the tiles at `positions` of the frame at `timestamp`, as when repairing
+/// an image which was missing them. Tiles which fail again are left out.
+pub fn download_tiles(
+    tiles: &dyn ImageSource,
+    product: &Product,
+    level: u32,
+    timestamp: &DateTime<Utc>,
+    positions: Vec<(u32, u32)>,
+    retry: RetryOptions,
+    metrics: &Metrics,
+) -> Result<Vec<(u32, u32, image::DynamicImage)>, AppErr> {
+    // The retry budget is that of the whole frame, as only a few tiles are asked for
+    let breaker = Breaker::new(level * level, retry);
+    let progress = Progress::new(positions.len() as u32);
+    let mut chunks = Vec::new();
+    for_each_chunk(tiles, product, level, timestamp, positions, &breaker, &progress, metrics, |x, y, chunk| {
+        chunks.push((x, y, chunk));
+        Ok(())
+    })?;
+    drop(progress);
+    breaker.summarise(timestamp);
+    breaker.check()?;
+    Ok(chunks)
+}
+
+/// Downloads the chunks at `chunk_positions`, as many at once as --concurrency allows, and
+/// hands each to `on_chunk` as it arrives, leaving out any which fail. Failed chunks are
+/// retried with backoff from a budget shared by the frame through `breaker`, which gives up
+/// early on a server that fails most of them. Placeholder chunks are left out too, and
+/// forgotten so that they are downloaded again.
+#[allow(clippy::too_many_arguments)]
+fn for_each_chunk<F>(
+    tiles: &dyn ImageSource,
+    product: &Product,
+    level: u32,
+    timestamp: &DateTime<Utc>,
+    chunk_positions: Vec<(u32, u32)>,
+    breaker: &Breaker,
+    progress: &Progress,
+    metrics: &Metrics,
+    mut on_chunk: F,
+) -> Result<(), AppErr>
+where
+    F: FnMut(u32, u32, image::DynamicImage) -> Result<(), AppErr>,
+{
+    let download_chunk = |x: u32, y: u32| async move {
+        let image = tiles.fetch(product, level, timestamp, x, y).await?;
+        // Decode off the runtime's threads, so downloads carry on meanwhile
+        let decode = move || {
+            let image = load_from_memory_with_format(&image, ImageFormat::Png)?;
+            let is_placeholder = placeholder::is_placeholder(&image);
+            Ok::<_, AppErr>((image, is_placeholder))
+        };
+        let (image, is_placeholder) = tokio::task::spawn_blocking(decode).await??;
+        if is_placeholder {
+            tiles.forget(product, level, timestamp, x, y);
+            return Err(AppErr::from(PlaceholderTile {
+                timestamp: *timestamp,
+                x,
+                y,
+            }));
+        }
+        Ok::<_, AppErr>(image)
+    };
+
+    let retry_chunk = |x: u32, y: u32| async move {
+        let mut retries = 0;
+        // Requests sent for the chunk over every retry, for the summary of those which failed
+        let mut attempts = 0;
+        loop {
+            breaker.check()?;
+            let result = download_chunk(x, y).await;
+            if let Err(ref err) = result {
+                attempts += err.tile_failures().first().map_or(1, |failure| failure.attempts);
+            }
+            let delay = match result {
+                // The server won't have the tile moments later, so a placeholder isn't retried
+                Err(ref err) if placeholder::is_placeholder_tile(err) => None,
+                Err(_) if tiles.is_remote() => breaker.take_retry(retries),
+                _ => None,
+            };
+            match (result, delay) {
+                (Err(err), Some(delay)) => {
+                    warn!("{}, retrying in {} ms", err, delay.as_millis());
+                    progress.retried();
+                    metrics.record_retry();
+                    tokio::time::sleep(delay).await;
+                    retries += 1;
+                }
+                (Err(err), None) if placeholder::is_placeholder_tile(&err) => {
+                    breaker.finish_placeholder();
+                    progress.finish(false);
+                    return Err(err);
+                }
+                (result, _) => {
+                    let result = result.map_err(|err| {
+                        let status = err.tile_failures().first().and_then(|failure| failure.status);
+                        err.for_tile(tiles.location(product, level, timestamp, x, y), status, attempts)
+                    });
+                    breaker.finish(result.as_ref().err());
+                    progress.finish(result.is_ok());
+                    return result;
+                }
+            }
+        }
+    };
+
+    // Download each chunk into memory, sharing the client's connections
+    let chunks = stream::iter(chunk_positions)
+        .map(|(x, y)| async move { (x, y, retry_chunk(x, y).await) })
+        .buffer_unordered(tiles.concurrency())
+        .filter_map(|(x, y, result)| async move {
+            match result {
+                Ok(c) => Some((x, y, c)),
+                // Chunks skipped by the breaker are reported by the caller
+                Err(_) if breaker.is_open() => None,
+                Err(err) => {
+                    // Leave a hole in the final image, unless --min-tiles rejects it. The
+                    // breaker sums up the failures once the frame is done.
+                    debug!("{}", err);
+                    None
+                }
+            }
+        });
+    tiles.block_on(
+        async {
+            let mut chunks = Box::pin(chunks);
+            while let Some((x, y, chunk)) = chunks.next().await {
+                on_chunk(x, y, chunk)?;
+            }
+            Ok(())
+        }
+        .boxed_local(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tiles::DirectorySource;
+    use image::Rgba;
+
+    const COLOURS: [[u8; 4]; 4] = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 0, 255]];
+
+    #[test]
+    fn composite_from_tile_directory() {
+        let dir = std::env::temp_dir().join(format!("himawari-desktop-updater-composite-{}", std::process::id()));
+        let product = Product {
+            tile_width: 4,
+            levels: Some(vec![2]),
+            ..Product::default()
+        };
+        let timestamp = Utc.ymd(2022, 11, 1).and_hms(3, 20, 0);
+        // A fixture frame of 2x2 tiles, each of one colour, laid out as --save-tiles leaves them
+        for (i, colour) in COLOURS.iter().enumerate() {
+            let (x, y) = (i as u32 % 2, i as u32 / 2);
+            let path = dir.join(product.tile_path(2, &timestamp, x, y));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            RgbaImage::from_pixel(4, 4, Rgba(*colour)).save(&path).unwrap();
+        }
+
+        let source = DirectorySource { dir: dir.clone() };
+        assert_eq!(source.newest(&product, 2).unwrap(), timestamp);
+        let options = CompositeOptions {
+            product: product.clone(),
+            level: OutputLevel::max(&product),
+            margins: Margins {
+                top: 1,
+                ..Margins::default()
+            },
+            resize: None,
+            resize_mode: ResizeMode::default(),
+            background: None,
+            retry: RetryOptions::default(),
+        };
+        let image = composite(&source, &timestamp, &options).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(image.dimensions(), (8, 9));
+        assert_eq!(image.get_pixel(0, 0).0, Colour::TRANSPARENT.0 .0);
+        assert_eq!(image.get_pixel(0, 1).0, COLOURS[0]);
+        assert_eq!(image.get_pixel(7, 4).0, COLOURS[1]);
+        assert_eq!(image.get_pixel(3, 5).0, COLOURS[2]);
+        assert_eq!(image.get_pixel(7, 8).0, COLOURS[3]);
+    }
+}
@@ -0,0 +1,453 @@
+//! Settings read from a config file, so a scheduled task doesn't need a long command line.
+//!
+//! The file is TOML, with a key for any of the command line options, named without the
+//! leading dashes. Flags are booleans, options which take a list can be given an array,
+//! and the rest take a string or a number:
+//!
+//! ```toml
+//! output-dir = "C:\\Users\\me\\Pictures\\Himawari"
+//! output-level = 8
+//! output-format = ["jpeg", "png"]
+//! margins = [0, 0, 40, 0]
+//! set-wallpaper = true
+//! resolve = ["himawari8-dl.nict.go.jp=203.0.113.7"]
+//! ```
+//!
+//! The file is `--config FILE` (or `HIMAWARI_CONFIG`), or `config.toml` in the user's config
+//! directory if there is one. Settings become the defaults of their options, so the command
+//! line overrides them.
+//!
+//! Below the config file, any option can be set with an environment variable named after it,
+//! for containers and CI jobs: `HIMAWARI_OUTPUT_DIR` for --output-dir, `HIMAWARI_OUTPUT_LEVEL`
+//! for --output-level, and so on. Flags are turned on with `true` or `1`, and the values of
+//! options which can be given several times are comma separated.
+
+use std::env::VarError;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches};
+use serde_derive::{Deserialize, Serialize};
+use toml::value::{Table, Value};
+
+use crate::error::{AppErr, ErrorKind};
+use crate::messages::tr;
+
+const CONFIG_DIR_NAME: &str = "himawari-desktop-updater";
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// The start of the name of the environment variable for each option
+const ENV_PREFIX: &str = "HIMAWARI_";
+
+/// Options which make no sense in a config file
+const EXCLUDED: [&str; 2] = ["config", "settings"];
+
+/// The settings in a config file, by option name
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Config(Table);
+
+#[cfg(feature = "gui")]
+impl Config {
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.0.get(name)?.as_str()
+    }
+
+    pub fn get_u32(&self, name: &str) -> Option<u32> {
+        use std::convert::TryInto;
+        self.0.get(name)?.as_integer()?.try_into().ok()
+    }
+
+    pub fn get_bool(&self, name: &str) -> bool {
+        self.0.get(name).and_then(Value::as_bool).unwrap_or(false)
+    }
+
+    /// Sets or, given None, removes a setting
+    pub fn set<V: Into<Value>>(&mut self, name: &str, value: Option<V>) {
+        match value {
+            Some(value) => self.0.insert(name.to_string(), value.into()),
+            None => self.0.remove(name),
+        };
+    }
+}
+
+/// The config file in the user's config directory for the platform
+pub fn default_path() -> Option<PathBuf> {
+    let var = |name| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(windows) {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))
+    };
+    base.map(|base| base.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+/// The path given with --config, which has to be found before the arguments are parsed
+pub fn path_from_args(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// The environment variable which sets the option `name`, e.g. HIMAWARI_OUTPUT_DIR
+pub fn env_var_name(name: &str) -> String {
+    format!("{}{}", ENV_PREFIX, name.to_ascii_uppercase().replace('-', "_"))
+}
+
+/// Finds the config file: the one given with --config or HIMAWARI_CONFIG, which has to exist,
+/// or else the one in the user's config directory, if there is one
+pub fn find(args: &[OsString]) -> Result<Option<PathBuf>, AppErr> {
+    let from_env = || {
+        std::env::var_os(env_var_name("config"))
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    match path_from_args(args).or_else(from_env) {
+        Some(path) if path.is_file() => Ok(Some(path)),
+        Some(path) => Err(AppErr::of_kind(
+            ErrorKind::Config,
+            tr!("config-not-found", path = path.display()),
+        )),
+        None => Ok(default_path().filter(|path| path.is_file())),
+    }
+}
+
+/// Reads the config file at `path`. A missing file is the same as an empty one.
+pub fn load(path: &Path) -> Result<Config, AppErr> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => toml::from_str(&text).map_err(|err| {
+            AppErr::of_kind(ErrorKind::Config, tr!("config-invalid", path = path.display(), error = err))
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(feature = "gui")]
+pub fn save(path: &Path, config: &Config) -> Result<(), AppErr> {
+    let text = toml::to_string(config)?;
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new().recursive(true).create(dir)?;
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// A setting as it would be written on the command line
+fn to_arg_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Integer(n) => Some(n.to_string()),
+        Value::Float(n) => Some(n.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        Value::Datetime(time) => Some(time.to_string()),
+        Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
+/// Makes the settings in `config` the defaults of their options in `command`
+pub fn apply(command: clap::Command, config: &Config) -> Result<clap::Command, AppErr> {
+    let invalid = |name: &str| AppErr::of_kind(ErrorKind::Config, tr!("config-invalid-value", name = name));
+    let mut defaults: Vec<(String, Vec<String>)> = Vec::new();
+    for (name, value) in &config.0 {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == name.as_str() && arg.get_long().is_some())
+            .filter(|_| !EXCLUDED.contains(&name.as_str()))
+            .ok_or_else(|| AppErr::of_kind(ErrorKind::Config, tr!("config-unknown-setting", name = name)))?;
+        let values = match (arg.get_action(), value) {
+            // Flags can only be turned on, as the command line can't turn them off again
+            (ArgAction::SetTrue, Value::Boolean(true)) => vec!["true".to_string()],
+            (ArgAction::SetTrue, Value::Boolean(false)) => continue,
+            (ArgAction::SetTrue, _) => return Err(invalid(name)),
+            (ArgAction::Append, Value::Array(items)) => items
+                .iter()
+                .map(to_arg_value)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| invalid(name))?,
+            // Lists of a single option are written comma separated, e.g. margins
+            (_, Value::Array(items)) => {
+                let items = items
+                    .iter()
+                    .map(to_arg_value)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| invalid(name))?;
+                vec![items.join(",")]
+            }
+            (_, value) => vec![to_arg_value(value).ok_or_else(|| invalid(name))?],
+        };
+        defaults.push((name.clone(), values));
+    }
+    Ok(defaults.into_iter().fold(command, |command, (name, values)| {
+        command.mut_arg(name, |arg| arg.default_values(values))
+    }))
+}
+
+/// Makes the settings in the environment the defaults of their options in `command`. Apply
+/// these before the config file, whose settings take their place.
+pub fn apply_env(command: clap::Command) -> Result<clap::Command, AppErr> {
+    apply_vars(command, |var| std::env::var(var))
+}
+
+/// Makes the settings in the variables `lookup` finds by name the defaults of their options in
+/// `command`
+fn apply_vars<F>(command: clap::Command, lookup: F) -> Result<clap::Command, AppErr>
+where
+    F: Fn(&str) -> Result<String, VarError>,
+{
+    let mut defaults: Vec<(String, Vec<String>)> = Vec::new();
+    for arg in command.get_arguments().filter(|arg| arg.get_long().is_some()) {
+        let name = arg.get_id().as_str();
+        if EXCLUDED.contains(&name) {
+            continue;
+        }
+        let var = env_var_name(name);
+        let invalid = || AppErr::of_kind(ErrorKind::Config, tr!("env-invalid-value", name = var));
+        let value = match lookup(&var) {
+            Ok(value) if !value.is_empty() => value,
+            Ok(_) | Err(VarError::NotPresent) => continue,
+            Err(VarError::NotUnicode(_)) => return Err(invalid()),
+        };
+        let values = match arg.get_action() {
+            ArgAction::SetTrue => match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => vec!["true".to_string()],
+                "false" | "0" | "no" | "off" => continue,
+                _ => return Err(invalid()),
+            },
+            ArgAction::Append => value.split(',').map(|item| item.trim().to_string()).collect(),
+            _ => vec![value],
+        };
+        defaults.push((name.to_string(), values));
+    }
+    Ok(defaults.into_iter().fold(command, |command, (name, values)| {
+        command.mut_arg(name, |arg| arg.default_values(values))
+    }))
+}
+
+/// Checks the settings `command` took from the config file and the environment against the
+/// requirements and conflicts of its options, which clap only checks on the command line.
+/// `plain` is the command without those settings, and `matches` what `command` made of `argv`.
+pub fn validate(plain: clap::Command, command: &clap::Command, matches: &ArgMatches, argv: &[OsString]) -> Result<(), AppErr> {
+    let mut settings = Vec::new();
+    for arg in command.get_arguments().filter(|arg| arg.get_long().is_some()) {
+        let name = arg.get_id().as_str();
+        let default = plain
+            .get_arguments()
+            .find(|plain_arg| plain_arg.get_id() == name)
+            .map_or(&[][..], |plain_arg| plain_arg.get_default_values());
+        // Settings the command line overrides have been checked already
+        if arg.get_default_values() == default || matches.value_source(name) != Some(ValueSource::DefaultValue) {
+            continue;
+        }
+        match arg.get_action() {
+            ArgAction::SetTrue => settings.push(OsString::from(format!("--{}", name))),
+            _ => {
+                for value in arg.get_default_values() {
+                    let mut setting = OsString::from(format!("--{}=", name));
+                    setting.push(value);
+                    settings.push(setting);
+                }
+            }
+        }
+    }
+    if settings.is_empty() {
+        return Ok(());
+    }
+
+    // Given before the command line, so that they aren't taken as a subcommand's
+    let merged = argv.iter().take(1).chain(&settings).chain(argv.iter().skip(1));
+    match plain.try_get_matches_from(merged) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            // Without the usage and the hint, which are about the command line
+            let error = err.to_string();
+            let error = error.split("\n\n").next().unwrap_or_default().trim_start_matches("error: ");
+            Err(AppErr::of_kind(ErrorKind::Config, tr!("settings-invalid", error = error)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::{Arg, Command};
+
+    fn command() -> Command {
+        Command::new("test")
+            .arg(Arg::new("output-dir").long("output-dir"))
+            .arg(Arg::new("output-level").long("output-level").value_parser(clap::value_parser!(u32)))
+            .arg(Arg::new("set-wallpaper").long("set-wallpaper").action(ArgAction::SetTrue))
+            .arg(Arg::new("resolve").long("resolve").action(ArgAction::Append))
+            .arg(Arg::new("margins").long("margins"))
+            .arg(Arg::new("config").long("config"))
+            .arg(Arg::new("file"))
+    }
+
+    fn config(text: &str) -> Config {
+        toml::from_str(text).unwrap()
+    }
+
+    fn apply_err(text: &str) -> Option<ErrorKind> {
+        apply(command(), &config(text)).err().map(|app_err| app_err.kind())
+    }
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn settings_become_defaults() {
+        let text = r#"
+            output-dir = "frames"
+            output-level = 8
+            set-wallpaper = true
+            resolve = ["a.example=203.0.113.7", "b.example=203.0.113.8"]
+            margins = [0, 0, 40, 0]
+        "#;
+        let matches = apply(command(), &config(text)).unwrap().get_matches_from(["test"]);
+        assert_eq!(matches.get_one::<String>("output-dir").unwrap(), "frames");
+        assert_eq!(matches.get_one::<u32>("output-level"), Some(&8));
+        assert!(matches.get_flag("set-wallpaper"));
+        let resolve: Vec<_> = matches.get_many::<String>("resolve").unwrap().collect();
+        assert_eq!(resolve, ["a.example=203.0.113.7", "b.example=203.0.113.8"]);
+        assert_eq!(matches.get_one::<String>("margins").unwrap(), "0,0,40,0");
+    }
+
+    #[test]
+    fn command_line_overrides_settings() {
+        let matches = apply(command(), &config(r#"output-dir = "frames""#))
+            .unwrap()
+            .get_matches_from(["test", "--output-dir", "elsewhere"]);
+        assert_eq!(matches.get_one::<String>("output-dir").unwrap(), "elsewhere");
+    }
+
+    #[test]
+    fn false_flags_stay_off() {
+        let matches = apply(command(), &config("set-wallpaper = false")).unwrap().get_matches_from(["test"]);
+        assert!(!matches.get_flag("set-wallpaper"));
+    }
+
+    #[test]
+    fn invalid_settings() {
+        assert_eq!(apply_err("unknown = 1"), Some(ErrorKind::Config));
+        assert_eq!(apply_err(r#"config = "other.toml""#), Some(ErrorKind::Config));
+        assert_eq!(apply_err(r#"file = "frame.png""#), Some(ErrorKind::Config));
+        assert_eq!(apply_err(r#"set-wallpaper = "yes""#), Some(ErrorKind::Config));
+        assert_eq!(apply_err("resolve = [[1]]"), Some(ErrorKind::Config));
+        assert_eq!(apply_err("output-dir = { path = \"frames\" }"), Some(ErrorKind::Config));
+        assert_eq!(apply_err(""), None);
+    }
+
+    /// A command whose --prefer-daylight needs --location, and --watch conflicts with --dry-run
+    fn checked_command() -> Command {
+        Command::new("test")
+            .arg(Arg::new("prefer-daylight").long("prefer-daylight").action(ArgAction::SetTrue).requires("location"))
+            .arg(Arg::new("location").long("location"))
+            .arg(Arg::new("watch").long("watch").conflicts_with("dry-run"))
+            .arg(Arg::new("dry-run").long("dry-run").action(ArgAction::SetTrue))
+            .subcommand(Command::new("status"))
+    }
+
+    fn validate_err(text: &str, argv: &[&str]) -> Option<ErrorKind> {
+        let command = apply(checked_command(), &config(text)).unwrap();
+        let argv = args(argv);
+        let matches = command.clone().try_get_matches_from(&argv).unwrap();
+        validate(checked_command(), &command, &matches, &argv).err().map(|app_err| app_err.kind())
+    }
+
+    #[test]
+    fn settings_are_checked_together() {
+        assert_eq!(validate_err("prefer-daylight = true", &["test"]), Some(ErrorKind::Config));
+        assert_eq!(validate_err("prefer-daylight = true\nlocation = \"35,139\"", &["test"]), None);
+        assert_eq!(validate_err("prefer-daylight = true", &["test", "--location", "35,139"]), None);
+        assert_eq!(validate_err("watch = \"10\"\ndry-run = true", &["test"]), Some(ErrorKind::Config));
+        assert_eq!(validate_err("watch = \"10\"", &["test", "--dry-run"]), Some(ErrorKind::Config));
+        // The command line takes the place of a setting
+        assert_eq!(validate_err("prefer-daylight = true\nwatch = \"10\"", &["test", "--watch", "5", "--location", "0,0"]), None);
+        assert_eq!(validate_err("location = \"35,139\"", &["test", "status"]), None);
+        assert_eq!(validate_err("", &["test"]), None);
+    }
+
+    #[test]
+    fn load_files() {
+        let dir = std::env::temp_dir().join(format!("himawari-desktop-updater-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+
+        let _ = std::fs::remove_file(&path);
+        assert!(load(&path).unwrap().0.is_empty());
+        std::fs::write(&path, "output-level = 8\n").unwrap();
+        assert_eq!(load(&path).unwrap().0.get("output-level"), Some(&Value::Integer(8)));
+        std::fs::write(&path, "output-level = \n").unwrap();
+        assert_eq!(load(&path).err().map(|app_err| app_err.kind()), Some(ErrorKind::Config));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn env_var_names() {
+        assert_eq!(env_var_name("output-dir"), "HIMAWARI_OUTPUT_DIR");
+        assert_eq!(env_var_name("config"), "HIMAWARI_CONFIG");
+    }
+
+    #[test]
+    fn config_path_from_args() {
+        assert_eq!(path_from_args(&args(&["test", "--config", "a.toml"])), Some(PathBuf::from("a.toml")));
+        assert_eq!(path_from_args(&args(&["test", "-v", "--config=b.toml"])), Some(PathBuf::from("b.toml")));
+        assert_eq!(path_from_args(&args(&["test", "--", "--config", "a.toml"])), None);
+        // The first argument is the program
+        assert_eq!(path_from_args(&args(&["--config", "a.toml"])), None);
+        assert_eq!(path_from_args(&args(&["test", "--config"])), None);
+    }
+
+    #[test]
+    fn env_settings_become_defaults() {
+        // Variables are looked up in a list rather than the environment, which tests share
+        let vars = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+                    .ok_or(VarError::NotPresent)
+            }
+        };
+        let env: &'static [(&str, &str)] = &[
+            ("HIMAWARI_SET_WALLPAPER", "1"),
+            ("HIMAWARI_RESOLVE", "a.example=203.0.113.7, b.example=203.0.113.8"),
+            ("HIMAWARI_MARGINS", "0,0,40,0"),
+            ("HIMAWARI_OUTPUT_DIR", ""),
+            ("HIMAWARI_CONFIG", "ignored.toml"),
+        ];
+
+        let matches = apply_vars(command(), vars(env)).unwrap().get_matches_from(["test"]);
+        assert!(matches.get_flag("set-wallpaper"));
+        let resolve: Vec<_> = matches.get_many::<String>("resolve").unwrap().collect();
+        assert_eq!(resolve, ["a.example=203.0.113.7", "b.example=203.0.113.8"]);
+        assert_eq!(matches.get_one::<String>("margins").unwrap(), "0,0,40,0");
+        assert_eq!(matches.get_one::<String>("output-dir"), None);
+        assert_eq!(matches.get_one::<String>("config"), None);
+
+        let matches = apply_vars(command(), vars(&[("HIMAWARI_SET_WALLPAPER", "off")])).unwrap().get_matches_from(["test"]);
+        assert!(!matches.get_flag("set-wallpaper"));
+        let invalid = apply_vars(command(), vars(&[("HIMAWARI_SET_WALLPAPER", "maybe")]));
+        assert_eq!(invalid.err().map(|app_err| app_err.kind()), Some(ErrorKind::Config));
+    }
+}
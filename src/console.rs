@@ -0,0 +1,101 @@
+//! Console output for people running the updater by hand.
+//!
+//! Colours are used only when both output streams are terminals, and never with
+//! `--no-color` or the NO_COLOR environment variable, so scripts and cron jobs get plain
+//! text. Summaries are only printed to terminals, as the log already records the same.
+
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Detects whether the updater is running in a terminal, and whether to use colours there.
+/// Returns the colour choice for the console logger.
+pub fn init(no_color: bool) -> ColorChoice {
+    let interactive = std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+    let color = interactive && !no_color && std::env::var_os("NO_COLOR").is_none();
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+    COLOR.store(color, Ordering::Relaxed);
+    match color {
+        true => ColorChoice::Auto,
+        false => ColorChoice::Never,
+    }
+}
+
+/// Whether the updater is running in a terminal, rather than as a scheduled task
+pub fn is_interactive() -> bool {
+    INTERACTIVE.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy)]
+pub enum Status {
+    Good,
+    Warning,
+    Bad,
+    Plain,
+}
+
+impl Status {
+    fn color(self) -> Option<Color> {
+        match self {
+            Status::Good => Some(Color::Green),
+            Status::Warning => Some(Color::Yellow),
+            Status::Bad => Some(Color::Red),
+            Status::Plain => None,
+        }
+    }
+}
+
+/// Labelled values printed in aligned columns at the end of a run
+#[derive(Default)]
+pub struct Summary {
+    rows: Vec<(String, String, Status)>,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary::default()
+    }
+
+    pub fn row<V: ToString>(mut self, label: &str, value: V) -> Summary {
+        self.rows
+            .push((label.to_string(), value.to_string(), Status::Plain));
+        self
+    }
+
+    pub fn status<V: ToString>(mut self, label: &str, value: V, status: Status) -> Summary {
+        self.rows
+            .push((label.to_string(), value.to_string(), status));
+        self
+    }
+
+    /// Prints the summary if the updater is running in a terminal
+    pub fn print(&self) {
+        if !INTERACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+        let choice = match COLOR.load(Ordering::Relaxed) {
+            true => ColorChoice::Auto,
+            false => ColorChoice::Never,
+        };
+        // Follow any log lines still buffered, rather than appearing amongst them
+        log::logger().flush();
+        let mut stdout = StandardStream::stdout(choice);
+        let width = self
+            .rows
+            .iter()
+            .map(|(label, _, _)| label.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (label, value, status) in &self.rows {
+            let _ = stdout.set_color(ColorSpec::new().set_bold(true));
+            let _ = write!(stdout, "{:<width$}  ", label, width = width);
+            let _ = stdout.set_color(ColorSpec::new().set_fg(status.color()));
+            let _ = writeln!(stdout, "{}", value);
+        }
+        let _ = stdout.reset();
+    }
+}
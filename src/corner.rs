@@ -0,0 +1,50 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// A corner of the image, where something is drawn
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+#[derive(Clone)]
+pub struct CornerValueParser;
+
+impl clap::builder::TypedValueParser for CornerValueParser {
+    type Value = Corner;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match value.to_string_lossy().as_ref().trim() {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid corner, use top-left, top-right, bottom-left or bottom-right")),
+        }
+    }
+}
+
+impl Corner {
+    pub fn is_top(self) -> bool {
+        matches!(self, Corner::TopLeft | Corner::TopRight)
+    }
+
+    pub fn is_left(self) -> bool {
+        matches!(self, Corner::TopLeft | Corner::BottomLeft)
+    }
+}
+
+impl Display for Corner {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        };
+        write!(f, "{}", s)
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use crate::region::Region;
+
+/// A position or length along one side of the image, in pixels or as a percentage of it
+#[derive(Clone, Copy, PartialEq)]
+pub enum Extent {
+    Pixels(u32),
+    Percent(f64),
+}
+
+impl Extent {
+    fn try_parse(input: &str) -> Option<Extent> {
+        let input = input.trim();
+        match input.strip_suffix('%') {
+            Some(percent) => {
+                let percent = percent.trim().parse::<f64>().ok()?;
+                (0.0..=100.0).contains(&percent).then_some(Extent::Percent(percent))
+            }
+            None => input.parse().ok().map(Extent::Pixels),
+        }
+    }
+
+    /// The extent in pixels, along a side `length` pixels long
+    fn to_pixels(self, length: u32) -> u32 {
+        match self {
+            Extent::Pixels(pixels) => pixels,
+            Extent::Percent(percent) => (length as f64 * percent / 100.0).round() as u32,
+        }
+    }
+}
+
+impl Display for Extent {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            Extent::Pixels(pixels) => write!(f, "{}", pixels),
+            Extent::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// A region of interest to crop the composited image to, from its top left corner
+#[derive(Clone, PartialEq)]
+pub struct Crop {
+    pub x: Extent,
+    pub y: Extent,
+    pub width: Extent,
+    pub height: Extent,
+}
+
+#[derive(Clone)]
+pub struct CropValueParser;
+
+impl clap::builder::TypedValueParser for CropValueParser {
+    type Value = Crop;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match Crop::try_parse(value.to_string_lossy().as_ref()) {
+            Some(crop) => Ok(crop),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Use format X,Y,WIDTH,HEIGHT, each in pixels or as a percentage like 25%")),
+        }
+    }
+}
+
+impl Crop {
+    pub fn try_parse(input: &str) -> Option<Crop> {
+        let mut parts = input.split(',').map(Extent::try_parse);
+        let crop = Crop {
+            x: parts.next()??,
+            y: parts.next()??,
+            width: parts.next()??,
+            height: parts.next()??,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(crop)
+    }
+
+    /// How many times larger the image needs to be for the crop to be a given size. Only a
+    /// crop in percentages can say, as one in pixels is tied to the level.
+    pub fn zoom(&self) -> f64 {
+        match (self.width, self.height) {
+            (Extent::Percent(width), Extent::Percent(height)) if width > 0.0 || height > 0.0 => {
+                100.0 / width.max(height)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// The region to keep of an image `width` x `height` pixels, cut short at its edges.
+    /// None if the region lies entirely outside the image.
+    pub fn region(&self, width: u32, height: u32) -> Option<Region> {
+        let x = self.x.to_pixels(width);
+        let y = self.y.to_pixels(height);
+        if x >= width || y >= height {
+            return None;
+        }
+        let region = Region {
+            x,
+            y,
+            width: self.width.to_pixels(width).min(width - x),
+            height: self.height.to_pixels(height).min(height - y),
+        };
+        (region.width > 0 && region.height > 0).then_some(region)
+    }
+}
+
+impl Display for Crop {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
@@ -0,0 +1,285 @@
+//! Controlling a running daemon from other programs, or with `himawari-desktop-updater ctl`.
+//!
+//! In watch mode the daemon listens on a Unix domain socket (a named pipe on Windows) for
+//! one command per connection, as a line of text, and answers with one line of JSON:
+//!
+//! ```text
+//! pause
+//! {"ok":true,"status":{"paused":true,"last_update":"2022-11-01T12:01:02Z",...}}
+//! ```
+//!
+//! The commands are "pause" and "resume", which stop and restart the scheduled updates,
+//! "update-now", which runs an update straight away, and "status".
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::download::DownloadedFrame;
+use crate::error::AppErr;
+use crate::messages::tr;
+
+pub const COMMANDS: [&str; 4] = ["pause", "resume", "update-now", "status"];
+
+/// What the daemon is doing, as reported by the "status" command
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub paused: bool,
+    /// When the last update finished
+    pub last_update: Option<DateTime<Utc>>,
+    /// The latest frame, and when it was captured
+    pub frame: Option<PathBuf>,
+    pub frame_timestamp: Option<DateTime<Utc>>,
+    /// Why the last update failed, if it did
+    pub last_error: Option<String>,
+    pub next_update: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<DaemonStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    status: DaemonStatus,
+    update_now: bool,
+}
+
+/// The state shared between the daemon and the control channel
+#[derive(Default)]
+pub struct Control {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+impl Control {
+    pub fn new() -> Arc<Control> {
+        Arc::new(Control::default())
+    }
+
+    fn handle(&self, command: &str) -> Response {
+        let mut state = self.state.lock().unwrap();
+        match command {
+            "pause" => state.status.paused = true,
+            "resume" => state.status.paused = false,
+            "update-now" => {
+                state.update_now = true;
+                self.wake.notify_all();
+            }
+            "status" => {}
+            _ => {
+                return Response {
+                    ok: false,
+                    status: None,
+                    error: Some(tr!("ctl-unknown-command", command = command)),
+                }
+            }
+        }
+        info!("Control command: {}", command);
+        Response {
+            ok: true,
+            status: Some(state.status.clone()),
+            error: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().status.paused
+    }
+
+    /// True if an update was asked for since the last call
+    pub fn take_update_now(&self) -> bool {
+        std::mem::take(&mut self.state.lock().unwrap().update_now)
+    }
+
+    /// Notes the outcome of an update for the "status" command
+    pub fn record(&self, result: &Result<DownloadedFrame, AppErr>) {
+        let mut state = self.state.lock().unwrap();
+        let status = &mut state.status;
+        status.last_update = Some(Utc::now());
+        match result {
+            Ok(frame) => {
+                status.frame = Some(frame.path.clone());
+                status.frame_timestamp = Some(frame.timestamp);
+                status.last_error = None;
+            }
+            Err(app_err) => status.last_error = Some(app_err.to_string()),
+        }
+    }
+
+    /// Waits for `duration`, or until an update is asked for.
+    /// Returns true if it was cut short.
+    pub fn wait(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let mut state = self.state.lock().unwrap();
+        state.status.next_update = chrono::Duration::from_std(duration)
+            .ok()
+            .map(|duration| Utc::now() + duration);
+        loop {
+            if state.update_now {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self.wake.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
+
+/// The default endpoint for the control channel
+#[cfg(not(windows))]
+pub fn default_endpoint() -> PathBuf {
+    let mut path = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    path.push("himawari-desktop-updater-ctl.sock");
+    path
+}
+
+/// The default endpoint for the control channel
+#[cfg(windows)]
+pub fn default_endpoint() -> PathBuf {
+    PathBuf::from(r"\\.\pipe\himawari-desktop-updater-ctl")
+}
+
+/// Reads a command from `connection` and writes back the response
+fn serve<S: std::io::Read + Write>(control: &Control, connection: S) {
+    let mut reader = BufReader::new(connection);
+    let mut command = String::new();
+    if reader.read_line(&mut command).is_err() {
+        return;
+    }
+    let response = control.handle(command.trim());
+    if let Ok(mut line) = serde_json::to_vec(&response) {
+        line.push(b'\n');
+        let _ = reader.get_mut().write_all(&line);
+    }
+}
+
+/// Starts accepting control commands on a background thread
+pub fn start(endpoint: &Path, control: Arc<Control>) -> Result<(), AppErr> {
+    listen(endpoint, control)?;
+    info!("Listening for control commands on {}", endpoint.display());
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn listen(endpoint: &Path, control: Arc<Control>) -> Result<(), AppErr> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    // Clean up a socket left behind by a previous instance
+    if let Ok(metadata) = std::fs::metadata(endpoint) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(endpoint)?;
+        }
+    }
+
+    let listener = UnixListener::bind(endpoint)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // Don't let a client which never sends a command block the others
+            let timeout = Some(Duration::from_secs(1));
+            if stream.set_read_timeout(timeout).is_ok() && stream.set_write_timeout(timeout).is_ok()
+            {
+                serve(&control, stream);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(windows)]
+fn listen(endpoint: &Path, control: Arc<Control>) -> Result<(), AppErr> {
+    use crate::ffi_windows::os_str_to_wchar;
+    use std::fs::File;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+    use std::ptr::null_mut;
+    use winapi::shared::winerror::ERROR_PIPE_CONNECTED;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe};
+    use winapi::um::winbase::{
+        PIPE_ACCESS_DUPLEX, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES,
+        PIPE_WAIT,
+    };
+
+    fn create_pipe_instance(name: &[u16]) -> Result<File, AppErr> {
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(unsafe { File::from_raw_handle(handle as _) })
+    }
+
+    let name = os_str_to_wchar(endpoint.as_os_str());
+    // Create the instance up front so errors are reported to the caller
+    let pipe = create_pipe_instance(&name)?;
+    std::thread::spawn(move || loop {
+        // Blocks until a client connects
+        let connected = unsafe {
+            ConnectNamedPipe(pipe.as_raw_handle() as _, null_mut()) != 0
+                || GetLastError() == ERROR_PIPE_CONNECTED
+        };
+        if connected {
+            serve(&control, &pipe);
+        }
+        // Reuse the instance for the next client
+        unsafe { DisconnectNamedPipe(pipe.as_raw_handle() as _) };
+    });
+    Ok(())
+}
+
+/// Sends `command` to the daemon listening on `endpoint`, returning its status
+pub fn send(endpoint: &Path, command: &str) -> Result<DaemonStatus, AppErr> {
+    #[cfg(not(windows))]
+    let connection = std::os::unix::net::UnixStream::connect(endpoint);
+    #[cfg(windows)]
+    let connection = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .open(endpoint);
+
+    let mut connection = match connection {
+        Ok(connection) => connection,
+        Err(err) => {
+            warn!("{}", err);
+            return Err(AppErr::new(tr!(
+                "ctl-not-running",
+                endpoint = endpoint.display()
+            )));
+        }
+    };
+    connection.write_all(format!("{}\n", command).as_bytes())?;
+    connection.flush()?;
+    let mut line = String::new();
+    BufReader::new(connection).read_line(&mut line)?;
+    let response: Response = serde_json::from_str(&line)?;
+    match (response.ok, response.status) {
+        (true, Some(status)) => Ok(status),
+        (_, _) => Err(AppErr::new(response.error.unwrap_or_default())),
+    }
+}
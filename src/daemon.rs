@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use log::{error, info, warn};
+
+use crate::archive;
+use crate::ctl::Control;
+use crate::download::DownloadedFrame;
+use crate::eclipse;
+use crate::error::AppErr;
+use crate::ipc::NotificationServer;
+use crate::orientation::Orientation;
+use crate::wallpaper::WallpaperSetter;
+
+/// How soon a failed update is retried. The delay doubles with each failure in a row,
+/// up to the usual interval.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Cycles the wallpaper through recent archived frames while waiting for the next update
+pub struct Animation {
+    pub output_dir: PathBuf,
+    /// How many of the most recent frames to cycle through
+    pub frames: usize,
+    /// How long to show each frame for
+    pub step: Duration,
+    pub wallpaper: WallpaperSetter,
+    /// The --variants versions of each frame, set on monitors of their orientation
+    pub variants: Vec<Orientation>,
+}
+
+/// Runs `update` every `interval`, forever.
+/// Errors are logged and do not stop the daemon, but bring the next update forward.
+/// In eclipse mode, `update` runs at the satellite's cadence during eclipses.
+/// Scheduled updates are skipped while paused through `control`.
+pub fn run<F>(
+    interval: Duration,
+    eclipse_mode: bool,
+    notifications: Option<NotificationServer>,
+    animation: Option<Animation>,
+    control: Arc<Control>,
+    mut update: F,
+) -> !
+where
+    F: FnMut() -> Result<DownloadedFrame, AppErr>,
+{
+    // How many updates in a row have failed
+    let mut failures: u32 = 0;
+    loop {
+        // An update asked for through the control channel runs even while paused
+        let update_now = control.take_update_now();
+        if control.is_paused() && !update_now {
+            info!("Paused, skipping the update");
+        } else {
+            let result = update();
+            control.record(&result);
+            match result {
+                Ok(frame) => {
+                    failures = 0;
+                    if let Some(ref notifications) = notifications {
+                        if frame.written {
+                            notifications.notify("frame", &frame.path, &frame.timestamp);
+                        }
+                        for event in frame.events {
+                            notifications.notify(event.name, &event.path, &frame.timestamp);
+                        }
+                    }
+                }
+                Err(app_err) => {
+                    error!("{}", app_err);
+                    failures += 1;
+                }
+            }
+        }
+
+        let sleep = match eclipse_mode {
+            true => eclipse::sleep_duration(&Utc::now(), interval),
+            false => interval,
+        };
+        let sleep = match failures {
+            0 => {
+                info!("Sleeping for {} minutes...", sleep.as_secs() / 60);
+                sleep
+            }
+            failures => {
+                let sleep = sleep.min(retry_delay(failures));
+                info!("Retrying in {} minutes...", sleep.as_secs() / 60);
+                sleep
+            }
+        };
+        match animation {
+            Some(ref animation) if !control.is_paused() => animate(animation, sleep, &control),
+            _ => {
+                control.wait(sleep);
+            }
+        }
+    }
+}
+
+/// The delay before retrying after `failures` failed updates in a row
+fn retry_delay(failures: u32) -> Duration {
+    RETRY_DELAY.saturating_mul(2u32.saturating_pow(failures - 1))
+}
+
+/// Shows each of the most recent archived frames in turn, oldest first, for `duration`,
+/// or until an update is asked for through `control`, then puts the newest back
+fn animate(animation: &Animation, duration: Duration, control: &Control) {
+    let deadline = Instant::now() + duration;
+
+    let mut frames = match archive::list_frames(&animation.output_dir) {
+        Ok(frames) => frames,
+        Err(app_err) => {
+            warn!("Failed to list archived frames: {}", app_err);
+            Vec::new()
+        }
+    };
+    frames.truncate(animation.frames);
+    frames.reverse();
+
+    // Nothing to animate, so leave the latest frame in place
+    if frames.len() < 2 {
+        control.wait(duration);
+        return;
+    }
+
+    for frame in frames.iter().cycle() {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        if let Err(app_err) = animation.wallpaper.set_frame(&frame.path, &animation.variants) {
+            warn!("{}", app_err);
+        }
+        if control.wait(animation.step.min(deadline - now)) {
+            break;
+        }
+    }
+
+    // Updates in watch mode only set the wallpaper when a new frame arrives
+    let newest = frames.last().unwrap();
+    if let Err(app_err) = animation.wallpaper.set_frame(&newest.path, &animation.variants) {
+        warn!("{}", app_err);
+    }
+}
@@ -0,0 +1,124 @@
+//! Preferring frames in which a location is in daylight, with --prefer-daylight.
+//!
+//! When the location is on the night side of the disk, the frame is taken from another
+//! source, such as the infrared band or another satellite, or else is the most recent one
+//! captured while the location was in daylight.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use chrono::prelude::*;
+
+use crate::astro;
+use crate::band::Band;
+use crate::product::{Product, FRAME_INTERVAL_MINUTES};
+use crate::satellite::Satellite;
+
+/// How high the Sun needs to be, in degrees, for the ground to be seen clearly
+const MIN_SUN_ELEVATION: f64 = 5.0;
+
+/// How far back to look for a frame in daylight, which covers all but the polar night
+const MAX_SEARCH_HOURS: i64 = 24;
+
+/// A point on the Earth's surface, in degrees
+#[derive(Clone, Copy, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Clone)]
+pub struct LocationValueParser;
+
+impl clap::builder::TypedValueParser for LocationValueParser {
+    type Value = Location;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match Location::try_parse(value.to_string_lossy().as_ref()) {
+            Some(location) => Ok(location),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Use format LAT,LON in degrees, e.g. -33.9,151.2")),
+        }
+    }
+}
+
+impl Location {
+    pub fn try_parse(input: &str) -> Option<Location> {
+        let (latitude, longitude) = input.split_once(',')?;
+        let latitude = latitude.trim().parse::<f64>().ok()?;
+        let longitude = longitude.trim().parse::<f64>().ok()?;
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return None;
+        }
+        Some(Location { latitude, longitude })
+    }
+
+    /// True if the Sun is high enough at the location at `time` to light the ground
+    pub fn is_sunlit(&self, time: &DateTime<Utc>) -> bool {
+        astro::sun_elevation(time, self.latitude, self.longitude) >= MIN_SUN_ELEVATION
+    }
+
+    /// The most recent frame time at or before `time` when the location was in daylight,
+    /// or None during the polar night
+    pub fn last_sunlit(&self, time: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let frames = MAX_SEARCH_HOURS * 60 / FRAME_INTERVAL_MINUTES;
+        (0..=frames)
+            .map(|age| *time - chrono::Duration::minutes(FRAME_INTERVAL_MINUTES * age))
+            .find(|time| self.is_sunlit(time))
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{},{}", self.latitude, self.longitude)
+    }
+}
+
+/// Where frames come from while the location is dark
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NightSource {
+    /// Another band of the same Himawari frame, e.g. infrared
+    Band(Band),
+    /// Another satellite, which may be looking at the location in daylight
+    Satellite(Satellite),
+}
+
+#[derive(Clone)]
+pub struct NightSourceValueParser;
+
+impl clap::builder::TypedValueParser for NightSourceValueParser {
+    type Value = NightSource;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match value.to_string_lossy().trim().to_ascii_lowercase().as_str() {
+            "infrared" => Ok(NightSource::Band(Band::Infrared)),
+            "himawari" => Ok(NightSource::Satellite(Satellite::Himawari)),
+            "goes-east" => Ok(NightSource::Satellite(Satellite::GoesEast)),
+            "goes-west" => Ok(NightSource::Satellite(Satellite::GoesWest)),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid night source, use infrared, himawari, goes-east or goes-west")),
+        }
+    }
+}
+
+impl NightSource {
+    pub fn to_product(self) -> Product {
+        match self {
+            NightSource::Band(band) => band.to_product(),
+            NightSource::Satellite(satellite) => satellite.default_product(),
+        }
+    }
+}
+
+impl Display for NightSource {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            NightSource::Band(band) => write!(f, "{}", band),
+            NightSource::Satellite(satellite) => write!(f, "{}", satellite),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DaylightOptions {
+    pub location: Location,
+    /// Where to get frames from while the location is dark, instead of going back in time
+    pub night_source: Option<NightSource>,
+}
@@ -0,0 +1,248 @@
+//! Resolving hostnames without the system resolver, for networks where it can't be trusted.
+//!
+//! Hosts can be pinned to addresses with `--resolve`, or looked up through a chosen DNS
+//! server or DNS-over-HTTPS endpoint. Either way the addresses are handed to the HTTP
+//! client when it is built, so the hosts the downloads use are resolved up front.
+
+use std::io::ErrorKind::{TimedOut, WouldBlock};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::info;
+use reqwest::ClientBuilder;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+
+use crate::error::{AppErr, ErrorKind};
+use crate::messages::tr;
+
+const DNS_PORT: u16 = 53;
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+const DNS_MESSAGE_MIME: &str = "application/dns-message";
+
+// Record types
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+
+#[derive(Clone)]
+pub enum Resolver {
+    /// A DNS server, queried over UDP
+    Server(IpAddr),
+    /// The URL of a DNS-over-HTTPS endpoint, e.g. "https://1.1.1.1/dns-query"
+    DnsOverHttps(String),
+}
+
+/// A host pinned to particular addresses
+#[derive(Clone)]
+pub struct HostOverride {
+    pub host: String,
+    pub addrs: Vec<IpAddr>,
+}
+
+/// Parses a host override, e.g. "himawari8-dl.nict.go.jp=203.0.113.7"
+#[derive(Clone)]
+pub struct HostOverrideValueParser;
+
+impl clap::builder::TypedValueParser for HostOverrideValueParser {
+    type Value = HostOverride;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let value = value.to_string_lossy();
+        let parse = || -> Option<HostOverride> {
+            let (host, addrs) = value.split_once('=')?;
+            let addrs = addrs
+                .split(',')
+                .map(|addr| addr.trim().parse().ok())
+                .collect::<Option<Vec<IpAddr>>>()?;
+            Some(HostOverride {
+                host: host.trim().to_string(),
+                addrs,
+            })
+        };
+        match parse() {
+            Some(host_override) if !host_override.host.is_empty() => Ok(host_override),
+            _ => Err(Error::raw(
+                ErrorKind::InvalidValue,
+                "Invalid host override, use HOST=IP[,IP...]",
+            )),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DnsOptions {
+    pub resolver: Option<Resolver>,
+    pub overrides: Vec<HostOverride>,
+    /// The hosts to look up with the resolver
+    pub hosts: Vec<String>,
+}
+
+fn socket_addrs(addrs: &[IpAddr]) -> Vec<SocketAddr> {
+    // The port is ignored, the client uses the one in the URL
+    addrs.iter().map(|addr| SocketAddr::new(*addr, 0)).collect()
+}
+
+/// Adds the addresses `options` give each host to `builder`, looking them up through `proxy`
+/// if it's set
+pub fn apply(mut builder: ClientBuilder, options: &DnsOptions, proxy: Option<&reqwest::Proxy>) -> Result<ClientBuilder, AppErr> {
+    for host_override in &options.overrides {
+        builder =
+            builder.resolve_to_addrs(&host_override.host, &socket_addrs(&host_override.addrs));
+    }
+    if let Some(ref resolver) = options.resolver {
+        let overridden = |host: &String| {
+            options
+                .overrides
+                .iter()
+                .any(|o| o.host.eq_ignore_ascii_case(host))
+        };
+        for host in options.hosts.iter().filter(|host| !overridden(host)) {
+            let addrs = lookup(resolver, host, proxy)?;
+            info!("Resolved {} to {:?}", host, addrs);
+            builder = builder.resolve_to_addrs(host, &socket_addrs(&addrs));
+        }
+    }
+    Ok(builder)
+}
+
+fn encode_query(id: u16, host: &str, record_type: u16) -> Vec<u8> {
+    let mut query = id.to_be_bytes().to_vec();
+    // Recursion desired, with one question
+    query.extend([0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.trim_end_matches('.').split('.') {
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend(record_type.to_be_bytes());
+    // The Internet class
+    query.extend(1u16.to_be_bytes());
+    query
+}
+
+/// The position after the (possibly compressed) name at `pos`
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)? as usize;
+        match len {
+            0 => return Some(pos + 1),
+            // A pointer to a name elsewhere in the message
+            len if len & 0xC0 == 0xC0 => return Some(pos + 2),
+            len => pos += 1 + len,
+        }
+    }
+}
+
+fn decode_answers(message: &[u8], id: u16) -> Result<Vec<IpAddr>, AppErr> {
+    let u16_at = |pos: usize| {
+        Some(u16::from_be_bytes([
+            *message.get(pos)?,
+            *message.get(pos + 1)?,
+        ]))
+    };
+    if u16_at(0) != Some(id) {
+        return Err(AppErr::of_kind(ErrorKind::Network, "The DNS response does not match the query"));
+    }
+    match message.get(3).map(|flags| flags & 0x0F) {
+        Some(0) => {}
+        Some(code) => {
+            return Err(AppErr::of_kind(ErrorKind::Network, format!(
+                "The DNS server returned error code {}",
+                code
+            )))
+        }
+        None => return Err(AppErr::of_kind(ErrorKind::Network, "The DNS response is truncated")),
+    }
+
+    let decode = || -> Option<Vec<IpAddr>> {
+        let mut pos = 12;
+        for _ in 0..u16_at(4)? {
+            pos = skip_name(message, pos)? + 4;
+        }
+        let mut addrs = Vec::new();
+        for _ in 0..u16_at(6)? {
+            pos = skip_name(message, pos)?;
+            let record_type = u16_at(pos)?;
+            let len = u16_at(pos + 8)? as usize;
+            let data = message.get(pos + 10..pos + 10 + len)?;
+            match (record_type, len) {
+                (TYPE_A, 4) => addrs.push(IpAddr::V4(Ipv4Addr::new(
+                    data[0], data[1], data[2], data[3],
+                ))),
+                (TYPE_AAAA, 16) => {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(data);
+                    addrs.push(IpAddr::V6(Ipv6Addr::from(octets)));
+                }
+                // CNAMEs are followed by the server, which includes the addresses too
+                _ => {}
+            }
+            pos += 10 + len;
+        }
+        Some(addrs)
+    };
+    decode().ok_or_else(|| AppErr::of_kind(ErrorKind::Network, "The DNS response is truncated"))
+}
+
+fn query_server(server: IpAddr, query: &[u8]) -> Result<Vec<u8>, AppErr> {
+    let local = match server {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(local, 0))?;
+    socket.set_read_timeout(Some(LOOKUP_TIMEOUT))?;
+    socket.send_to(query, SocketAddr::new(server, DNS_PORT))?;
+    let mut response = [0u8; 4096];
+    let (len, _) = match socket.recv_from(&mut response) {
+        Ok(received) => received,
+        Err(err) if matches!(err.kind(), WouldBlock | TimedOut) => {
+            return Err(AppErr::of_kind(ErrorKind::Network, format!(
+                "The DNS server {} did not respond",
+                server
+            )))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    Ok(response[..len].to_vec())
+}
+
+fn query_doh(url: &str, query: &[u8], proxy: Option<&reqwest::Proxy>) -> Result<Vec<u8>, AppErr> {
+    let mut builder = reqwest::blocking::Client::builder().timeout(LOOKUP_TIMEOUT);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy.clone());
+    }
+    let response = builder
+        .build()?
+        .post(url)
+        .header(CONTENT_TYPE, DNS_MESSAGE_MIME)
+        .header(ACCEPT, DNS_MESSAGE_MIME)
+        .body(query.to_vec())
+        .send()?
+        .error_for_status()?;
+    Ok(response.bytes()?.to_vec())
+}
+
+/// Looks up the IPv4 and IPv6 addresses of `host` with `resolver`, through `proxy` for
+/// DNS-over-HTTPS
+pub fn lookup(resolver: &Resolver, host: &str, proxy: Option<&reqwest::Proxy>) -> Result<Vec<IpAddr>, AppErr> {
+    let mut addrs = Vec::new();
+    for record_type in [TYPE_A, TYPE_AAAA].iter() {
+        let (id, response) = match resolver {
+            Resolver::Server(server) => {
+                let id = SystemTime::now().duration_since(UNIX_EPOCH)?.subsec_nanos() as u16;
+                (
+                    id,
+                    query_server(*server, &encode_query(id, host, *record_type))?,
+                )
+            }
+            // DNS-over-HTTPS asks for an ID of 0, so responses can be cached
+            Resolver::DnsOverHttps(url) => {
+                (0, query_doh(url, &encode_query(0, host, *record_type), proxy)?)
+            }
+        };
+        addrs.extend(decode_answers(&response, id)?);
+    }
+    match addrs.is_empty() {
+        true => Err(AppErr::of_kind(ErrorKind::Network, tr!("no-addresses", host = host))),
+        false => Ok(addrs),
+    }
+}
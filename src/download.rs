@@ -0,0 +1,1456 @@
+//! Downloading frames and writing them out: the latest frame or one at a given time, a
+//! numbered sequence of recent frames, or an animation of them, each in every format and
+//! band asked for, with the lock screen version and event captures alongside.
+
+use std::fs::DirBuilder;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::prelude::*;
+use image::{GenericImage, Rgba, RgbaImage};
+use log::{info, warn};
+use rayon::prelude::*;
+
+use crate::analysis;
+use crate::animated::AnimationWriter;
+use crate::archive::{self, MissingTiles, Sidecar, TilePlacement};
+use crate::astro;
+use crate::backdrop::{self, Backdrop};
+use crate::band::Band;
+use crate::breaker::RetryOptions;
+use crate::checksums;
+use crate::colour::Colour;
+use crate::composite;
+use crate::crop::Crop;
+use crate::daylight::DaylightOptions;
+use crate::eclipse;
+use crate::encode::{self, EncodeOptions, PngBandWriter};
+use crate::error::AppErr;
+use crate::exif;
+use crate::frame_time;
+use crate::frames::{FrameHandle, FrameStream, Order, StreamOptions};
+use crate::geo;
+use crate::http::HttpClient;
+use crate::latest::LatestFrame;
+use crate::lockscreen::{self, LockscreenOptions};
+use crate::map_layers::{self, MapLayer};
+use crate::margins::Margins;
+use crate::metrics::{Metrics, Phase};
+use crate::messages::tr;
+use crate::naming::Naming;
+use crate::night_dim::{self, NightDimOptions};
+use crate::orientation::{Orientation, ORIENTATIONS};
+use crate::output_format::OutputFormat;
+use crate::output_level::OutputLevel;
+use crate::overlay::{self, OverlayOptions};
+use crate::panorama::{self, Join};
+use crate::placeholder::{self, PlaceholderFrame};
+use crate::postprocess::PostProcess;
+use crate::projection::{self, Projection};
+use crate::preflight;
+use crate::product::{Product, FRAME_INTERVAL_MINUTES};
+use crate::region::Region;
+use crate::report::Report;
+use crate::resize;
+use crate::resize_mode::ResizeMode;
+use crate::size::Size;
+use crate::state::{FailureState, NumberedState, State, UpdateState};
+use crate::storm::{self, FrameGeometry, StormOptions};
+use crate::tiles::ImageSource;
+
+#[derive(Clone)]
+pub struct DownloadOptions {
+    pub store_latest_only: bool,
+    pub latest_file_name: Option<String>,
+    pub force: bool,
+    /// Fetch the tiles missing from frames already written, instead of skipping them
+    pub repair: bool,
+    pub capture_moon: bool,
+    pub eclipse_mode: bool,
+    pub keep_raw: bool,
+    pub write_sidecar: bool,
+    pub write_exif: bool,
+    pub checksums: bool,
+    /// Also write each checksum beside its file, as `<file>.sha256`
+    pub checksum_sidecars: bool,
+    pub margins: Margins,
+    pub output_dir: PathBuf,
+    pub output_format: OutputFormat,
+    /// Formats to write each frame in alongside `output_format`
+    pub extra_formats: Vec<OutputFormat>,
+    pub encoding: EncodeOptions,
+    pub naming: Naming,
+    pub output_level: OutputLevel,
+    pub requested_time: Option<DateTime<Utc>>,
+    pub frames: u32,
+    pub product: Product,
+    /// Bands to download alongside `product`
+    pub extra_bands: Vec<Band>,
+    /// Where to get image fragments from
+    pub tiles: Arc<dyn ImageSource>,
+    /// How failed image fragments are retried, and how many a frame needs
+    pub retry: RetryOptions,
+    /// The client for everything downloaded besides the image fragments, like the storm feed
+    pub http: HttpClient,
+    /// The metrics of each update, kept if they are written with --metrics
+    pub metrics: Metrics,
+    /// The tile requests of each update, kept if they are written with --report
+    pub report: Report,
+    pub follow_storm: Option<StormOptions>,
+    pub resize: Option<Size>,
+    pub resize_mode: ResizeMode,
+    pub lockscreen: Option<LockscreenOptions>,
+    /// Text drawn in a corner of each frame
+    pub overlay: Option<OverlayOptions>,
+    /// Map layers drawn over the disk of each frame
+    pub map_layers: Vec<MapLayer>,
+    /// Adjustments made to each frame, such as its brightness
+    pub post_process: PostProcess,
+    /// How the Earth is drawn, as the disk or reprojected onto a map
+    pub projection: Projection,
+    /// Optional colour to fill the margins with, which are otherwise transparent
+    pub background: Option<Colour>,
+    /// What is painted around the disk, over the margins and the black of space
+    pub backdrop: Backdrop,
+    /// Optional region of interest to crop each frame to
+    pub crop: Option<Crop>,
+    /// Optionally prefer frames in which a location is in daylight
+    pub daylight: Option<DaylightOptions>,
+    /// Fall back on the frame before the latest if the latest has "No Image" placeholder tiles
+    pub placeholder_fallback: bool,
+    /// Optionally join the disk of another satellite to each frame, for a wide panorama
+    pub panorama: Option<Join>,
+    /// Optionally write a JPEG preview this many pixels wide next to each frame
+    pub thumbnail: Option<u32>,
+    /// Optionally dim and warm the image written at night
+    pub night_dim: Option<NightDimOptions>,
+    /// Versions of each frame to write at the --resize size turned to each orientation
+    pub variants: Vec<Orientation>,
+}
+
+/// What an update would download and write, worked out without downloading any tiles or
+/// touching the output directory
+pub struct DownloadPlan {
+    pub timestamp: DateTime<Utc>,
+    /// The image which would be written
+    pub path: PathBuf,
+    /// The URL, or path in the tile directory, of each tile which would be read
+    pub tiles: Vec<String>,
+    /// A rough estimate of the bytes downloaded, nothing for a tile directory
+    pub download_bytes: u64,
+    /// A generous estimate of the bytes written
+    pub output_bytes: u64,
+}
+
+pub struct DownloadedFrame {
+    pub path: PathBuf,
+    pub timestamp: DateTime<Utc>,
+    /// False if the frame had already been downloaded
+    pub written: bool,
+    /// Set if, with --store-latest-only, there has been no new frame since the last run
+    pub unchanged: bool,
+    /// High resolution copies of the frame archived for special events
+    pub events: Vec<CapturedEvent>,
+    /// Set if a lock screen version of the frame was written
+    pub lockscreen: Option<PathBuf>,
+}
+
+pub struct CapturedEvent {
+    /// The kind of event, e.g. "moon" or "eclipse"
+    pub name: &'static str,
+    pub path: PathBuf,
+}
+
+/// The smallest level at which frames are written as their tiles arrive, where they can be.
+/// The rows of tiles are then downloaded one after another, which is slower, but from level 16
+/// a whole frame takes hundreds of megabytes of memory.
+const STREAM_MIN_LEVEL: u32 = 16;
+
+/// Downloads frames and writes them to the output directory, as `options` say
+pub struct Downloader {
+    options: DownloadOptions,
+}
+
+impl Downloader {
+    pub fn new(options: DownloadOptions) -> Downloader {
+        Downloader { options }
+    }
+
+    pub fn options(&self) -> &DownloadOptions {
+        &self.options
+    }
+
+    /// Downloads the latest frame, or the one at the requested time, along with any extra
+    /// bands, frames and events asked for. Frames already written are left alone.
+    pub fn download(&self) -> Result<DownloadedFrame, AppErr> {
+        let result = download_latest_himawari_image(&self.options);
+        record_update(&self.options.output_dir, &result);
+        result
+    }
+
+    /// Works out what `download` would do, from the latest metadata alone
+    pub fn plan(&self) -> Result<DownloadPlan, AppErr> {
+        plan_download(&self.options)
+    }
+}
+
+/// Records the outcome of an update in the state file, for the status subcommand and the
+/// next run
+fn record_update(output_dir: &Path, result: &Result<DownloadedFrame, AppErr>) {
+    // Nothing can be recorded if the output directory couldn't even be made
+    if !output_dir.exists() {
+        return;
+    }
+    let mut state = State::load(output_dir);
+    match result {
+        Ok(frame) if frame.written || state.last_update.is_none() => {
+            let missing_tiles = archive::read_missing_tiles(&frame.path)
+                .filter(|missing| missing.timestamp == frame.timestamp)
+                .map_or(0, |missing| missing.tiles.len());
+            state.last_update = Some(UpdateState {
+                timestamp: frame.timestamp,
+                path: frame.path.clone(),
+                missing_tiles,
+                finished: Utc::now(),
+            });
+            state.last_failure = None;
+        }
+        Ok(_) => state.last_failure = None,
+        Err(app_err) => {
+            let count = state.last_failure.as_ref().map_or(0, |failure| failure.count);
+            state.last_failure = Some(FailureState {
+                error: app_err.to_string(),
+                time: Utc::now(),
+                count: count + 1,
+            });
+        }
+    }
+    if let Err(app_err) = state.save(output_dir) {
+        warn!("Failed to save state: {}", app_err);
+    }
+}
+
+fn plan_download(options: &DownloadOptions) -> Result<DownloadPlan, AppErr> {
+    let DownloadOptions {
+        store_latest_only,
+        ref output_dir,
+        ref output_format,
+        naming,
+        ref output_level,
+        requested_time,
+        frames,
+        ref product,
+        ref tiles,
+        ..
+    } = *options;
+
+    // NOTE: Unlike ImageSource::latest, ImageSource::newest keeps no cache in the output directory
+    let level = output_level.to_level();
+    let timestamp = match (requested_time, tiles) {
+        (Some(ref time), _) => requested_frame_time(time)?,
+        (None, _) => tiles.newest(product, level)?,
+    };
+    if let Some(options) = daylight_options(options, &timestamp) {
+        return plan_download(&options);
+    }
+
+    let file_name = if output_format.is_animated() || frames > 1 {
+        archive::frame_file_name(&timestamp, output_format)
+    } else if naming == Naming::Sequence {
+        let number = match State::load(output_dir).numbered {
+            Some(ref last) if last.timestamp >= timestamp => last.number,
+            Some(ref last) => last.number + 1,
+            None => 0,
+        };
+        archive::numbered_file_name(number, output_format)
+    } else if store_latest_only {
+        match options.latest_file_name {
+            Some(ref name) => name.clone(),
+            None => archive::latest_file_name(output_format),
+        }
+    } else {
+        archive::frame_file_name(&timestamp, output_format)
+    };
+
+    let products = std::iter::once(product.clone())
+        .chain(options.extra_bands.iter().map(|band| product.alongside(band.to_product())))
+        .collect::<Vec<_>>();
+    let mut locations = Vec::new();
+    let mut download_bytes = 0;
+    for age in 0..frames.max(1) {
+        let timestamp = timestamp - chrono::Duration::minutes(FRAME_INTERVAL_MINUTES * age as i64);
+        for product in &products {
+            for (x, y) in (0..level).flat_map(|y| (0..level).map(move |x| (x, y))) {
+                locations.push(tiles.location(product, level, &timestamp, x, y));
+            }
+            if tiles.is_remote() {
+                download_bytes += preflight::estimate_tile_bytes(product.tile_width) * (level * level) as u64;
+            }
+        }
+    }
+
+    Ok(DownloadPlan {
+        timestamp,
+        path: output_dir.join(file_name),
+        tiles: locations,
+        download_bytes,
+        output_bytes: estimated_output_bytes(options),
+    })
+}
+
+fn download_latest_himawari_image(options: &DownloadOptions) -> Result<DownloadedFrame, AppErr> {
+    let DownloadOptions {
+        store_latest_only,
+        force,
+        capture_moon,
+        eclipse_mode,
+        checksums,
+        ref output_dir,
+        ref output_format,
+        ref encoding,
+        naming,
+        ref output_level,
+        requested_time,
+        frames,
+        ref product,
+        ref tiles,
+        ..
+    } = *options;
+
+    // Prepare the output folder
+    info!("Preparing output dir...");
+    if !output_dir.exists() {
+        DirBuilder::new().recursive(true).create(output_dir)?;
+    }
+
+    // Make sure there is room for everything about to be written
+    preflight::check_space(output_dir, estimated_output_bytes(options))?;
+
+    let metadata_timer = options.metrics.time(Phase::Metadata);
+    let latest = match requested_time {
+        Some(ref time) => LatestFrame::new(requested_frame_time(time)?),
+        None => tiles.latest(product, output_level.to_level(), output_dir)?,
+    };
+    drop(metadata_timer);
+    let latest_date = latest.timestamp;
+
+    // Prefer a frame in which the location is in daylight
+    if let Some(options) = daylight_options(options, &latest_date) {
+        return download_latest_himawari_image(&options);
+    }
+
+    // Keep high resolution copies of frames from special events
+    let mut events = Vec::new();
+    if capture_moon && astro::moon_in_view(&latest_date, product.satellite.longitude()) {
+        info!("The Moon is in view");
+        events.extend(capture_event_frame(
+            "moon",
+            tiles.as_ref(),
+            options.retry,
+            &options.metrics,
+            product,
+            &latest_date,
+            output_dir,
+            output_format,
+            encoding,
+        ));
+    }
+    if eclipse_mode && eclipse::is_active(&latest_date) {
+        info!("A solar eclipse is in progress");
+        events.extend(capture_event_frame(
+            "eclipse",
+            tiles.as_ref(),
+            options.retry,
+            &options.metrics,
+            product,
+            &latest_date,
+            output_dir,
+            &OutputFormat::PNG,
+            encoding,
+        ));
+    }
+    if checksums {
+        for event in &events {
+            record_checksum(options, &event.path);
+        }
+    }
+
+    if output_format.is_animated() {
+        let frame = download_animation(options, &latest_date, events)?;
+        latest.remember();
+        return Ok(frame);
+    }
+
+    if frames > 1 {
+        let frame = download_frame_sequence(options, &latest_date, events)?;
+        latest.remember();
+        return Ok(frame);
+    }
+
+    if naming == Naming::Sequence {
+        return download_numbered_frame(options, &latest, events);
+    }
+
+    // The filename that will be written
+    let file_name = match store_latest_only {
+        true => match options.latest_file_name {
+            Some(ref name) => name.clone(),
+            None => archive::latest_file_name(output_format),
+        },
+        false => archive::frame_file_name(&latest_date, output_format),
+    };
+    let output_file_path = output_dir.join(&file_name);
+
+    // Has the latest frame already been written by a previous run? The state file can tell
+    // even when latest.json couldn't be requested conditionally.
+    let unchanged = !latest.changed || State::load(output_dir).wrote(&latest_date, &output_file_path);
+    if store_latest_only && unchanged && output_file_path.exists() && !force {
+        let written = repair_bands(options, &latest_date, output_dir, &file_name);
+        if !written {
+            warn!("No new frame since the last run. Use --force to overwrite");
+        }
+        return Ok(DownloadedFrame {
+            path: output_file_path,
+            timestamp: latest_date,
+            written,
+            unchanged: !written,
+            events,
+            lockscreen: None,
+        });
+    }
+
+    // Have we already downloaded this one?
+    if output_file_path.exists() && !store_latest_only && !force {
+        let written = repair_bands(options, &latest_date, output_dir, &file_name);
+        if !written {
+            warn!(
+                "Output file {} already exists. Use --force to overwrite",
+                output_file_path.display()
+            );
+        }
+        latest.remember();
+        return Ok(DownloadedFrame {
+            path: output_file_path,
+            timestamp: latest_date,
+            written,
+            unchanged: false,
+            events,
+            lockscreen: None,
+        });
+    }
+
+    let lockscreen = lockscreen_file_path(options);
+    match render_bands(options, &latest_date, output_dir, &file_name, lockscreen.as_deref()) {
+        // The latest frame isn't finished yet, so take the one before. The latest is left
+        // unremembered, so the next run tries it again.
+        Err(app_err)
+            if options.placeholder_fallback && requested_time.is_none() && placeholder::is_placeholder_frame(&app_err) =>
+        {
+            warn!("{}, falling back on the frame before", app_err);
+            let mut options = options.clone();
+            options.requested_time = Some(latest_date - chrono::Duration::minutes(FRAME_INTERVAL_MINUTES));
+            options.placeholder_fallback = false;
+            return download_latest_himawari_image(&options);
+        }
+        result => result?,
+    }
+    latest.remember();
+
+    Ok(DownloadedFrame {
+        path: output_file_path,
+        timestamp: latest_date,
+        written: true,
+        unchanged: false,
+        events,
+        lockscreen,
+    })
+}
+
+/// Archives the latest frame with the next sequence number, which is tracked in the state file
+fn download_numbered_frame(
+    options: &DownloadOptions,
+    latest: &LatestFrame,
+    events: Vec<CapturedEvent>,
+) -> Result<DownloadedFrame, AppErr> {
+    let DownloadOptions {
+        force,
+        ref output_dir,
+        ref output_format,
+        ..
+    } = *options;
+
+    let mut state = State::load(output_dir);
+    let (number, already_written) = match state.numbered {
+        Some(ref last) if last.timestamp >= latest.timestamp => (last.number, true),
+        Some(ref last) => (last.number + 1, false),
+        None => (0, false),
+    };
+    let file_name = archive::numbered_file_name(number, output_format);
+    let output_file_path = output_dir.join(&file_name);
+
+    // Have we already downloaded this one?
+    if already_written && !force {
+        let written = repair_bands(options, &latest.timestamp, output_dir, &file_name);
+        if !written {
+            warn!(
+                "Frame {} was already archived as {}. Use --force to overwrite",
+                latest.timestamp,
+                output_file_path.display()
+            );
+        }
+        latest.remember();
+        return Ok(DownloadedFrame {
+            path: output_file_path,
+            timestamp: latest.timestamp,
+            written,
+            unchanged: false,
+            events,
+            lockscreen: None,
+        });
+    }
+
+    let lockscreen = lockscreen_file_path(options);
+    render_bands(options, &latest.timestamp, output_dir, &file_name, lockscreen.as_deref())?;
+    latest.remember();
+
+    state.numbered = Some(NumberedState {
+        number,
+        timestamp: latest.timestamp,
+    });
+    state.save(output_dir)?;
+
+    Ok(DownloadedFrame {
+        path: output_file_path,
+        timestamp: latest.timestamp,
+        written: true,
+        unchanged: false,
+        events,
+        lockscreen,
+    })
+}
+
+/// A generous estimate of how many bytes an update writes to the output directory
+fn estimated_output_bytes(options: &DownloadOptions) -> u64 {
+    let level = options.output_level.to_level();
+    let margins = &options.margins;
+    let disk = options.product.tile_width * level;
+    let (width, height) = match options.resize {
+        Some(ref size) => (size.width, size.height),
+        None => (margins.left + disk + margins.right, margins.top + disk + margins.bottom),
+    };
+    let formats = std::iter::once(&options.output_format).chain(&options.extra_formats);
+    let mut frame: u64 = formats
+        .map(|format| preflight::estimate_bytes(width, height, format))
+        .sum();
+    if options.keep_raw {
+        frame += preflight::estimate_bytes(disk, disk, &OutputFormat::PNG);
+    }
+    let bands = 1 + options.extra_bands.len() as u64;
+    let mut bytes = frame * bands * options.frames.max(1) as u64;
+    if let Some(ref lockscreen) = options.lockscreen {
+        let size = lockscreen.size.as_ref().map_or((width, height), |size| (size.width, size.height));
+        bytes += preflight::estimate_bytes(size.0, size.1, &options.output_format);
+    }
+    bytes
+}
+
+/// The options to download a frame in daylight with, when --prefer-daylight is given and the
+/// location is dark in the latest frame, captured at `timestamp`. None to keep that frame.
+fn daylight_options(options: &DownloadOptions, timestamp: &DateTime<Utc>) -> Option<DownloadOptions> {
+    let daylight = options.daylight.as_ref()?;
+    if options.requested_time.is_some() || daylight.location.is_sunlit(timestamp) {
+        return None;
+    }
+    let mut options = options.clone();
+    options.daylight = None;
+    match daylight.night_source {
+        Some(source) => {
+            info!("{} is dark at {}, so downloading from {}", daylight.location, timestamp, source);
+            options.product = options.product.alongside(source.to_product());
+        }
+        None => match daylight.location.last_sunlit(timestamp) {
+            Some(time) => {
+                info!("{} is dark at {}, so downloading the frame from {}", daylight.location, timestamp, time);
+                options.requested_time = Some(time);
+            }
+            None => {
+                warn!("{} has been dark all day, so keeping the latest frame", daylight.location);
+                return None;
+            }
+        },
+    }
+    Some(options)
+}
+
+/// The time of the frame captured nearest to `time`
+fn requested_frame_time(time: &DateTime<Utc>) -> Result<DateTime<Utc>, AppErr> {
+    let frame_time = frame_time::snap(time);
+    if frame_time > Utc::now() {
+        return Err(AppErr::new(tr!("no-frame-yet", time = frame_time)));
+    }
+    info!("Requested time {}, using the frame with timestamp {}", time, frame_time);
+    Ok(frame_time)
+}
+
+/// Where the lock screen version of the latest frame is written, if one is wanted
+fn lockscreen_file_path(options: &DownloadOptions) -> Option<PathBuf> {
+    options.lockscreen.as_ref().map(|_| {
+        options
+            .output_dir
+            .join(archive::lockscreen_file_name(&options.output_format))
+    })
+}
+
+/// Writes the `frames` most recent frames up to `latest_date` to the sequence directory.
+/// The newest frame is the one returned.
+fn download_frame_sequence(
+    options: &DownloadOptions,
+    latest_date: &DateTime<Utc>,
+    events: Vec<CapturedEvent>,
+) -> Result<DownloadedFrame, AppErr> {
+    let DownloadOptions {
+        force,
+        ref output_dir,
+        ref output_format,
+        frames,
+        ..
+    } = *options;
+
+    let sequence_dir = archive::sequence_dir(output_dir);
+    let newest_path = sequence_dir.join(archive::sequence_file_name(frames, output_format));
+
+    // Is the sequence already up to date?
+    let up_to_date = archive::read_sequence_manifest(output_dir).is_some_and(|manifest| {
+        manifest.frames.len() == frames as usize
+            && manifest.frames.last().map(|frame| frame.timestamp) == Some(*latest_date)
+    });
+    if up_to_date && !force {
+        warn!(
+            "Sequence in {} is already up to date. Use --force to overwrite",
+            sequence_dir.display()
+        );
+        return Ok(DownloadedFrame {
+            path: newest_path,
+            timestamp: *latest_date,
+            written: false,
+            unchanged: false,
+            events,
+            lockscreen: None,
+        });
+    }
+
+    DirBuilder::new().recursive(true).create(&sequence_dir)?;
+
+    let lockscreen = lockscreen_file_path(options);
+    let mut manifest = archive::SequenceManifest { frames: Vec::new() };
+    let age = chrono::Duration::minutes(FRAME_INTERVAL_MINUTES * (frames - 1) as i64);
+    let stream = FrameStream::new(
+        &*options.tiles,
+        StreamOptions {
+            product: options.product.clone(),
+            level: options.output_level.to_level(),
+            from: Some(*latest_date - age),
+            to: Some(*latest_date),
+            order: Order::OldestFirst,
+            retry: options.retry,
+        },
+    )?;
+    for (index, frame) in (1..=frames).zip(stream) {
+        let timestamp = frame.timestamp;
+        let file = archive::sequence_file_name(index, output_format);
+        info!("Frame {} of {}, with timestamp {}", index, frames, timestamp);
+        // Only the newest frame gets a lock screen version
+        let lockscreen_path = match index == frames {
+            true => lockscreen.as_deref(),
+            false => None,
+        };
+        render_bands(options, &timestamp, &sequence_dir, &file, lockscreen_path)?;
+        manifest.frames.push(archive::SequenceFrame { file, timestamp });
+    }
+
+    // Remove the tail of any longer sequence written previously
+    for index in frames + 1.. {
+        let path = sequence_dir.join(archive::sequence_file_name(index, output_format));
+        if std::fs::remove_file(&path).is_err() {
+            break;
+        }
+        let _ = std::fs::remove_file(archive::sidecar_path(&path));
+        let _ = std::fs::remove_file(archive::missing_tiles_path(&path));
+        let _ = std::fs::remove_file(archive::thumbnail_path(&path));
+        let copies = options.extra_formats.iter().map(|format| path.with_extension(format.to_string()));
+        let variants = ORIENTATIONS.iter().map(|&orientation| archive::variant_path(&path, orientation));
+        let copies: Vec<_> = copies.chain(variants).filter(|copy| std::fs::remove_file(copy).is_ok()).collect();
+        if options.checksums {
+            for path in std::iter::once(&path).chain(&copies) {
+                if let Err(app_err) = checksums::forget(output_dir, path) {
+                    warn!("Failed to update the checksum manifest: {}", app_err);
+                }
+            }
+        }
+    }
+
+    archive::write_sequence_manifest(output_dir, &manifest)?;
+
+    Ok(DownloadedFrame {
+        path: newest_path,
+        timestamp: *latest_date,
+        written: true,
+        unchanged: false,
+        events,
+        lockscreen,
+    })
+}
+
+/// Writes the `frames` most recent frames up to `latest_date` as one animation, named after
+/// the newest frame
+fn download_animation(
+    options: &DownloadOptions,
+    latest_date: &DateTime<Utc>,
+    events: Vec<CapturedEvent>,
+) -> Result<DownloadedFrame, AppErr> {
+    let DownloadOptions {
+        force,
+        checksums,
+        ref margins,
+        ref output_dir,
+        ref output_format,
+        ref output_level,
+        frames,
+        ref resize,
+        resize_mode,
+        ..
+    } = *options;
+
+    let path = output_dir.join(archive::frame_file_name(latest_date, output_format));
+    if path.exists() && !force {
+        warn!("Output file {} already exists. Use --force to overwrite", path.display());
+        return Ok(DownloadedFrame {
+            path,
+            timestamp: *latest_date,
+            written: false,
+            unchanged: false,
+            events,
+            lockscreen: None,
+        });
+    }
+
+    let level = output_level.to_level();
+    let width = options.product.tile_width;
+    let full_size = (
+        margins.left + (width * level) + margins.right,
+        margins.top + (width * level) + margins.bottom,
+    );
+    let crop_region = crop_region(options, full_size);
+    let cropped_size = crop_region.as_ref().map_or(full_size, |region| (region.width, region.height));
+    let reduce_factor = resize
+        .as_ref()
+        .and_then(|size| resize::box_factor(cropped_size.0, cropped_size.1, size, resize_mode));
+
+    let age = chrono::Duration::minutes(FRAME_INTERVAL_MINUTES * (frames - 1) as i64);
+    let stream = FrameStream::new(
+        &*options.tiles,
+        StreamOptions {
+            product: options.product.clone(),
+            level,
+            from: Some(*latest_date - age),
+            to: Some(*latest_date),
+            order: Order::OldestFirst,
+            retry: options.retry,
+        },
+    )?;
+    let mut writer = None;
+    for (index, frame) in (1..=frames).zip(stream) {
+        info!("Frame {} of {}, with timestamp {}", index, frames, frame.timestamp);
+        let frame = frame.with_background(options.background.unwrap_or(Colour::TRANSPARENT));
+        let mut buf = match reduce_factor {
+            Some(factor) => frame.fetch_reduced(margins, factor)?,
+            None => frame.fetch(margins)?,
+        };
+        options.post_process.apply(&mut buf);
+        draw_backdrop(options, &frame.product, &mut buf);
+        draw_map_layers(options, &frame.product, &frame.timestamp, &mut buf);
+        buf = reproject(options, &frame.product, buf);
+        if let Some(ref region) = crop_region {
+            buf = crop_to(&buf, region, reduce_factor);
+        }
+        if let Some(size) = resize {
+            buf = resize::resize(buf, size, resize_mode, pad_colour(options));
+        }
+        if let Some(ref overlay) = options.overlay {
+            let lines = overlay::lines(overlay, &frame.timestamp, frame.product.satellite.full_name());
+            overlay::draw(&mut buf, &lines, overlay);
+        }
+        // The animation is the size of its first frame
+        let writer = match writer {
+            Some(ref mut writer) => writer,
+            None => {
+                info!("Writing out to {}", path.display());
+                let (width, height) = buf.dimensions();
+                writer.insert(AnimationWriter::create(&path, output_format, width, height, frames)?)
+            }
+        };
+        writer.add(buf)?;
+    }
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
+    if checksums {
+        record_checksum(options, &path);
+    }
+
+    Ok(DownloadedFrame {
+        path,
+        timestamp: *latest_date,
+        written: true,
+        unchanged: false,
+        events,
+        lockscreen: None,
+    })
+}
+
+/// Renders the frame at `timestamp` to `file_name` in `dir`, and at the same time renders
+/// each extra band to `file_name` in a subdirectory named after the band.
+/// Failures of the extra bands are logged rather than failing the update.
+pub(crate) fn render_bands(
+    options: &DownloadOptions,
+    timestamp: &DateTime<Utc>,
+    dir: &Path,
+    file_name: &str,
+    lockscreen_path: Option<&Path>,
+) -> Result<(), AppErr> {
+    let path = dir.join(file_name);
+    let (result, _) = rayon::join(
+        || render_frame(options, &options.product, timestamp, &path, lockscreen_path),
+        || {
+            options.extra_bands.par_iter().for_each(|band| {
+                let render_band = || -> Result<(), AppErr> {
+                    let band_dir = dir.join(band.to_string());
+                    DirBuilder::new().recursive(true).create(&band_dir)?;
+                    let path = band_dir.join(file_name);
+                    render_frame(options, &options.product.alongside(band.to_product()), timestamp, &path, None)
+                };
+                if let Err(app_err) = render_band() {
+                    warn!("Failed to download the {} band: {}", band, app_err);
+                }
+            })
+        },
+    );
+    result
+}
+
+/// Downloads the frame of `product` at `timestamp`, processes it and writes it out to `path`.
+/// Also writes a lock screen version to `lockscreen_path`, if given.
+fn render_frame(
+    options: &DownloadOptions,
+    product: &Product,
+    timestamp: &DateTime<Utc>,
+    path: &Path,
+    lockscreen_path: Option<&Path>,
+) -> Result<(), AppErr> {
+    let DownloadOptions {
+        keep_raw,
+        write_sidecar,
+        checksums,
+        ref margins,
+        ref output_dir,
+        ref output_level,
+        ref encoding,
+        requested_time,
+        ref tiles,
+        ref follow_storm,
+        ref resize,
+        resize_mode,
+        ref lockscreen,
+        ref overlay,
+        ..
+    } = *options;
+
+    // Width and Level determine the dimensions and count of image fragments downloaded
+    let width = product.tile_width;
+    // Level can be 4, 8, 16, 20
+    let level = output_level.to_level();
+
+    let frame = FrameHandle::new(tiles.as_ref(), product.clone(), level, *timestamp)
+        .with_background(options.background.unwrap_or(Colour::TRANSPARENT))
+        .with_retry(options.retry)
+        .with_metrics(options.metrics.clone());
+
+    if can_stream(options, path, lockscreen_path) {
+        return stream_frame(options, &frame, path);
+    }
+
+    // Unless something needs the full resolution image, reduce it as the chunks arrive
+    let needs_full_size = keep_raw
+        || write_sidecar
+        || follow_storm.is_some()
+        || lockscreen_path.is_some()
+        || !options.variants.is_empty();
+    let full_size = (
+        margins.left + (width * level) + margins.right,
+        margins.top + (width * level) + margins.bottom,
+    );
+    // A crop is made before resizing, so it is the cropped region which is reduced
+    let crop_region = crop_region(options, full_size);
+    let cropped_size = crop_region.as_ref().map_or(full_size, |region| (region.width, region.height));
+    let reduce_factor = resize
+        .as_ref()
+        .filter(|_| !needs_full_size)
+        .and_then(|size| resize::box_factor(cropped_size.0, cropped_size.1, size, resize_mode));
+    if let Some(factor) = reduce_factor {
+        info!("Reducing by a factor of {} as chunks arrive...", factor);
+    }
+    let tiles_timer = options.metrics.time(Phase::Tiles);
+    let stitched = frame.fetch_stitched(margins, reduce_factor)?;
+    drop(tiles_timer);
+    if options.placeholder_fallback && requested_time.is_none() && stitched.placeholders > 0 {
+        return Err(AppErr::from(PlaceholderFrame {
+            timestamp: *timestamp,
+            tiles: stitched.placeholders,
+        }));
+    }
+    let mut buf = stitched.image;
+    let (w, h) = buf.dimensions();
+
+    // Keep the original, without margins, if the output will be any different
+    let processed = *margins != Margins::default()
+        || crop_region.is_some()
+        || follow_storm.is_some()
+        || resize.is_some()
+        || options.projection != Projection::Disk;
+    if keep_raw && processed {
+        let raw_path = archive::raw_path(output_dir, path);
+        let size = width * level;
+        let raw = image::imageops::crop_imm(&buf, margins.left, margins.top, size, size);
+        if let Some(raw_dir) = raw_path.parent() {
+            DirBuilder::new().recursive(true).create(raw_dir)?;
+        }
+        info!("Writing untouched image out to {}", raw_path.display());
+        encode::save(&raw.to_image(), &raw_path, encoding)?;
+        if checksums {
+            record_checksum(options, &raw_path);
+        }
+    }
+
+    // Analyse the image before any resizing
+    let sidecar = write_sidecar.then(|| {
+        info!("Analysing image...");
+        let stats = analysis::analyze(&buf, margins.left, margins.top, width * level);
+        info!(
+            "Estimated cloud cover {:.1}%, mean brightness {:.2}",
+            stats.cloud_cover, stats.mean_brightness
+        );
+        Sidecar {
+            timestamp: *timestamp,
+            requested_timestamp: requested_time
+                .filter(|time| time != timestamp && frame_time::snap(time) == *timestamp),
+            level,
+            cloud_cover: stats.cloud_cover,
+            mean_brightness: stats.mean_brightness,
+        }
+    });
+
+    if !options.post_process.is_empty() {
+        info!("Adjusting image...");
+        options.post_process.apply(&mut buf);
+    }
+    draw_backdrop(options, product, &mut buf);
+    draw_map_layers(options, product, timestamp, &mut buf);
+    buf = reproject(options, product, buf);
+
+    if let Some(join) = options.panorama {
+        buf = join_panorama(options, product, timestamp, buf, join)?;
+    }
+
+    if let Some(ref region) = crop_region {
+        info!("Cropping to {}x{} at {},{}...", region.width, region.height, region.x, region.y);
+        buf = crop_to(&buf, region, reduce_factor);
+    }
+
+    if let Some(storm) = follow_storm {
+        let aspect = resize.as_ref().map_or(1.0, |size| size.width as f64 / size.height as f64);
+        let geometry = FrameGeometry {
+            disk_x: margins.left,
+            disk_y: margins.top,
+            disk_size: width * level,
+            canvas_width: w,
+            canvas_height: h,
+        };
+        let region = storm::storm_region(&options.http, storm, &geometry, aspect)
+            .unwrap_or_else(|err| {
+                // Carry on with the whole disk rather than fail the update
+                warn!("{}", err);
+                None
+            });
+        if let Some(region) = region {
+            buf = image::imageops::crop_imm(&buf, region.x, region.y, region.width, region.height)
+                .to_image();
+        }
+    }
+
+    // Render the lock screen version from the full resolution image
+    let lockscreen = lockscreen_path.zip(lockscreen.as_ref()).map(|(path, lockscreen)| {
+        info!("Rendering lock screen image...");
+        (path, lockscreen::render(&buf, lockscreen))
+    });
+
+    // Render the versions for monitors of each orientation, also from the full resolution image
+    let variants = resize.as_ref().map_or_else(Vec::new, |size| {
+        options
+            .variants
+            .iter()
+            .map(|&orientation| {
+                let size = orientation.orient(size);
+                info!("Rendering {} version at {}...", orientation, size);
+                (orientation, resize::resize(buf.clone(), &size, resize_mode, pad_colour(options)))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    if let Some(size) = resize {
+        info!("Resizing to {} ({})...", size, resize_mode);
+        buf = resize::resize(buf, size, resize_mode, pad_colour(options));
+    }
+
+    finish_image(options, product, timestamp, &mut buf);
+    write_image(options, product, level, timestamp, &buf, path)?;
+
+    if let Some((lockscreen_path, lockscreen_buf)) = lockscreen {
+        info!("Writing lock screen image out to {}", lockscreen_path.display());
+        encode::save(&lockscreen_buf, lockscreen_path, encoding)?;
+    }
+
+    for (orientation, mut variant) in variants {
+        finish_image(options, product, timestamp, &mut variant);
+        let variant_path = archive::variant_path(path, orientation);
+        info!("Writing {} version out to {}", orientation, variant_path.display());
+        encode::save(&variant, &variant_path, encoding)?;
+        if checksums {
+            record_checksum(options, &variant_path);
+        }
+    }
+
+    if let Some(sidecar) = sidecar {
+        archive::write_sidecar(path, &sidecar)?;
+    }
+
+    // Note any tiles left black, so --repair can fetch them later. They can be patched into
+    // the image in place unless it was resized, cropped or drawn on.
+    let drawn_on = overlay.is_some()
+        || !options.map_layers.is_empty()
+        || !options.post_process.is_empty()
+        || options.panorama.is_some()
+        || options.night_dim.is_some()
+        || options.projection != Projection::Disk;
+    let cropped = crop_region.is_some() || follow_storm.is_some();
+    let placement = (!cropped && resize.is_none() && !drawn_on).then_some(TilePlacement {
+        left: margins.left,
+        top: margins.top,
+        tile_width: width,
+    });
+    let missing = MissingTiles {
+        timestamp: *timestamp,
+        level,
+        tiles: stitched.missing,
+        placement,
+    };
+    archive::write_missing_tiles(path, &missing)?;
+
+    Ok(())
+}
+
+/// Draws the overlay on `buf`, a frame of `product` at `timestamp` at its final size, and dims
+/// it for the night
+fn finish_image(options: &DownloadOptions, product: &Product, timestamp: &DateTime<Utc>, buf: &mut RgbaImage) {
+    if let Some(ref overlay) = options.overlay {
+        overlay::draw(buf, &overlay::lines(overlay, timestamp, product.satellite.full_name()), overlay);
+    }
+
+    // By the time the image is written, not when the frame was captured
+    if let Some(ref night_dim) = options.night_dim {
+        let amount = night_dim.amount(&Utc::now());
+        if amount > 0.0 {
+            info!("Dimming image for the night ({:.0}%)...", amount * 100.0);
+            night_dim::apply(buf, night_dim, amount);
+        }
+    }
+}
+
+/// True if the frame can be written to `path` as its tiles arrive: a PNG at a large level,
+/// with nothing which needs the whole image at once, such as resizing, cropping or drawing on it
+fn can_stream(options: &DownloadOptions, path: &Path, lockscreen_path: Option<&Path>) -> bool {
+    let format = path
+        .extension()
+        .and_then(|ext| OutputFormat::from_extension(&ext.to_string_lossy()));
+    options.output_level.to_level() >= STREAM_MIN_LEVEL
+        && format == Some(OutputFormat::PNG)
+        && options.extra_formats.is_empty()
+        && !options.keep_raw
+        && !options.write_sidecar
+        && options.resize.is_none()
+        && options.crop.is_none()
+        && options.follow_storm.is_none()
+        && lockscreen_path.is_none()
+        && options.overlay.is_none()
+        && options.map_layers.is_empty()
+        && options.post_process.is_empty()
+        && options.backdrop == Backdrop::Black
+        && options.panorama.is_none()
+        && options.thumbnail.is_none()
+        && options.night_dim.is_none()
+        && options.projection == Projection::Disk
+}
+
+/// Writes `frame` out to `path` a row of tiles at a time as they arrive, so that neither the
+/// image nor all of its tiles are ever held in memory
+fn stream_frame(options: &DownloadOptions, frame: &FrameHandle, path: &Path) -> Result<(), AppErr> {
+    let DownloadOptions {
+        ref margins,
+        ref encoding,
+        requested_time,
+        ..
+    } = *options;
+    let disk = frame.product.tile_width * frame.level;
+    let (w, h) = (margins.left + disk + margins.right, margins.top + disk + margins.bottom);
+
+    info!("Writing out to {} as the tiles arrive...", path.display());
+    let tiles_timer = options.metrics.time(Phase::Tiles);
+    let streamed = encode::write_atomically(path, |temp_path| {
+        let mut writer = PngBandWriter::create(temp_path, w, h, encoding)?;
+        let streamed = frame.stream(margins, |band| writer.write(band))?;
+        writer.finish()?;
+        // Leave any earlier image in place
+        if options.placeholder_fallback && requested_time.is_none() && streamed.placeholders > 0 {
+            return Err(AppErr::from(PlaceholderFrame {
+                timestamp: frame.timestamp,
+                tiles: streamed.placeholders,
+            }));
+        }
+        Ok(streamed)
+    })?;
+    drop(tiles_timer);
+    annotate_image(options, &frame.product, frame.level, &frame.timestamp, path)?;
+
+    // The image is as stitched, so missing tiles can always be patched into it in place
+    let missing = MissingTiles {
+        timestamp: frame.timestamp,
+        level: frame.level,
+        tiles: streamed.missing,
+        placement: Some(TilePlacement {
+            left: margins.left,
+            top: margins.top,
+            tile_width: frame.product.tile_width,
+        }),
+    };
+    archive::write_missing_tiles(path, &missing)?;
+    Ok(())
+}
+
+/// The region of the full size image, `full_size` pixels, which --crop keeps
+fn crop_region(options: &DownloadOptions, full_size: (u32, u32)) -> Option<Region> {
+    options.crop.as_ref().and_then(|crop| crop.region(full_size.0, full_size.1))
+}
+
+/// Crops `buf` to `region` of the full size image, which `buf` may have been reduced from
+fn crop_to(buf: &RgbaImage, region: &Region, reduce_factor: Option<u32>) -> RgbaImage {
+    let factor = reduce_factor.unwrap_or(1);
+    let (width, height) = ((region.width / factor).max(1), (region.height / factor).max(1));
+    image::imageops::crop_imm(buf, region.x / factor, region.y / factor, width, height).to_image()
+}
+
+/// The colour which --resize-mode pad fills around the image with
+fn pad_colour(options: &DownloadOptions) -> Rgba<u8> {
+    options.background.unwrap_or(Colour::BLACK).0
+}
+
+/// Where the disk is in `buf`, a frame of `product` which may have been reduced
+fn disk_in(options: &DownloadOptions, product: &Product, buf: &RgbaImage) -> map_layers::Disk {
+    let margins = &options.margins;
+    let disk = product.tile_width * options.output_level.to_level();
+    let scale = buf.width() as f64 / (margins.left + disk + margins.right) as f64;
+    map_layers::Disk {
+        left: margins.left as f64 * scale,
+        top: margins.top as f64 * scale,
+        size: disk as f64 * scale,
+    }
+}
+
+/// Paints --background around the disk of `product` in `buf`
+fn draw_backdrop(options: &DownloadOptions, product: &Product, buf: &mut RgbaImage) {
+    if options.backdrop != Backdrop::Black {
+        backdrop::paint(buf, disk_in(options, product, buf), options.backdrop);
+    }
+}
+
+/// Draws the map layers asked for over the disk of `product` in `buf`, which may have been
+/// reduced from its full size
+fn draw_map_layers(options: &DownloadOptions, product: &Product, timestamp: &DateTime<Utc>, buf: &mut RgbaImage) {
+    if options.map_layers.is_empty() {
+        return;
+    }
+    let disk = disk_in(options, product, buf);
+    map_layers::draw(buf, disk, product.satellite.longitude(), timestamp, &options.map_layers);
+}
+
+/// Reprojects the disk of `product` in `buf` onto a map, if --projection asks for one
+fn reproject(options: &DownloadOptions, product: &Product, buf: RgbaImage) -> RgbaImage {
+    if options.projection == Projection::Disk {
+        return buf;
+    }
+    info!("Reprojecting to {}...", options.projection);
+    let disk = disk_in(options, product, &buf);
+    let fill = options.background.unwrap_or(Colour::TRANSPARENT).0;
+    projection::reproject(buf, disk, product.satellite.longitude(), options.projection, fill)
+}
+
+/// Downloads the frame of the eastern satellite nearest to `timestamp`, adjusted and drawn on
+/// as `buf` was, and joins it to the right of `buf`, the disk of `product`
+fn join_panorama(
+    options: &DownloadOptions,
+    product: &Product,
+    timestamp: &DateTime<Utc>,
+    buf: RgbaImage,
+    join: Join,
+) -> Result<RgbaImage, AppErr> {
+    let eastern = options.product.alongside(panorama::EASTERN_SATELLITE.default_product());
+    // The disk of `buf` may have been reduced, so fetch the smallest level which covers it
+    let size = buf.height();
+    let levels = eastern.levels();
+    let level = levels
+        .iter()
+        .copied()
+        .find(|level| eastern.tile_width * level >= size)
+        .unwrap_or_else(|| levels[levels.len() - 1]);
+    let eastern_time = options.tiles.nearest(&eastern, level, timestamp)?;
+    if (eastern_time - *timestamp).num_minutes().abs() > FRAME_INTERVAL_MINUTES {
+        warn!(
+            "The nearest {} frame to {} is from {}",
+            eastern.satellite.full_name(),
+            timestamp,
+            eastern_time
+        );
+    }
+
+    info!("Downloading the {} frame from {} for the panorama...", eastern.satellite.full_name(), eastern_time);
+    let tiles_timer = options.metrics.time(Phase::Tiles);
+    let frame = FrameHandle::new(&*options.tiles, eastern.clone(), level, eastern_time)
+        .with_background(options.background.unwrap_or(Colour::TRANSPARENT))
+        .with_retry(options.retry)
+        .with_metrics(options.metrics.clone());
+    let eastern_buf = frame.fetch(&Margins::default())?;
+    drop(tiles_timer);
+    let mut eastern_buf = resize::stretch(eastern_buf, &Size { width: size, height: size });
+    options.post_process.apply(&mut eastern_buf);
+    draw_map_layers(options, &eastern, &eastern_time, &mut eastern_buf);
+
+    info!("Joining the disks ({})...", join);
+    let longitudes = (product.satellite.longitude(), eastern.satellite.longitude());
+    Ok(panorama::join(&buf, &eastern_buf, join, longitudes))
+}
+
+/// Writes `buf`, the frame of `product` at `timestamp` composed of `level` tiles a side, out
+/// to `path` and a copy in each extra format next to it
+fn write_image(
+    options: &DownloadOptions,
+    product: &Product,
+    level: u32,
+    timestamp: &DateTime<Utc>,
+    buf: &RgbaImage,
+    path: &Path,
+) -> Result<(), AppErr> {
+    let DownloadOptions {
+        checksums,
+        ref extra_formats,
+        ref encoding,
+        thumbnail,
+        ..
+    } = *options;
+    let _timer = options.metrics.time(Phase::Write);
+
+    // NOTE: Output format detemined by file extension (jpeg or png)
+    let write = |path: &Path| -> Result<(), AppErr> {
+        info!("Writing out to {}", path.display());
+        encode::save(buf, path, encoding)?;
+        annotate_image(options, product, level, timestamp, path)
+    };
+
+    // Encode a copy in each extra format at the same time, next to the main one
+    let paths: Vec<_> = std::iter::once(path.to_path_buf())
+        .chain(extra_formats.iter().map(|format| path.with_extension(format.to_string())))
+        .collect();
+    paths.par_iter().map(|path| write(path)).collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(width) = thumbnail {
+        let thumbnail_path = archive::thumbnail_path(path);
+        info!("Writing thumbnail out to {}", thumbnail_path.display());
+        let (w, h) = buf.dimensions();
+        let height = (h as u64 * width as u64 / w as u64).max(1) as u32;
+        let thumbnail = resize::fit_within(buf.clone(), &Size { width, height });
+        encode::save(&thumbnail, &thumbnail_path, encoding)?;
+        if checksums {
+            record_checksum(options, &thumbnail_path);
+        }
+    }
+    Ok(())
+}
+
+/// Writes EXIF metadata into the image of the frame of `product` at `timestamp` just written
+/// to `path`, and records its checksum, if asked for
+fn annotate_image(
+    options: &DownloadOptions,
+    product: &Product,
+    level: u32,
+    timestamp: &DateTime<Utc>,
+    path: &Path,
+) -> Result<(), AppErr> {
+    if options.write_exif {
+        let camera = exif::Camera {
+            satellite: product.satellite.full_name(),
+            latitude: 0.0,
+            longitude: product.satellite.longitude(),
+            altitude_km: geo::SATELLITE_ALTITUDE_KM,
+        };
+        let capture = exif::Capture {
+            timestamp: *timestamp,
+            product: &product.name,
+            level,
+            source: options.tiles.location(product, level, timestamp, 0, 0),
+        };
+        exif::write_exif(path, &camera, &capture)?;
+    }
+    if options.checksums {
+        record_checksum(options, path);
+    }
+    Ok(())
+}
+
+/// With --repair, fetches the tiles missing from the frame at `timestamp` already written to
+/// `file_name` in `dir`, and from each extra band. Returns whether any frame was repaired.
+/// Failures are logged rather than failing the update.
+fn repair_bands(options: &DownloadOptions, timestamp: &DateTime<Utc>, dir: &Path, file_name: &str) -> bool {
+    if !options.repair {
+        return false;
+    }
+    let main = (options.product.clone(), dir.join(file_name), lockscreen_file_path(options));
+    let bands = options.extra_bands.iter().map(|band| {
+        (options.product.alongside(band.to_product()), dir.join(band.to_string()).join(file_name), None)
+    });
+    let mut repaired = false;
+    for (product, path, lockscreen_path) in std::iter::once(main).chain(bands) {
+        match repair_frame(options, &product, timestamp, &path, lockscreen_path.as_deref()) {
+            Ok(frame_repaired) => repaired |= frame_repaired,
+            Err(app_err) => warn!("Failed to repair {}: {}", path.display(), app_err),
+        }
+    }
+    repaired
+}
+
+/// Fetches the tiles of `product` missing from the frame written to `path`, and patches
+/// them into it. An image which was resized or cropped is rendered again instead, which
+/// takes the tiles it already had from the tile cache. Returns whether the frame changed.
+fn repair_frame(
+    options: &DownloadOptions,
+    product: &Product,
+    timestamp: &DateTime<Utc>,
+    path: &Path,
+    lockscreen_path: Option<&Path>,
+) -> Result<bool, AppErr> {
+    let mut missing = match archive::read_missing_tiles(path) {
+        Some(missing) if missing.timestamp == *timestamp && path.exists() => missing,
+        _ => return Ok(false),
+    };
+    info!("{} is missing {} tiles, repairing...", path.display(), missing.tiles.len());
+
+    // Only a full size image read back from disk can be patched
+    let patchable = missing
+        .placement
+        .and_then(|placement| Some((placement, image::open(path).ok()?)));
+    let (placement, existing) = match patchable {
+        Some(patchable) => patchable,
+        None => {
+            info!("Rendering {} again...", path.display());
+            render_frame(options, product, timestamp, path, lockscreen_path)?;
+            return Ok(true);
+        }
+    };
+
+    let chunks = composite::download_tiles(
+        &*options.tiles,
+        product,
+        missing.level,
+        timestamp,
+        missing.tiles.clone(),
+        options.retry,
+        &options.metrics,
+    )?;
+    if chunks.is_empty() {
+        warn!("None of the tiles missing from {} could be downloaded", path.display());
+        return Ok(false);
+    }
+
+    info!("Patching {} tiles into {}...", chunks.len(), path.display());
+    let mut buf = existing.to_rgba8();
+    for (x, y, chunk) in &chunks {
+        let x_pos = placement.left + (x * placement.tile_width);
+        let y_pos = placement.top + (y * placement.tile_width);
+        buf.copy_from(chunk, x_pos, y_pos)?;
+    }
+    write_image(options, product, missing.level, timestamp, &buf, path)?;
+
+    missing
+        .tiles
+        .retain(|&(x, y)| !chunks.iter().any(|(cx, cy, _)| (*cx, *cy) == (x, y)));
+    archive::write_missing_tiles(path, &missing)?;
+    Ok(true)
+}
+
+/// Adds a newly written frame to the checksum manifest, and its sidecar if `options` ask for one.
+/// The frame itself was written, so failures are only logged.
+pub(crate) fn record_checksum(options: &DownloadOptions, path: &Path) {
+    if let Err(app_err) = checksums::record(&options.output_dir, path, options.checksum_sidecars) {
+        warn!("Failed to update the checksum manifest: {}", app_err);
+    }
+}
+
+/// Archives a copy of the frame at `timestamp` at the highest level in the events directory,
+/// unless it has already been captured. Failures are logged rather than failing the update.
+#[allow(clippy::too_many_arguments)]
+fn capture_event_frame(
+    name: &'static str,
+    tiles: &dyn ImageSource,
+    retry: RetryOptions,
+    metrics: &Metrics,
+    product: &Product,
+    timestamp: &DateTime<Utc>,
+    output_dir: &Path,
+    output_format: &OutputFormat,
+    encoding: &EncodeOptions,
+) -> Option<CapturedEvent> {
+    let mut path = archive::events_dir(output_dir);
+    path.push(archive::frame_file_name(timestamp, output_format));
+    if path.exists() {
+        return None;
+    }
+
+    let capture = || -> Result<(), AppErr> {
+        info!("Capturing the frame at the highest level...");
+        let level = OutputLevel::max(product).to_level();
+        let frame = FrameHandle::new(tiles, product.clone(), level, *timestamp)
+            .with_retry(retry)
+            .with_metrics(metrics.clone());
+        let buf = frame.fetch(&Margins::default())?;
+        DirBuilder::new()
+            .recursive(true)
+            .create(archive::events_dir(output_dir))?;
+        info!("Writing out to {}", path.display());
+        encode::save(&buf, &path, encoding)?;
+        Ok(())
+    };
+
+    match capture() {
+        Ok(()) => Some(CapturedEvent { name, path }),
+        Err(app_err) => {
+            warn!("{}", app_err);
+            None
+        }
+    }
+}
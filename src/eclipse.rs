@@ -0,0 +1,62 @@
+//! Solar eclipses whose shadow crosses the Himawari field of view.
+
+use std::time::Duration;
+
+use chrono::prelude::*;
+
+use crate::product::FRAME_INTERVAL_MINUTES;
+
+/// Times of greatest eclipse (UTC) from the NASA eclipse catalogue, for eclipses
+/// with some part of the penumbra over Asia, Australasia or the western Pacific
+const ECLIPSES: &[(i32, u32, u32, u32, u32)] = &[
+    (2023, 4, 20, 4, 17),
+    (2025, 9, 21, 19, 43),
+    (2026, 2, 17, 12, 13),
+    (2028, 7, 22, 2, 56),
+    (2030, 6, 1, 6, 29),
+    (2030, 11, 25, 6, 51),
+    (2031, 5, 21, 7, 16),
+    (2032, 11, 3, 5, 34),
+    (2033, 3, 30, 18, 2),
+    (2034, 3, 20, 10, 18),
+    (2035, 3, 9, 23, 5),
+    (2035, 9, 2, 1, 56),
+];
+
+/// The partial phases of an eclipse last up to around five hours,
+/// so the event window extends this far either side of greatest eclipse
+const WINDOW_HOURS: i64 = 3;
+
+/// How often to check for new frames during an eclipse (the satellite's cadence)
+pub const ECLIPSE_INTERVAL: Duration = Duration::from_secs(FRAME_INTERVAL_MINUTES as u64 * 60);
+
+/// The start and end of each eclipse event window
+fn windows() -> impl Iterator<Item = (DateTime<Utc>, DateTime<Utc>)> {
+    ECLIPSES.iter().map(|&(year, month, day, hour, minute)| {
+        let greatest = Utc.ymd(year, month, day).and_hms(hour, minute, 0);
+        let half_window = chrono::Duration::hours(WINDOW_HOURS);
+        (greatest - half_window, greatest + half_window)
+    })
+}
+
+/// True if `time` falls within an eclipse event window
+pub fn is_active(time: &DateTime<Utc>) -> bool {
+    windows().any(|(start, end)| start <= *time && *time <= end)
+}
+
+/// How long the daemon should sleep for: at most the eclipse interval during an eclipse,
+/// and never past the start of the next one
+pub fn sleep_duration(now: &DateTime<Utc>, interval: Duration) -> Duration {
+    if is_active(now) {
+        return interval.min(ECLIPSE_INTERVAL);
+    }
+    let until_next = windows()
+        .map(|(start, _)| start)
+        .filter(|start| start > now)
+        .min()
+        .and_then(|start| (start - *now).to_std().ok());
+    match until_next {
+        Some(until_next) => interval.min(until_next),
+        None => interval,
+    }
+}
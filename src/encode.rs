@@ -0,0 +1,173 @@
+//! Writing images out with the encoder settings chosen on the command line.
+//!
+//! WebP is lossless unless a quality is given, which needs the libwebp C library and so the
+//! "libwebp" feature. AVIF is always lossy. BMP is written without transparency, which some
+//! older wallpaper settings choke on.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{FilterType, PngEncoder};
+use image::{ColorType, DynamicImage, ImageEncoder, ImageFormat, ImageOutputFormat, RgbaImage};
+use rgb::FromSlice;
+
+use crate::error::AppErr;
+use crate::output_format::OutputFormat;
+use crate::png_compression::PngCompression;
+
+/// Trades AVIF file size for encoding speed, from 1 (slowest) to 10. The slower speeds take
+/// minutes at the larger levels.
+const AVIF_SPEED: u8 = 8;
+
+#[derive(Clone)]
+pub struct EncodeOptions {
+    /// From 1 to 100, trading file size against artifacts
+    pub jpeg_quality: u8,
+    pub png_compression: PngCompression,
+    /// None for lossless WebP
+    pub webp_quality: Option<u8>,
+    pub avif_quality: u8,
+    /// Write TIFF images with 16 bits a channel rather than 8
+    pub tiff_16_bit: bool,
+}
+
+/// Whether lossy WebP can be written, which needs the "libwebp" feature
+pub fn lossy_webp_available() -> bool {
+    cfg!(feature = "libwebp")
+}
+
+/// Writes `image` to `path`, in the format its extension names
+pub fn save(image: &RgbaImage, path: &Path, options: &EncodeOptions) -> Result<(), AppErr> {
+    let format = path
+        .extension()
+        .and_then(|ext| OutputFormat::from_extension(&ext.to_string_lossy()));
+    let (width, height) = image.dimensions();
+    write_atomically(path, |temp_path| {
+        match format {
+            Some(OutputFormat::JPEG) => {
+                let mut file = BufWriter::new(File::create(temp_path)?);
+                JpegEncoder::new_with_quality(&mut file, options.jpeg_quality).encode_image(image)?;
+                file.flush()?;
+            }
+            Some(OutputFormat::PNG) => {
+                let mut file = BufWriter::new(File::create(temp_path)?);
+                let compression = options.png_compression.to_compression_type();
+                PngEncoder::new_with_quality(&mut file, compression, FilterType::Adaptive)
+                    .write_image(image.as_raw(), width, height, ColorType::Rgba8)?;
+                file.flush()?;
+            }
+            Some(OutputFormat::WEBP) => match options.webp_quality {
+                Some(quality) => std::fs::write(temp_path, encode_lossy_webp(image, quality)?)?,
+                None => {
+                    let mut file = BufWriter::new(File::create(temp_path)?);
+                    image_webp::WebPEncoder::new(&mut file).encode(
+                        image.as_raw(),
+                        width,
+                        height,
+                        image_webp::ColorType::Rgba8,
+                    )?;
+                    file.flush()?;
+                }
+            },
+            Some(OutputFormat::AVIF) => {
+                let pixels = ravif::Img::new(image.as_raw().as_rgba(), width as usize, height as usize);
+                let encoded = ravif::Encoder::new()
+                    .with_quality(options.avif_quality as f32)
+                    .with_speed(AVIF_SPEED)
+                    .encode_rgba(pixels)?;
+                std::fs::write(temp_path, encoded.avif_file)?;
+            }
+            Some(OutputFormat::BMP) => {
+                let mut file = BufWriter::new(File::create(temp_path)?);
+                let rgb = DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+                BmpEncoder::new(&mut file).encode(rgb.as_raw(), width, height, ColorType::Rgb8)?;
+                file.flush()?;
+            }
+            Some(OutputFormat::TIFF) => {
+                let mut file = BufWriter::new(File::create(temp_path)?);
+                let image = DynamicImage::ImageRgba8(image.clone());
+                // The frames only have 8 bits a channel, so this widens them for tools which
+                // expect 16 rather than adding detail
+                let image = match options.tiff_16_bit {
+                    true => DynamicImage::ImageRgba16(image.to_rgba16()),
+                    false => image,
+                };
+                image.write_to(&mut file, ImageOutputFormat::Tiff)?;
+                file.flush()?;
+            }
+            // NOTE: The temporary file's extension doesn't name the format, so the target's must
+            _ => image.save_with_format(temp_path, ImageFormat::from_path(path)?)?,
+        }
+        Ok(())
+    })
+}
+
+/// Writes a PNG image a band of rows at a time, for images too large to hold in memory
+pub struct PngBandWriter {
+    writer: png::StreamWriter<'static, BufWriter<File>>,
+}
+
+impl PngBandWriter {
+    /// Starts writing an image of `width` by `height` pixels to `path`
+    pub fn create(path: &Path, width: u32, height: u32, options: &EncodeOptions) -> Result<PngBandWriter, AppErr> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(options.png_compression.to_png_compression());
+        encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+        let writer = encoder.write_header()?.into_stream_writer()?;
+        Ok(PngBandWriter { writer })
+    }
+
+    /// Writes the next `band` of rows, which must be as wide as the image
+    pub fn write(&mut self, band: &RgbaImage) -> Result<(), AppErr> {
+        self.writer.write_all(band.as_raw())?;
+        Ok(())
+    }
+
+    /// Finishes the image, once every row has been written
+    pub fn finish(self) -> Result<(), AppErr> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Writes `path` by having `write` write a temporary file next to it, which replaces `path`
+/// once complete. A process killed part way through then leaves the old file whole, rather
+/// than a truncated one which Windows shows as a black wallpaper.
+pub fn write_atomically<T, F>(path: &Path, write: F) -> Result<T, AppErr>
+where
+    F: FnOnce(&Path) -> Result<T, AppErr>,
+{
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let result = write(&temp_path).and_then(|value| -> Result<T, AppErr> {
+        File::options().write(true).open(&temp_path)?.sync_all()?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(value)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(feature = "libwebp")]
+fn encode_lossy_webp(image: &RgbaImage, quality: u8) -> Result<Vec<u8>, AppErr> {
+    let (width, height) = image.dimensions();
+    webp::Encoder::from_rgba(image.as_raw(), width, height)
+        .encode_simple(false, quality as f32)
+        .map(|encoded| encoded.to_vec())
+        .map_err(|err| AppErr::new(format!("Failed to encode WebP: {:?}", err)))
+}
+
+#[cfg(not(feature = "libwebp"))]
+fn encode_lossy_webp(_image: &RgbaImage, _quality: u8) -> Result<Vec<u8>, AppErr> {
+    use crate::messages::tr;
+    Err(AppErr::new(tr!("lossy-webp-unavailable")))
+}
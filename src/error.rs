@@ -1,14 +1,106 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Error as FmtError, Formatter};
 
-pub struct AppErr(String, Option<Box<dyn Error>>);
+use serde_derive::Serialize;
+
+use crate::placeholder::{PlaceholderFrame, PlaceholderTile};
+
+/// What went wrong, broadly, so a script can tell a failure worth retrying from one which
+/// needs fixing. Each kind has its own exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The server couldn't be reached, or failed to serve a frame
+    Network,
+    /// The server sent something which couldn't be understood
+    Parse,
+    /// Reading or writing a file failed
+    Io,
+    /// Setting the wallpaper or lock screen failed
+    Wallpaper,
+    /// The command line or config file is invalid
+    Config,
+    /// The latest release has no build for this platform
+    NoRelease,
+    Other,
+}
+
+impl ErrorKind {
+    /// The code the updater exits with on an error of this kind. 2 is taken by there being
+    /// no new frame, which isn't an error.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Network => 3,
+            ErrorKind::Io => 4,
+            ErrorKind::Wallpaper => 5,
+            ErrorKind::Config => 6,
+            ErrorKind::Parse => 7,
+            ErrorKind::NoRelease => 8,
+        }
+    }
+}
+
+/// How the download of one tile failed, in the end
+#[derive(Clone, Debug, Serialize)]
+pub struct TileFailure {
+    /// The tile's URL, or its path in the tile directory
+    pub url: String,
+    /// The status of the last response, if there was one
+    pub status: Option<u16>,
+    /// Requests sent for the tile, over every retry
+    pub attempts: u32,
+    pub error: String,
+}
+
+/// An error, with the failures of the tiles behind it if it came of downloading a frame: one
+/// for a tile which failed, or all of them for a frame missing too many
+pub struct AppErr(String, Option<Box<dyn Error + Send + Sync>>, ErrorKind, Vec<TileFailure>);
 
 impl AppErr {
-    fn from_err<E>(kind: &str, error: E) -> AppErr
+    pub fn new<S: Into<String>>(message: S) -> AppErr {
+        AppErr(message.into(), None, ErrorKind::Other, Vec::new())
+    }
+
+    /// An error of a particular kind
+    pub fn of_kind<S: Into<String>>(kind: ErrorKind, message: S) -> AppErr {
+        AppErr(message.into(), None, kind, Vec::new())
+    }
+
+    fn from_err<E>(name: &str, kind: ErrorKind, error: E) -> AppErr
     where
-        E: Error + 'static,
+        E: Error + Send + Sync + 'static,
     {
-        AppErr(format!("[{}] {}", kind, error), Some(Box::new(error)))
+        AppErr(format!("[{}] {}", name, error), Some(Box::new(error)), kind, Vec::new())
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.2
+    }
+
+    /// The same error, as one of `kind`, for errors whose kind depends on what was being done
+    pub fn with_kind(self, kind: ErrorKind) -> AppErr {
+        AppErr(self.0, self.1, kind, self.3)
+    }
+
+    /// The same error, as the failure of the tile at `url` after `attempts` requests in all
+    pub fn for_tile(self, url: String, status: Option<u16>, attempts: u32) -> AppErr {
+        let failure = TileFailure {
+            url,
+            status,
+            attempts,
+            error: self.0.clone(),
+        };
+        AppErr(self.0, self.1, self.2, vec![failure])
+    }
+
+    /// The same error, with the failures of the tiles which led to it
+    pub fn with_tile_failures(self, failures: Vec<TileFailure>) -> AppErr {
+        AppErr(self.0, self.1, self.2, failures)
+    }
+
+    /// The failures of the tiles which led to this error, if any
+    pub fn tile_failures(&self) -> &[TileFailure] {
+        &self.3
     }
 }
 
@@ -38,19 +130,31 @@ impl Error for AppErr {
 }
 
 macro_rules! impl_from_error {
-    ($type:ty) => {
+    ($type:ty, $kind:expr) => {
         impl From<$type> for AppErr {
             fn from(err: $type) -> Self {
-                AppErr::from_err(stringify!($type), err)
+                AppErr::from_err(stringify!($type), $kind, err)
             }
         }
     };
 }
 
 // Error conversions
-impl_from_error!(std::io::Error);
-impl_from_error!(std::time::SystemTimeError);
-impl_from_error!(reqwest::Error);
-impl_from_error!(serde_json::Error);
-impl_from_error!(chrono::ParseError);
-impl_from_error!(image::ImageError);
+impl_from_error!(std::io::Error, ErrorKind::Io);
+impl_from_error!(std::time::SystemTimeError, ErrorKind::Other);
+impl_from_error!(reqwest::Error, ErrorKind::Network);
+impl_from_error!(tokio::task::JoinError, ErrorKind::Other);
+impl_from_error!(serde_json::Error, ErrorKind::Parse);
+impl_from_error!(chrono::ParseError, ErrorKind::Parse);
+impl_from_error!(image::ImageError, ErrorKind::Parse);
+impl_from_error!(png::EncodingError, ErrorKind::Other);
+impl_from_error!(image_webp::EncodingError, ErrorKind::Other);
+impl_from_error!(ravif::Error, ErrorKind::Other);
+impl_from_error!(toml::de::Error, ErrorKind::Config);
+impl_from_error!(toml::ser::Error, ErrorKind::Config);
+impl_from_error!(PlaceholderTile, ErrorKind::Network);
+impl_from_error!(PlaceholderFrame, ErrorKind::Network);
+#[cfg(windows)]
+impl_from_error!(windows::core::Error, ErrorKind::Other);
+#[cfg(not(any(windows, target_os = "macos")))]
+impl_from_error!(notify_rust::error::Error, ErrorKind::Other);
@@ -0,0 +1,481 @@
+//! Embeds EXIF metadata in written frames.
+//!
+//! The image encoders can't write EXIF, so a minimal TIFF structure is built by hand and
+//! spliced into the file afterwards: as an APP1 segment in JPEGs, and an eXIf chunk in PNGs.
+//! PNGs also get the capture time and source as text chunks, which more tools show. Other
+//! formats are left without.
+
+use std::path::Path;
+
+use chrono::prelude::*;
+use log::warn;
+
+use crate::encode;
+use crate::error::AppErr;
+
+// TIFF field types
+const BYTE: u16 = 1;
+const ASCII: u16 = 2;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+
+// IFD0 tags, which must be in this order
+const TAG_IMAGE_DESCRIPTION: u16 = 0x010E;
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_GPS_IFD: u16 = 0x8825;
+
+// GPS IFD tags
+const TAG_GPS_VERSION: u16 = 0x0000;
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+const TAG_GPS_ALTITUDE_REF: u16 = 0x0005;
+const TAG_GPS_ALTITUDE: u16 = 0x0006;
+const TAG_GPS_TIMESTAMP: u16 = 0x0007;
+const TAG_GPS_DATESTAMP: u16 = 0x001D;
+
+/// Where the image was taken from
+pub struct Camera {
+    /// The satellite, e.g. "Himawari"
+    pub satellite: &'static str,
+    /// The sub-satellite point
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude_km: f64,
+}
+
+/// What the image shows, and where it came from
+pub struct Capture<'a> {
+    pub timestamp: DateTime<Utc>,
+    /// The image product, e.g. "D531106"
+    pub product: &'a str,
+    /// How many tiles a side the frame was composed of
+    pub level: u32,
+    /// Where the frame's first tile came from, a URL or a path
+    pub source: String,
+}
+
+impl Capture<'_> {
+    fn description(&self, camera: &Camera) -> String {
+        format!(
+            "{} {} at {}, {} tiles a side, from {}",
+            camera.satellite,
+            self.product,
+            self.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            self.level,
+            self.source
+        )
+    }
+}
+
+fn software() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+struct Field {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+fn ascii(tag: u16, value: &str) -> Field {
+    let mut data = value.as_bytes().to_vec();
+    data.push(0);
+    Field {
+        tag,
+        field_type: ASCII,
+        count: data.len() as u32,
+        data,
+    }
+}
+
+fn rationals(tag: u16, values: &[(u32, u32)]) -> Field {
+    let mut data = Vec::new();
+    for (numerator, denominator) in values {
+        data.extend(numerator.to_be_bytes());
+        data.extend(denominator.to_be_bytes());
+    }
+    Field {
+        tag,
+        field_type: RATIONAL,
+        count: values.len() as u32,
+        data,
+    }
+}
+
+fn bytes(tag: u16, values: &[u8]) -> Field {
+    Field {
+        tag,
+        field_type: BYTE,
+        count: values.len() as u32,
+        data: values.to_vec(),
+    }
+}
+
+fn long(tag: u16, value: u32) -> Field {
+    Field {
+        tag,
+        field_type: LONG,
+        count: 1,
+        data: value.to_be_bytes().to_vec(),
+    }
+}
+
+/// Degrees as degrees, minutes and hundredths of seconds
+fn dms(degrees: f64) -> [(u32, u32); 3] {
+    let hundredths = (degrees.abs() * 360_000.0).round() as u32;
+    [
+        (hundredths / 360_000, 1),
+        (hundredths / 6000 % 60, 1),
+        (hundredths % 6000, 100),
+    ]
+}
+
+/// Encodes an IFD starting at `offset` in the TIFF structure, followed by any values
+/// which don't fit in their entries
+fn encode_ifd(fields: &[Field], offset: u32) -> Vec<u8> {
+    let values_offset = offset + 2 + 12 * fields.len() as u32 + 4;
+    let mut entries = Vec::new();
+    let mut values = Vec::new();
+
+    entries.extend((fields.len() as u16).to_be_bytes());
+    for field in fields {
+        entries.extend(field.tag.to_be_bytes());
+        entries.extend(field.field_type.to_be_bytes());
+        entries.extend(field.count.to_be_bytes());
+        if field.data.len() <= 4 {
+            let mut value = field.data.clone();
+            value.resize(4, 0);
+            entries.extend(value);
+        } else {
+            entries.extend((values_offset + values.len() as u32).to_be_bytes());
+            values.extend(&field.data);
+            // Values must start on a word boundary
+            if values.len() % 2 != 0 {
+                values.push(0);
+            }
+        }
+    }
+    // No further IFDs
+    entries.extend(0u32.to_be_bytes());
+
+    entries.extend(values);
+    entries
+}
+
+/// Builds the TIFF structure holding the EXIF metadata
+fn encode_tiff(camera: &Camera, capture: &Capture) -> Vec<u8> {
+    let timestamp = &capture.timestamp;
+    let latitude_ref = if camera.latitude < 0.0 { "S" } else { "N" };
+    let longitude_ref = if camera.longitude < 0.0 { "W" } else { "E" };
+    let altitude_m = (camera.altitude_km * 1000.0).round() as u32;
+    let gps_fields = [
+        bytes(TAG_GPS_VERSION, &[2, 3, 0, 0]),
+        ascii(TAG_GPS_LATITUDE_REF, latitude_ref),
+        rationals(TAG_GPS_LATITUDE, &dms(camera.latitude)),
+        ascii(TAG_GPS_LONGITUDE_REF, longitude_ref),
+        rationals(TAG_GPS_LONGITUDE, &dms(camera.longitude)),
+        // Above sea level
+        bytes(TAG_GPS_ALTITUDE_REF, &[0]),
+        rationals(TAG_GPS_ALTITUDE, &[(altitude_m, 1)]),
+        rationals(
+            TAG_GPS_TIMESTAMP,
+            &[
+                (timestamp.hour(), 1),
+                (timestamp.minute(), 1),
+                (timestamp.second(), 1),
+            ],
+        ),
+        ascii(TAG_GPS_DATESTAMP, &timestamp.format("%Y:%m:%d").to_string()),
+    ];
+
+    // The IFD0 fields don't depend on where the GPS IFD ends up, so its size is known up front
+    let ifd0_fields = |gps_offset| {
+        [
+            ascii(TAG_IMAGE_DESCRIPTION, &capture.description(camera)),
+            ascii(TAG_MAKE, camera.satellite),
+            ascii(TAG_MODEL, camera.satellite),
+            ascii(TAG_SOFTWARE, &software()),
+            ascii(TAG_DATE_TIME, &timestamp.format("%Y:%m:%d %H:%M:%S").to_string()),
+            long(TAG_GPS_IFD, gps_offset),
+        ]
+    };
+    let ifd0_len = encode_ifd(&ifd0_fields(0), 8).len() as u32;
+    let gps_offset = 8 + ifd0_len;
+
+    // Big endian header, then the offset of IFD0
+    let mut tiff = b"MM\0\x2A".to_vec();
+    tiff.extend(8u32.to_be_bytes());
+    tiff.extend(encode_ifd(&ifd0_fields(gps_offset), 8));
+    tiff.extend(encode_ifd(&gps_fields, gps_offset));
+    tiff
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn insert_into_jpeg(image: &[u8], tiff: &[u8]) -> Result<Vec<u8>, AppErr> {
+    if !image.starts_with(&[0xFF, 0xD8]) {
+        return Err(AppErr::new("Not a JPEG file"));
+    }
+    // Keep a JFIF APP0 segment first, where readers expect it
+    let mut position = 2;
+    if image.get(2..4) == Some(&[0xFF, 0xE0]) {
+        let len = match image.get(4..6) {
+            Some(&[high, low]) => u16::from_be_bytes([high, low]) as usize,
+            _ => return Err(AppErr::new("Truncated JPEG file")),
+        };
+        position += 2 + len;
+        if position > image.len() {
+            return Err(AppErr::new("Truncated JPEG file"));
+        }
+    }
+
+    // The segment length covers itself, and can't be more than 16 bits
+    let len = 2 + 6 + tiff.len();
+    if len > u16::MAX as usize {
+        return Err(AppErr::new(format!("EXIF metadata too long for a JPEG file, at {} bytes", len - 2)));
+    }
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend((len as u16).to_be_bytes());
+    segment.extend(b"Exif\0\0");
+    segment.extend(tiff);
+
+    let mut output = image[..position].to_vec();
+    output.extend(segment);
+    output.extend(&image[position..]);
+    Ok(output)
+}
+
+/// A PNG chunk of type `kind` holding `data`
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    let mut body = kind.to_vec();
+    body.extend(data);
+    chunk.extend(&body);
+    chunk.extend(crc32(&body).to_be_bytes());
+    chunk
+}
+
+/// A PNG text chunk, tEXt where the text is ASCII and otherwise iTXt, which is UTF-8
+fn png_text(keyword: &str, text: &str) -> Vec<u8> {
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    if text.is_ascii() {
+        data.extend(text.as_bytes());
+        return png_chunk(b"tEXt", &data);
+    }
+    // Uncompressed, with no language tag or translated keyword
+    data.extend([0, 0, 0, 0]);
+    data.extend(text.as_bytes());
+    png_chunk(b"iTXt", &data)
+}
+
+fn insert_into_png(image: &[u8], tiff: &[u8], camera: &Camera, capture: &Capture) -> Result<Vec<u8>, AppErr> {
+    // The signature, then the IHDR chunk, which must come first
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+    if image.len() < IHDR_END || &image[12..16] != b"IHDR" {
+        return Err(AppErr::new("Not a PNG file"));
+    }
+
+    let mut output = image[..IHDR_END].to_vec();
+    output.extend(png_chunk(b"eXIf", tiff));
+    // Keywords from the PNG specification
+    output.extend(png_text("Title", &format!("{} {}", camera.satellite, capture.product)));
+    output.extend(png_text("Description", &capture.description(camera)));
+    output.extend(png_text("Creation Time", &capture.timestamp.to_rfc2822()));
+    output.extend(png_text("Source", &capture.source));
+    output.extend(png_text("Software", &software()));
+    output.extend(&image[IHDR_END..]);
+    Ok(output)
+}
+
+/// Adds EXIF metadata recording where and when the frame at `path` was taken, and what
+/// it was made from
+pub fn write_exif(path: &Path, camera: &Camera, capture: &Capture) -> Result<(), AppErr> {
+    let image = std::fs::read(path)?;
+    let tiff = encode_tiff(camera, capture);
+    let output = if image.starts_with(b"\x89PNG") {
+        insert_into_png(&image, &tiff, camera, capture)?
+    } else if image.starts_with(b"\xFF\xD8") {
+        insert_into_jpeg(&image, &tiff)?
+    } else {
+        warn!("Not writing EXIF metadata to {}, only JPEG and PNG images have it", path.display());
+        return Ok(());
+    };
+    encode::write_atomically(path, |temp_path| Ok(std::fs::write(temp_path, output)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera {
+            satellite: "Himawari",
+            latitude: 0.0,
+            longitude: 140.7,
+            altitude_km: 35786.0,
+        }
+    }
+
+    fn capture(source: &str) -> Capture<'static> {
+        Capture {
+            timestamp: Utc.ymd(2022, 11, 1).and_hms(3, 20, 0),
+            product: "D531106",
+            level: 4,
+            source: source.to_string(),
+        }
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+    }
+
+    /// The entries of the IFD at `offset` in `tiff`, as (tag, type, count, value or offset)
+    fn ifd_entries(tiff: &[u8], offset: usize) -> Vec<(u16, u16, u32, u32)> {
+        let count = u16_at(tiff, offset) as usize;
+        (0..count)
+            .map(|i| {
+                let entry = offset + 2 + 12 * i;
+                (u16_at(tiff, entry), u16_at(tiff, entry + 2), u32_at(tiff, entry + 4), u32_at(tiff, entry + 8))
+            })
+            .collect()
+    }
+
+    /// The NUL terminated string at `offset` in `tiff`
+    fn ascii_at(tiff: &[u8], offset: usize, count: u32) -> &str {
+        let bytes = &tiff[offset..offset + count as usize];
+        assert_eq!(bytes.last(), Some(&0));
+        std::str::from_utf8(&bytes[..bytes.len() - 1]).unwrap()
+    }
+
+    #[test]
+    fn tiff_offsets_parse_back() {
+        let capture = capture("https://example.com/tile.png");
+        let tiff = encode_tiff(&camera(), &capture);
+        assert_eq!(&tiff[..4], b"MM\0\x2A");
+        assert_eq!(u32_at(&tiff, 4), 8);
+
+        let ifd0 = ifd_entries(&tiff, 8);
+        let tags: Vec<_> = ifd0.iter().map(|entry| entry.0).collect();
+        assert_eq!(
+            tags,
+            [TAG_IMAGE_DESCRIPTION, TAG_MAKE, TAG_MODEL, TAG_SOFTWARE, TAG_DATE_TIME, TAG_GPS_IFD]
+        );
+        // IFD0 is followed by no other IFD
+        assert_eq!(u32_at(&tiff, 8 + 2 + 12 * ifd0.len()), 0);
+
+        let (_, field_type, count, offset) = ifd0[0];
+        assert_eq!(field_type, ASCII);
+        assert_eq!(ascii_at(&tiff, offset as usize, count), capture.description(&camera()));
+        let (_, _, count, offset) = ifd0[4];
+        assert_eq!(ascii_at(&tiff, offset as usize, count), "2022:11:01 03:20:00");
+
+        let (_, field_type, _, gps_offset) = ifd0[5];
+        assert_eq!(field_type, LONG);
+        let gps = ifd_entries(&tiff, gps_offset as usize);
+        assert_eq!(gps.len(), 9);
+        assert_eq!(gps[0], (TAG_GPS_VERSION, BYTE, 4, 0x0203_0000));
+        let (tag, _, count, offset) = gps[3];
+        assert_eq!(tag, TAG_GPS_LONGITUDE_REF);
+        assert_eq!(count, 2);
+        assert_eq!(offset >> 24, b'E' as u32);
+        let (tag, field_type, count, offset) = gps[4];
+        assert_eq!((tag, field_type, count), (TAG_GPS_LONGITUDE, RATIONAL, 3));
+        let offset = offset as usize;
+        assert_eq!((u32_at(&tiff, offset), u32_at(&tiff, offset + 4)), (140, 1));
+        assert_eq!((u32_at(&tiff, offset + 8), u32_at(&tiff, offset + 12)), (42, 1));
+        let (tag, _, count, offset) = gps[8];
+        assert_eq!(tag, TAG_GPS_DATESTAMP);
+        assert_eq!(ascii_at(&tiff, offset as usize, count), "2022:11:01");
+    }
+
+    #[test]
+    fn jpeg_app1_follows_jfif() {
+        let mut image = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        image.extend(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        image.extend([0xFF, 0xD9]);
+        let tiff = encode_tiff(&camera(), &capture("tile.png"));
+
+        let output = insert_into_jpeg(&image, &tiff).unwrap();
+        assert_eq!(&output[..20], &image[..20]);
+        assert_eq!(&output[20..22], &[0xFF, 0xE1]);
+        assert_eq!(u16_at(&output, 22) as usize, 2 + 6 + tiff.len());
+        assert_eq!(&output[24..30], b"Exif\0\0");
+        assert_eq!(&output[30..30 + tiff.len()], &tiff[..]);
+        assert_eq!(&output[30 + tiff.len()..], &[0xFF, 0xD9]);
+    }
+
+    #[test]
+    fn jpeg_app1_without_jfif() {
+        let image = [0xFF, 0xD8, 0xFF, 0xDB, 0xFF, 0xD9];
+        let output = insert_into_jpeg(&image, b"MM\0\x2A").unwrap();
+        let mut expected = vec![0xFF, 0xD8, 0xFF, 0xE1, 0, 12];
+        expected.extend(b"Exif\0\0MM\0\x2A");
+        expected.extend(&image[2..]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn jpeg_truncated_jfif_is_rejected() {
+        assert!(insert_into_jpeg(&[0xFF, 0xD8, 0xFF, 0xE0], b"MM\0\x2A").is_err());
+        assert!(insert_into_jpeg(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00], b"MM\0\x2A").is_err());
+        assert!(insert_into_jpeg(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x00], b"MM\0\x2A").is_err());
+    }
+
+    #[test]
+    fn jpeg_oversized_exif_is_rejected() {
+        let image = [0xFF, 0xD8, 0xFF, 0xD9];
+        assert!(insert_into_jpeg(&image, &[0; 65535 - 8]).is_ok());
+        assert!(insert_into_jpeg(&image, &[0; 65535 - 7]).is_err());
+    }
+
+    #[test]
+    fn png_exif_follows_ihdr() {
+        let mut image = b"\x89PNG\r\n\x1A\n".to_vec();
+        image.extend(png_chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]));
+        let iend = png_chunk(b"IEND", &[]);
+        // The CRC of IEND is the same in every PNG file
+        assert_eq!(&iend[8..], &[0xAE, 0x42, 0x60, 0x82]);
+        image.extend(&iend);
+        let camera = camera();
+        let capture = capture("tile.png");
+        let tiff = encode_tiff(&camera, &capture);
+
+        let output = insert_into_png(&image, &tiff, &camera, &capture).unwrap();
+        assert_eq!(&output[..33], &image[..33]);
+        assert_eq!(u32_at(&output, 33) as usize, tiff.len());
+        assert_eq!(&output[37..41], b"eXIf");
+        assert_eq!(&output[41..41 + tiff.len()], &tiff[..]);
+        let crc = u32_at(&output, 41 + tiff.len());
+        assert_eq!(crc, crc32(&output[37..41 + tiff.len()]));
+        assert_eq!(&output[41 + tiff.len() + 4..][4..8], b"tEXt");
+        assert!(output.ends_with(&iend));
+    }
+
+    #[test]
+    fn png_without_ihdr_is_rejected() {
+        assert!(insert_into_png(b"\x89PNG\r\n\x1A\n", b"MM\0\x2A", &camera(), &capture("tile.png")).is_err());
+    }
+}
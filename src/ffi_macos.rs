@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::process::Command;
+
+use log::{info, warn};
+
+use crate::error::AppErr;
+use crate::messages::tr;
+use crate::size::Size;
+use crate::wallpaper::WallpaperSetter;
+use crate::wallpaper_style::WallpaperStyle;
+
+/// Sets the picture of every desktop, on every display, through System Events.
+/// The path is passed as an argument to the script, so it needs no quoting.
+const SET_DESKTOP_PICTURE: &str = r#"on run argv
+    tell application "System Events"
+        repeat with theDesktop in every desktop
+            set picture of theDesktop to POSIX file (item 1 of argv)
+        end repeat
+    end tell
+end run"#;
+
+/// Sets the picture of every screen through NSWorkspace, which unlike System Events can say
+/// how it is scaled. Takes the path, an NSImageScaling value and whether to allow clipping.
+const SET_DESKTOP_IMAGE: &str = r#"ObjC.import('AppKit');
+function run(argv) {
+    var url = $.NSURL.fileURLWithPath(argv[0]);
+    var options = $.NSMutableDictionary.alloc.init;
+    options.setObjectForKey($(Number(argv[1])), $.NSWorkspaceDesktopImageScalingKey);
+    options.setObjectForKey($(argv[2] === 'true'), $.NSWorkspaceDesktopImageAllowClippingKey);
+    var screens = $.NSScreen.screens;
+    for (var i = 0; i < screens.count; i++) {
+        var screen = screens.objectAtIndex(i);
+        if (!$.NSWorkspace.sharedWorkspace.setDesktopImageURLForScreenOptionsError(url, screen, options, null)) {
+            throw new Error('Failed to set the desktop image');
+        }
+    }
+}"#;
+
+pub fn set_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    info!("Setting macOS desktop picture");
+    let mut command = Command::new("osascript");
+    // Without --wallpaper-style the desktop's own placement is kept
+    match setter.style {
+        Some(style) => {
+            // Proportionally up or down (3), axes independently (1) or none (2). macOS
+            // can't span screens, so fits the image to each instead.
+            let (scaling, clipping) = match style {
+                WallpaperStyle::Fit | WallpaperStyle::Span => ("3", "false"),
+                WallpaperStyle::Fill => ("3", "true"),
+                WallpaperStyle::Stretch => ("1", "false"),
+                WallpaperStyle::Center => ("2", "false"),
+            };
+            command
+                .args(["-l", "JavaScript", "-e", SET_DESKTOP_IMAGE])
+                .arg(image_path)
+                .args([scaling, clipping])
+        }
+        None => command.arg("-e").arg(SET_DESKTOP_PICTURE).arg(image_path),
+    };
+    let status = command.status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("command-failed", command = "osascript", status = status))),
+    }
+}
+
+/// The size in pixels of the main display, from System Information
+pub fn screen_size(_setter: &WallpaperSetter) -> Result<Size, AppErr> {
+    let output = Command::new("system_profiler").arg("SPDisplaysDataType").output()?;
+    if !output.status.success() {
+        let status = output.status;
+        return Err(AppErr::new(tr!("command-failed", command = "system_profiler", status = status)));
+    }
+    // Each display lists its resolution, e.g. "Resolution: 3024 x 1964 Retina",
+    // before whether it is the main one
+    let mut first = None;
+    let mut current = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let line = line.trim();
+        if let Some(resolution) = line.strip_prefix("Resolution:") {
+            current = resolution.replace(' ', "").split_once('x').and_then(|(width, height)| {
+                let height: String = height.chars().take_while(char::is_ascii_digit).collect();
+                Size::try_parse(&format!("{}x{}", width, height))
+            });
+            first = first.or_else(|| current.clone());
+        }
+        if line == "Main Display: Yes" {
+            if let Some(size) = current.take() {
+                return Ok(size);
+            }
+        }
+    }
+    first.ok_or_else(|| AppErr::new(tr!("screen-size-unknown")))
+}
+
+/// Shows a notification from the arguments to the script, so they need no quoting
+const DISPLAY_NOTIFICATION: &str = r#"on run argv
+    display notification (item 2 of argv) with title (item 1 of argv)
+end run"#;
+
+/// Raises a user notification through Notification Center. It can't show an image.
+pub fn show_notification(title: &str, body: &str, _image: Option<&Path>) -> Result<(), AppErr> {
+    let status = Command::new("osascript")
+        .arg("-e")
+        .arg(DISPLAY_NOTIFICATION)
+        .arg(title)
+        .arg(body)
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("command-failed", command = "osascript", status = status))),
+    }
+}
+
+pub fn set_lockscreen(_image_path: &Path) -> Result<(), AppErr> {
+    warn!("{}", tr!("lockscreen-unsupported"));
+    Ok(())
+}
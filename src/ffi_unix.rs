@@ -1,10 +1,435 @@
+use std::ffi::OsStr;
 use std::path::Path;
-use log::warn;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{info, warn};
 
 use crate::error::AppErr;
+use crate::messages::tr;
+use crate::size::Size;
+use crate::wallpaper::WallpaperSetter;
+use crate::wallpaper_backend::WallpaperBackend;
+use crate::wallpaper_style::WallpaperStyle;
+
+/// The placement chosen with --wallpaper-style
+fn style(setter: &WallpaperSetter) -> WallpaperStyle {
+    setter.style.unwrap_or_default()
+}
+
+/// The colour chosen with --background-color, around the wallpaper where it doesn't fill
+/// the screen, as #RRGGBB
+fn background_hex(setter: &WallpaperSetter) -> String {
+    setter.background.to_rgb_hex()
+}
+
+/// The backend chosen with --wallpaper-backend, or else the one for the running desktop
+fn backend(setter: &WallpaperSetter) -> Option<WallpaperBackend> {
+    setter.backend.or_else(detect_backend)
+}
+
+/// The swaybg started for the current wallpaper, replaced with the next
+static SWAYBG: Mutex<Option<Child>> = Mutex::new(None);
+
+/// Detects the running desktop environment from the session's environment variables
+fn detect_backend() -> Option<WallpaperBackend> {
+    let has_var = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+    if has_var("SWAYSOCK") {
+        return Some(WallpaperBackend::Sway);
+    }
+    if has_var("HYPRLAND_INSTANCE_SIGNATURE") {
+        return Some(WallpaperBackend::Hyprpaper);
+    }
+    let desktops = std::env::var("XDG_CURRENT_DESKTOP")
+        .or_else(|_| std::env::var("DESKTOP_SESSION"))
+        .unwrap_or_default();
+    // A colon separated list, e.g. "ubuntu:GNOME"
+    desktops
+        .split(':')
+        .find_map(|desktop| match desktop.to_ascii_lowercase().as_str() {
+            "gnome" | "gnome-classic" | "gnome-flashback" | "ubuntu" | "unity" | "pantheon" => {
+                Some(WallpaperBackend::Gnome)
+            }
+            "kde" | "plasma" => Some(WallpaperBackend::Kde),
+            "xfce" => Some(WallpaperBackend::Xfce),
+            "sway" => Some(WallpaperBackend::Sway),
+            "hyprland" => Some(WallpaperBackend::Hyprpaper),
+            _ => None,
+        })
+}
+
+fn check(program: &str, status: std::process::ExitStatus) -> Result<(), AppErr> {
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("command-failed", command = program, status = status))),
+    }
+}
+
+fn run<I, S>(program: &str, args: I) -> Result<(), AppErr>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let status = Command::new(program).args(args).status()?;
+    check(program, status)
+}
+
+/// Runs `program` and returns what it wrote to stdout
+fn output<I, S>(program: &str, args: I) -> Result<String, AppErr>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new(program).args(args).output()?;
+    check(program, output.status)?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn file_uri(image_path: &Path) -> Result<reqwest::Url, AppErr> {
+    reqwest::Url::from_file_path(image_path)
+        .map_err(|_| AppErr::new(format!("Not an absolute path: {}", image_path.display())))
+}
+
+fn set_gnome_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    info!("Setting GNOME desktop background");
+    let uri = file_uri(image_path)?;
+
+    const SCHEMA: &str = "org.gnome.desktop.background";
+    let options = match style(setter) {
+        WallpaperStyle::Fit => "scaled",
+        WallpaperStyle::Fill => "zoom",
+        WallpaperStyle::Stretch => "stretched",
+        WallpaperStyle::Center => "centered",
+        WallpaperStyle::Span => "spanned",
+    };
+    // Place the image on a solid background, like on Windows
+    run("gsettings", ["set", SCHEMA, "picture-options", options])?;
+    run("gsettings", ["set", SCHEMA, "color-shading-type", "solid"])?;
+    run("gsettings", ["set", SCHEMA, "primary-color", background_hex(setter).as_str()])?;
+    run("gsettings", ["set", SCHEMA, "picture-uri", uri.as_str()])?;
+    // Only GNOME 42 and later have a separate background for the dark style
+    if let Err(app_err) = run("gsettings", ["set", SCHEMA, "picture-uri-dark", uri.as_str()]) {
+        info!("Not setting the dark style background: {}", app_err);
+    }
+    Ok(())
+}
+
+/// Sets the wallpaper of every Plasma desktop, placed on a solid background
+const PLASMA_SCRIPT: &str = r##"
+var image = "IMAGE_URI";
+desktops().forEach(function (desktop) {
+    desktop.wallpaperPlugin = "org.kde.image";
+    desktop.currentConfigGroup = ["Wallpaper", "org.kde.image", "General"];
+    desktop.writeConfig("Image", image);
+    desktop.writeConfig("FillMode", FILL_MODE);
+    desktop.writeConfig("Color", "BACKGROUND_COLOUR");
+});
+"##;
+
+fn set_kde_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    info!("Setting KDE Plasma desktop background");
+    let uri = file_uri(image_path)?;
+    let uri = uri.as_str().replace('\\', "\\\\").replace('"', "\\\"");
+    // Plasma can't span monitors, so fits the image to each instead
+    let fill_mode = match style(setter) {
+        WallpaperStyle::Fit | WallpaperStyle::Span => "1",
+        WallpaperStyle::Fill => "2",
+        WallpaperStyle::Stretch => "0",
+        WallpaperStyle::Center => "6",
+    };
+    let script = PLASMA_SCRIPT
+        .replace("FILL_MODE", fill_mode)
+        .replace("BACKGROUND_COLOUR", &background_hex(setter))
+        .replace("IMAGE_URI", &uri);
+    let args = [
+        "org.kde.plasmashell",
+        "/PlasmaShell",
+        "org.kde.PlasmaShell.evaluateScript",
+        &script,
+    ];
+    // Plasma 6 names its qdbus after the Qt version, as do some distributions
+    for qdbus in ["qdbus6", "qdbus", "qdbus-qt5"] {
+        match run(qdbus, args) {
+            Err(app_err) if is_not_found(&app_err) => continue,
+            result => return result,
+        }
+    }
+    Err(AppErr::new(tr!("program-not-found", program = "qdbus")))
+}
+
+fn is_not_found(app_err: &AppErr) -> bool {
+    use std::error::Error;
+    app_err
+        .source()
+        .and_then(|err| err.downcast_ref::<std::io::Error>())
+        .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound)
+}
+
+fn set_xfce_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    info!("Setting XFCE desktop background");
+    let properties = output("xfconf-query", ["--channel", "xfce4-desktop", "--list"])?;
+    // Each monitor and workspace has its own background, e.g.
+    // /backdrop/screen0/monitorHDMI-1/workspace0/last-image
+    let images = properties
+        .lines()
+        .filter(|property| property.ends_with("/last-image"))
+        .collect::<Vec<_>>();
+    if images.is_empty() {
+        return Err(AppErr::new(tr!("xfce-no-backgrounds")));
+    }
+    // 4 is "Scaled", which fits the image to the screen, and 5 "Zoomed", which fills it
+    let image_style = match style(setter) {
+        WallpaperStyle::Fit => "4",
+        WallpaperStyle::Fill => "5",
+        WallpaperStyle::Stretch => "3",
+        WallpaperStyle::Center => "1",
+        WallpaperStyle::Span => "6",
+    };
+    for image in images {
+        let style = image.replace("/last-image", "/image-style");
+        run("xfconf-query", [
+            OsStr::new("--channel"),
+            OsStr::new("xfce4-desktop"),
+            OsStr::new("--property"),
+            OsStr::new(image),
+            OsStr::new("--set"),
+            image_path.as_os_str(),
+        ])?;
+        run("xfconf-query", [
+            "--channel",
+            "xfce4-desktop",
+            "--property",
+            &style,
+            "--create",
+            "--type",
+            "int",
+            "--set",
+            image_style,
+        ])?;
+    }
+    Ok(())
+}
+
+/// The --mode of sway and swaybg, which can't span outputs, so fit the image to each instead
+fn sway_mode(setter: &WallpaperSetter) -> &'static str {
+    match style(setter) {
+        WallpaperStyle::Fit | WallpaperStyle::Span => "fit",
+        WallpaperStyle::Fill => "fill",
+        WallpaperStyle::Stretch => "stretch",
+        WallpaperStyle::Center => "center",
+    }
+}
+
+fn set_sway_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    info!("Setting sway output background");
+    run("swaymsg", [
+        OsStr::new("output"),
+        OsStr::new("*"),
+        OsStr::new("bg"),
+        image_path.as_os_str(),
+        OsStr::new(sway_mode(setter)),
+        OsStr::new(&background_hex(setter)),
+    ])
+}
+
+fn swaybg_pid_path() -> std::path::PathBuf {
+    std::env::temp_dir().join("himawari-desktop-updater-swaybg.pid")
+}
+
+/// Starts swaybg showing the image, then stops the swaybg showing the previous one
+fn set_swaybg_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    info!("Starting swaybg");
+    let child = Command::new("swaybg")
+        .args(["--mode", sway_mode(setter), "--color", &background_hex(setter)[1..], "--image"])
+        .arg(image_path)
+        // It outlives the update, so don't tie it to the updater's output
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let pid = child.id();
+
+    let mut current = SWAYBG.lock().unwrap();
+    match current.replace(child) {
+        Some(mut previous) => {
+            let _ = previous.kill();
+            let _ = previous.wait();
+        }
+        // The previous swaybg may have been started by an earlier run
+        None => {
+            if let Ok(previous) = std::fs::read_to_string(swaybg_pid_path()) {
+                let _ = Command::new("kill").arg(previous.trim()).status();
+            }
+        }
+    }
+    if let Err(err) = std::fs::write(swaybg_pid_path(), pid.to_string()) {
+        warn!("Failed to note the swaybg process: {}", err);
+    }
+    Ok(())
+}
+
+fn set_swww_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    // Start the daemon if it isn't running yet
+    if run("swww", ["query"]).is_err() {
+        info!("Starting swww-daemon");
+        Command::new("swww-daemon")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    info!("Setting swww wallpaper");
+    // swww can neither stretch nor span, so fits the image instead
+    let resize = match style(setter) {
+        WallpaperStyle::Fit | WallpaperStyle::Stretch | WallpaperStyle::Span => "fit",
+        WallpaperStyle::Fill => "crop",
+        WallpaperStyle::Center => "no",
+    };
+    run("swww", [
+        OsStr::new("img"),
+        OsStr::new("--resize"),
+        OsStr::new(resize),
+        OsStr::new("--fill-color"),
+        OsStr::new("000000"),
+        image_path.as_os_str(),
+    ])
+}
+
+fn set_hyprpaper_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    info!("Setting hyprpaper wallpaper");
+    let path = image_path.to_string_lossy();
+    run("hyprctl", ["hyprpaper", "preload", &path])?;
+    // hyprpaper covers the screen unless told to fit the image
+    let mode = match setter.style {
+        Some(WallpaperStyle::Fit) => "contain:",
+        _ => "",
+    };
+    // An empty monitor name sets the wallpaper of every monitor
+    run("hyprctl", ["hyprpaper", "wallpaper", &format!(",{}{}", mode, path)])?;
+    // Free the previous wallpapers
+    run("hyprctl", ["hyprpaper", "unload", "unused"])
+}
+
+pub fn set_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    match backend(setter) {
+        Some(WallpaperBackend::Gnome) => set_gnome_wallpaper(image_path, setter),
+        Some(WallpaperBackend::Kde) => set_kde_wallpaper(image_path, setter),
+        Some(WallpaperBackend::Xfce) => set_xfce_wallpaper(image_path, setter),
+        Some(WallpaperBackend::Sway) => set_sway_wallpaper(image_path, setter),
+        Some(WallpaperBackend::Swaybg) => set_swaybg_wallpaper(image_path, setter),
+        Some(WallpaperBackend::Swww) => set_swww_wallpaper(image_path, setter),
+        Some(WallpaperBackend::Hyprpaper) => set_hyprpaper_wallpaper(image_path, setter),
+        None => {
+            warn!("{}", tr!("wallpaper-no-backend"));
+            Ok(())
+        }
+    }
+}
+
+#[derive(serde_derive::Deserialize)]
+struct SwayOutput {
+    active: bool,
+    focused: bool,
+    current_mode: Option<SwayMode>,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct SwayMode {
+    width: u32,
+    height: u32,
+}
+
+#[derive(serde_derive::Deserialize)]
+struct HyprlandMonitor {
+    width: u32,
+    height: u32,
+    focused: bool,
+}
+
+/// The focused output of sway, or else the first
+fn sway_screen_size() -> Result<Option<Size>, AppErr> {
+    let outputs: Vec<SwayOutput> = serde_json::from_str(&output("swaymsg", ["-t", "get_outputs", "--raw"])?)?;
+    let active: Vec<_> = outputs.iter().filter(|output| output.active).collect();
+    let output = active.iter().find(|output| output.focused).or_else(|| active.first());
+    Ok(output
+        .and_then(|output| output.current_mode.as_ref())
+        .map(|mode| Size {
+            width: mode.width,
+            height: mode.height,
+        }))
+}
+
+/// The focused monitor of Hyprland, or else the first
+fn hyprland_screen_size() -> Result<Option<Size>, AppErr> {
+    let monitors: Vec<HyprlandMonitor> = serde_json::from_str(&output("hyprctl", ["monitors", "-j"])?)?;
+    let monitor = monitors
+        .iter()
+        .find(|monitor| monitor.focused)
+        .or_else(|| monitors.first());
+    Ok(monitor.map(|monitor| Size {
+        width: monitor.width,
+        height: monitor.height,
+    }))
+}
+
+/// The primary output in xrandr, or else the first connected one, e.g. from
+/// "DP-1 connected primary 2560x1440+0+0 (normal left inverted right) 597mm x 336mm"
+fn xrandr_screen_size() -> Result<Option<Size>, AppErr> {
+    let outputs = output("xrandr", ["--query"])?;
+    let sizes: Vec<(bool, Size)> = outputs
+        .lines()
+        .filter(|line| line.contains(" connected"))
+        .filter_map(|line| {
+            let primary = line.contains(" primary ");
+            let geometry = line.split_whitespace().find(|word| word.contains('+'))?;
+            let size = geometry.split('+').next()?;
+            Some((primary, Size::try_parse(size)?))
+        })
+        .collect();
+    let size = sizes.iter().find(|(primary, _)| *primary).or_else(|| sizes.first());
+    Ok(size.map(|(_, size)| size.clone()))
+}
+
+type ScreenSizeDetector = fn() -> Result<Option<Size>, AppErr>;
+
+/// The size in pixels of the primary screen: asked of the Wayland compositor for sway and
+/// Hyprland, and otherwise of X (which also covers other compositors, through XWayland)
+pub fn screen_size(setter: &WallpaperSetter) -> Result<Size, AppErr> {
+    let detectors: [ScreenSizeDetector; 3] =
+        match backend(setter) {
+            Some(WallpaperBackend::Sway) | Some(WallpaperBackend::Swaybg) => {
+                [sway_screen_size, xrandr_screen_size, hyprland_screen_size]
+            }
+            Some(WallpaperBackend::Hyprpaper) => {
+                [hyprland_screen_size, xrandr_screen_size, sway_screen_size]
+            }
+            _ => [xrandr_screen_size, sway_screen_size, hyprland_screen_size],
+        };
+    for detect in detectors {
+        match detect() {
+            Ok(Some(size)) => return Ok(size),
+            Ok(None) => {}
+            Err(app_err) => info!("{}", app_err),
+        }
+    }
+    Err(AppErr::new(tr!("screen-size-unknown")))
+}
+
+pub fn set_lockscreen(_image_path: &Path) -> Result<(), AppErr> {
+    warn!("{}", tr!("lockscreen-unsupported"));
+    Ok(())
+}
 
-pub fn set_wallpaper(_image_path: &Path) -> Result<(), AppErr> {
-    // TODO: Linux/OSX versions of set_wallpaper?
-    warn!("Setting the wallpaper is not supported on this platform");
+/// Raises a notification through the desktop's notification server, over D-Bus
+pub fn show_notification(title: &str, body: &str, image: Option<&Path>) -> Result<(), AppErr> {
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .appname("Himawari Desktop Updater")
+        .summary(title)
+        .body(body);
+    if let Some(image) = image {
+        notification.image_path(&image.display().to_string());
+    }
+    notification.show()?;
     Ok(())
 }
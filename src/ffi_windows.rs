@@ -1,8 +1,132 @@
 use crate::error::AppErr;
-use log::info;
+use crate::messages::tr;
+use crate::monitor::Monitor;
+use crate::orientation::Orientation;
+use crate::size::Size;
+use crate::wallpaper::WallpaperSetter;
+use crate::wallpaper_style::WallpaperStyle;
+use log::{info, warn};
 use std::path::Path;
+use std::ptr::null_mut;
+use std::time::Duration;
+
+use winapi::shared::winerror::{FAILED, HRESULT, SUCCEEDED};
+use winapi::um::shobjidl_core::IDesktopWallpaper;
+
+/// The placement of the wallpaper chosen with --wallpaper-style, which --span always spans
+fn style(setter: &WallpaperSetter, monitor: Monitor) -> WallpaperStyle {
+    match monitor {
+        Monitor::Span => WallpaperStyle::Span,
+        Monitor::All | Monitor::One(_) => setter.style.unwrap_or_default(),
+    }
+}
+
+/// The colour chosen with --background-color, around the wallpaper where it doesn't fill
+/// the screen, as a COLORREF, 0x00BBGGRR
+fn background_colorref(setter: &WallpaperSetter) -> u32 {
+    let [r, g, b, _] = setter.background.0 .0;
+    (r as u32) | (g as u32) << 8 | (b as u32) << 16
+}
+
+fn check(call: &str, hr: HRESULT) -> Result<(), AppErr> {
+    match FAILED(hr) {
+        true => Err(AppErr::new(format!("{} failed: 0x{:08X}", call, hr))),
+        false => Ok(()),
+    }
+}
+
+/// Runs `action` with the shell's IDesktopWallpaper, which Windows 8 and later have
+fn with_desktop_wallpaper<T, F>(action: F) -> Result<T, AppErr>
+where
+    F: FnOnce(&IDesktopWallpaper) -> Result<T, AppErr>,
+{
+    use winapi::um::combaseapi::{CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_ALL};
+    use winapi::um::objbase::COINIT_APARTMENTTHREADED;
+    use winapi::um::shobjidl_core::CLSID_DesktopWallpaper;
+    use winapi::Interface;
+
+    unsafe {
+        let initialized = SUCCEEDED(CoInitializeEx(null_mut(), COINIT_APARTMENTTHREADED));
+        let mut wallpaper: *mut IDesktopWallpaper = null_mut();
+        let hr = CoCreateInstance(
+            &CLSID_DesktopWallpaper,
+            null_mut(),
+            CLSCTX_ALL,
+            &IDesktopWallpaper::uuidof(),
+            &mut wallpaper as *mut *mut IDesktopWallpaper as *mut _,
+        );
+        let result = match check("CoCreateInstance(DesktopWallpaper)", hr) {
+            Ok(()) => {
+                let result = action(&*wallpaper);
+                (*wallpaper).Release();
+                result
+            }
+            Err(app_err) => Err(app_err),
+        };
+        if initialized {
+            CoUninitialize();
+        }
+        result
+    }
+}
+
+/// The device path IDesktopWallpaper knows monitor number `number` by, counting from 1
+fn monitor_id(wallpaper: &IDesktopWallpaper, number: u32) -> Result<Vec<u16>, AppErr> {
+    use winapi::um::combaseapi::CoTaskMemFree;
+    use winapi::um::winnt::LPWSTR;
+
+    unsafe {
+        let mut count = 0;
+        check("GetMonitorDevicePathCount", wallpaper.GetMonitorDevicePathCount(&mut count))?;
+        if number == 0 || number > count {
+            return Err(AppErr::new(tr!("monitor-not-found", monitor = number, count = count)));
+        }
+        let mut path: LPWSTR = null_mut();
+        check("GetMonitorDevicePathAt", wallpaper.GetMonitorDevicePathAt(number - 1, &mut path))?;
+        let mut id = Vec::new();
+        while *path.add(id.len()) != 0 {
+            id.push(*path.add(id.len()));
+        }
+        id.push(0);
+        CoTaskMemFree(path as *mut _);
+        Ok(id)
+    }
+}
+
+/// Sets the background colour and placement of the wallpaper through IDesktopWallpaper
+unsafe fn set_placement(wallpaper: &IDesktopWallpaper, setter: &WallpaperSetter, monitor: Monitor) -> Result<(), AppErr> {
+    use winapi::um::shobjidl_core::{DWPOS_CENTER, DWPOS_FILL, DWPOS_FIT, DWPOS_SPAN, DWPOS_STRETCH};
+
+    check("SetBackgroundColor", wallpaper.SetBackgroundColor(background_colorref(setter)))?;
+    let position = match style(setter, monitor) {
+        WallpaperStyle::Fit => DWPOS_FIT,
+        WallpaperStyle::Fill => DWPOS_FILL,
+        WallpaperStyle::Stretch => DWPOS_STRETCH,
+        WallpaperStyle::Center => DWPOS_CENTER,
+        WallpaperStyle::Span => DWPOS_SPAN,
+    };
+    check("SetPosition", wallpaper.SetPosition(position))
+}
+
+/// Sets the wallpaper through IDesktopWallpaper, on the monitors chosen
+fn set_desktop_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    let monitor = setter.monitor;
+    let image_path = os_str_to_wchar(image_path.as_os_str());
+    with_desktop_wallpaper(|wallpaper| unsafe {
+        set_placement(wallpaper, setter, monitor)?;
+        // No monitor sets the wallpaper of every monitor
+        let id = match monitor {
+            Monitor::One(number) => Some(monitor_id(wallpaper, number)?),
+            Monitor::All | Monitor::Span => None,
+        };
+        let id_ptr = id.as_ref().map_or(std::ptr::null(), |id| id.as_ptr());
+        check("SetWallpaper", wallpaper.SetWallpaper(id_ptr, image_path.as_ptr()))
+    })
+}
+
+pub fn set_wallpaper(image_path: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    let monitor = setter.monitor;
 
-pub fn set_wallpaper(image_path: &Path) -> Result<(), AppErr> {
     // Set registry flags to control wallpaper style
     info!("Setting Windows desktop wallpaper registry keys");
 
@@ -11,35 +135,304 @@ pub fn set_wallpaper(image_path: &Path) -> Result<(), AppErr> {
 
     let hkcu = RegKey::predef(HKEY_CURRENT_USER);
     let key_colors = hkcu.open_subkey_with_flags("Control Panel\\Colors", KEY_WRITE)?;
-    key_colors.set_value("Background", &"0 0 0")?;
+    let [r, g, b, _] = setter.background.0 .0;
+    key_colors.set_value("Background", &format!("{} {} {}", r, g, b))?;
     let key_desktop = hkcu.open_subkey_with_flags("Control Panel\\Desktop", KEY_WRITE)?;
-    key_desktop.set_value("Wallpaper", &image_path.as_os_str())?;
-    key_desktop.set_value("WallpaperStyle", &"6")?;
+    if let Monitor::All | Monitor::Span = monitor {
+        key_desktop.set_value("Wallpaper", &image_path.as_os_str())?;
+    }
+    let style = match style(setter, monitor) {
+        WallpaperStyle::Fit => "6",
+        WallpaperStyle::Fill => "10",
+        WallpaperStyle::Stretch => "2",
+        WallpaperStyle::Center => "0",
+        WallpaperStyle::Span => "22",
+    };
+    key_desktop.set_value("WallpaperStyle", &style)?;
     key_desktop.set_value("TileWallpaper", &"0")?;
 
-    // Also set wallpaper and fill color through user32 API
-    info!("Setting Windows desktop wallpaper");
+    // Also set wallpaper and fill color through the shell
+    info!("Setting Windows desktop wallpaper ({})", monitor);
 
     use winapi::um::winnt::PVOID;
     use winapi::um::winuser::{
         SetSysColors, SystemParametersInfoW, COLOR_BACKGROUND, SPI_SETDESKWALLPAPER,
     };
 
-    // Background fill (black)
+    // Background fill
     unsafe {
-        SetSysColors(1, [COLOR_BACKGROUND].as_ptr(), [0, 0, 0].as_ptr());
+        SetSysColors(1, [COLOR_BACKGROUND].as_ptr(), [background_colorref(setter)].as_ptr());
     }
 
-    // Desktop wallpaper
-    unsafe {
-        let image_path = os_str_to_wchar(image_path.as_os_str());
-        SystemParametersInfoW(SPI_SETDESKWALLPAPER, 0, image_path.as_ptr() as PVOID, 0);
+    // Desktop wallpaper. Before Windows 8 there is only the one, set through user32.
+    match set_desktop_wallpaper(image_path, setter) {
+        Ok(()) => {}
+        Err(app_err) if monitor == Monitor::All => {
+            warn!("Failed to set the wallpaper through the shell: {}", app_err);
+            unsafe {
+                let image_path = os_str_to_wchar(image_path.as_os_str());
+                SystemParametersInfoW(SPI_SETDESKWALLPAPER, 0, image_path.as_ptr() as PVOID, 0);
+            }
+        }
+        Err(app_err) => return Err(app_err),
+    }
+
+    Ok(())
+}
+
+/// Sets `landscape` as the wallpaper of each monitor wider than it is tall, and `portrait` as
+/// that of each of the others, through IDesktopWallpaper
+pub fn set_wallpaper_by_orientation(landscape: &Path, portrait: &Path, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    use winapi::shared::windef::RECT;
+
+    info!("Setting Windows desktop wallpaper of each monitor by its orientation");
+
+    let landscape = os_str_to_wchar(landscape.as_os_str());
+    let portrait = os_str_to_wchar(portrait.as_os_str());
+    with_desktop_wallpaper(|wallpaper| unsafe {
+        set_placement(wallpaper, setter, Monitor::All)?;
+        let mut count = 0;
+        check("GetMonitorDevicePathCount", wallpaper.GetMonitorDevicePathCount(&mut count))?;
+        for number in 1..=count {
+            let id = monitor_id(wallpaper, number)?;
+            let mut rect: RECT = std::mem::zeroed();
+            // A monitor which isn't connected has no rectangle, and needs no wallpaper
+            if FAILED(wallpaper.GetMonitorRECT(id.as_ptr(), &mut rect)) {
+                continue;
+            }
+            let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+            let image_path = match Orientation::of(width.max(0) as u32, height.max(0) as u32) {
+                Orientation::Landscape => &landscape,
+                Orientation::Portrait => &portrait,
+            };
+            check("SetWallpaper", wallpaper.SetWallpaper(id.as_ptr(), image_path.as_ptr()))?;
+        }
+        Ok(())
+    })
+}
+
+/// Points the desktop slideshow at the images in `dir`, shown in order of their names and
+/// changing every `interval`. The shell saves this in the current theme, as choosing a
+/// slideshow folder in the Settings app does.
+pub fn set_slideshow(dir: &Path, interval: Duration, setter: &WallpaperSetter) -> Result<(), AppErr> {
+    use winapi::um::shobjidl_core::{
+        IShellItem, IShellItemArray, SHCreateItemFromParsingName, SHCreateShellItemArrayFromShellItem,
+    };
+    use winapi::Interface;
+
+    info!("Setting Windows desktop slideshow to {}", dir.display());
+
+    // The shell only takes absolute paths
+    let dir = std::env::current_dir()?.join(dir);
+    let dir = os_str_to_wchar(dir.as_os_str());
+    with_desktop_wallpaper(|wallpaper| unsafe {
+        // The slideshow is always shown on every monitor
+        set_placement(wallpaper, setter, Monitor::All)?;
+        let mut folder: *mut IShellItem = null_mut();
+        check(
+            "SHCreateItemFromParsingName",
+            SHCreateItemFromParsingName(dir.as_ptr(), null_mut(), &IShellItem::uuidof(), &mut folder as *mut *mut IShellItem as *mut _),
+        )?;
+        let mut items: *mut IShellItemArray = null_mut();
+        let hr = SHCreateShellItemArrayFromShellItem(folder, &IShellItemArray::uuidof(), &mut items as *mut *mut IShellItemArray as *mut _);
+        (*folder).Release();
+        check("SHCreateShellItemArrayFromShellItem", hr)?;
+        let hr = wallpaper.SetSlideshow(items);
+        (*items).Release();
+        check("SetSlideshow", hr)?;
+        // No options means in order rather than shuffled, which plays the frames forwards
+        check("SetSlideshowOptions", wallpaper.SetSlideshowOptions(0, interval.as_millis() as u32))
+    })
+}
+
+/// Sets the lock screen image of the current user through the WinRT LockScreen API, or
+/// failing that through the PersonalizationCSP policy keys.
+pub fn set_lockscreen(image_path: &Path) -> Result<(), AppErr> {
+    match set_user_lockscreen(image_path) {
+        Ok(()) => Ok(()),
+        Err(app_err) => {
+            info!("Failed to set the lock screen image for the current user: {}", app_err);
+            set_lockscreen_policy(image_path)
+        }
     }
+}
+
+/// Sets the lock screen image of the current user, which needs no administrator rights
+fn set_user_lockscreen(image_path: &Path) -> Result<(), AppErr> {
+    info!("Setting Windows lock screen image");
 
+    use windows::core::HSTRING;
+    use windows::Storage::StorageFile;
+    use windows::System::UserProfile::LockScreen;
+
+    // StorageFile only takes absolute paths
+    let image_path = std::env::current_dir()?.join(image_path);
+    let file = StorageFile::GetFileFromPathAsync(&HSTRING::from(image_path.as_os_str()))?.get()?;
+    LockScreen::SetImageFileAsync(&file)?.get()?;
     Ok(())
 }
 
-fn os_str_to_wchar(oss: &std::ffi::OsStr) -> Vec<u16> {
+/// Sets the lock screen image of every user through the PersonalizationCSP policy keys.
+/// NOTE: These live under HKEY_LOCAL_MACHINE, so this needs to run as an administrator.
+fn set_lockscreen_policy(image_path: &Path) -> Result<(), AppErr> {
+    info!("Setting Windows lock screen image registry keys");
+
+    use winreg::enums::HKEY_LOCAL_MACHINE;
+    use winreg::RegKey;
+
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let (key_csp, _) =
+        hklm.create_subkey("SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\PersonalizationCSP")?;
+    key_csp.set_value("LockScreenImagePath", &image_path.as_os_str())?;
+    key_csp.set_value("LockScreenImageUrl", &image_path.as_os_str())?;
+    key_csp.set_value("LockScreenImageStatus", &1u32)?;
+
+    Ok(())
+}
+
+/// The AppUserModelID notifications are raised under, which Windows shows the name of
+const APP_USER_MODEL_ID: &str = "Himawari.DesktopUpdater";
+
+/// Registers the AppUserModelID with a display name, which an unpackaged application needs
+/// before Windows will show its notifications
+fn register_app_user_model_id() -> Result<(), AppErr> {
+    use winreg::enums::HKEY_CURRENT_USER;
+    use winreg::RegKey;
+
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    let path = format!("Software\\Classes\\AppUserModelId\\{}", APP_USER_MODEL_ID);
+    let (key, _) = hkcu.create_subkey(path)?;
+    key.set_value("DisplayName", &"Himawari Desktop Updater")?;
+    Ok(())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Raises a toast notification, with an image if given
+pub fn show_notification(title: &str, body: &str, image: Option<&Path>) -> Result<(), AppErr> {
+    use windows::core::HSTRING;
+    use windows::Data::Xml::Dom::XmlDocument;
+    use windows::UI::Notifications::{ToastNotification, ToastNotificationManager};
+
+    register_app_user_model_id()?;
+
+    let image = match image {
+        Some(path) => format!(
+            r#"<image src="file:///{}"/>"#,
+            escape_xml(&path.display().to_string().replace('\\', "/"))
+        ),
+        None => String::new(),
+    };
+    let xml = format!(
+        r#"<toast><visual><binding template="ToastGeneric"><text>{}</text><text>{}</text>{}</binding></visual></toast>"#,
+        escape_xml(title),
+        escape_xml(body),
+        image
+    );
+
+    let document = XmlDocument::new()?;
+    document.LoadXml(&HSTRING::from(xml))?;
+    let toast = ToastNotification::CreateToastNotification(&document)?;
+    let notifier = ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(APP_USER_MODEL_ID))?;
+    notifier.Show(&toast)?;
+    Ok(())
+}
+
+/// The size in pixels of the monitor the wallpaper is set on: the primary monitor, unless
+/// --monitor chose another, or the whole desktop with --span
+pub fn screen_size(setter: &WallpaperSetter) -> Result<Size, AppErr> {
+    use winapi::um::winuser::SetProcessDPIAware;
+
+    // Otherwise a scaled display reports its size in scaled pixels
+    unsafe {
+        SetProcessDPIAware();
+    }
+    let size = match setter.monitor {
+        Monitor::All => primary_monitor_size(),
+        Monitor::One(number) => monitor_size(number)?,
+        Monitor::Span => virtual_desktop_size(),
+    };
+    match size {
+        Some((width, height)) if width > 0 && height > 0 => Ok(Size {
+            width: width as u32,
+            height: height as u32,
+        }),
+        _ => Err(AppErr::new(tr!("screen-size-unknown"))),
+    }
+}
+
+fn monitor_size(number: u32) -> Result<Option<(i32, i32)>, AppErr> {
+    use winapi::shared::windef::RECT;
+
+    with_desktop_wallpaper(|wallpaper| unsafe {
+        let id = monitor_id(wallpaper, number)?;
+        let mut rect: RECT = std::mem::zeroed();
+        check("GetMonitorRECT", wallpaper.GetMonitorRECT(id.as_ptr(), &mut rect))?;
+        Ok(Some((rect.right - rect.left, rect.bottom - rect.top)))
+    })
+}
+
+/// The bounds of every monitor together, which a spanned wallpaper covers
+fn virtual_desktop_size() -> Option<(i32, i32)> {
+    use winapi::um::winuser::{GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN};
+
+    unsafe {
+        Some((
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+        ))
+    }
+}
+
+fn primary_monitor_size() -> Option<(i32, i32)> {
+    use std::ptr::null;
+    use winapi::shared::minwindef::{BOOL, FALSE, LPARAM, TRUE};
+    use winapi::shared::windef::{HDC, HMONITOR, LPRECT};
+    use winapi::um::winuser::{EnumDisplayMonitors, GetMonitorInfoW, MONITORINFO, MONITORINFOF_PRIMARY};
+
+    unsafe extern "system" fn find_primary(
+        monitor: HMONITOR,
+        _: HDC,
+        _: LPRECT,
+        data: LPARAM,
+    ) -> BOOL {
+        let mut info: MONITORINFO = std::mem::zeroed();
+        info.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
+        if GetMonitorInfoW(monitor, &mut info) == 0 || info.dwFlags & MONITORINFOF_PRIMARY == 0 {
+            return TRUE;
+        }
+        let rect = info.rcMonitor;
+        *(data as *mut Option<(i32, i32)>) = Some((rect.right - rect.left, rect.bottom - rect.top));
+        FALSE
+    }
+
+    let mut size: Option<(i32, i32)> = None;
+    unsafe {
+        EnumDisplayMonitors(null_mut(), null(), Some(find_primary), &mut size as *mut _ as LPARAM);
+    }
+    size
+}
+
+/// The user's locale name, e.g. "ja-JP"
+pub fn user_locale() -> Option<String> {
+    use winapi::um::winnls::GetUserDefaultLocaleName;
+    use winapi::um::winnt::LOCALE_NAME_MAX_LENGTH;
+
+    let mut name = [0u16; LOCALE_NAME_MAX_LENGTH];
+    let len = unsafe { GetUserDefaultLocaleName(name.as_mut_ptr(), name.len() as i32) };
+    match len {
+        // The length includes the terminating null
+        0 => None,
+        len => Some(String::from_utf16_lossy(&name[..len as usize - 1])),
+    }
+}
+
+pub fn os_str_to_wchar(oss: &std::ffi::OsStr) -> Vec<u16> {
     use std::iter::once;
     use std::os::windows::ffi::OsStrExt;
     // NUL-terminated unicode string
@@ -0,0 +1,127 @@
+use chrono::prelude::*;
+
+use crate::product::FRAME_INTERVAL_MINUTES;
+
+/// Formats accepted for times without an offset, which are taken to be UTC
+const NAIVE_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+];
+
+#[derive(Clone)]
+pub struct FrameTimeValueParser;
+
+impl clap::builder::TypedValueParser for FrameTimeValueParser {
+    type Value = DateTime<Utc>;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match try_parse(value.to_string_lossy().as_ref()) {
+            Some(time) => Ok(time),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Invalid time, use RFC 3339 or \"YYYY-MM-DD HH:MM\" in UTC")),
+        }
+    }
+}
+
+/// Parses a calendar date, e.g. "2022-11-01"
+#[derive(Clone)]
+pub struct DateValueParser;
+
+impl clap::builder::TypedValueParser for DateValueParser {
+    type Value = NaiveDate;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match NaiveDate::parse_from_str(value.to_string_lossy().trim(), "%Y-%m-%d") {
+            Ok(date) => Ok(date),
+            Err(_) => Err(Error::raw(ErrorKind::InvalidValue, "Invalid date, use YYYY-MM-DD")),
+        }
+    }
+}
+
+fn try_parse(input: &str) -> Option<DateTime<Utc>> {
+    let input = input.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Some(time.with_timezone(&Utc));
+    }
+    NAIVE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        .map(|time| DateTime::from_utc(time, Utc))
+}
+
+const INTERVAL_SECS: i64 = FRAME_INTERVAL_MINUTES * 60;
+
+/// The frame time nearest to `time`
+pub fn snap(time: &DateTime<Utc>) -> DateTime<Utc> {
+    let secs = (time.timestamp() + INTERVAL_SECS / 2).div_euclid(INTERVAL_SECS) * INTERVAL_SECS;
+    Utc.timestamp(secs, 0)
+}
+
+/// The time of the last frame captured at or before `time`
+pub fn floor(time: &DateTime<Utc>) -> DateTime<Utc> {
+    Utc.timestamp(time.timestamp().div_euclid(INTERVAL_SECS) * INTERVAL_SECS, 0)
+}
+
+/// The time of the first frame captured at or after `time`
+pub fn ceil(time: &DateTime<Utc>) -> DateTime<Utc> {
+    let frame = floor(time);
+    match frame < *time {
+        true => frame + chrono::Duration::seconds(INTERVAL_SECS),
+        false => frame,
+    }
+}
+
+/// True if a frame was captured at exactly `time`
+pub fn is_frame_time(time: &DateTime<Utc>) -> bool {
+    snap(time) == *time
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.ymd(2022, 11, 1).and_hms(hour, minute, second)
+    }
+
+    #[test]
+    fn snap_to_nearest() {
+        assert_eq!(snap(&at(3, 20, 0)), at(3, 20, 0));
+        assert_eq!(snap(&at(3, 24, 59)), at(3, 20, 0));
+        assert_eq!(snap(&at(3, 25, 0)), at(3, 30, 0));
+        assert_eq!(snap(&at(23, 55, 0)), Utc.ymd(2022, 11, 2).and_hms(0, 0, 0));
+        assert_eq!(snap(&Utc.ymd(1969, 12, 31).and_hms(23, 56, 0)), Utc.timestamp(0, 0));
+    }
+
+    #[test]
+    fn floor_to_earlier() {
+        assert_eq!(floor(&at(3, 20, 0)), at(3, 20, 0));
+        assert_eq!(floor(&at(3, 29, 59)), at(3, 20, 0));
+        assert_eq!(floor(&Utc.ymd(1969, 12, 31).and_hms(23, 56, 0)), Utc.ymd(1969, 12, 31).and_hms(23, 50, 0));
+    }
+
+    #[test]
+    fn ceil_to_later() {
+        assert_eq!(ceil(&at(3, 20, 0)), at(3, 20, 0));
+        assert_eq!(ceil(&at(3, 20, 1)), at(3, 30, 0));
+        assert_eq!(ceil(&at(23, 55, 0)), Utc.ymd(2022, 11, 2).and_hms(0, 0, 0));
+    }
+
+    #[test]
+    fn frame_times() {
+        assert!(is_frame_time(&at(3, 20, 0)));
+        assert!(!is_frame_time(&at(3, 20, 1)));
+        assert!(!is_frame_time(&at(3, 25, 0)));
+    }
+
+    #[test]
+    fn parse_times() {
+        assert_eq!(try_parse("2022-11-01T12:20:00+09:00"), Some(at(3, 20, 0)));
+        assert_eq!(try_parse("2022-11-01T03:20:00Z"), Some(at(3, 20, 0)));
+        assert_eq!(try_parse("2022-11-01 03:20"), Some(at(3, 20, 0)));
+        assert_eq!(try_parse(" 2022-11-01T03:20:30 "), Some(at(3, 20, 30)));
+        assert_eq!(try_parse("2022-11-01"), None);
+        assert_eq!(try_parse("yesterday"), None);
+    }
+}
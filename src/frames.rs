@@ -0,0 +1,211 @@
+//! Listing the frames available from a tile source, to browse imagery before fetching any.
+//!
+//! A `FrameStream` yields a `FrameHandle` for every frame time in a range, newest or oldest
+//! first, without touching the network. Each handle downloads its frame only when asked.
+
+use chrono::prelude::*;
+use image::RgbaImage;
+
+use crate::breaker::RetryOptions;
+use crate::colour::Colour;
+use crate::composite::{download_composite, download_composite_reduced, download_composite_streamed, Stitched, Streamed};
+use crate::error::AppErr;
+use crate::frame_time;
+use crate::margins::Margins;
+use crate::metrics::Metrics;
+use crate::product::{Product, FRAME_INTERVAL_MINUTES};
+use crate::tiles::ImageSource;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    NewestFirst,
+    OldestFirst,
+}
+
+pub struct StreamOptions {
+    pub product: Product,
+    /// The level each frame is fetched at: 4, 8, 16 or 20
+    pub level: u32,
+    /// The oldest frame to yield. Needed when listing oldest first.
+    pub from: Option<DateTime<Utc>>,
+    /// The newest frame to yield, by default the latest one available
+    pub to: Option<DateTime<Utc>>,
+    pub order: Order,
+    /// How the tiles of each frame are retried, and how many are needed
+    pub retry: RetryOptions,
+}
+
+/// A frame which can be fetched from its source
+pub struct FrameHandle<'a> {
+    pub timestamp: DateTime<Utc>,
+    pub product: Product,
+    pub level: u32,
+    source: &'a dyn ImageSource,
+    /// What the image is filled with before the tiles are copied in
+    background: Colour,
+    retry: RetryOptions,
+    /// Where retried tiles are counted
+    metrics: Metrics,
+}
+
+impl<'a> FrameHandle<'a> {
+    pub fn new(
+        source: &'a dyn ImageSource,
+        product: Product,
+        level: u32,
+        timestamp: DateTime<Utc>,
+    ) -> FrameHandle<'a> {
+        FrameHandle {
+            timestamp,
+            product,
+            level,
+            source,
+            background: Colour::TRANSPARENT,
+            retry: RetryOptions::default(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Fills the margins, and any tiles which fail to download, with `background`
+    pub fn with_background(mut self, background: Colour) -> FrameHandle<'a> {
+        self.background = background;
+        self
+    }
+
+    /// Retries the tiles which fail, and rejects the frame if too many are missing, as `retry`
+    /// says
+    pub fn with_retry(mut self, retry: RetryOptions) -> FrameHandle<'a> {
+        self.retry = retry;
+        self
+    }
+
+    /// Counts the tiles retried in `metrics`
+    pub fn with_metrics(mut self, metrics: Metrics) -> FrameHandle<'a> {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Downloads and stitches together the tiles of the frame, surrounded by `margins`
+    pub fn fetch(&self, margins: &Margins) -> Result<RgbaImage, AppErr> {
+        Ok(self.fetch_stitched(margins, None)?.image)
+    }
+
+    /// Like `fetch`, but reduces the frame by `factor` as each row of tiles arrives.
+    /// Holds much less in memory than fetching the frame and then scaling it down.
+    pub fn fetch_reduced(&self, margins: &Margins, factor: u32) -> Result<RgbaImage, AppErr> {
+        Ok(self.fetch_stitched(margins, Some(factor))?.image)
+    }
+
+    /// Like `fetch`, or `fetch_reduced` given a factor, but also says which tiles are missing
+    pub fn fetch_stitched(&self, margins: &Margins, reduce_factor: Option<u32>) -> Result<Stitched, AppErr> {
+        match reduce_factor {
+            Some(factor) => download_composite_reduced(
+                self.source,
+                &self.product,
+                self.level,
+                &self.timestamp,
+                margins,
+                self.background,
+                self.retry,
+                &self.metrics,
+                factor,
+            ),
+            None => download_composite(
+                self.source,
+                &self.product,
+                self.level,
+                &self.timestamp,
+                margins,
+                self.background,
+                self.retry,
+                &self.metrics,
+            ),
+        }
+    }
+
+    /// Downloads the frame surrounded by `margins` a row of tiles at a time, handing each band
+    /// of the image to `write` from top to bottom, so that it is never held whole
+    pub fn stream<F>(&self, margins: &Margins, write: F) -> Result<Streamed, AppErr>
+    where
+        F: FnMut(&RgbaImage) -> Result<(), AppErr>,
+    {
+        download_composite_streamed(
+            self.source,
+            &self.product,
+            self.level,
+            &self.timestamp,
+            margins,
+            self.background,
+            self.retry,
+            &self.metrics,
+            write,
+        )
+    }
+}
+
+/// Every frame time between two bounds, in order
+pub struct FrameStream<'a> {
+    source: &'a dyn ImageSource,
+    options: StreamOptions,
+    /// The next frame time to yield, or None once the range is exhausted
+    next: Option<DateTime<Utc>>,
+}
+
+impl<'a> FrameStream<'a> {
+    /// Lists the frames of `source` in the range given by `options`.
+    /// Finding the latest frame may mean downloading the product's latest.json.
+    pub fn new(source: &'a dyn ImageSource, options: StreamOptions) -> Result<FrameStream<'a>, AppErr> {
+        let to = match (options.to, source) {
+            (Some(to), _) => frame_time::floor(&to),
+            (None, source) => source.newest(&options.product, options.level)?,
+        };
+        let from = options.from.map(|from| frame_time::ceil(&from));
+        let next = match (options.order, from) {
+            (Order::NewestFirst, _) => Some(to),
+            (Order::OldestFirst, Some(from)) => Some(from),
+            (Order::OldestFirst, None) => {
+                return Err(AppErr::new(
+                    "Listing frames oldest first needs a start time",
+                ))
+            }
+        };
+        let next = next.filter(|_| from.is_none_or(|from| from <= to));
+        let options = StreamOptions {
+            from,
+            to: Some(to),
+            ..options
+        };
+        Ok(FrameStream {
+            source,
+            options,
+            next,
+        })
+    }
+}
+
+impl<'a> Iterator for FrameStream<'a> {
+    type Item = FrameHandle<'a>;
+
+    fn next(&mut self) -> Option<FrameHandle<'a>> {
+        let timestamp = self.next?;
+        let step = chrono::Duration::minutes(FRAME_INTERVAL_MINUTES);
+        let StreamOptions {
+            from, to, order, ..
+        } = self.options;
+        self.next = match order {
+            Order::NewestFirst => {
+                Some(timestamp - step).filter(|next| from.is_none_or(|from| *next >= from))
+            }
+            Order::OldestFirst => {
+                Some(timestamp + step).filter(|next| to.is_none_or(|to| *next <= to))
+            }
+        };
+        let frame = FrameHandle::new(
+            self.source,
+            self.options.product.clone(),
+            self.options.level,
+            timestamp,
+        );
+        Some(frame.with_retry(self.options.retry))
+    }
+}
@@ -0,0 +1,136 @@
+//! Geostationary projection maths for full disk images, following the
+//! CGMS LRIT/HRIT normalized geostationary projection.
+
+/// Longitude of the point directly below Himawari-8/9
+pub const HIMAWARI_LONGITUDE: f64 = 140.7;
+
+const EQUATORIAL_RADIUS_KM: f64 = 6378.137;
+const POLAR_RADIUS_KM: f64 = 6356.7523;
+/// Distance from the centre of the Earth to a geostationary satellite
+const SATELLITE_DISTANCE_KM: f64 = 42164.0;
+/// Height of a geostationary satellite above the equator
+pub const SATELLITE_ALTITUDE_KM: f64 = SATELLITE_DISTANCE_KM - EQUATORIAL_RADIUS_KM;
+/// Half the angular width of a full disk image, in degrees
+/// (5500 columns either side of the centre at the AHI column scaling factor)
+const IMAGE_HALF_ANGLE: f64 = 5500.0 / (40932549.0 / 65536.0);
+
+/// The radius of the Earth's disk in a full disk image, as a fraction of the image width
+pub fn disk_radius() -> f64 {
+    let earth_angle = (EQUATORIAL_RADIUS_KM / SATELLITE_DISTANCE_KM).asin().to_degrees();
+    earth_angle / (2.0 * IMAGE_HALF_ANGLE)
+}
+
+/// How far past the limb the glow of the atmosphere reaches, as a fraction of the disk radius
+const ATMOSPHERE_MARGIN: f64 = 0.02;
+
+/// True if the tile at (`x`, `y`) of a full disk image split into `level` tiles a side lies
+/// wholly in space, outside the Earth's disk and the glow of its atmosphere
+pub fn tile_in_space(level: u32, x: u32, y: u32) -> bool {
+    let size = 1.0 / level as f64;
+    // How far the point of the tile nearest the centre of the image is from it, along an axis
+    let nearest = |start: f64| 0.5f64.clamp(start, start + size) - 0.5;
+    let distance = nearest(x as f64 * size).hypot(nearest(y as f64 * size));
+    distance > disk_radius() * (1.0 + ATMOSPHERE_MARGIN)
+}
+
+/// Projects a point on the Earth's surface onto a full disk image taken from above
+/// `satellite_longitude`. Returns the position as fractions (0 to 1) of the image width
+/// and height from the top left corner, or None if the point is on the far side of the planet.
+pub fn project(latitude: f64, longitude: f64, satellite_longitude: f64) -> Option<(f64, f64)> {
+    let (a, b, h) = (EQUATORIAL_RADIUS_KM, POLAR_RADIUS_KM, SATELLITE_DISTANCE_KM);
+
+    let latitude = latitude.to_radians();
+    let delta_longitude = (longitude - satellite_longitude).to_radians();
+
+    // Geocentric latitude and distance from the centre of the Earth
+    let c_lat = ((b * b) / (a * a) * latitude.tan()).atan();
+    let e2 = 1.0 - (b * b) / (a * a);
+    let r_l = b / (1.0 - e2 * c_lat.cos().powi(2)).sqrt();
+
+    // Vector from the satellite to the point
+    let r1 = h - r_l * c_lat.cos() * delta_longitude.cos();
+    let r2 = -r_l * c_lat.cos() * delta_longitude.sin();
+    let r3 = r_l * c_lat.sin();
+
+    // The point is visible if its surface normal faces the satellite
+    if (h - r1) * r1 - r2 * r2 - r3 * r3 * (a * a) / (b * b) <= 0.0 {
+        return None;
+    }
+
+    let rn = (r1 * r1 + r2 * r2 + r3 * r3).sqrt();
+    let east_angle = (-r2 / r1).atan().to_degrees();
+    let north_angle = (r3 / rn).asin().to_degrees();
+
+    Some((
+        0.5 + east_angle / (2.0 * IMAGE_HALF_ANGLE),
+        0.5 - north_angle / (2.0 * IMAGE_HALF_ANGLE),
+    ))
+}
+
+/// Finds the point on the Earth's surface seen at `x`, `y` of a full disk image taken from
+/// above `satellite_longitude`, given as fractions of the image width and height from the top
+/// left corner. Returns the (latitude, longitude) in degrees, or None if it is off the disk.
+pub fn unproject(x: f64, y: f64, satellite_longitude: f64) -> Option<(f64, f64)> {
+    let (a, b, h) = (EQUATORIAL_RADIUS_KM, POLAR_RADIUS_KM, SATELLITE_DISTANCE_KM);
+
+    let east_angle = ((x - 0.5) * 2.0 * IMAGE_HALF_ANGLE).to_radians();
+    let north_angle = ((0.5 - y) * 2.0 * IMAGE_HALF_ANGLE).to_radians();
+
+    // Direction from the satellite, towards the centre of the Earth, to the west and north
+    let d1 = north_angle.cos() * east_angle.cos();
+    let d2 = -north_angle.cos() * east_angle.sin();
+    let d3 = north_angle.sin();
+
+    // The nearest point along it on the ellipsoid
+    let qa = (d1 * d1 + d2 * d2) / (a * a) + (d3 * d3) / (b * b);
+    let qb = -2.0 * h * d1 / (a * a);
+    let qc = (h * h) / (a * a) - 1.0;
+    let discriminant = qb * qb - 4.0 * qa * qc;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = (-qb - discriminant.sqrt()) / (2.0 * qa);
+
+    // Earth-centred coordinates, with x towards the satellite
+    let (px, py, pz) = (h - t * d1, -t * d2, t * d3);
+    let latitude = ((a * a) / (b * b) * pz / (px * px + py * py).sqrt()).atan().to_degrees();
+    let longitude = satellite_longitude + py.atan2(px).to_degrees();
+    let longitude = (longitude + 540.0).rem_euclid(360.0) - 180.0;
+    Some((latitude, longitude))
+}
+
+/// Finds where an object in space at `position` (Earth-fixed coordinates in km) appears in a
+/// full disk image taken from above `satellite_longitude`. Returns the position as fractions of
+/// the image width and height, or None if the object is outside the frame or hidden by the Earth.
+pub fn sky_position(position: [f64; 3], satellite_longitude: f64) -> Option<(f64, f64)> {
+    let (sin_lon, cos_lon) = satellite_longitude.to_radians().sin_cos();
+    let h = SATELLITE_DISTANCE_KM;
+
+    // Vector from the satellite to the object, split into components towards
+    // the centre of the Earth, to the east and to the north
+    let (x, y, z) = (position[0] - h * cos_lon, position[1] - h * sin_lon, position[2]);
+    let forward = -x * cos_lon - y * sin_lon;
+    let east = -x * sin_lon + y * cos_lon;
+    let north = z;
+    if forward <= 0.0 {
+        return None;
+    }
+
+    let distance = (forward * forward + east * east + north * north).sqrt();
+    let east_angle = (east / forward).atan().to_degrees();
+    let north_angle = (north / distance).asin().to_degrees();
+    if east_angle.abs() > IMAGE_HALF_ANGLE || north_angle.abs() > IMAGE_HALF_ANGLE {
+        return None;
+    }
+
+    // Anything further away than the satellite is hidden within the Earth's disk
+    let earth_angle = (EQUATORIAL_RADIUS_KM / h).asin();
+    if (forward / distance).acos() < earth_angle {
+        return None;
+    }
+
+    Some((
+        0.5 + east_angle / (2.0 * IMAGE_HALF_ANGLE),
+        0.5 - north_angle / (2.0 * IMAGE_HALF_ANGLE),
+    ))
+}
@@ -0,0 +1,296 @@
+//! The settings window, for people who would rather not use the command line.
+//!
+//! It edits the config file, previews the margins on the user's own screen, and can run an
+//! update or install the schedule with the settings saved. Built with the "gui" feature.
+
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use eframe::egui;
+use log::{info, warn};
+
+use crate::config::{self, Config};
+use crate::error::AppErr;
+use crate::margins::Margins;
+use crate::messages::tr;
+use crate::output_level::OutputLevel;
+use crate::product::Product;
+use crate::schedule;
+
+/// The tile width of the default product, for showing the size of each level
+const TILE_WIDTH: u32 = 550;
+
+const FORMATS: [&str; 6] = ["jpeg", "png", "webp", "avif", "bmp", "tiff"];
+
+/// The screen assumed if the window can't tell which it is on
+const FALLBACK_SCREEN: (f32, f32) = (1920.0, 1080.0);
+
+struct Settings {
+    path: PathBuf,
+    /// The settings loaded, including those the window doesn't show
+    config: Config,
+    output_dir: String,
+    level: u32,
+    format: String,
+    margins: Margins,
+    set_wallpaper: bool,
+    /// Minutes between the scheduled updates
+    interval: u32,
+    /// An update started with "Run now"
+    update: Option<Child>,
+    /// The outcome of the last button pressed
+    message: Option<String>,
+}
+
+impl Settings {
+    fn new(path: PathBuf, config: Config) -> Settings {
+        Settings {
+            path,
+            output_dir: config.get_str("output-dir").unwrap_or_default().to_string(),
+            level: config
+                .get_u32("output-level")
+                .unwrap_or_else(|| OutputLevel::default().to_level()),
+            format: config
+                .get_str("output-format")
+                .unwrap_or(FORMATS[0])
+                .to_string(),
+            margins: config
+                .get_str("margins")
+                .and_then(Margins::try_parse)
+                .unwrap_or_default(),
+            set_wallpaper: config.get_bool("set-wallpaper"),
+            config,
+            interval: 10,
+            update: None,
+            message: None,
+        }
+    }
+
+    fn to_config(&self) -> Config {
+        let mut config = self.config.clone();
+        let output_dir = Some(self.output_dir.trim()).filter(|dir| !dir.is_empty());
+        config.set("output-dir", output_dir);
+        config.set("output-level", Some(self.level as i64));
+        config.set("output-format", Some(self.format.as_str()));
+        config.set("margins", Some(self.margins.to_string()));
+        config.set("set-wallpaper", Some(self.set_wallpaper));
+        config
+    }
+
+    fn save(&mut self) -> Result<(), AppErr> {
+        config::save(&self.path, &self.to_config())?;
+        info!("Saved the settings to {}", self.path.display());
+        Ok(())
+    }
+
+    /// Saves the settings, then runs `action`, showing how it went
+    fn save_then<F>(&mut self, action: F)
+    where
+        F: FnOnce(&mut Settings) -> Result<String, AppErr>,
+    {
+        let result = self.save().and_then(|_| action(self));
+        self.message = Some(match result {
+            Ok(message) => message,
+            Err(app_err) => {
+                warn!("{}", app_err);
+                app_err.to_string()
+            }
+        });
+    }
+
+    fn run_update(&mut self) -> Result<String, AppErr> {
+        // With no arguments, the updater reads the settings just saved
+        let child = Command::new(std::env::current_exe()?).spawn()?;
+        self.update = Some(child);
+        Ok(tr!("settings-running"))
+    }
+
+    /// Notes when an update started with "Run now" finishes
+    fn poll_update(&mut self, ctx: &egui::Context) {
+        let status = match self.update.as_mut().map(|child| child.try_wait()) {
+            None => return,
+            Some(Ok(None)) => {
+                ctx.request_repaint_after(Duration::from_millis(500));
+                return;
+            }
+            Some(Ok(Some(status))) => status,
+            Some(Err(err)) => {
+                self.update = None;
+                self.message = Some(err.to_string());
+                return;
+            }
+        };
+        self.update = None;
+        self.message = Some(match status.success() {
+            true => tr!("settings-update-finished"),
+            false => tr!("settings-update-failed", status = status),
+        });
+    }
+
+    fn form(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("settings")
+            .num_columns(2)
+            .spacing([12.0, 8.0])
+            .show(ui, |ui| {
+                ui.label(tr!("settings-output-dir"));
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.output_dir);
+                    if ui.button(tr!("settings-browse")).clicked() {
+                        let picked = rfd::FileDialog::new()
+                            .set_title(tr!("folder-picker-title"))
+                            .pick_folder();
+                        if let Some(dir) = picked {
+                            self.output_dir = dir.display().to_string();
+                        }
+                    }
+                });
+                ui.end_row();
+
+                ui.label(tr!("settings-level"));
+                let size = |level: u32| {
+                    tr!("settings-level-size", level = level, pixels = level * TILE_WIDTH)
+                };
+                egui::ComboBox::from_id_salt("level")
+                    .selected_text(size(self.level))
+                    .show_ui(ui, |ui| {
+                        for &level in Product::default().levels() {
+                            ui.selectable_value(&mut self.level, level, size(level));
+                        }
+                    });
+                ui.end_row();
+
+                ui.label(tr!("settings-format"));
+                egui::ComboBox::from_id_salt("format")
+                    .selected_text(self.format.as_str())
+                    .show_ui(ui, |ui| {
+                        for format in FORMATS {
+                            ui.selectable_value(&mut self.format, format.to_string(), format);
+                        }
+                    });
+                ui.end_row();
+
+                ui.label(tr!("settings-margins"));
+                ui.horizontal(|ui| {
+                    let margins = &mut self.margins;
+                    for margin in [
+                        &mut margins.top,
+                        &mut margins.right,
+                        &mut margins.bottom,
+                        &mut margins.left,
+                    ] {
+                        ui.add(egui::DragValue::new(margin).range(0..=10_000).suffix(" px"));
+                    }
+                });
+                ui.end_row();
+
+                ui.label("");
+                ui.checkbox(&mut self.set_wallpaper, tr!("settings-set-wallpaper"));
+                ui.end_row();
+
+                ui.label(tr!("settings-interval"));
+                ui.add(
+                    egui::DragValue::new(&mut self.interval)
+                        .range(1..=1440)
+                        .suffix(" min"),
+                );
+                ui.end_row();
+            });
+    }
+
+    /// Draws the wallpaper as it would fit on the user's screen, on a black background
+    fn preview(&self, ui: &mut egui::Ui) {
+        let (screen_width, screen_height) = ui.ctx().input(|input| {
+            let viewport = input.viewport();
+            let scale = viewport.native_pixels_per_point.unwrap_or(1.0);
+            viewport
+                .monitor_size
+                .map_or(FALLBACK_SCREEN, |size| (size.x * scale, size.y * scale))
+        });
+        ui.label(tr!(
+            "settings-preview",
+            width = screen_width.round(),
+            height = screen_height.round()
+        ));
+
+        let width = ui.available_width().min(480.0);
+        let size = egui::vec2(width, width * screen_height / screen_width);
+        let (screen, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let painter = ui.painter_at(screen);
+        painter.rect_filled(screen, 0.0, egui::Color32::BLACK);
+
+        let disk = (self.level * TILE_WIDTH) as f32;
+        let margins = &self.margins;
+        let image_width = disk + (margins.left + margins.right) as f32;
+        let image_height = disk + (margins.top + margins.bottom) as f32;
+        // The wallpaper is shown whole, scaled to fit the screen
+        let scale = (screen.width() / image_width).min(screen.height() / image_height);
+        let image = egui::Rect::from_center_size(
+            screen.center(),
+            egui::vec2(image_width * scale, image_height * scale),
+        );
+        painter.rect_stroke(
+            image,
+            0.0,
+            egui::Stroke::new(1.0, egui::Color32::DARK_GRAY),
+            egui::StrokeKind::Inside,
+        );
+        let center = image.min + egui::vec2(
+            (margins.left as f32 + disk / 2.0) * scale,
+            (margins.top as f32 + disk / 2.0) * scale,
+        );
+        painter.circle_filled(center, disk / 2.0 * scale, egui::Color32::from_rgb(52, 88, 140));
+    }
+}
+
+impl eframe::App for Settings {
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        self.poll_update(ui.ctx());
+        egui::CentralPanel::default().show(ui, |ui| {
+            self.form(ui);
+            ui.separator();
+            self.preview(ui);
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button(tr!("settings-save")).clicked() {
+                    let path = self.path.display().to_string();
+                    self.save_then(|_| Ok(tr!("settings-saved", path = path)));
+                }
+                let running = self.update.is_some();
+                if ui
+                    .add_enabled(!running, egui::Button::new(tr!("settings-run-now")))
+                    .clicked()
+                {
+                    self.save_then(Settings::run_update);
+                }
+                if ui.button(tr!("settings-install-schedule")).clicked() {
+                    self.save_then(|settings| {
+                        schedule::install(settings.interval, &[])?;
+                        Ok(tr!("settings-schedule-installed", interval = settings.interval))
+                    });
+                }
+            });
+            if let Some(ref message) = self.message {
+                ui.label(message);
+            }
+        });
+    }
+}
+
+/// Opens the settings window, returning once it is closed
+pub fn run() -> Result<(), AppErr> {
+    let path = config::default_path().ok_or_else(|| AppErr::new(tr!("no-config-dir")))?;
+    let config = config::load(&path)?;
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title(tr!("settings-title"))
+            .with_inner_size([560.0, 620.0]),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "himawari-desktop-updater",
+        options,
+        Box::new(|_| Ok(Box::new(Settings::new(path, config)))),
+    )
+    .map_err(|err| AppErr::new(err.to_string()))
+}
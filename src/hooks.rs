@@ -0,0 +1,123 @@
+//! Commands run before and after each update, for custom steps like uploading the image.
+//!
+//! Hooks are run through the shell, with environment variables describing the update:
+//!
+//! - `HIMAWARI_HOOK`: "pre" or "post"
+//! - `HIMAWARI_OUTPUT_DIR`: the output directory
+//! - `HIMAWARI_STATUS`: after the update, "written", "unchanged" or "failed"
+//! - `HIMAWARI_OUTPUT_PATH` and `HIMAWARI_TIMESTAMP`: the frame, unless the update failed
+//! - `HIMAWARI_LOCKSCREEN_PATH`: the lock screen image, if one was written
+//! - `HIMAWARI_ERROR`: why the update failed
+//!
+//! The --exec command runs only once a new image is saved, with `{path}`, `{timestamp}` and
+//! `{dir}` in the command replaced by the image's path, its capture time and the output
+//! directory, quoted for the shell. The same environment variables as the post hook are set.
+
+use std::path::Path;
+use std::process::Command;
+
+use log::info;
+
+use crate::download::DownloadedFrame;
+use crate::error::AppErr;
+use crate::messages::tr;
+
+fn shell_command(command: &str) -> Command {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+fn run(command: &str, mut shell: Command) -> Result<(), AppErr> {
+    info!("Running hook: {}", command);
+    let status = shell.status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("hook-failed", command = command, status = status))),
+    }
+}
+
+/// Runs the pre-update hook. The update should be skipped if this fails.
+pub fn run_pre(command: &str, output_dir: &Path) -> Result<(), AppErr> {
+    let mut shell = shell_command(command);
+    shell
+        .env("HIMAWARI_HOOK", "pre")
+        .env("HIMAWARI_OUTPUT_DIR", output_dir);
+    run(command, shell)
+}
+
+/// Runs the post-update hook with the outcome of the update
+pub fn run_post(
+    command: &str,
+    output_dir: &Path,
+    result: &Result<DownloadedFrame, AppErr>,
+) -> Result<(), AppErr> {
+    let mut shell = shell_command(command);
+    shell
+        .env("HIMAWARI_HOOK", "post")
+        .env("HIMAWARI_OUTPUT_DIR", output_dir);
+    match result {
+        Ok(frame) => {
+            let status = match frame.written {
+                true => "written",
+                false => "unchanged",
+            };
+            shell
+                .env("HIMAWARI_STATUS", status)
+                .env("HIMAWARI_OUTPUT_PATH", &frame.path)
+                .env("HIMAWARI_TIMESTAMP", frame.timestamp.to_rfc3339());
+            if let Some(ref path) = frame.lockscreen {
+                shell.env("HIMAWARI_LOCKSCREEN_PATH", path);
+            }
+        }
+        Err(app_err) => {
+            shell
+                .env("HIMAWARI_STATUS", "failed")
+                .env("HIMAWARI_ERROR", app_err.to_string());
+        }
+    }
+    run(command, shell)
+}
+
+/// Runs the --exec command for the image of `frame` which was just saved in `output_dir`
+pub fn run_exec(command: &str, output_dir: &Path, frame: &DownloadedFrame) -> Result<(), AppErr> {
+    let timestamp = frame.timestamp.to_rfc3339();
+    let expanded = command
+        .replace("{path}", &quote(&frame.path.to_string_lossy()))
+        .replace("{timestamp}", &quote(&timestamp))
+        .replace("{dir}", &quote(&output_dir.to_string_lossy()));
+    let mut shell = shell_command(&expanded);
+    shell
+        .env("HIMAWARI_HOOK", "exec")
+        .env("HIMAWARI_OUTPUT_DIR", output_dir)
+        .env("HIMAWARI_STATUS", "written")
+        .env("HIMAWARI_OUTPUT_PATH", &frame.path)
+        .env("HIMAWARI_TIMESTAMP", timestamp);
+    if let Some(ref path) = frame.lockscreen {
+        shell.env("HIMAWARI_LOCKSCREEN_PATH", path);
+    }
+    run(&expanded, shell)
+}
+
+/// Quotes `text` as a single argument for the shell hooks run through
+#[cfg(windows)]
+fn quote(text: &str) -> String {
+    // cmd has no escape for a quote inside quotes, and paths can't contain one anyway
+    format!("\"{}\"", text.replace('"', ""))
+}
+
+#[cfg(not(windows))]
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
@@ -0,0 +1,202 @@
+//! An on-disk cache of downloaded tiles and feeds, shared between runs.
+//!
+//! Responses are kept for as long as their Cache-Control or Expires headers allow, so a
+//! retry or another run in the meantime reads them from disk instead of the network.
+//! latest.json is fetched with a cache buster, and revalidated through its ETag instead.
+
+use std::fs::DirBuilder;
+use std::path::PathBuf;
+use std::sync::Once;
+
+use chrono::prelude::*;
+use log::{info, warn};
+use reqwest::header::{HeaderMap, AGE, CACHE_CONTROL, EXPIRES};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::AppErr;
+
+const CACHE_DIR_NAME: &str = "himawari-desktop-updater";
+
+/// The cache is trimmed to this size, dropping the oldest responses first
+const MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+static PRUNE: Once = Once::new();
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    url: String,
+    expires: DateTime<Utc>,
+}
+
+/// The directory for this program in the user's cache directory for the platform, falling
+/// back to the temporary directory
+pub fn base_dir() -> PathBuf {
+    let var = |name| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let base = if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        var("XDG_CACHE_HOME").or_else(|| var("HOME").map(|home| home.join(".cache")))
+    };
+    base.unwrap_or_else(std::env::temp_dir).join(CACHE_DIR_NAME)
+}
+
+fn cache_dir() -> PathBuf {
+    base_dir().join("http")
+}
+
+fn paths(url: &str) -> (PathBuf, PathBuf) {
+    let key = format!("{:x}", Sha256::digest(url.as_bytes()));
+    let dir = cache_dir();
+    (
+        dir.join(format!("{}.json", key)),
+        dir.join(format!("{}.body", key)),
+    )
+}
+
+/// When a response with `headers` stops being fresh, or None if it can't be stored
+fn expiry(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    let now = Utc::now();
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if let Some(cache_control) = header(CACHE_CONTROL) {
+        let directives: Vec<_> = cache_control
+            .split(',')
+            .map(|directive| directive.trim().to_ascii_lowercase())
+            .collect();
+        // Without revalidation, responses which need it can't be reused
+        if directives
+            .iter()
+            .any(|directive| directive == "no-store" || directive == "no-cache")
+        {
+            return None;
+        }
+        let max_age = directives.iter().find_map(|directive| {
+            directive
+                .strip_prefix("max-age=")?
+                .trim_matches('"')
+                .parse::<i64>()
+                .ok()
+        });
+        if let Some(max_age) = max_age {
+            let age = header(AGE)
+                .and_then(|age| age.parse::<i64>().ok())
+                .unwrap_or(0);
+            let expires = now + chrono::Duration::seconds(max_age - age);
+            return (expires > now).then_some(expires);
+        }
+    }
+    let expires = DateTime::parse_from_rfc2822(header(EXPIRES)?)
+        .ok()?
+        .with_timezone(&Utc);
+    (expires > now).then_some(expires)
+}
+
+/// The cached body of the response to `url`, if it is still fresh
+pub fn get(url: &str) -> Option<Vec<u8>> {
+    let (entry_path, body_path) = paths(url);
+    let entry: CacheEntry = serde_json::from_reader(std::fs::File::open(entry_path).ok()?).ok()?;
+    if entry.url != url || entry.expires <= Utc::now() {
+        return None;
+    }
+    let body = std::fs::read(body_path).ok()?;
+    info!("Using the cached response to {}", url);
+    Some(body)
+}
+
+/// Drops the cached response to `url`, if there is one
+pub fn remove(url: &str) {
+    let (entry_path, body_path) = paths(url);
+    // The entry goes first, so it never points at a missing body
+    let _ = std::fs::remove_file(entry_path);
+    let _ = std::fs::remove_file(body_path);
+}
+
+/// Keeps the response to `url` if its headers allow it. Failures are only logged.
+pub fn put(url: &str, headers: &HeaderMap, body: &[u8]) {
+    let expires = match expiry(headers) {
+        Some(expires) => expires,
+        None => return,
+    };
+    PRUNE.call_once(|| {
+        if let Err(app_err) = prune() {
+            warn!("Failed to prune the HTTP cache: {}", app_err);
+        }
+    });
+
+    let store = || -> Result<(), AppErr> {
+        let (entry_path, body_path) = paths(url);
+        DirBuilder::new().recursive(true).create(cache_dir())?;
+        // Write the body first, so an entry never points at a partial one
+        std::fs::write(&body_path, body)?;
+        let entry = CacheEntry {
+            url: url.to_string(),
+            expires,
+        };
+        serde_json::to_writer(std::fs::File::create(&entry_path)?, &entry)?;
+        Ok(())
+    };
+    if let Err(app_err) = store() {
+        warn!("Failed to cache the response to {}: {}", url, app_err);
+    }
+}
+
+/// How many bytes of responses are cached
+pub fn size() -> u64 {
+    let entries = match std::fs::read_dir(cache_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Removes expired responses, then the oldest until the cache fits in MAX_CACHE_BYTES
+fn prune() -> Result<(), AppErr> {
+    let dir = cache_dir();
+    if !dir.exists() {
+        return Ok(());
+    }
+    let now = Utc::now();
+    let mut bodies = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let entry_path = entry?.path();
+        if entry_path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let body_path = entry_path.with_extension("body");
+        let expired = std::fs::File::open(&entry_path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, CacheEntry>(file).ok())
+            .is_none_or(|entry| entry.expires <= now);
+        match std::fs::metadata(&body_path) {
+            Ok(metadata) if !expired => {
+                bodies.push((metadata.modified()?, metadata.len(), entry_path, body_path))
+            }
+            _ => {
+                let _ = std::fs::remove_file(&body_path);
+                std::fs::remove_file(&entry_path)?;
+            }
+        }
+    }
+
+    let mut total: u64 = bodies.iter().map(|(_, len, _, _)| len).sum();
+    bodies.sort_by_key(|(modified, _, _, _)| *modified);
+    for (_, len, entry_path, body_path) in bodies {
+        if total <= MAX_CACHE_BYTES {
+            break;
+        }
+        std::fs::remove_file(&entry_path)?;
+        std::fs::remove_file(&body_path)?;
+        total -= len;
+    }
+    Ok(())
+}
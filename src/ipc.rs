@@ -0,0 +1,162 @@
+//! Local notification channel for daemon mode.
+//!
+//! Other programs connect to a Unix domain socket (a named pipe on Windows) and
+//! receive one JSON object per line each time a new frame is written:
+//!
+//! ```text
+//! {"event":"frame","path":"/home/me/himawari/himawari8_20221101_120000.jpeg","timestamp":"2022-11-01T12:00:00Z"}
+//! ```
+//!
+//! Frames captured for special events are announced with an event name
+//! other than "frame", such as "moon" or "eclipse".
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::prelude::*;
+use log::{info, warn};
+use serde_derive::Serialize;
+
+use crate::error::AppErr;
+
+type Client = Box<dyn Write + Send>;
+
+#[derive(Serialize)]
+struct FrameEvent<'a> {
+    event: &'static str,
+    path: &'a Path,
+    timestamp: &'a DateTime<Utc>,
+}
+
+pub struct NotificationServer {
+    clients: Arc<Mutex<Vec<Client>>>,
+}
+
+impl NotificationServer {
+    /// Starts accepting client connections on a background thread
+    pub fn start(endpoint: &Path) -> Result<NotificationServer, AppErr> {
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        listen(endpoint, clients.clone())?;
+        info!("Listening for notification clients on {}", endpoint.display());
+        Ok(NotificationServer { clients })
+    }
+
+    /// Sends a new frame event to every connected client
+    pub fn notify(&self, event: &'static str, path: &Path, timestamp: &DateTime<Utc>) {
+        let event = FrameEvent {
+            event,
+            path,
+            timestamp,
+        };
+        let mut line = match serde_json::to_vec(&event) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("Failed to serialize frame event: {}", err);
+                return;
+            }
+        };
+        line.push(b'\n');
+        // Drop any clients which have gone away
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| client.write_all(&line).and_then(|_| client.flush()).is_ok());
+    }
+}
+
+/// The default endpoint for the notification channel
+#[cfg(not(windows))]
+pub fn default_endpoint() -> PathBuf {
+    let mut path = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    path.push("himawari-desktop-updater.sock");
+    path
+}
+
+/// The default endpoint for the notification channel
+#[cfg(windows)]
+pub fn default_endpoint() -> PathBuf {
+    PathBuf::from(r"\\.\pipe\himawari-desktop-updater")
+}
+
+#[cfg(not(windows))]
+fn listen(endpoint: &Path, clients: Arc<Mutex<Vec<Client>>>) -> Result<(), AppErr> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+    use std::time::Duration;
+
+    // Clean up a socket left behind by a previous instance
+    if let Ok(metadata) = std::fs::metadata(endpoint) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(endpoint)?;
+        }
+    }
+
+    let listener = UnixListener::bind(endpoint)?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // Don't let a client which never reads stall the daemon
+            if stream.set_write_timeout(Some(Duration::from_secs(1))).is_ok() {
+                clients.lock().unwrap().push(Box::new(stream));
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(windows)]
+fn listen(endpoint: &Path, clients: Arc<Mutex<Vec<Client>>>) -> Result<(), AppErr> {
+    use crate::ffi_windows::os_str_to_wchar;
+    use std::fs::File;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+    use std::ptr::null_mut;
+    use winapi::shared::winerror::ERROR_PIPE_CONNECTED;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW};
+    use winapi::um::winbase::{
+        PIPE_ACCESS_OUTBOUND, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+        PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    fn create_pipe_instance(name: &[u16]) -> Result<File, AppErr> {
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_OUTBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                0,
+                0,
+                null_mut(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(unsafe { File::from_raw_handle(handle as _) })
+    }
+
+    let name = os_str_to_wchar(endpoint.as_os_str());
+    // Create the first instance up front so errors are reported to the caller
+    let mut pipe = create_pipe_instance(&name)?;
+    std::thread::spawn(move || loop {
+        // Blocks until a client connects to this instance
+        let connected = unsafe {
+            ConnectNamedPipe(pipe.as_raw_handle() as _, null_mut()) != 0
+                || GetLastError() == ERROR_PIPE_CONNECTED
+        };
+        if connected {
+            clients.lock().unwrap().push(Box::new(pipe));
+        }
+        pipe = match create_pipe_instance(&name) {
+            Ok(pipe) => pipe,
+            Err(err) => {
+                warn!("Failed to create notification pipe: {}", err);
+                return;
+            }
+        };
+    });
+    Ok(())
+}
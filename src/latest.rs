@@ -0,0 +1,302 @@
+//! Finds the latest frame from the product's `latest.json`.
+//!
+//! The last response is cached in the output directory along with its ETag, so it
+//! can be requested conditionally: when nothing has changed the server only has
+//! to answer "304 Not Modified".
+//!
+//! When `latest.json` can't be downloaded, or names a frame well over the usual delay old,
+//! the tiles of the last few Himawari frames are requested directly instead, most recent
+//! first, and the newest frame which is all there is taken.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::prelude::*;
+use image::{load_from_memory_with_format, ImageFormat};
+use log::{debug, info, warn};
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::{AppErr, ErrorKind};
+use crate::frame_time;
+use crate::http::HttpClient;
+use crate::placeholder;
+use crate::product::{Product, FRAME_INTERVAL_MINUTES};
+use crate::satellite::Satellite;
+
+/// `latest.json` is taken to be stale once its frame is this old, as frames are usually
+/// published within half an hour of being captured
+const MAX_LATEST_AGE_MINUTES: i64 = 60;
+
+/// How many of the most recent frame times are probed when `latest.json` lets us down
+const PROBE_FRAMES: i64 = 12;
+
+/// The last `latest.json` response, and the validators needed to request it conditionally
+#[derive(Serialize, Deserialize)]
+struct CachedLatest {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    timestamp: DateTime<Utc>,
+}
+
+pub struct LatestFrame {
+    pub timestamp: DateTime<Utc>,
+    /// False if the latest frame is the same as on the previous run
+    pub changed: bool,
+    /// The response to cache once the frame has been written
+    response: Option<(PathBuf, CachedLatest)>,
+}
+
+impl LatestFrame {
+    /// A frame which was not found through latest.json
+    pub fn new(timestamp: DateTime<Utc>) -> LatestFrame {
+        LatestFrame {
+            timestamp,
+            changed: true,
+            response: None,
+        }
+    }
+
+    /// Caches the response, so the next run can tell whether anything has changed.
+    /// NOTE: Only call this once the frame has been written, or the next run may skip it.
+    pub fn remember(&self) {
+        if let Some((ref path, ref cached)) = self.response {
+            if let Err(app_err) = write_cache(path, cached) {
+                warn!("Failed to cache the latest metadata: {}", app_err);
+            }
+        }
+    }
+}
+
+fn cache_path(output_dir: &Path, product: &Product) -> PathBuf {
+    let name = product.satellite.server().product_dir(&product.name);
+    output_dir.join(format!(".{}.latest.json", name))
+}
+
+fn read_cache(path: &Path) -> Option<CachedLatest> {
+    let file = std::fs::File::open(path).ok()?;
+    serde_json::from_reader(file).ok()
+}
+
+fn write_cache(path: &Path, cached: &CachedLatest) -> Result<(), AppErr> {
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer(file, cached)?;
+    Ok(())
+}
+
+fn cache_buster() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// The timestamp of the latest frame of `product`, without the cache kept by `fetch`
+pub fn newest(client: &HttpClient, product: &Product) -> Result<DateTime<Utc>, AppErr> {
+    let download = || -> Result<DateTime<Utc>, AppErr> {
+        info!("Downloading latest metadata...");
+        let url = product.latest_url(cache_buster());
+        let response = client.http_get(&url, HeaderMap::new())?.error_for_status(&url)?;
+        product.satellite.server().parse_latest(&response.body)
+    };
+    match download() {
+        Ok(timestamp) if !is_stale(&timestamp) => Ok(timestamp),
+        Ok(timestamp) => Ok(probe(client, product, Some(&timestamp)).unwrap_or(timestamp)),
+        Err(app_err) => fall_back(client, product, app_err),
+    }
+}
+
+/// The time of the frame of `product` nearest to `time`, of those its metadata lists. Only
+/// Himawari captures its frames at fixed times, so other satellites are limited to their
+/// recent frames.
+pub fn nearest(client: &HttpClient, product: &Product, time: &DateTime<Utc>) -> Result<DateTime<Utc>, AppErr> {
+    if product.satellite == Satellite::Himawari {
+        return Ok(frame_time::snap(time));
+    }
+    info!("Downloading latest metadata...");
+    let url = product.latest_url(cache_buster());
+    let response = client.http_get(&url, HeaderMap::new())?.error_for_status(&url)?;
+    let times = product.satellite.server().parse_times(&response.body)?;
+    times
+        .into_iter()
+        .min_by_key(|frame| (*frame - *time).num_seconds().abs())
+        .ok_or_else(|| AppErr::of_kind(ErrorKind::Parse, "No frames listed"))
+}
+
+/// Downloads and parses the "latest.json" metadata for `product`, or probes for the latest
+/// frame if the metadata fails or is stale
+pub fn fetch(client: &HttpClient, product: &Product, output_dir: &Path) -> Result<LatestFrame, AppErr> {
+    match fetch_metadata(client, product, output_dir) {
+        Ok(latest) if !is_stale(&latest.timestamp) => Ok(latest),
+        Ok(latest) => match probe(client, product, Some(&latest.timestamp)) {
+            Some(timestamp) => Ok(LatestFrame::new(timestamp)),
+            None => Ok(latest),
+        },
+        Err(app_err) => fall_back(client, product, app_err).map(LatestFrame::new),
+    }
+}
+
+fn is_stale(timestamp: &DateTime<Utc>) -> bool {
+    Utc::now() - *timestamp > chrono::Duration::minutes(MAX_LATEST_AGE_MINUTES)
+}
+
+/// The latest frame found by probing, or `app_err` if none was
+fn fall_back(client: &HttpClient, product: &Product, app_err: AppErr) -> Result<DateTime<Utc>, AppErr> {
+    warn!("Failed to download the latest metadata: {}", app_err);
+    probe(client, product, None).ok_or(app_err)
+}
+
+/// The newest of the last few frames of `product` which the server has in full, newer than
+/// `after` if given. Only Himawari captures its frames at fixed times which can be guessed.
+fn probe(client: &HttpClient, product: &Product, after: Option<&DateTime<Utc>>) -> Option<DateTime<Utc>> {
+    if product.satellite != Satellite::Himawari {
+        return None;
+    }
+    info!("Probing the last {} frame times for the latest frame...", PROBE_FRAMES);
+    let newest = frame_time::floor(&Utc::now());
+    let found = (0..PROBE_FRAMES)
+        .map(|age| newest - chrono::Duration::minutes(FRAME_INTERVAL_MINUTES * age))
+        .take_while(|timestamp| after.is_none_or(|after| timestamp > after))
+        .find(|timestamp| is_published(client, product, timestamp));
+    match found {
+        Some(timestamp) => info!("Found the frame with timestamp {}", timestamp),
+        None => warn!("No newer frame was found by probing"),
+    }
+    found
+}
+
+/// True if the frame of `product` at `timestamp` is all there, judging by the first tile of
+/// its smallest level and the last tile of its largest
+fn is_published(client: &HttpClient, product: &Product, timestamp: &DateTime<Utc>) -> bool {
+    let levels = product.levels();
+    let (smallest, largest) = match (levels.first(), levels.last()) {
+        (Some(&smallest), Some(&largest)) => (smallest, largest),
+        _ => return false,
+    };
+    [(smallest, 0), (largest, largest - 1)].iter().all(|&(level, last)| {
+        let url = product.tile_url(level, timestamp, last, last);
+        debug!("Probing {}...", url);
+        let response = match client.http_get(&url, HeaderMap::new()).and_then(|response| response.error_for_status(&url)) {
+            Ok(response) => response,
+            Err(_) => return false,
+        };
+        // The server answers with a "No Image" placeholder for tiles it doesn't have yet
+        load_from_memory_with_format(&response.body, ImageFormat::Png)
+            .is_ok_and(|image| !placeholder::is_placeholder(&image))
+    })
+}
+
+/// The headers which make the request for `latest.json` conditional on it having changed since
+/// the `cached` response
+fn conditional_headers(cached: Option<&CachedLatest>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(cached) = cached {
+        let etag = cached.etag.as_deref().and_then(|etag| HeaderValue::from_str(etag).ok());
+        if let Some(etag) = etag {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        let last_modified = cached
+            .last_modified
+            .as_deref()
+            .and_then(|last_modified| HeaderValue::from_str(last_modified).ok());
+        if let Some(last_modified) = last_modified {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    headers
+}
+
+fn fetch_metadata(client: &HttpClient, product: &Product, output_dir: &Path) -> Result<LatestFrame, AppErr> {
+    let cache_path = cache_path(output_dir, product);
+    let cached = read_cache(&cache_path);
+
+    info!("Downloading latest metadata...");
+    let url = product.latest_url(cache_buster());
+
+    let response = client.http_get(&url, conditional_headers(cached.as_ref()))?;
+
+    if let (StatusCode::NOT_MODIFIED, Some(cached)) = (response.status, cached.as_ref()) {
+        info!("Latest image is unchanged, with timestamp {}", cached.timestamp);
+        return Ok(LatestFrame {
+            timestamp: cached.timestamp,
+            changed: false,
+            response: None,
+        });
+    }
+
+    let response = response.error_for_status(&url)?;
+    let header = |name| {
+        response
+            .headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let timestamp = product.satellite.server().parse_latest(&response.body)?;
+
+    info!("Latest image available has timestamp {}", timestamp);
+
+    let changed = cached.is_none_or(|cached| cached.timestamp != timestamp);
+    let cached = CachedLatest {
+        etag,
+        last_modified,
+        timestamp,
+    };
+
+    Ok(LatestFrame {
+        timestamp,
+        changed,
+        response: Some((cache_path, cached)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cached(etag: Option<&str>, last_modified: Option<&str>) -> CachedLatest {
+        CachedLatest {
+            etag: etag.map(String::from),
+            last_modified: last_modified.map(String::from),
+            timestamp: Utc.ymd(2022, 11, 5).and_hms(3, 20, 0),
+        }
+    }
+
+    #[test]
+    fn conditional_request() {
+        assert!(conditional_headers(None).is_empty());
+
+        let headers = conditional_headers(Some(&cached(Some("\"5f3a\""), Some("Sat, 05 Nov 2022 03:31:02 GMT"))));
+        assert_eq!(headers.get(IF_NONE_MATCH).unwrap(), "\"5f3a\"");
+        assert_eq!(headers.get(IF_MODIFIED_SINCE).unwrap(), "Sat, 05 Nov 2022 03:31:02 GMT");
+
+        // Either validator is enough, and one which can't be sent back is left out
+        let headers = conditional_headers(Some(&cached(Some("bad\netag"), Some("Sat, 05 Nov 2022 03:31:02 GMT"))));
+        assert_eq!(headers.get(IF_NONE_MATCH), None);
+        assert!(headers.contains_key(IF_MODIFIED_SINCE));
+        assert!(conditional_headers(Some(&cached(None, None))).is_empty());
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let dir = std::env::temp_dir().join(format!("himawari-desktop-updater-latest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(".latest.json");
+
+        assert!(read_cache(&path).is_none());
+        write_cache(&path, &cached(Some("\"5f3a\""), None)).unwrap();
+        let read = read_cache(&path).unwrap();
+        assert_eq!(read.etag.as_deref(), Some("\"5f3a\""));
+        assert_eq!(read.last_modified, None);
+        assert_eq!(read.timestamp, Utc.ymd(2022, 11, 5).and_hms(3, 20, 0));
+
+        // A damaged cache is only a missed chance to skip the download
+        std::fs::write(&path, "{").unwrap();
+        assert!(read_cache(&path).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
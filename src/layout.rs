@@ -0,0 +1,116 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use crate::margins::Margins;
+use crate::size::Size;
+
+/// How much of the canvas's shorter side the disk covers
+const DISK_FILL: f64 = 0.9;
+
+/// Where the disk sits on the canvas
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Position {
+    #[default]
+    Center,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// The left and top edges of the disk, in pixels of the canvas
+    At { x: u32, y: u32 },
+}
+
+#[derive(Clone)]
+pub struct PositionValueParser;
+
+impl clap::builder::TypedValueParser for PositionValueParser {
+    type Value = Position;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match Position::try_parse(value.to_string_lossy().as_ref()) {
+            Some(position) => Ok(position),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Use center, left, right, top, bottom or X,Y in pixels")),
+        }
+    }
+}
+
+impl Position {
+    pub fn try_parse(input: &str) -> Option<Position> {
+        match input.trim() {
+            "center" | "centre" => Some(Position::Center),
+            "left" => Some(Position::Left),
+            "right" => Some(Position::Right),
+            "top" => Some(Position::Top),
+            "bottom" => Some(Position::Bottom),
+            input => {
+                let (x, y) = input.split_once(',')?;
+                Some(Position::At {
+                    x: x.trim().parse().ok()?,
+                    y: y.trim().parse().ok()?,
+                })
+            }
+        }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self {
+            Position::Center => write!(f, "center"),
+            Position::Left => write!(f, "left"),
+            Position::Right => write!(f, "right"),
+            Position::Top => write!(f, "top"),
+            Position::Bottom => write!(f, "bottom"),
+            Position::At { x, y } => write!(f, "{},{}", x, y),
+        }
+    }
+}
+
+/// The disk placed on a canvas the size of the final wallpaper
+#[derive(Clone)]
+pub struct Layout {
+    pub canvas: Size,
+    pub position: Position,
+}
+
+impl Layout {
+    /// How wide the disk appears on the canvas, in pixels
+    pub fn disk_pixels(&self) -> u32 {
+        (self.canvas.width.min(self.canvas.height) as f64 * DISK_FILL).round() as u32
+    }
+
+    /// The margins which place a disk `disk_width` pixels wide in an image with the aspect
+    /// ratio of the canvas, so that it is scaled down to `disk_pixels` wide
+    pub fn margins(&self, disk_width: u32) -> Margins {
+        let scale = disk_width as f64 / self.disk_pixels() as f64;
+        let padding = |canvas: u32| {
+            let canvas = (canvas as f64 * scale).round() as u32;
+            canvas.saturating_sub(disk_width)
+        };
+        let (horizontal, vertical) = (padding(self.canvas.width), padding(self.canvas.height));
+        // Against an edge, the disk keeps the gap it has along the canvas's shorter side
+        let gap = horizontal.min(vertical) / 2;
+        let (left, top) = match self.position {
+            Position::Center => (horizontal / 2, vertical / 2),
+            Position::Left => (gap, vertical / 2),
+            Position::Right => (horizontal - gap, vertical / 2),
+            Position::Top => (horizontal / 2, gap),
+            Position::Bottom => (horizontal / 2, vertical - gap),
+            Position::At { x, y } => (
+                ((x as f64 * scale).round() as u32).min(horizontal),
+                ((y as f64 * scale).round() as u32).min(vertical),
+            ),
+        };
+        Margins {
+            top,
+            right: horizontal - left,
+            bottom: vertical - top,
+            left,
+        }
+    }
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{} at {}", self.canvas, self.position)
+    }
+}
@@ -0,0 +1,110 @@
+//! Downloads full disk images of the Earth from the Himawari and GOES weather satellites,
+//! pieced together from the tiles their servers split each frame into, and sets them as the
+//! desktop wallpaper.
+//!
+//! The `himawari-desktop-updater` binary is built on this library. Other programs can use the
+//! pieces it does: a [`Downloader`] runs the whole pipeline from a set of [`DownloadOptions`],
+//! [`composite::composite`] pieces one frame together from an [`ImageSource`] with some
+//! [`CompositeOptions`], and a [`WallpaperSetter`] sets the result on the desktop. The binary
+//! reads its options from the command line with a [`DownloadOptionsBuilder`].
+
+pub mod analysis;
+pub mod animated;
+pub mod archive;
+pub mod astro;
+pub mod backdrop;
+pub mod backfill;
+pub mod band;
+pub mod breaker;
+pub mod cache_size;
+pub mod checksums;
+pub mod composite;
+pub mod colour;
+pub mod config;
+pub mod console;
+pub mod corner;
+pub mod crop;
+pub mod ctl;
+pub mod daemon;
+pub mod daylight;
+pub mod dns;
+pub mod download;
+pub mod eclipse;
+pub mod encode;
+pub mod error;
+pub mod exif;
+#[cfg(target_os = "macos")]
+pub mod ffi_macos;
+#[cfg(not(any(windows, target_os = "macos")))]
+pub mod ffi_unix;
+#[cfg(windows)]
+pub mod ffi_windows;
+pub mod frame_time;
+pub mod frames;
+pub mod geo;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod hooks;
+pub mod http;
+pub mod http_cache;
+pub mod ipc;
+pub mod latest;
+pub mod layout;
+pub mod lockscreen;
+pub mod logging;
+pub mod map_layers;
+pub mod margins;
+pub mod messages;
+pub mod metrics;
+pub mod min_tiles;
+pub mod monitor;
+pub mod naming;
+pub mod night_dim;
+pub mod notify;
+pub mod options;
+pub mod orientation;
+pub mod output_format;
+pub mod outcome;
+pub mod output_level;
+pub mod overlay;
+pub mod panorama;
+pub mod placeholder;
+pub mod png_compression;
+pub mod postprocess;
+pub mod preflight;
+pub mod presets;
+pub mod product;
+pub mod progress;
+pub mod projection;
+pub mod recording;
+pub mod region;
+pub mod report;
+pub mod resize;
+pub mod resize_mode;
+pub mod retention;
+pub mod resume;
+pub mod satellite;
+pub mod schedule;
+#[cfg(windows)]
+pub mod screensaver;
+pub mod self_update;
+pub mod size;
+pub mod state;
+pub mod storm;
+pub mod supersample;
+#[cfg(windows)]
+pub mod system_proxy;
+pub mod tile_cache;
+pub mod tiles;
+pub mod upload;
+pub mod wallpaper;
+pub mod wallpaper_backend;
+pub mod wallpaper_style;
+pub mod webhook;
+
+pub use composite::CompositeOptions;
+pub use download::{DownloadOptions, DownloadedFrame, Downloader};
+pub use error::AppErr;
+pub use options::DownloadOptionsBuilder;
+pub use tiles::{DirectorySource, HttpSource, ImageSource};
+pub use wallpaper::WallpaperSetter;
@@ -0,0 +1,39 @@
+use image::RgbaImage;
+
+use crate::resize;
+use crate::size::Size;
+
+/// How the lock screen version of the image differs from the desktop version
+#[derive(Clone)]
+pub struct LockscreenOptions {
+    /// Crop to fill this size, rather than fitting the whole disk
+    pub size: Option<Size>,
+    /// Standard deviation of the gaussian blur, in pixels. Zero disables blurring.
+    pub blur: f32,
+    /// Percentage to darken by, so a clock drawn over the image stays readable
+    pub darken: u8,
+}
+
+/// Renders the lock screen version of `image`
+pub fn render(image: &RgbaImage, options: &LockscreenOptions) -> RgbaImage {
+    let mut image = match options.size {
+        Some(ref size) => resize::fill(image.clone(), size),
+        None => image.clone(),
+    };
+
+    // NOTE: Blur after cropping and scaling, as the cost grows with the image size
+    if options.blur > 0.0 {
+        image = image::imageops::blur(&image, options.blur);
+    }
+
+    if options.darken > 0 {
+        let keep = 100 - options.darken.min(100) as u32;
+        for pixel in image.pixels_mut() {
+            for c in pixel.0.iter_mut().take(3) {
+                *c = (*c as u32 * keep / 100) as u8;
+            }
+        }
+    }
+
+    image
+}
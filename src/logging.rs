@@ -0,0 +1,185 @@
+//! Logging to the console and to a log file, which is rotated once it grows past a size.
+//!
+//! A scheduled task running every few minutes would otherwise grow the log forever. Once
+//! the file reaches its size limit, at the end of a line, it is renamed to `<name>.1`, older
+//! files move up one number, and the oldest beyond the number kept is deleted.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use simplelog::{ColorChoice, CombinedLogger, Config, LevelFilter, SharedLogger, TermLogger, TerminalMode, WriteLogger};
+
+pub const DEFAULT_LOG_FILE: &str = "himawari-desktop-updater.log";
+pub const DEFAULT_MAX_LOG_BYTES: u64 = 1024 * 1024;
+pub const DEFAULT_LOG_FILES_KEPT: u32 = 5;
+
+pub struct LogOptions {
+    pub level: LevelFilter,
+    pub path: PathBuf,
+    /// The size the log file is rotated at
+    pub max_bytes: u64,
+    /// How many log files to keep, counting the current one
+    pub keep: u32,
+    /// Log to stderr alone, leaving stdout to --json
+    pub stderr_only: bool,
+}
+
+impl Default for LogOptions {
+    fn default() -> LogOptions {
+        LogOptions {
+            level: LevelFilter::Info,
+            path: PathBuf::from(DEFAULT_LOG_FILE),
+            max_bytes: DEFAULT_MAX_LOG_BYTES,
+            keep: DEFAULT_LOG_FILES_KEPT,
+            stderr_only: false,
+        }
+    }
+}
+
+/// Logs at `options.level` to the console and to the log file, as the application will
+/// usually be running as a cron job or scheduled task
+pub fn init(color_choice: ColorChoice, options: &LogOptions) {
+    let level = options.level;
+    let mode = match options.stderr_only {
+        true => TerminalMode::Stderr,
+        false => TerminalMode::Mixed,
+    };
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![
+        TermLogger::new(level, Config::default(), mode, color_choice),
+    ];
+    let file_error = match RotatingFile::open(options) {
+        Ok(file) => {
+            loggers.push(WriteLogger::new(level, Config::default(), file));
+            None
+        }
+        Err(err) => Some(err),
+    };
+    CombinedLogger::init(loggers).expect("Constructing logger");
+    // Carry on with the console alone, rather than fail the update
+    if let Some(err) = file_error {
+        warn!("Failed to open the log file {}: {}", options.path.display(), err);
+    }
+}
+
+/// How the runs read back from the log went
+#[derive(Default)]
+pub struct LogHealth {
+    /// How many runs were found
+    pub runs: u32,
+    /// How many of those logged an error
+    pub failed: u32,
+}
+
+/// Reads back up to the last `runs` runs from the log files, newest first, or None if there
+/// is no log. Runs are told apart by their "Starting..." line, so none are found when the log
+/// level leaves it out.
+pub fn recent_runs(options: &LogOptions, runs: u32) -> Option<LogHealth> {
+    let paths = std::iter::once(options.path.clone())
+        .chain((1..options.keep).map(|number| numbered_path(&options.path, number)));
+    let mut health: Option<LogHealth> = None;
+    // Whether the run being read back logged an error, which may be in a newer file than its start
+    let mut failed = false;
+    for path in paths {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => break,
+        };
+        let health = health.get_or_insert_with(LogHealth::default);
+        for line in text.lines().rev() {
+            failed |= line.contains("[ERROR]");
+            if line.ends_with("Starting...") {
+                health.runs += 1;
+                health.failed += failed as u32;
+                failed = false;
+                if health.runs == runs {
+                    return Some(std::mem::take(health));
+                }
+            }
+        }
+    }
+    health
+}
+
+/// `<name>.<number>`, the path of an older log file
+fn numbered_path(path: &Path, number: u32) -> PathBuf {
+    let mut name = path.to_path_buf().into_os_string();
+    name.push(format!(".{}", number));
+    PathBuf::from(name)
+}
+
+/// A log file which is rotated once it reaches `max_bytes`
+struct RotatingFile {
+    path: PathBuf,
+    /// Only None while rotating, as Windows can't rename a file which is open
+    file: Option<File>,
+    len: u64,
+    max_bytes: u64,
+    keep: u32,
+    /// Whether the last write ended a line, as lines are written in pieces and shouldn't be
+    /// split across files
+    at_line_start: bool,
+}
+
+impl RotatingFile {
+    fn open(options: &LogOptions) -> io::Result<RotatingFile> {
+        let file = open_append(&options.path)?;
+        Ok(RotatingFile {
+            path: options.path.clone(),
+            len: file.metadata()?.len(),
+            file: Some(file),
+            max_bytes: options.max_bytes,
+            keep: options.keep.max(1),
+            at_line_start: true,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+        if self.keep == 1 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            // Files which aren't there yet are fine to skip
+            let _ = std::fs::remove_file(numbered_path(&self.path, self.keep - 1));
+            for number in (1..self.keep - 1).rev() {
+                let _ = std::fs::rename(numbered_path(&self.path, number), numbered_path(&self.path, number + 1));
+            }
+            std::fs::rename(&self.path, numbered_path(&self.path, 1))?;
+        }
+        self.file = Some(open_append(&self.path)?);
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.at_line_start && self.len >= self.max_bytes {
+            self.rotate()?;
+        }
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => self.file.insert(open_append(&self.path)?),
+        };
+        let written = file.write(buf)?;
+        self.len += written as u64;
+        if written > 0 {
+            self.at_line_start = buf[written - 1] == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file {
+            Some(ref mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    File::options().append(true).create(true).open(path)
+}
@@ -0,0 +1,258 @@
+//! Map layers drawn over the disk with `--overlay`: a graticule of latitude and longitude
+//! lines, and coastlines, so the image can be read as a map, and the line between day and
+//! night, or shading over the night side, at the time of capture.
+//!
+//! All are projected onto the disk as seen from the satellite. The coastlines are a coarse
+//! outline, simplified to a point every few degrees, embedded from `data/coastlines.txt`.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use chrono::prelude::*;
+use image::{Rgba, RgbaImage};
+
+use crate::astro;
+use crate::geo;
+use crate::overlay::blend;
+
+const COASTLINES: &str = include_str!("../data/coastlines.txt");
+
+/// Degrees between the lines of the graticule
+const GRID_STEP: i32 = 15;
+
+/// Degrees between the points each line of the graticule is drawn through
+const GRID_RESOLUTION: f64 = 1.0;
+
+const GRID_COLOUR: Rgba<u8> = Rgba([255, 255, 255, 96]);
+const COASTLINE_COLOUR: Rgba<u8> = Rgba([255, 220, 64, 192]);
+const TERMINATOR_COLOUR: Rgba<u8> = Rgba([255, 128, 32, 192]);
+/// Light enough that detail in the infrared band still shows through
+const NIGHT_COLOUR: Rgba<u8> = Rgba([0, 0, 16, 112]);
+
+/// The night side is shaded fully once the Sun is this many degrees below the horizon, at
+/// the end of civil twilight, and less so until then
+const TWILIGHT_DEGREES: f64 = 6.0;
+
+/// Lines are this fraction of the disk's width thick, and at least a pixel
+const LINE_WIDTH_FRACTION: f64 = 1.0 / 1500.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MapLayer {
+    Grid,
+    Coastlines,
+    Terminator,
+    Night,
+}
+
+#[derive(Clone)]
+pub struct MapLayersValueParser;
+
+impl clap::builder::TypedValueParser for MapLayersValueParser {
+    type Value = Vec<MapLayer>;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let mut layers = Vec::new();
+        for name in value.to_string_lossy().split(',') {
+            let layer = match name.trim() {
+                "grid" => MapLayer::Grid,
+                "coastlines" => MapLayer::Coastlines,
+                "terminator" => MapLayer::Terminator,
+                "night" => MapLayer::Night,
+                _ => return Err(Error::raw(ErrorKind::InvalidValue, "Invalid map layer, use a comma separated list of grid, coastlines, terminator and night")),
+            };
+            if !layers.contains(&layer) {
+                layers.push(layer);
+            }
+        }
+        Ok(layers)
+    }
+}
+
+impl Display for MapLayer {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            MapLayer::Grid => "grid",
+            MapLayer::Coastlines => "coastlines",
+            MapLayer::Terminator => "terminator",
+            MapLayer::Night => "night",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Where the disk is in an image: the left and top of the square it fills, and its width
+#[derive(Clone, Copy)]
+pub struct Disk {
+    pub left: f64,
+    pub top: f64,
+    pub size: f64,
+}
+
+/// Draws `layers` over `disk` in `image`, as seen from above `satellite_longitude` at `timestamp`
+pub fn draw(
+    image: &mut RgbaImage,
+    disk: Disk,
+    satellite_longitude: f64,
+    timestamp: &DateTime<Utc>,
+    layers: &[MapLayer],
+) {
+    let width = (disk.size * LINE_WIDTH_FRACTION).max(1.0);
+    for layer in layers {
+        match layer {
+            MapLayer::Grid => {
+                for line in graticule() {
+                    draw_path(image, disk, satellite_longitude, &line, width, GRID_COLOUR);
+                }
+            }
+            MapLayer::Coastlines => {
+                for line in coastlines() {
+                    draw_path(image, disk, satellite_longitude, &line, width, COASTLINE_COLOUR);
+                }
+            }
+            MapLayer::Terminator => {
+                let line = terminator(timestamp);
+                draw_path(image, disk, satellite_longitude, &line, width, TERMINATOR_COLOUR);
+            }
+            MapLayer::Night => shade_night(image, disk, satellite_longitude, timestamp),
+        }
+    }
+}
+
+/// The lines of latitude and longitude every GRID_STEP degrees, as (longitude, latitude) points
+fn graticule() -> Vec<Vec<(f64, f64)>> {
+    let points = |from: f64, to: f64| {
+        let count = ((to - from) / GRID_RESOLUTION) as usize;
+        (0..=count).map(move |i| from + i as f64 * GRID_RESOLUTION)
+    };
+    let parallels = (-90 / GRID_STEP + 1..90 / GRID_STEP)
+        .map(|i| (i * GRID_STEP) as f64)
+        .map(|latitude| points(-180.0, 180.0).map(|longitude| (longitude, latitude)).collect());
+    // Meridians stop short of the poles, where they all meet
+    let last_parallel = (90 / GRID_STEP - 1) as f64 * GRID_STEP as f64;
+    let meridians = (-180 / GRID_STEP..180 / GRID_STEP)
+        .map(|i| (i * GRID_STEP) as f64)
+        .map(|longitude| {
+            points(-last_parallel, last_parallel)
+                .map(|latitude| (longitude, latitude))
+                .collect()
+        });
+    parallels.chain(meridians).collect()
+}
+
+/// The embedded coastlines, as (longitude, latitude) points
+fn coastlines() -> impl Iterator<Item = Vec<(f64, f64)>> {
+    COASTLINES
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.split_whitespace()
+                .filter_map(|point| {
+                    let (longitude, latitude) = point.split_once(',')?;
+                    Some((longitude.parse().ok()?, latitude.parse().ok()?))
+                })
+                .collect()
+        })
+}
+
+/// The line between day and night at `timestamp`, as (longitude, latitude) points: the great
+/// circle a quarter turn from the point the Sun is overhead
+fn terminator(timestamp: &DateTime<Utc>) -> Vec<(f64, f64)> {
+    let sun = astro::sun_position(timestamp);
+    let length = (sun[0] * sun[0] + sun[1] * sun[1] + sun[2] * sun[2]).sqrt();
+    let sun = [sun[0] / length, sun[1] / length, sun[2] / length];
+    // Two directions at right angles to the Sun and each other
+    let horizontal = (sun[0] * sun[0] + sun[1] * sun[1]).sqrt();
+    let u = [-sun[1] / horizontal, sun[0] / horizontal, 0.0];
+    let v = [
+        sun[1] * u[2] - sun[2] * u[1],
+        sun[2] * u[0] - sun[0] * u[2],
+        sun[0] * u[1] - sun[1] * u[0],
+    ];
+    let count = (360.0 / GRID_RESOLUTION) as usize;
+    (0..=count)
+        .map(|i| {
+            let (sin, cos) = (i as f64 * GRID_RESOLUTION).to_radians().sin_cos();
+            let point = [0, 1, 2].map(|axis| cos * u[axis] + sin * v[axis]);
+            (point[1].atan2(point[0]).to_degrees(), point[2].asin().to_degrees())
+        })
+        .collect()
+}
+
+/// Darkens the parts of the disk where the Sun has set at `timestamp`, fading in through
+/// twilight
+fn shade_night(image: &mut RgbaImage, disk: Disk, satellite_longitude: f64, timestamp: &DateTime<Utc>) {
+    let sun = astro::sun_position(timestamp);
+    let length = (sun[0] * sun[0] + sun[1] * sun[1] + sun[2] * sun[2]).sqrt();
+    let min_x = disk.left.floor().max(0.0) as i32;
+    let max_x = (disk.left + disk.size).ceil().min(image.width() as f64) as i32;
+    let min_y = disk.top.floor().max(0.0) as i32;
+    let max_y = (disk.top + disk.size).ceil().min(image.height() as f64) as i32;
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let fx = (x as f64 + 0.5 - disk.left) / disk.size;
+            let fy = (y as f64 + 0.5 - disk.top) / disk.size;
+            let (latitude, longitude) = match geo::unproject(fx, fy, satellite_longitude) {
+                Some(point) => point,
+                None => continue,
+            };
+            let (sin_lat, cos_lat) = latitude.to_radians().sin_cos();
+            let (sin_lon, cos_lon) = longitude.to_radians().sin_cos();
+            let up = [cos_lat * cos_lon, cos_lat * sin_lon, sin_lat];
+            let dot = (0..3).map(|i| up[i] * sun[i]).sum::<f64>() / length;
+            let elevation = dot.asin().to_degrees();
+            let darkness = (-elevation / TWILIGHT_DEGREES).clamp(0.0, 1.0);
+            if darkness > 0.0 {
+                blend(image, x, y, NIGHT_COLOUR, darkness as f32);
+            }
+        }
+    }
+}
+
+/// Draws the path through `points`, leaving out the parts on the far side of the planet
+fn draw_path(
+    image: &mut RgbaImage,
+    disk: Disk,
+    satellite_longitude: f64,
+    points: &[(f64, f64)],
+    width: f64,
+    colour: Rgba<u8>,
+) {
+    let to_pixels = |&(longitude, latitude): &(f64, f64)| {
+        let (x, y) = geo::project(latitude, longitude, satellite_longitude)?;
+        Some((disk.left + x * disk.size, disk.top + y * disk.size))
+    };
+    let projected: Vec<_> = points.iter().map(to_pixels).collect();
+    for pair in projected.windows(2) {
+        if let (Some(from), Some(to)) = (pair[0], pair[1]) {
+            draw_segment(image, from, to, width, colour);
+        }
+    }
+}
+
+/// Draws a line `width` pixels wide from `from` to `to`, blending it over the image
+fn draw_segment(image: &mut RgbaImage, from: (f64, f64), to: (f64, f64), width: f64, colour: Rgba<u8>) {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length_squared = dx * dx + dy * dy;
+    let radius = width / 2.0;
+
+    // Cover each pixel near the segment by how close its centre is to it
+    let min_x = (from.0.min(to.0) - radius - 1.0).floor() as i32;
+    let max_x = (from.0.max(to.0) + radius + 1.0).ceil() as i32;
+    let min_y = (from.1.min(to.1) - radius - 1.0).floor() as i32;
+    let max_y = (from.1.max(to.1) + radius + 1.0).ceil() as i32;
+    for y in min_y.max(0)..=max_y.min(image.height() as i32 - 1) {
+        for x in min_x.max(0)..=max_x.min(image.width() as i32 - 1) {
+            let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+            let t = match length_squared {
+                l if l > 0.0 => (((px - from.0) * dx + (py - from.1) * dy) / l).clamp(0.0, 1.0),
+                _ => 0.0,
+            };
+            let (nx, ny) = (from.0 + t * dx, from.1 + t * dy);
+            let distance = ((px - nx).powi(2) + (py - ny).powi(2)).sqrt();
+            let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+            if coverage > 0.0 {
+                blend(image, x, y, colour, coverage as f32);
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-#[derive(Clone)]
+#[derive(Clone, Default, PartialEq, Eq)]
 pub struct Margins {
     pub top: u32,
     pub right: u32,
@@ -8,7 +8,7 @@ pub struct Margins {
     pub left: u32,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct MarginsValueParser;
 
 impl clap::builder::TypedValueParser for MarginsValueParser {
@@ -61,14 +61,3 @@ impl Display for Margins {
         )
     }
 }
-
-impl Default for Margins {
-    fn default() -> Margins {
-        Margins {
-            top: 0,
-            right: 0,
-            bottom: 0,
-            left: 0,
-        }
-    }
-}
@@ -0,0 +1,148 @@
+//! The catalog of user-facing messages, so that errors and notifications can be translated.
+//!
+//! Messages are written in a subset of the Fluent syntax: one `id = text` per line, with
+//! `{ $name }` placeables and `#` comments. The English catalog is built in, and others are
+//! looked up by language in the `locales` directory next to the executable, falling back to
+//! English for anything they don't translate. Use the `tr!` macro to format a message.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use log::warn;
+
+const ENGLISH: &str = include_str!("../locales/en.ftl");
+
+/// Environment variables naming the user's language, in order of precedence
+const LANGUAGE_VARS: [&str; 3] = ["LC_ALL", "LC_MESSAGES", "LANG"];
+
+type Catalog = HashMap<String, String>;
+
+struct Messages {
+    translation: Option<Catalog>,
+    english: Catalog,
+}
+
+static MESSAGES: OnceLock<Messages> = OnceLock::new();
+
+fn parse(source: &str) -> Catalog {
+    let mut catalog = Catalog::new();
+    let mut current: Option<String> = None;
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            current = None;
+            continue;
+        }
+        // Indented lines continue the previous message
+        if line.starts_with(char::is_whitespace) {
+            if let Some(text) = current.as_ref().and_then(|id| catalog.get_mut(id)) {
+                text.push('\n');
+                text.push_str(trimmed);
+            }
+            continue;
+        }
+        match trimmed.split_once('=') {
+            Some((id, text)) => {
+                let id = id.trim().to_string();
+                catalog.insert(id.clone(), text.trim().to_string());
+                current = Some(id);
+            }
+            None => current = None,
+        }
+    }
+    catalog
+}
+
+/// The user's preferred language as a BCP 47 tag, e.g. "pt-BR"
+fn user_language() -> Option<String> {
+    let from_env = LANGUAGE_VARS
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+    #[cfg(windows)]
+    let from_env = from_env.or_else(crate::ffi_windows::user_locale);
+    // POSIX locales look like "pt_BR.UTF-8" or "de_DE@euro"
+    let tag = from_env?.split(['.', '@']).next()?.replace('_', "-");
+    match tag.as_str() {
+        "" | "C" | "POSIX" => None,
+        _ => Some(tag),
+    }
+}
+
+/// Reads the catalog for `language`, trying the language without its region if needed
+fn load_translation(language: &str) -> Option<Catalog> {
+    let locales_dir = std::env::current_exe().ok()?.parent()?.join("locales");
+    let base = language.split('-').next().unwrap_or(language);
+    for name in [language, base].iter() {
+        if name.eq_ignore_ascii_case("en") {
+            return None;
+        }
+        let path: PathBuf = locales_dir.join(format!("{}.ftl", name));
+        match std::fs::read_to_string(&path) {
+            Ok(source) => return Some(parse(&source)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => warn!(
+                "Failed to read translations from {}: {}",
+                path.display(),
+                err
+            ),
+        }
+    }
+    None
+}
+
+fn messages() -> &'static Messages {
+    MESSAGES.get_or_init(|| Messages {
+        translation: user_language().and_then(|language| load_translation(&language)),
+        english: parse(ENGLISH),
+    })
+}
+
+/// Formats the message `id` in the user's language, filling in its placeables from `args`
+pub fn translate(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let messages = messages();
+    let text = messages
+        .translation
+        .as_ref()
+        .and_then(|catalog| catalog.get(id))
+        .or_else(|| messages.english.get(id));
+    let text = match text {
+        Some(text) => text,
+        // A missing message is a bug, but is better shown as its id than not at all
+        None => return id.to_string(),
+    };
+
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let placeable = rest[start + 1..end].trim();
+        let name = placeable.strip_prefix('$').unwrap_or(placeable);
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => output.push_str(&value.to_string()),
+            None => output.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Formats a message from the catalog, e.g. `tr!("no-fragments", time = timestamp)`
+#[macro_export]
+macro_rules! tr {
+    ($id:expr) => {
+        $crate::messages::translate($id, &[])
+    };
+    ($id:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::messages::translate($id, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+])
+    };
+}
+
+pub use tr;
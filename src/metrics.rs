@@ -0,0 +1,221 @@
+//! Run metrics written with `--metrics <path>`, so an updater left running on a server can be
+//! alerted on when updates start failing or slowing down.
+//!
+//! A path ending in `.prom` is written in the Prometheus text format, for node_exporter's
+//! textfile collector, and any other path as JSON. The file is replaced after each update,
+//! so it always describes the last one, and is written to one side first so a collector
+//! never reads it half written.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::DirBuilder;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use serde_derive::Serialize;
+
+use crate::error::AppErr;
+use crate::outcome::Outcome;
+use crate::report::{Source, Transfer};
+
+/// The parts of an update which are timed
+#[derive(Clone, Copy)]
+pub enum Phase {
+    /// Finding the latest frame
+    Metadata,
+    /// Downloading and stitching together the tiles
+    Tiles,
+    /// Encoding and writing the images
+    Write,
+    /// Setting the wallpaper and lock screen
+    Wallpaper,
+}
+
+const PHASES: [Phase; 4] = [Phase::Metadata, Phase::Tiles, Phase::Write, Phase::Wallpaper];
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Metadata => "metadata",
+            Phase::Tiles => "tiles",
+            Phase::Write => "write",
+            Phase::Wallpaper => "wallpaper",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    /// Tiles downloaded from the server
+    tiles_downloaded: AtomicU64,
+    /// Tiles read back from the cache, or a recording
+    tiles_cached: AtomicU64,
+    /// Attempts at tiles which failed, including those which were retried
+    tiles_failed: AtomicU64,
+    bytes: AtomicU64,
+    /// Requests sent for tiles, including those which resumed an interrupted body
+    requests: AtomicU64,
+    retries: AtomicU64,
+    /// Microseconds spent in each phase, in the order of PHASES
+    phase_micros: [AtomicU64; 4],
+}
+
+/// The metrics of the update in progress, kept by everything taking part in it. Clones share
+/// their counts. The default keeps none.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    counters: Option<Arc<Counters>>,
+}
+
+impl Metrics {
+    /// Metrics which are kept track of, to be written once each update ends
+    pub fn new() -> Metrics {
+        Metrics {
+            counters: Some(Arc::default()),
+        }
+    }
+
+    /// Notes how the download of a tile went, `bytes` long if it succeeded
+    pub fn record_tile(&self, transfer: &Transfer, bytes: Option<usize>) {
+        let counters = match self.counters {
+            Some(ref counters) => counters,
+            None => return,
+        };
+        let counter = match (bytes, transfer.source) {
+            (None, _) => &counters.tiles_failed,
+            (Some(_), Source::Network) => &counters.tiles_downloaded,
+            (Some(_), _) => &counters.tiles_cached,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        counters.requests.fetch_add(transfer.attempts as u64, Ordering::Relaxed);
+        if let (Some(bytes), Source::Network) = (bytes, transfer.source) {
+            counters.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Notes that a tile is being retried
+    pub fn record_retry(&self) {
+        if let Some(ref counters) = self.counters {
+            counters.retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Times `phase` until the returned timer is dropped. Phases which run on several threads
+    /// at once, such as the tiles of extra bands, add up their times.
+    pub fn time(&self, phase: Phase) -> PhaseTimer<'_> {
+        PhaseTimer {
+            metrics: self,
+            phase,
+            started: Instant::now(),
+        }
+    }
+
+    /// Writes the metrics of the update which ended with `outcome` to `path`, then starts afresh
+    pub fn write(&self, path: &Path, outcome: &Outcome, last_success: Option<DateTime<Utc>>) -> Result<(), AppErr> {
+        let counters = match self.counters {
+            Some(ref counters) => counters,
+            None => return Ok(()),
+        };
+        let take = |counter: &AtomicU64| counter.swap(0, Ordering::Relaxed);
+        let metrics = Written {
+            outcome,
+            last_success,
+            tiles_downloaded: take(&counters.tiles_downloaded),
+            tiles_cached: take(&counters.tiles_cached),
+            tiles_failed: take(&counters.tiles_failed),
+            bytes_downloaded: take(&counters.bytes),
+            requests: take(&counters.requests),
+            retries: take(&counters.retries),
+            phases: PHASES
+                .iter()
+                .map(|&phase| {
+                    let micros = take(&counters.phase_micros[phase as usize]);
+                    (phase.name(), Duration::from_micros(micros).as_secs_f64())
+                })
+                .collect(),
+        };
+        let text = match path.extension().is_some_and(|ext| ext == "prom") {
+            true => prometheus(&metrics),
+            false => serde_json::to_string_pretty(&metrics)?,
+        };
+
+        if let Some(dir) = path.parent() {
+            DirBuilder::new().recursive(true).create(dir)?;
+        }
+        let part_path = path.with_extension("part");
+        std::fs::write(&part_path, text)?;
+        std::fs::rename(&part_path, path)?;
+        Ok(())
+    }
+}
+
+/// Adds the time until it is dropped to `phase`
+pub struct PhaseTimer<'a> {
+    metrics: &'a Metrics,
+    phase: Phase,
+    started: Instant,
+}
+
+impl Drop for PhaseTimer<'_> {
+    fn drop(&mut self) {
+        if let Some(ref counters) = self.metrics.counters {
+            let micros = self.started.elapsed().as_micros() as u64;
+            counters.phase_micros[self.phase as usize].fetch_add(micros, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The metrics of an update as they are written
+#[derive(Serialize)]
+struct Written<'a> {
+    #[serde(flatten)]
+    outcome: &'a Outcome,
+    /// When the last update which succeeded finished, which may be this one
+    last_success: Option<DateTime<Utc>>,
+    tiles_downloaded: u64,
+    tiles_cached: u64,
+    tiles_failed: u64,
+    bytes_downloaded: u64,
+    requests: u64,
+    retries: u64,
+    /// Seconds spent in each phase
+    phases: BTreeMap<&'static str, f64>,
+}
+
+/// The metrics in the Prometheus text exposition format
+fn prometheus(metrics: &Written) -> String {
+    let mut text = String::new();
+    let mut gauge = |name: &str, help: &str, value: String| {
+        let _ = writeln!(text, "# HELP himawari_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE himawari_{} gauge", name);
+        let _ = writeln!(text, "himawari_{} {}", name, value);
+    };
+    let outcome = metrics.outcome;
+    let seconds = |time: &DateTime<Utc>| time.timestamp().to_string();
+    gauge("last_run_success", "Whether the last update succeeded", (outcome.success as u8).to_string());
+    gauge("last_run_timestamp_seconds", "When the last update finished", seconds(&Utc::now()));
+    gauge("last_run_duration_seconds", "How long the last update took", outcome.duration_secs.to_string());
+    if let Some(ref last_success) = metrics.last_success {
+        gauge("last_success_timestamp_seconds", "When the last successful update finished", seconds(last_success));
+    }
+    if let Some(ref timestamp) = outcome.timestamp {
+        gauge("frame_timestamp_seconds", "The capture time of the last frame", seconds(timestamp));
+    }
+    gauge("frame_written", "Whether the last update wrote a new frame", (outcome.written as u8).to_string());
+    gauge("tiles_downloaded", "Tiles downloaded from the server by the last update", metrics.tiles_downloaded.to_string());
+    gauge("tiles_cached", "Tiles read from the cache by the last update", metrics.tiles_cached.to_string());
+    gauge("tiles_failed", "Attempts at tiles which failed in the last update", metrics.tiles_failed.to_string());
+    gauge("bytes_downloaded", "Bytes of tiles downloaded by the last update", metrics.bytes_downloaded.to_string());
+    gauge("requests", "Requests sent for tiles by the last update", metrics.requests.to_string());
+    gauge("retries", "Tiles retried by the last update", metrics.retries.to_string());
+
+    let _ = writeln!(text, "# HELP himawari_phase_duration_seconds Time spent in each part of the last update");
+    let _ = writeln!(text, "# TYPE himawari_phase_duration_seconds gauge");
+    for (phase, seconds) in &metrics.phases {
+        let _ = writeln!(text, "himawari_phase_duration_seconds{{phase=\"{}\"}} {}", phase, seconds);
+    }
+    text
+}
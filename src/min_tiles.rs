@@ -0,0 +1,51 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// How many of a frame's image fragments must download for the frame to be kept
+#[derive(Clone, Copy)]
+pub enum MinTiles {
+    Count(u32),
+    Percent(u32),
+}
+
+#[derive(Clone)]
+pub struct MinTilesValueParser;
+
+impl clap::builder::TypedValueParser for MinTilesValueParser {
+    type Value = MinTiles;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let value = value.to_string_lossy();
+        let value = value.trim();
+        let parsed = match value.strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<u32>().ok().filter(|n| *n <= 100).map(MinTiles::Percent),
+            None => value.parse::<u32>().ok().map(MinTiles::Count),
+        };
+        parsed.ok_or_else(|| Error::raw(ErrorKind::InvalidValue, "Invalid number of fragments, use a count like 60 or a percentage like 90%"))
+    }
+}
+
+impl MinTiles {
+    /// How many of the `total` fragments of a frame are needed
+    pub fn required(&self, total: u32) -> u32 {
+        match *self {
+            MinTiles::Count(count) => count.min(total),
+            MinTiles::Percent(percent) => (total * percent).div_ceil(100),
+        }
+        .max(1)
+    }
+}
+
+impl Display for MinTiles {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            MinTiles::Count(count) => write!(f, "{}", count),
+            MinTiles::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+impl Default for MinTiles {
+    fn default() -> MinTiles {
+        MinTiles::Count(1)
+    }
+}
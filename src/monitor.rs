@@ -0,0 +1,23 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// Which monitors the wallpaper is set on. Only Windows can tell its monitors apart.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Monitor {
+    /// The same image on every monitor
+    #[default]
+    All,
+    /// Only the monitor with this number, counting from 1
+    One(u32),
+    /// One image stretched across the whole desktop
+    Span,
+}
+
+impl Display for Monitor {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match *self {
+            Monitor::All => write!(f, "all"),
+            Monitor::One(number) => write!(f, "{}", number),
+            Monitor::Span => write!(f, "span"),
+        }
+    }
+}
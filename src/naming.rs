@@ -0,0 +1,37 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// How archived frames are named
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Naming {
+    /// After the time the frame was captured, e.g. `himawari8_20221101_120000.jpeg`
+    #[default]
+    Timestamp,
+    /// With a number one higher than the previous frame, e.g. `frame_000123.jpeg`,
+    /// for tools such as ffmpeg which expect a dense numeric sequence
+    Sequence,
+}
+
+#[derive(Clone)]
+pub struct NamingValueParser;
+
+impl clap::builder::TypedValueParser for NamingValueParser {
+    type Value = Naming;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match value.to_string_lossy().as_ref().trim() {
+            "timestamp" => Ok(Naming::Timestamp),
+            "sequence" => Ok(Naming::Sequence),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid naming, use timestamp or sequence")),
+        }
+    }
+}
+
+impl Display for Naming {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            Naming::Timestamp => "timestamp",
+            Naming::Sequence => "sequence",
+        };
+        write!(f, "{}", s)
+    }
+}
@@ -0,0 +1,135 @@
+//! Dimming and warming the image at night with `--night-dim`, so that the bright white cloud
+//! tops aren't glaring on an evening desktop.
+//!
+//! Night is judged by the local clock, between the --night-hours, or with --location by how
+//! far the Sun is below the horizon there. Either way the dimming fades in and out, over the
+//! first and last hour of the night hours, or over civil twilight.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use chrono::prelude::*;
+use image::RgbaImage;
+
+use crate::astro;
+use crate::daylight::Location;
+
+/// How far the Sun is below the horizon, in degrees, when it is fully night: the end of civil
+/// twilight
+const NIGHT_SUN_ELEVATION: f64 = -6.0;
+
+/// How long the dimming takes to fade in or out by the clock
+const FADE_MINUTES: u32 = 60;
+
+/// What each channel is scaled by to warm the image fully, roughly the white of a 3400K lamp
+const WARM: [f32; 3] = [1.0, 0.85, 0.65];
+
+/// The local hours of the night, from the start hour up to the end hour, e.g. 21-7
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct NightHours {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Clone)]
+pub struct NightHoursValueParser;
+
+impl clap::builder::TypedValueParser for NightHoursValueParser {
+    type Value = NightHours;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match NightHours::try_parse(value.to_string_lossy().as_ref()) {
+            Some(hours) => Ok(hours),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Use format START-END in hours from 0 to 23, e.g. 21-7")),
+        }
+    }
+}
+
+impl NightHours {
+    pub fn try_parse(input: &str) -> Option<NightHours> {
+        let (start, end) = input.split_once('-')?;
+        let start = start.trim().parse::<u32>().ok().filter(|hour| *hour < 24)?;
+        let end = end.trim().parse::<u32>().ok().filter(|hour| *hour < 24)?;
+        (start != end).then_some(NightHours { start, end })
+    }
+
+    /// How far into the night it is at `minute` past local midnight, from 0 to 1
+    fn amount(&self, minute: u32) -> f32 {
+        let day = 24 * 60;
+        let length = (self.end + 24 - self.start) % 24 * 60;
+        let into = (minute + day - self.start * 60) % day;
+        if into >= length {
+            return 0.0;
+        }
+        let fade = FADE_MINUTES.min(length / 2) as f32;
+        let from_edge = into.min(length - into) as f32;
+        (from_edge / fade).min(1.0)
+    }
+}
+
+impl Display for NightHours {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// How night is told from day
+#[derive(Clone, Copy, PartialEq)]
+pub enum Night {
+    Hours(NightHours),
+    /// Night by the Sun at a location
+    Sun(Location),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct NightDimOptions {
+    /// How much darker the image is at night, as a percentage
+    pub percent: u8,
+    pub night: Night,
+}
+
+impl NightDimOptions {
+    /// How far into the night it is at `time`, from 0 by day to 1 at night
+    pub fn amount(&self, time: &DateTime<Utc>) -> f32 {
+        match self.night {
+            Night::Hours(hours) => {
+                let local = time.with_timezone(&Local);
+                hours.amount(local.hour() * 60 + local.minute())
+            }
+            Night::Sun(location) => {
+                let elevation = astro::sun_elevation(time, location.latitude, location.longitude);
+                (elevation / NIGHT_SUN_ELEVATION).clamp(0.0, 1.0) as f32
+            }
+        }
+    }
+}
+
+impl Display for NightDimOptions {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        match self.night {
+            Night::Hours(hours) => write!(f, "{}% from {}", self.percent, hours),
+            Night::Sun(location) => write!(f, "{}% after sunset at {}", self.percent, location),
+        }
+    }
+}
+
+/// Dims and warms the colour channels of `image` as `options` say for `amount` of the way into
+/// the night, leaving alpha alone
+pub fn apply(image: &mut RgbaImage, options: &NightDimOptions, amount: f32) {
+    if amount <= 0.0 || options.percent == 0 {
+        return;
+    }
+    let dim = 1.0 - amount * options.percent as f32 / 100.0;
+    let tables = WARM.map(|warm| {
+        let scale = dim * (1.0 + (warm - 1.0) * amount);
+        let mut table = [0u8; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = (i as f32 * scale).round() as u8;
+        }
+        table
+    });
+    for pixel in image.pixels_mut() {
+        for (c, table) in pixel.0.iter_mut().zip(&tables) {
+            *c = table[*c as usize];
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Desktop notifications, raised with --notify when a new wallpaper is applied or an update
+//! fails, as a scheduled run otherwise gives no sign of either.
+//!
+//! Notifications are a courtesy, so failing to raise one is only logged.
+
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use log::{debug, warn};
+
+use crate::error::AppErr;
+use crate::messages::tr;
+
+#[cfg(target_os = "macos")]
+use crate::ffi_macos::show_notification;
+#[cfg(not(any(windows, target_os = "macos")))]
+use crate::ffi_unix::show_notification;
+#[cfg(windows)]
+use crate::ffi_windows::show_notification;
+
+/// The size of the thumbnail shown in a notification, which are drawn no larger
+const THUMBNAIL_PIXELS: u32 = 256;
+
+/// Tells the user the wallpaper is now the frame captured at `timestamp`, with a thumbnail of
+/// the image at `image_path`
+pub fn wallpaper_updated(timestamp: &DateTime<Utc>, image_path: &Path) {
+    let time = timestamp.format("%Y-%m-%d %H:%M UTC");
+    let thumbnail = match write_thumbnail(image_path) {
+        Ok(path) => Some(path),
+        Err(app_err) => {
+            debug!("Not showing a thumbnail: {}", app_err);
+            None
+        }
+    };
+    let title = tr!("notify-updated-title");
+    let body = tr!("notify-updated-body", time = time);
+    if let Err(app_err) = show_notification(&title, &body, thumbnail.as_deref()) {
+        warn!("Failed to show a notification: {}", app_err);
+    }
+}
+
+/// Tells the user an update failed with `error`
+pub fn update_failed(error: &AppErr) {
+    let title = tr!("notify-failed-title");
+    if let Err(app_err) = show_notification(&title, &error.to_string(), None) {
+        warn!("Failed to show a notification: {}", app_err);
+    }
+}
+
+/// Writes a small copy of the image at `image_path` to the temporary directory, as some
+/// platforms refuse to show large images
+fn write_thumbnail(image_path: &Path) -> Result<PathBuf, AppErr> {
+    let path = std::env::temp_dir().join("himawari-desktop-updater-thumbnail.png");
+    let thumbnail = image::open(image_path)?.thumbnail(THUMBNAIL_PIXELS, THUMBNAIL_PIXELS);
+    thumbnail.save(&path)?;
+    Ok(path)
+}
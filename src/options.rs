@@ -0,0 +1,784 @@
+//! Assembling the options of an update from the parsed command line.
+//!
+//! Settings from the config file and the environment are already the defaults of the command
+//! line by the time it is parsed, so [`DownloadOptionsBuilder`] reads every option from the
+//! matches alone. It checks the options go together, and logs them as it goes.
+
+use std::env::current_dir;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use clap::ArgMatches;
+use log::info;
+
+use crate::archive;
+use crate::backdrop::Backdrop;
+use crate::band::Band;
+use crate::breaker::RetryOptions;
+use crate::cache_size::CacheSize;
+use crate::colour::Colour;
+use crate::corner::Corner;
+use crate::crop::Crop;
+use crate::daylight::{DaylightOptions, Location, NightSource};
+use crate::dns::{DnsOptions, HostOverride, Resolver};
+use crate::download::DownloadOptions;
+use crate::encode::{self, EncodeOptions};
+use crate::error::{AppErr, ErrorKind};
+use crate::frame_time;
+use crate::http::{self, HttpClient, HttpOptions, Timeouts};
+use crate::layout::{Layout, Position};
+use crate::lockscreen::LockscreenOptions;
+use crate::map_layers::MapLayer;
+use crate::margins::Margins;
+use crate::messages::tr;
+use crate::metrics::Metrics;
+use crate::min_tiles::MinTiles;
+use crate::naming::Naming;
+use crate::night_dim::{Night, NightDimOptions, NightHours};
+use crate::orientation::Orientation;
+use crate::output_format::OutputFormat;
+use crate::output_level::OutputLevel;
+use crate::overlay::{OverlayItem, OverlayOptions};
+use crate::panorama::{self, Join};
+use crate::png_compression::PngCompression;
+use crate::postprocess::{Adjustment, PostProcess};
+use crate::presets::Preset;
+use crate::product::{Product, FRAME_INTERVAL_MINUTES};
+use crate::projection::Projection;
+use crate::recording::Recording;
+use crate::report::Report;
+use crate::resize_mode::ResizeMode;
+use crate::satellite::{self, Satellite};
+use crate::size::Size;
+use crate::storm::StormOptions;
+use crate::supersample::Supersample;
+use crate::tile_cache::{self, TileCache, TileCacheOptions};
+use crate::tiles::{DirectorySource, HttpSource, ImageSource};
+
+/// Builds the [`DownloadOptions`] for the updates the command line asks for
+pub struct DownloadOptionsBuilder<'a> {
+    args: &'a ArgMatches,
+    output_dir: PathBuf,
+    screen: Option<Size>,
+    recompose: bool,
+    sets_wallpaper: bool,
+}
+
+impl<'a> DownloadOptionsBuilder<'a> {
+    /// Options read from `args`, writing to `output_dir`
+    pub fn new(args: &'a ArgMatches, output_dir: PathBuf) -> DownloadOptionsBuilder<'a> {
+        DownloadOptionsBuilder {
+            args,
+            output_dir,
+            screen: None,
+            recompose: false,
+            sets_wallpaper: false,
+        }
+    }
+
+    /// Fits the image to `screen`, unless another size is given
+    pub fn screen(mut self, screen: Option<Size>) -> DownloadOptionsBuilder<'a> {
+        self.screen = screen;
+        self
+    }
+
+    /// Rebuilds a past frame from the tile cache, without going online
+    pub fn recompose(mut self, recompose: bool) -> DownloadOptionsBuilder<'a> {
+        self.recompose = recompose;
+        self
+    }
+
+    /// Notes that each image is set as the wallpaper, which an animation can't be
+    pub fn sets_wallpaper(mut self, sets_wallpaper: bool) -> DownloadOptionsBuilder<'a> {
+        self.sets_wallpaper = sets_wallpaper;
+        self
+    }
+
+    pub fn build(self) -> Result<DownloadOptions, AppErr> {
+        let DownloadOptionsBuilder {
+            args,
+            output_dir,
+            screen,
+            recompose,
+            sets_wallpaper,
+        } = self;
+
+        // If set, write only to "latest.png"
+        let store_latest_only = args.get_flag("store-latest-only");
+
+        // If set, overwrite output image. Recomposing a frame replaces the image written before.
+        let force = args.get_flag("force") || recompose;
+
+        // If set, fill in the fragments missing from an existing output image
+        let repair = args.get_flag("repair");
+
+        // Optional colour to fill the margins and the desktop around the image with
+        let background = args.get_one::<Colour>("background-color").copied();
+
+        // What to paint around the disk
+        let backdrop = args.get_one::<Backdrop>("background").copied().unwrap_or_default();
+
+        // If set, keep a high resolution copy of frames with the Moon in view
+        let capture_moon = args.get_flag("capture-moon");
+
+        // If set, keep lossless high resolution copies of frames during eclipses
+        let eclipse_mode = args.get_flag("eclipse-mode");
+
+        // If set, keep the original alongside processed images
+        let keep_raw = args.get_flag("keep-raw");
+
+        // Optionally write a small preview of each image
+        let thumbnail = args.get_one::<u32>("thumbnail").copied();
+
+        // If set, write a metadata sidecar next to the image
+        let write_sidecar = args.get_flag("write-sidecar");
+
+        // If set, embed the satellite's position in the image
+        let write_exif = args.get_flag("write-exif");
+
+        // If set, also write the checksum of each frame beside it
+        let checksum_sidecars = args.get_flag("checksum-sidecars");
+
+        // If set, keep a manifest of checksums of the frames written
+        let checksums = args.get_flag("checksums") || checksum_sidecars;
+
+        // Optional name for the latest file
+        let latest_file_name = args.get_one::<String>("latest-file-name").cloned();
+
+        // Optional output image formats, which a custom latest file name implies
+        let output_formats = match latest_file_name {
+            Some(ref name) => vec![archive::latest_file_format(name)?],
+            None => args
+                .get_one::<Vec<OutputFormat>>("output-format")
+                .cloned()
+                .unwrap_or_default(),
+        };
+        let output_format = output_formats.first().cloned().unwrap_or_default();
+        let extra_formats = output_formats.iter().skip(1).cloned().collect::<Vec<_>>();
+
+        // How images are encoded
+        let encoding = EncodeOptions {
+            jpeg_quality: args.get_one::<u8>("jpeg-quality").copied().unwrap(),
+            png_compression: args.get_one::<PngCompression>("png-compression").copied().unwrap(),
+            webp_quality: args.get_one::<u8>("webp-quality").copied(),
+            avif_quality: args.get_one::<u8>("avif-quality").copied().unwrap(),
+            tiff_16_bit: args.get_flag("tiff-16-bit"),
+        };
+        if encoding.webp_quality.is_some() && !encode::lossy_webp_available() {
+            return Err(AppErr::of_kind(ErrorKind::Config, tr!("lossy-webp-unavailable")));
+        }
+
+        // Optionally download several bands at once
+        let bands = args
+            .get_one::<Vec<Band>>("band")
+            .cloned()
+            .unwrap_or_default();
+
+        // Optional mirror to download from
+        let base_url = args
+            .get_one::<String>("base-url")
+            .map(|url| satellite::parse_base_url(url))
+            .transpose()?;
+
+        // Optionally override the levels the server is known to serve
+        let levels = args.get_one::<Vec<u32>>("levels").cloned();
+
+        // The satellite and image product to download
+        let satellite = args.get_one::<Satellite>("satellite").copied().unwrap_or_default();
+        let product = match bands.first() {
+            Some(band) => band.to_product(),
+            None => {
+                let default = satellite.default_product();
+                Product {
+                    satellite,
+                    name: args.get_one::<String>("product").cloned().unwrap_or(default.name),
+                    tile_width: args.get_one::<u32>("tile-width").copied().unwrap_or(default.tile_width),
+                    ..default
+                }
+            }
+        };
+        let product = Product {
+            base_url: base_url.clone(),
+            levels: levels.clone(),
+            ..product
+        };
+        if let Some(ref levels) = levels {
+            product.satellite.server().check_levels(levels)?;
+        }
+        let extra_bands = bands.iter().skip(1).copied().collect::<Vec<_>>();
+
+        // Optionally prefer frames in which a location is in daylight
+        let daylight = args.get_flag("prefer-daylight").then(|| DaylightOptions {
+            location: args.get_one::<Location>("location").copied().unwrap(),
+            night_source: args.get_one::<NightSource>("night-source").copied(),
+        });
+
+        // Where image fragments are cached, and how much of them to keep
+        let cache_dir = args
+            .get_one::<String>("cache-dir")
+            .map(|path| current_dir().map(|dir| dir.join(path)))
+            .transpose()?;
+        let max_cache_size = args.get_one::<CacheSize>("max-cache-size").copied().unwrap();
+
+        // Optional preset for a common screen
+        let preset = args.get_one::<Preset>("preset").copied();
+
+        // Optionally lay the disk out on a canvas, that of the preset unless another is given
+        let layout = args
+            .get_one::<Size>("canvas")
+            .cloned()
+            .or_else(|| preset.map(|preset| preset.size()))
+            .map(|canvas| Layout {
+                canvas,
+                position: args.get_one::<Position>("position").copied().unwrap_or_default(),
+            });
+
+        // Optional size to scale the output image down to
+        let resize = args
+            .get_one::<Size>("resize")
+            .cloned()
+            .or_else(|| layout.as_ref().map(|layout| layout.canvas.clone()))
+            .or_else(|| screen.clone());
+
+        // Optionally write versions for monitors turned either way, at that size
+        let variants = args.get_one::<Vec<Orientation>>("variants").cloned().unwrap_or_default();
+        if !variants.is_empty() && resize.is_none() {
+            return Err(AppErr::of_kind(ErrorKind::Config, tr!("variants-need-size")));
+        }
+
+        // How the image is made to suit that size. The screen is filled exactly.
+        let resize_mode = args
+            .get_one::<ResizeMode>("resize-mode")
+            .copied()
+            .unwrap_or(match screen {
+                Some(_) => ResizeMode::Pad,
+                None => ResizeMode::default(),
+            });
+
+        // Optionally write a lock screen version of the image
+        let lockscreen = args.get_flag("lockscreen").then(|| LockscreenOptions {
+            size: args.get_one::<Size>("lockscreen-size").cloned().or_else(|| resize.clone()),
+            blur: args.get_one::<f32>("lockscreen-blur").copied().unwrap().max(0.0),
+            darken: args.get_one::<u8>("lockscreen-darken").copied().unwrap(),
+        });
+
+        // Optionally adjust the image, applied in this order
+        let brightness = args.get_one::<i32>("brightness").copied().unwrap_or(0);
+        let contrast = args.get_one::<i32>("contrast").copied().unwrap_or(0);
+        let gamma = args.get_one::<f32>("gamma").copied().unwrap_or(1.0).max(0.1);
+        let post_process = PostProcess::new()
+            .then(Adjustment::Brightness(brightness as f32))
+            .then(Adjustment::Contrast(contrast as f32))
+            .then(Adjustment::Gamma(gamma));
+
+        // How the Earth is drawn, as the disk or reprojected onto a map
+        let projection = args.get_one::<Projection>("projection").copied().unwrap_or_default();
+
+        // Optionally dim the image at night, by the clock or the Sun
+        let night_dim = args.get_one::<u8>("night-dim").map(|&percent| NightDimOptions {
+            percent,
+            night: match args.get_one::<Location>("location") {
+                Some(&location) => Night::Sun(location),
+                None => Night::Hours(args.get_one::<NightHours>("night-hours").copied().unwrap()),
+            },
+        });
+
+        // Optionally draw a map over the disk
+        let map_layers = args.get_one::<Vec<MapLayer>>("overlay").cloned().unwrap_or_default();
+
+        // Optionally write the capture time and a caption on the image
+        let overlay_items = args.get_one::<Vec<OverlayItem>>("overlay-text").cloned().unwrap_or_default();
+        let overlay_caption = args.get_one::<String>("overlay-caption").cloned();
+        let overlay = (!overlay_items.is_empty() || overlay_caption.is_some()).then(|| OverlayOptions {
+            items: overlay_items,
+            caption: overlay_caption,
+            corner: args.get_one::<Corner>("overlay-position").copied().unwrap(),
+            size: args.get_one::<u32>("overlay-size").map(|size| *size as f32),
+            colour: args.get_one::<Colour>("overlay-colour").copied().unwrap(),
+        });
+
+        // If set, don't reuse cached responses
+        let no_cache = args.get_flag("no-cache");
+
+        // If set, reuse the fragments in space from earlier frames
+        let differential = args.get_flag("differential");
+
+        // How hard to try for each image fragment, and how many are needed
+        let tile_retries = args.get_one::<u32>("tile-retries").copied().unwrap();
+        let min_tiles = args.get_one::<MinTiles>("min-tiles").copied().unwrap_or_default();
+
+        // Optionally join GOES-West's disk to Himawari's
+        let panorama = args.get_one::<Join>("panorama").copied();
+
+        // If set, take the frame before the latest when the latest isn't finished
+        let placeholder_fallback = args.get_flag("placeholder-fallback");
+
+        // Optionally crop to follow a storm
+        let follow_storm = args
+            .get_one::<String>("follow-storm")
+            .map(|name| StormOptions {
+                name: name.clone(),
+                feed_url: args.get_one::<String>("storm-feed").cloned().unwrap(),
+                zoom: args.get_one::<u32>("storm-zoom").copied().unwrap(),
+                satellite_longitude: product.satellite.longitude(),
+            });
+        if recompose && follow_storm.is_some() {
+            return Err(AppErr::of_kind(
+                ErrorKind::Config,
+                tr!("recompose-offline", option = "--follow-storm"),
+            ));
+        }
+
+        // The client shared by every download, which resolves the hosts they use up front if
+        // another resolver or their addresses are given
+        let hosts = std::iter::once(product.base_url())
+            .chain(follow_storm.as_ref().map(|storm| storm.feed_url.as_str()))
+            .filter_map(|url| reqwest::Url::parse(url).ok()?.host_str().map(String::from))
+            .collect();
+        let http_options = http_options(args, hosts)?;
+        let http = HttpClient::new(http_options.clone());
+
+        // Where to get the image fragments from
+        let tile_dir = match args.get_one::<String>("from-tiles") {
+            Some(location) => Some(DirectorySource::new(location)?),
+            // The tile cache is laid out like --save-tiles, so can be read like a tile directory
+            None if recompose => Some(DirectorySource {
+                dir: cache_dir.clone().unwrap_or_else(tile_cache::default_dir),
+            }),
+            None => None,
+        };
+        let save_tiles = args
+            .get_one::<String>("save-tiles")
+            .map(|dir| current_dir().map(|current| current.join(dir)))
+            .transpose()?;
+
+        // Optionally keep the metrics of each update, and a report of its tile requests
+        let metrics = match args.contains_id("metrics") {
+            true => Metrics::new(),
+            false => Metrics::default(),
+        };
+        let report = match args.contains_id("report") {
+            true => Report::new(),
+            false => Report::default(),
+        };
+
+        let tiles: Arc<dyn ImageSource> = match tile_dir {
+            Some(ref tile_dir) => Arc::new(tile_dir.clone()),
+            None => Arc::new(HttpSource {
+                client: http.clone(),
+                cache: TileCache::new(TileCacheOptions {
+                    dir: cache_dir.clone(),
+                    max_bytes: max_cache_size.0,
+                    differential,
+                }),
+                save_dir: save_tiles.clone(),
+                metrics: metrics.clone(),
+                report: report.clone(),
+            }),
+        };
+
+        // Optionally crop to a region of interest
+        let crop = args.get_one::<Crop>("crop").cloned();
+
+        // Optionally render larger than the resize target
+        let supersample = args
+            .get_one::<Supersample>("supersample")
+            .cloned()
+            .unwrap_or_default();
+
+        // Optional output image resolution.
+        // When resizing, default to the smallest level which needs no upscaling.
+        let output_level = args
+            .get_one::<OutputLevel>("output-level")
+            .cloned()
+            .or_else(|| {
+                resize.as_ref().map(|size| {
+                    let zoom = follow_storm.as_ref().map_or(1, |storm| storm.zoom);
+                    // A layout leaves room around the disk, so only the disk itself needs covering
+                    let disk = layout.as_ref().map_or(size.width.max(size.height), Layout::disk_pixels);
+                    let pixels = disk * supersample.to_factor() * zoom;
+                    // A crop in percentages keeps only part of the image, which must still cover it
+                    let pixels = (pixels as f64 * crop.as_ref().map_or(1.0, Crop::zoom)).ceil() as u32;
+                    OutputLevel::smallest_covering(pixels, &product)
+                })
+            })
+            .unwrap_or_default();
+
+        // Optional naming scheme for archived frames
+        let naming = args
+            .get_one::<Naming>("naming")
+            .copied()
+            .unwrap_or_default();
+
+        // Optional margins to put on the image
+        let margins = args
+            .get_one::<Margins>("margins")
+            .cloned()
+            .or_else(|| layout.as_ref().map(|layout| layout.margins(product.tile_width * output_level.to_level())))
+            .unwrap_or_default();
+
+        if let Some(ref crop) = crop {
+            let disk = product.tile_width * output_level.to_level();
+            let (width, height) = (margins.left + disk + margins.right, margins.top + disk + margins.bottom);
+            if crop.region(width, height).is_none() {
+                return Err(AppErr::of_kind(
+                    ErrorKind::Config,
+                    tr!("crop-outside-image", crop = crop, width = width, height = height),
+                ));
+            }
+        }
+
+        // Optionally download the frame from a particular time
+        let requested_time = match args.subcommand() {
+            Some(("recompose", args)) => args.get_one::<DateTime<Utc>>("time").copied(),
+            _ => args.get_one::<DateTime<Utc>>("time").copied(),
+        };
+        if let Some(ref time) = requested_time {
+            if args.get_flag("strict") && !frame_time::is_frame_time(time) {
+                return Err(AppErr::of_kind(
+                    ErrorKind::Config,
+                    tr!("no-frame-at-time", time = time, interval = FRAME_INTERVAL_MINUTES),
+                ));
+            }
+        }
+
+        // Optionally download several of the most recent frames
+        let frames = args.get_one::<u32>("frames").copied().unwrap_or(1);
+
+        // Animations are made of those frames, and can't be the wallpaper
+        if let Some(format) = output_formats.iter().find(|format| format.is_animated()) {
+            if output_formats.len() > 1 || sets_wallpaper || args.get_flag("set-lockscreen") {
+                return Err(AppErr::of_kind(ErrorKind::Config, tr!("animated-format-alone", format = format)));
+            }
+            if frames < 2 {
+                return Err(AppErr::of_kind(
+                    ErrorKind::Config,
+                    tr!("animated-format-needs-frames", format = format),
+                ));
+            }
+        }
+
+        // Only Himawari frames are captured at known times, every ten minutes on the minute
+        let needs_frame_times = requested_time.is_some()
+            || daylight.as_ref().is_some_and(|daylight| daylight.night_source.is_none())
+            || frames > 1
+            || args.contains_id("animate")
+            || matches!(args.subcommand(), Some(("backfill", _)) | Some(("frames", _)));
+        if satellite != Satellite::Himawari && needs_frame_times {
+            return Err(AppErr::of_kind(
+                ErrorKind::Config,
+                tr!("satellite-latest-only", satellite = satellite),
+            ));
+        }
+        if panorama.is_some() && needs_frame_times {
+            return Err(AppErr::of_kind(
+                ErrorKind::Config,
+                tr!("satellite-latest-only", satellite = panorama::EASTERN_SATELLITE),
+            ));
+        }
+        // Each band is served at its own levels
+        let night_product = daylight
+            .as_ref()
+            .and_then(|daylight| daylight.night_source)
+            .map(|source| product.alongside(source.to_product()));
+        let products = std::iter::once(product.clone())
+            .chain(extra_bands.iter().map(|band| product.alongside(band.to_product())))
+            .chain(night_product);
+        for product in products {
+            if !product.levels().contains(&output_level.to_level()) {
+                let levels = product.levels().iter().map(|level| level.to_string()).collect::<Vec<_>>();
+                return Err(AppErr::of_kind(
+                    ErrorKind::Config,
+                    tr!(
+                        "level-unsupported",
+                        level = output_level,
+                        product = product.name,
+                        levels = levels.join(", ")
+                    ),
+                ));
+            }
+        }
+
+        info!("store-latest-only: {}", store_latest_only);
+        if let Some(ref name) = latest_file_name {
+            info!("latest-file-name: {}", name);
+        }
+        info!("force: {}", force);
+        info!("repair: {}", repair);
+        info!("capture-moon: {}", capture_moon);
+        info!("eclipse-mode: {}", eclipse_mode);
+        info!("keep-raw: {}", keep_raw);
+        if let Some(width) = thumbnail {
+            info!("thumbnail: {}px wide", width);
+        }
+        if !variants.is_empty() {
+            let names = variants.iter().map(|orientation| orientation.to_string()).collect::<Vec<_>>();
+            info!("variants: {}", names.join(", "));
+        }
+        info!("write-sidecar: {}", write_sidecar);
+        info!("write-exif: {}", write_exif);
+        info!("checksums: {}", checksums);
+        if checksum_sidecars {
+            info!("checksum-sidecars: true");
+        }
+        info!("output-dir: {}", output_dir.display());
+        info!("output-format: {}", output_format);
+        for extra_format in &extra_formats {
+            info!("output-format: {}", extra_format);
+        }
+        info!("jpeg-quality: {}", encoding.jpeg_quality);
+        info!("png-compression: {}", encoding.png_compression);
+        match encoding.webp_quality {
+            Some(quality) => info!("webp-quality: {}", quality),
+            None => info!("webp-quality: lossless"),
+        }
+        info!("avif-quality: {}", encoding.avif_quality);
+        if encoding.tiff_16_bit {
+            info!("tiff-16-bit: true");
+        }
+        info!("naming: {}", naming);
+        info!("satellite: {}", satellite);
+        info!("product: {} ({}px tiles)", product.name, product.tile_width);
+        if !bands.is_empty() {
+            let names = bands.iter().map(|band| band.to_string()).collect::<Vec<_>>();
+            info!("band: {}", names.join(", "));
+        }
+        if let Some(ref daylight) = daylight {
+            match daylight.night_source {
+                Some(source) => info!("prefer-daylight: at {}, else from {}", daylight.location, source),
+                None => info!("prefer-daylight: at {}", daylight.location),
+            }
+        }
+        match (tile_dir, save_tiles) {
+            (Some(tile_dir), _) => info!("from-tiles: {}", tile_dir.dir.display()),
+            (None, Some(dir)) => info!("save-tiles: {}", dir.display()),
+            (None, None) => {}
+        }
+        match http_options.recording {
+            Some(Recording::Record(ref dir)) => info!("record: {}", dir.display()),
+            Some(Recording::Replay(ref dir)) => info!("replay: {}", dir.display()),
+            None => {}
+        }
+        info!("no-cache: {}", no_cache);
+        if let Some(ref dir) = cache_dir {
+            info!("cache-dir: {}", dir.display());
+        }
+        info!("max-cache-size: {}", max_cache_size);
+        if differential {
+            info!("differential: true");
+        }
+        info!("concurrency: {}", http_options.concurrency);
+        if let Some(max_bandwidth) = http_options.max_bandwidth {
+            info!("max-bandwidth: {} KB/s", max_bandwidth / 1024);
+        }
+        info!("timeout: {}s", http_options.timeouts.total.as_secs());
+        if let Some(timeout) = http_options.timeouts.connect {
+            info!("connect-timeout: {}s", timeout.as_secs());
+        }
+        if let Some(proxy) = args.get_one::<String>("proxy") {
+            info!("proxy: {}", http::without_password(proxy));
+        }
+        if let Some(ref base_url) = base_url {
+            info!("base-url: {}", base_url);
+        }
+        if let Some(ref levels) = levels {
+            let levels = levels.iter().map(|level| level.to_string()).collect::<Vec<_>>();
+            info!("levels: {}", levels.join(", "));
+        }
+        info!("tile-retries: {}", tile_retries);
+        info!("min-tiles: {}", min_tiles);
+        if placeholder_fallback {
+            info!("placeholder-fallback: true");
+        }
+        if let Some(join) = panorama {
+            info!("panorama: {}", join);
+        }
+        if let Some(ref dns) = http_options.dns {
+            for host_override in &dns.overrides {
+                info!("resolve: {} = {:?}", host_override.host, host_override.addrs);
+            }
+            match dns.resolver {
+                Some(Resolver::Server(ref server)) => info!("dns-server: {}", server),
+                Some(Resolver::DnsOverHttps(ref url)) => info!("doh: {}", url),
+                None => {}
+            }
+        }
+        info!("output-level: {}", output_level);
+        if let Some(ref time) = requested_time {
+            info!("time: {}", time);
+        }
+        info!("frames: {}", frames);
+        info!(
+            "margins: {}, {}, {}, {}",
+            margins.top, margins.right, margins.bottom, margins.left
+        );
+        if let Some(background) = background {
+            info!("background-color: {}", background);
+        }
+        if backdrop != Backdrop::Black {
+            info!("background: {}", backdrop);
+        }
+        if let Some(ref lockscreen) = lockscreen {
+            info!(
+                "lockscreen: size {}, blur {}, darken {}%",
+                lockscreen.size.as_ref().map_or("unchanged".to_string(), |size| size.to_string()),
+                lockscreen.blur,
+                lockscreen.darken
+            );
+        }
+        for adjustment in post_process.adjustments() {
+            match adjustment {
+                Adjustment::Brightness(percent) => info!("brightness: {}%", percent),
+                Adjustment::Contrast(percent) => info!("contrast: {}%", percent),
+                Adjustment::Gamma(gamma) => info!("gamma: {}", gamma),
+            }
+        }
+        if projection != Projection::Disk {
+            info!("projection: {}", projection);
+        }
+        if let Some(ref night_dim) = night_dim {
+            info!("night-dim: {}", night_dim);
+        }
+        if !map_layers.is_empty() {
+            let names = map_layers.iter().map(|layer| layer.to_string()).collect::<Vec<_>>();
+            info!("overlay: {}", names.join(", "));
+        }
+        if let Some(ref overlay) = overlay {
+            let items = overlay.items.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+            info!(
+                "overlay-text: {} in the {} corner, {} in {}",
+                items.join(", "),
+                overlay.corner,
+                overlay.size.map_or("auto".to_string(), |size| format!("{}px", size)),
+                overlay.colour
+            );
+            if let Some(ref caption) = overlay.caption {
+                info!("overlay-caption: {}", caption);
+            }
+        }
+        if let Some(ref storm) = follow_storm {
+            info!("follow-storm: {} (zoom {})", storm.name, storm.zoom);
+        }
+        if let Some(ref crop) = crop {
+            info!("crop: {}", crop);
+        }
+        if let Some(ref preset) = preset {
+            info!("preset: {}", preset);
+        }
+        if let Some(ref layout) = layout {
+            info!("layout: {}", layout);
+        }
+        if let Some(ref size) = resize {
+            info!("resize: {} ({})", size, resize_mode);
+            info!("supersample: {}", supersample);
+        }
+
+        Ok(DownloadOptions {
+            store_latest_only,
+            latest_file_name,
+            force,
+            repair,
+            capture_moon,
+            eclipse_mode,
+            keep_raw,
+            write_sidecar,
+            write_exif,
+            checksums,
+            checksum_sidecars,
+            margins,
+            output_dir,
+            output_format,
+            extra_formats,
+            encoding,
+            naming,
+            output_level,
+            requested_time,
+            frames,
+            product,
+            extra_bands,
+            tiles,
+            retry: RetryOptions {
+                tile_retries,
+                min_tiles,
+            },
+            http,
+            metrics,
+            report,
+            follow_storm,
+            resize,
+            resize_mode,
+            lockscreen,
+            overlay,
+            map_layers,
+            post_process,
+            projection,
+            background,
+            backdrop,
+            crop,
+            daylight,
+            placeholder_fallback,
+            panorama,
+            thumbnail,
+            night_dim,
+            variants,
+        })
+    }
+}
+
+/// The options of the HTTP client given on the command line, looking up `hosts` up front if
+/// another resolver is given
+pub fn http_options(args: &ArgMatches, hosts: Vec<String>) -> Result<HttpOptions, AppErr> {
+    // How many image fragments to download at once
+    let concurrency = args.get_one::<u32>("concurrency").copied().unwrap();
+
+    // How fast to download image fragments, in kilobytes per second
+    let max_bandwidth = args.get_one::<u64>("max-bandwidth").copied();
+
+    // How long requests are given
+    let timeouts = Timeouts {
+        total: Duration::from_secs(args.get_one::<u64>("timeout").copied().unwrap()),
+        connect: args.get_one::<u64>("connect-timeout").map(|&seconds| Duration::from_secs(seconds)),
+    };
+
+    // Optional proxy to send every request through
+    let proxy = args.get_one::<String>("proxy").map(|url| http::parse_proxy(url)).transpose()?;
+
+    // Optionally resolve hosts without the system resolver
+    let resolver = match (args.get_one::<IpAddr>("dns-server"), args.get_one::<String>("doh")) {
+        (Some(server), _) => Some(Resolver::Server(*server)),
+        (None, Some(url)) => Some(Resolver::DnsOverHttps(url.clone())),
+        (None, None) => None,
+    };
+    let overrides = args
+        .get_many::<HostOverride>("resolve")
+        .map(|overrides| overrides.cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let dns = (resolver.is_some() || !overrides.is_empty()).then_some(DnsOptions {
+        resolver,
+        overrides,
+        hosts,
+    });
+
+    // Optionally record the responses to every request, or replay them
+    let recording = match (args.get_one::<String>("record"), args.get_one::<String>("replay")) {
+        (Some(dir), _) => Some(Recording::Record(current_dir()?.join(dir))),
+        (None, Some(dir)) => Some(Recording::Replay(current_dir()?.join(dir))),
+        (None, None) => None,
+    };
+
+    // Even the HTTP cache stays as it is on a dry run
+    let cache_responses = !args.get_flag("no-cache") && !args.get_flag("dry-run");
+
+    Ok(HttpOptions {
+        concurrency: concurrency as usize,
+        timeouts,
+        proxy,
+        max_bandwidth: max_bandwidth.map(|max_bandwidth| max_bandwidth * 1024),
+        dns,
+        recording,
+        cache_responses,
+    })
+}
@@ -0,0 +1,88 @@
+//! Versions of the image for monitors turned either way, with `--variants`, so that a rotated
+//! monitor gets a portrait image and the others a landscape one from the same download.
+//!
+//! Each version is the --resize size turned to its orientation, and is written next to the
+//! image, named like himawari8_<timestamp>_portrait.png.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::size::Size;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// Wider than it is tall
+    Landscape,
+    /// Taller than it is wide
+    Portrait,
+}
+
+pub const ORIENTATIONS: [Orientation; 2] = [Orientation::Landscape, Orientation::Portrait];
+
+#[derive(Clone)]
+pub struct OrientationsValueParser;
+
+impl clap::builder::TypedValueParser for OrientationsValueParser {
+    type Value = Vec<Orientation>;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let mut orientations = Vec::new();
+        for name in value.to_string_lossy().split(',') {
+            let orientation = match Orientation::try_parse(name) {
+                Some(orientation) => orientation,
+                None => return Err(Error::raw(ErrorKind::InvalidValue, "Use landscape or portrait, or both comma separated")),
+            };
+            if !orientations.contains(&orientation) {
+                orientations.push(orientation);
+            }
+        }
+        Ok(orientations)
+    }
+}
+
+impl Orientation {
+    pub fn try_parse(input: &str) -> Option<Orientation> {
+        match input.trim() {
+            "landscape" => Some(Orientation::Landscape),
+            "portrait" => Some(Orientation::Portrait),
+            _ => None,
+        }
+    }
+
+    /// The orientation of a screen `width` by `height` pixels. A square one counts as landscape.
+    pub fn of(width: u32, height: u32) -> Orientation {
+        match height > width {
+            true => Orientation::Portrait,
+            false => Orientation::Landscape,
+        }
+    }
+
+    /// `size` turned to this orientation
+    pub fn orient(self, size: &Size) -> Size {
+        let (long, short) = (size.width.max(size.height), size.width.min(size.height));
+        match self {
+            Orientation::Landscape => Size { width: long, height: short },
+            Orientation::Portrait => Size { width: short, height: long },
+        }
+    }
+}
+
+impl Display for Orientation {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            Orientation::Landscape => "landscape",
+            Orientation::Portrait => "portrait",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The image to show on monitors in `orientation`: its version for them, if `variants` has
+/// one, or else the image at `image_path` itself
+pub fn image_for(image_path: &Path, variants: &[Orientation], orientation: Orientation) -> PathBuf {
+    match variants.contains(&orientation) {
+        true => archive::variant_path(image_path, orientation),
+        false => image_path.to_path_buf(),
+    }
+}
@@ -0,0 +1,82 @@
+//! The outcome of an update, printed as JSON with --json so scripts and monitoring can read it
+//! without parsing the log.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use serde_derive::Serialize;
+
+use crate::archive;
+use crate::download::DownloadedFrame;
+use crate::error::{AppErr, TileFailure};
+
+#[derive(Serialize)]
+pub struct Outcome {
+    pub success: bool,
+    /// The capture time of the frame
+    pub timestamp: Option<DateTime<Utc>>,
+    pub path: Option<PathBuf>,
+    /// False if the frame had already been downloaded
+    pub written: bool,
+    pub tiles_ok: Option<u32>,
+    pub tiles_failed: Option<u32>,
+    pub duration_secs: f64,
+    /// Whether the wallpaper was set, or None if it wasn't asked for
+    pub wallpaper_set: Option<bool>,
+    pub error: Option<String>,
+    /// How the tiles went wrong, for an update which failed for want of them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tile_failures: Vec<TileFailure>,
+}
+
+impl Outcome {
+    /// The outcome of an update which took `duration`, downloading frames `level` tiles a side
+    pub fn new(
+        result: &Result<DownloadedFrame, AppErr>,
+        level: u32,
+        duration: Duration,
+        wallpaper_set: Option<bool>,
+    ) -> Outcome {
+        match result {
+            Ok(frame) => {
+                // Only a frame left incomplete has a record of its tiles
+                let (total, failed) = match archive::read_missing_tiles(&frame.path) {
+                    Some(missing) if missing.timestamp == frame.timestamp => {
+                        (missing.level * missing.level, missing.tiles.len() as u32)
+                    }
+                    _ => (level * level, 0),
+                };
+                Outcome {
+                    success: true,
+                    timestamp: Some(frame.timestamp),
+                    path: Some(frame.path.clone()),
+                    written: frame.written,
+                    tiles_ok: Some(total - failed),
+                    tiles_failed: Some(failed),
+                    duration_secs: duration.as_secs_f64(),
+                    wallpaper_set,
+                    error: None,
+                    tile_failures: Vec::new(),
+                }
+            }
+            Err(app_err) => Outcome {
+                success: false,
+                timestamp: None,
+                path: None,
+                written: false,
+                tiles_ok: None,
+                tiles_failed: None,
+                duration_secs: duration.as_secs_f64(),
+                wallpaper_set,
+                error: Some(app_err.to_string()),
+                tile_failures: app_err.tile_failures().to_vec(),
+            },
+        }
+    }
+
+    /// Prints the outcome to stdout as a single line of JSON
+    pub fn print(&self) {
+        println!("{}", serde_json::to_string(self).unwrap());
+    }
+}
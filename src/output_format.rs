@@ -1,22 +1,67 @@
 use std::fmt::{Display, Error as FmtError, Formatter};
 
-#[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Default, PartialEq, Eq)]
 pub enum OutputFormat {
     PNG,
+    #[default]
     JPEG,
+    /// Lossless unless --webp-quality is given
+    WEBP,
+    AVIF,
+    /// Uncompressed, without transparency, for older Windows wallpaper settings
+    BMP,
+    /// Uncompressed, in 16 bits a channel with --tiff-16-bit
+    TIFF,
+    /// An animation of the frames asked for with --frames
+    GIF,
+    APNG,
 }
 
 #[derive(Clone)]
-pub struct OutputFormatValueParser;
+pub struct OutputFormatsValueParser;
 
-impl clap::builder::TypedValueParser for OutputFormatValueParser {
-    type Value = OutputFormat;
+impl clap::builder::TypedValueParser for OutputFormatsValueParser {
+    type Value = Vec<OutputFormat>;
     fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
         use clap::error::{Error, ErrorKind};
-        match value.to_string_lossy().as_ref().trim() {
-            "PNG" | "png" => Ok(OutputFormat::PNG),
-            "JPEG" | "jpeg" => Ok(OutputFormat::JPEG),
-            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid image format, use JPEG or PNG")),
+        let mut formats = Vec::new();
+        for name in value.to_string_lossy().split(',') {
+            let format = match name.trim() {
+                "PNG" | "png" => OutputFormat::PNG,
+                "JPEG" | "jpeg" => OutputFormat::JPEG,
+                "WEBP" | "webp" => OutputFormat::WEBP,
+                "AVIF" | "avif" => OutputFormat::AVIF,
+                "BMP" | "bmp" => OutputFormat::BMP,
+                "TIFF" | "tiff" | "tif" => OutputFormat::TIFF,
+                "GIF" | "gif" => OutputFormat::GIF,
+                "APNG" | "apng" => OutputFormat::APNG,
+                _ => return Err(Error::raw(ErrorKind::InvalidValue, "Invalid image format, use JPEG, PNG, WEBP, AVIF, BMP or TIFF, or several comma separated, or GIF or APNG for an animation")),
+            };
+            if !formats.contains(&format) {
+                formats.push(format);
+            }
+        }
+        Ok(formats)
+    }
+}
+
+impl OutputFormat {
+    pub fn is_animated(&self) -> bool {
+        matches!(self, OutputFormat::GIF | OutputFormat::APNG)
+    }
+
+    /// Maps a file extension back to the format which would have written it.
+    /// Animations are not frames, so their extensions are left out.
+    pub fn from_extension(ext: &str) -> Option<OutputFormat> {
+        match ext.to_ascii_lowercase().as_str() {
+            "png" => Some(OutputFormat::PNG),
+            "jpeg" | "jpg" => Some(OutputFormat::JPEG),
+            "webp" => Some(OutputFormat::WEBP),
+            "avif" => Some(OutputFormat::AVIF),
+            "bmp" => Some(OutputFormat::BMP),
+            "tiff" | "tif" => Some(OutputFormat::TIFF),
+            _ => None,
         }
     }
 }
@@ -26,13 +71,13 @@ impl Display for OutputFormat {
         let s = match *self {
             OutputFormat::PNG => "png",
             OutputFormat::JPEG => "jpeg",
+            OutputFormat::WEBP => "webp",
+            OutputFormat::AVIF => "avif",
+            OutputFormat::BMP => "bmp",
+            OutputFormat::TIFF => "tiff",
+            OutputFormat::GIF => "gif",
+            OutputFormat::APNG => "apng",
         };
         write!(f, "{}", s)
     }
 }
-
-impl Default for OutputFormat {
-    fn default() -> OutputFormat {
-        OutputFormat::JPEG
-    }
-}
@@ -1,8 +1,13 @@
 use std::fmt::{Display, Error as FmtError, Formatter};
 
+use crate::product::Product;
+
 #[derive(Clone)]
 pub struct OutputLevel(u32);
 
+/// The most tiles a side any server splits a frame into
+const MAX_LEVEL: u32 = 64;
+
 #[derive(Clone)]
 pub struct OutputLevelValueParser;
 
@@ -10,22 +15,67 @@ impl clap::builder::TypedValueParser for OutputLevelValueParser {
     type Value = OutputLevel;
     fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
         use clap::error::{Error, ErrorKind};
-        match value.to_string_lossy().as_ref().trim().parse::<u32>() {
-            Ok(n) if n == 4 || n == 8 || n == 16 || n == 20 => Ok(OutputLevel(n)),
-            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid level, use 4, 8, 16 or 20")),
+        // Which levels are served depends on the product, so is checked once that is known
+        match parse_level(value.to_string_lossy().as_ref()) {
+            Some(n) => Ok(OutputLevel(n)),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Invalid level, use a number of tiles a side, e.g. 4, 8, 16 or 20")),
+        }
+    }
+}
+
+/// A list of levels, as served by a mirror
+#[derive(Clone)]
+pub struct LevelsValueParser;
+
+impl clap::builder::TypedValueParser for LevelsValueParser {
+    type Value = Vec<u32>;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let mut levels = Vec::new();
+        for level in value.to_string_lossy().split(',') {
+            match parse_level(level) {
+                Some(n) => levels.push(n),
+                None => return Err(Error::raw(ErrorKind::InvalidValue, "Invalid levels, use a comma separated list of numbers of tiles a side")),
+            }
         }
+        levels.sort_unstable();
+        levels.dedup();
+        Ok(levels)
     }
 }
 
+fn parse_level(input: &str) -> Option<u32> {
+    let level = input.trim().parse::<u32>().ok()?;
+    (1..=MAX_LEVEL).contains(&level).then_some(level)
+}
+
 impl OutputLevel {
     pub fn to_level(&self) -> u32 {
         self.0
     }
+
+    /// The largest level `product` is served at
+    pub fn max(product: &Product) -> OutputLevel {
+        let levels = product.levels();
+        OutputLevel(levels[levels.len() - 1])
+    }
+
+    /// The smallest level of `product` whose stitched image is at least `pixels` wide,
+    /// or the largest level if none are big enough
+    pub fn smallest_covering(pixels: u32, product: &Product) -> OutputLevel {
+        let levels = product.levels();
+        let level = levels
+            .iter()
+            .copied()
+            .find(|level| level * product.tile_width >= pixels)
+            .unwrap_or(levels[levels.len() - 1]);
+        OutputLevel(level)
+    }
 }
 
 impl Display for OutputLevel {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
-        let &OutputLevel(ref n) = self;
+        let OutputLevel(n) = self;
         write!(f, "{}", n)
     }
 }
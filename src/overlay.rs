@@ -0,0 +1,176 @@
+//! Text drawn in a corner of the image with `--overlay-text`: the time the frame was
+//! captured, in UTC or local time, the satellite's name and a caption, one to a line.
+//!
+//! The text is set in the bundled DejaVu Sans, so it looks the same everywhere, with a
+//! dark shadow to keep it readable over cloud.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use chrono::prelude::*;
+use image::{Rgba, RgbaImage};
+use log::warn;
+
+use crate::colour::Colour;
+use crate::corner::Corner;
+
+const FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// The text size used unless --overlay-size is given, as a fraction of the image height
+const DEFAULT_SIZE_FRACTION: f32 = 1.0 / 40.0;
+
+const MIN_SIZE: f32 = 12.0;
+
+/// Something written on a line of the overlay
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OverlayItem {
+    /// The capture time in UTC
+    Utc,
+    /// The capture time in the computer's time zone
+    Local,
+    Satellite,
+}
+
+#[derive(Clone)]
+pub struct OverlayItemsValueParser;
+
+impl clap::builder::TypedValueParser for OverlayItemsValueParser {
+    type Value = Vec<OverlayItem>;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let mut items = Vec::new();
+        for name in value.to_string_lossy().split(',') {
+            let item = match name.trim() {
+                "utc" => OverlayItem::Utc,
+                "local" => OverlayItem::Local,
+                "satellite" => OverlayItem::Satellite,
+                _ => return Err(Error::raw(ErrorKind::InvalidValue, "Invalid overlay text, use a comma separated list of utc, local and satellite")),
+            };
+            if !items.contains(&item) {
+                items.push(item);
+            }
+        }
+        Ok(items)
+    }
+}
+
+impl Display for OverlayItem {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            OverlayItem::Utc => "utc",
+            OverlayItem::Local => "local",
+            OverlayItem::Satellite => "satellite",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone)]
+pub struct OverlayOptions {
+    pub items: Vec<OverlayItem>,
+    /// A line written after the items
+    pub caption: Option<String>,
+    pub corner: Corner,
+    /// The height of the text in pixels, or None to suit the image
+    pub size: Option<f32>,
+    pub colour: Colour,
+}
+
+/// The lines written on the frame captured at `timestamp` by `satellite`
+pub fn lines(options: &OverlayOptions, timestamp: &DateTime<Utc>, satellite: &str) -> Vec<String> {
+    let items = options.items.iter().map(|item| match item {
+        OverlayItem::Utc => timestamp.format("%Y-%m-%d %H:%M UTC").to_string(),
+        OverlayItem::Local => timestamp
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M (UTC%:z)")
+            .to_string(),
+        OverlayItem::Satellite => satellite.to_string(),
+    });
+    items.chain(options.caption.clone()).collect()
+}
+
+/// Blends `colour` into the pixel at (`x`, `y`) with `coverage` from 0 to 1, if it is in the image
+pub(crate) fn blend(image: &mut RgbaImage, x: i32, y: i32, colour: Rgba<u8>, coverage: f32) {
+    if x < 0 || y < 0 || x >= image.width() as i32 || y >= image.height() as i32 {
+        return;
+    }
+    let alpha = coverage.clamp(0.0, 1.0) * colour.0[3] as f32 / 255.0;
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    for (c, new) in pixel.0.iter_mut().zip(colour.0).take(3) {
+        *c = (*c as f32 * (1.0 - alpha) + new as f32 * alpha).round() as u8;
+    }
+}
+
+/// Draws `lines` in a corner of `image`, as `options` say
+pub fn draw(image: &mut RgbaImage, lines: &[String], options: &OverlayOptions) {
+    if lines.is_empty() {
+        return;
+    }
+    let font = match FontRef::try_from_slice(FONT) {
+        Ok(font) => font,
+        Err(err) => {
+            warn!("Failed to load the overlay font: {}", err);
+            return;
+        }
+    };
+    let size = options
+        .size
+        .unwrap_or(image.height() as f32 * DEFAULT_SIZE_FRACTION)
+        .max(MIN_SIZE);
+    let scale = PxScale::from(size);
+    let scaled = font.as_scaled(scale);
+    let line_height = scaled.height() + scaled.line_gap();
+    let margin = size;
+    let shadow_offset = (size / 16.0).max(1.0).round() as i32;
+    let shadow = Rgba([0, 0, 0, options.colour.0 .0[3]]);
+
+    let line_width = |line: &str| {
+        let mut width = 0.0;
+        let mut previous = None;
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                width += scaled.kern(previous, id);
+            }
+            width += scaled.h_advance(id);
+            previous = Some(id);
+        }
+        width
+    };
+
+    let block_height = line_height * lines.len() as f32;
+    let top = match options.corner.is_top() {
+        true => margin,
+        false => image.height() as f32 - margin - block_height,
+    };
+    for (i, line) in lines.iter().enumerate() {
+        let left = match options.corner.is_left() {
+            true => margin,
+            false => image.width() as f32 - margin - line_width(line),
+        };
+        let baseline = top + line_height * i as f32 + scaled.ascent();
+
+        // The shadow first, then the text over it
+        for (colour, offset) in [(shadow, shadow_offset), (options.colour.0, 0)] {
+            let mut x = left;
+            let mut previous = None;
+            for c in line.chars() {
+                let id = scaled.glyph_id(c);
+                if let Some(previous) = previous {
+                    x += scaled.kern(previous, id);
+                }
+                let glyph = id.with_scale_and_position(scale, point(x, baseline));
+                x += scaled.h_advance(id);
+                previous = Some(id);
+                if let Some(outline) = font.outline_glyph(glyph) {
+                    let bounds = outline.px_bounds();
+                    outline.draw(|gx, gy, coverage| {
+                        let px = bounds.min.x as i32 + gx as i32 + offset;
+                        let py = bounds.min.y as i32 + gy as i32 + offset;
+                        blend(image, px, py, colour, coverage);
+                    });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Wide panoramas of two satellites' disks for ultrawide screens, with `--panorama`: Himawari
+//! on the left, and GOES-West to the east of it on the right, which between them see from
+//! the Indian Ocean to the Americas.
+//!
+//! The disks can be laid side by side, or blended. Blending overlaps them so that the point on
+//! the equator halfway between the satellites is in the same place in both, and fades from one
+//! disk to the other across the overlap.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use image::{Rgba, RgbaImage};
+
+use crate::geo;
+use crate::satellite::Satellite;
+
+/// The satellite whose disk is joined to the right of Himawari's
+pub const EASTERN_SATELLITE: Satellite = Satellite::GoesWest;
+
+/// How the two disks are joined
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Join {
+    #[default]
+    SideBySide,
+    Blend,
+}
+
+#[derive(Clone)]
+pub struct JoinValueParser;
+
+impl clap::builder::TypedValueParser for JoinValueParser {
+    type Value = Join;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match Join::try_parse(value.to_string_lossy().as_ref()) {
+            Some(join) => Ok(join),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Invalid panorama, use side-by-side or blend")),
+        }
+    }
+}
+
+impl Join {
+    pub fn try_parse(input: &str) -> Option<Join> {
+        match input.trim() {
+            "side-by-side" => Some(Join::SideBySide),
+            "blend" => Some(Join::Blend),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Join {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            Join::SideBySide => "side-by-side",
+            Join::Blend => "blend",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How far right of the left disk the right disk goes, as a fraction of the disk's width
+fn offset(join: Join, left_longitude: f64, right_longitude: f64) -> f64 {
+    match join {
+        Join::SideBySide => 1.0,
+        Join::Blend => {
+            // Halfway round from the left satellite to the right one, going east
+            let middle = left_longitude + (right_longitude - left_longitude).rem_euclid(360.0) / 2.0;
+            match (geo::project(0.0, middle, left_longitude), geo::project(0.0, middle, right_longitude)) {
+                (Some((left_x, _)), Some((right_x, _))) => (left_x - right_x).clamp(0.0, 1.0),
+                // The satellites are too far apart to see a point in common
+                _ => 1.0,
+            }
+        }
+    }
+}
+
+/// Joins `right`, the disk of the satellite above `longitudes.1`, to the right of `left`, the
+/// disk of the satellite above `longitudes.0`. Both disks must be the same size and square.
+pub fn join(left: &RgbaImage, right: &RgbaImage, join: Join, longitudes: (f64, f64)) -> RgbaImage {
+    let size = left.height();
+    let offset = ((offset(join, longitudes.0, longitudes.1) * size as f64).round() as u32).min(size);
+    let overlap = size - offset;
+
+    let mut image = RgbaImage::new(offset + size, size);
+    image::imageops::replace(&mut image, left, 0, 0);
+
+    let centre = size as f64 / 2.0;
+    let radius = size as f64 * geo::disk_radius();
+    let on_disk = |x: u32, y: u32| {
+        (x as f64 + 0.5 - centre).powi(2) + (y as f64 + 0.5 - centre).powi(2) <= radius * radius
+    };
+    for y in 0..size {
+        for x in offset..offset + size {
+            let right_pixel = *right.get_pixel(x - offset, y);
+            if x >= size {
+                image.put_pixel(x, y, right_pixel);
+                continue;
+            }
+            let left_pixel = *left.get_pixel(x, y);
+            let pixel = match (on_disk(x, y), on_disk(x - offset, y)) {
+                (true, false) => left_pixel,
+                (false, true) => right_pixel,
+                // Fade from the left disk to the right across the overlap
+                (true, true) => mix(left_pixel, right_pixel, (x - offset) as f32 / overlap as f32),
+                // Keep the glow of both atmospheres
+                (false, false) => Rgba([0, 1, 2, 3].map(|c| left_pixel.0[c].max(right_pixel.0[c]))),
+            };
+            image.put_pixel(x, y, pixel);
+        }
+    }
+    image
+}
+
+/// `a` and `b` mixed, `t` of the way from `a` to `b`
+fn mix(a: Rgba<u8>, b: Rgba<u8>, t: f32) -> Rgba<u8> {
+    Rgba([0, 1, 2, 3].map(|c| (a.0[c] as f32 * (1.0 - t) + b.0[c] as f32 * t).round() as u8))
+}
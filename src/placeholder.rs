@@ -0,0 +1,92 @@
+//! Spotting the "No Image" tiles the server sends for parts of a frame it doesn't have yet.
+//!
+//! These come back as ordinary PNGs with a 200 status, so would otherwise be stitched into
+//! the frame, and kept in the tile cache for good. The placeholder is a flat grey square with
+//! a little text on it. A real tile is never mostly one shade of grey: space is black, and
+//! even a tile of cloud tops varies from pixel to pixel.
+
+use std::error::Error;
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use chrono::prelude::*;
+use image::DynamicImage;
+
+use crate::error::AppErr;
+use crate::messages::tr;
+
+/// How much of a tile has to be one shade of grey for it to be a placeholder
+const MIN_FLAT_FRACTION: f64 = 0.9;
+
+/// The shades the placeholder's grey may be, which leaves out the black of space and the
+/// white of saturated cloud
+const PLACEHOLDER_GREYS: std::ops::RangeInclusive<u8> = 48..=224;
+
+/// True if `image` looks like a "No Image" placeholder rather than a piece of the disk
+pub fn is_placeholder(image: &DynamicImage) -> bool {
+    let image = image.to_rgb8();
+    let pixels = image.width() as u64 * image.height() as u64;
+    if pixels == 0 {
+        return false;
+    }
+    // Count the pixels of each shade of grey, ignoring any colour
+    let mut greys = [0u64; 256];
+    for pixel in image.pixels() {
+        let [r, g, b] = pixel.0;
+        if r == g && g == b {
+            greys[r as usize] += 1;
+        }
+    }
+    let (shade, count) = greys
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, count)| **count)
+        .map(|(shade, count)| (shade as u8, *count))
+        .unwrap_or_default();
+    PLACEHOLDER_GREYS.contains(&shade) && count as f64 >= pixels as f64 * MIN_FLAT_FRACTION
+}
+
+/// A tile which was a placeholder, and is left out of the frame like a failed download
+#[derive(Debug)]
+pub struct PlaceholderTile {
+    pub timestamp: DateTime<Utc>,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Display for PlaceholderTile {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{}", tr!("placeholder-tile", x = self.x, y = self.y, time = self.timestamp))
+    }
+}
+
+impl Error for PlaceholderTile {}
+
+/// A frame with placeholder tiles, which the server hasn't finished publishing
+#[derive(Debug)]
+pub struct PlaceholderFrame {
+    pub timestamp: DateTime<Utc>,
+    /// How many of its tiles were placeholders
+    pub tiles: u32,
+}
+
+impl Display for PlaceholderFrame {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{}", tr!("placeholder-frame", count = self.tiles, time = self.timestamp))
+    }
+}
+
+impl Error for PlaceholderFrame {}
+
+/// True if `app_err` is a tile which was a placeholder
+pub fn is_placeholder_tile(app_err: &AppErr) -> bool {
+    app_err
+        .source()
+        .is_some_and(|err| err.is::<PlaceholderTile>())
+}
+
+/// True if `app_err` is a frame with placeholder tiles, which an earlier frame might stand in for
+pub fn is_placeholder_frame(app_err: &AppErr) -> bool {
+    app_err
+        .source()
+        .is_some_and(|err| err.is::<PlaceholderFrame>())
+}
@@ -0,0 +1,58 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use image::codecs::png::CompressionType;
+
+/// How hard the PNG encoder works to make files smaller
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+#[derive(Clone)]
+pub struct PngCompressionValueParser;
+
+impl clap::builder::TypedValueParser for PngCompressionValueParser {
+    type Value = PngCompression;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match value.to_string_lossy().as_ref().trim() {
+            "fast" => Ok(PngCompression::Fast),
+            "default" => Ok(PngCompression::Default),
+            "best" => Ok(PngCompression::Best),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid PNG compression, use fast, default or best")),
+        }
+    }
+}
+
+impl PngCompression {
+    pub fn to_compression_type(self) -> CompressionType {
+        match self {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        }
+    }
+
+    /// The same compression for the png crate, which images written a band at a time use
+    pub fn to_png_compression(self) -> png::Compression {
+        match self {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Default => png::Compression::Default,
+            PngCompression::Best => png::Compression::Best,
+        }
+    }
+}
+
+impl Display for PngCompression {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            PngCompression::Fast => "fast",
+            PngCompression::Default => "default",
+            PngCompression::Best => "best",
+        };
+        write!(f, "{}", s)
+    }
+}
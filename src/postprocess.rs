@@ -0,0 +1,83 @@
+//! Adjustments made to the composited image before it is written, such as brightening the
+//! night side of the disk, which is otherwise almost entirely black.
+//!
+//! Adjustments are chained in the order they are added. Those which only change the tone of
+//! each channel are folded into a single lookup table, so a chain of them costs one pass
+//! over the image.
+
+use image::RgbaImage;
+
+/// A change to the tone of each colour channel
+#[derive(Clone, Copy, PartialEq)]
+pub enum Adjustment {
+    /// Adds a percentage of full brightness, from -100 to 100
+    Brightness(f32),
+    /// Stretches (or with a negative percentage, squeezes) the channels away from mid grey
+    Contrast(f32),
+    /// Raises each channel to the power 1/gamma, so values over 1 lift the shadows
+    Gamma(f32),
+}
+
+impl Adjustment {
+    /// Maps a channel value from 0 to 1
+    fn map(self, value: f32) -> f32 {
+        match self {
+            Adjustment::Brightness(percent) => value + percent / 100.0,
+            Adjustment::Contrast(percent) => (value - 0.5) * (100.0 + percent) / 100.0 + 0.5,
+            Adjustment::Gamma(gamma) => value.max(0.0).powf(1.0 / gamma),
+        }
+        .clamp(0.0, 1.0)
+    }
+}
+
+/// A chain of adjustments, applied in turn
+#[derive(Clone, Default)]
+pub struct PostProcess {
+    adjustments: Vec<Adjustment>,
+}
+
+impl PostProcess {
+    pub fn new() -> PostProcess {
+        PostProcess::default()
+    }
+
+    /// Adds `adjustment` to the end of the chain, unless it would change nothing
+    pub fn then(mut self, adjustment: Adjustment) -> PostProcess {
+        let unchanged = match adjustment {
+            Adjustment::Brightness(percent) | Adjustment::Contrast(percent) => percent == 0.0,
+            Adjustment::Gamma(gamma) => gamma == 1.0,
+        };
+        if !unchanged {
+            self.adjustments.push(adjustment);
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.adjustments.is_empty()
+    }
+
+    pub fn adjustments(&self) -> &[Adjustment] {
+        &self.adjustments
+    }
+
+    /// Applies the chain to the colour channels of `image`, leaving alpha alone
+    pub fn apply(&self, image: &mut RgbaImage) {
+        if self.is_empty() {
+            return;
+        }
+        let mut table = [0u8; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let value = self
+                .adjustments
+                .iter()
+                .fold(i as f32 / 255.0, |value, adjustment| adjustment.map(value));
+            *entry = (value * 255.0).round() as u8;
+        }
+        for pixel in image.pixels_mut() {
+            for c in pixel.0.iter_mut().take(3) {
+                *c = table[*c as usize];
+            }
+        }
+    }
+}
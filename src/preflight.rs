@@ -0,0 +1,154 @@
+//! Checks made before downloading a frame: that the output volume has room for the images
+//! about to be written, and whether it is a network drive, which some desktops can't set
+//! the wallpaper from.
+//!
+//! The size of an image is estimated from its pixel count and a generous ratio for its
+//! format, so the check errs on the side of wanting too much space.
+
+use std::path::Path;
+
+use log::{debug, warn};
+
+use crate::error::AppErr;
+use crate::messages::tr;
+use crate::output_format::OutputFormat;
+
+/// Space kept free beyond the estimate, for the state file, sidecars and the like
+const SPARE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// An upper estimate of the bytes a full disk image takes per pixel in `format`
+fn bytes_per_pixel(format: &OutputFormat) -> f64 {
+    match *format {
+        OutputFormat::PNG | OutputFormat::APNG => 2.0,
+        OutputFormat::WEBP => 1.5,
+        OutputFormat::JPEG => 0.5,
+        OutputFormat::AVIF => 0.3,
+        OutputFormat::BMP => 3.0,
+        // Enough for 16 bits a channel
+        OutputFormat::TIFF => 8.0,
+        OutputFormat::GIF => 1.0,
+    }
+}
+
+/// An estimate of the size of an image of `width` × `height` pixels in `format`
+pub fn estimate_bytes(width: u32, height: u32, format: &OutputFormat) -> u64 {
+    (width as f64 * height as f64 * bytes_per_pixel(format)) as u64
+}
+
+/// An estimate of the size of a tile `tile_width` pixels square, as served. Tiles are PNG,
+/// but mostly dark space and cloud, so compress far better than a finished image.
+pub fn estimate_tile_bytes(tile_width: u32) -> u64 {
+    (tile_width as f64 * tile_width as f64 * 0.5) as u64
+}
+
+/// `bytes` in megabytes, for messages
+pub fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Fails unless the volume `dir` is on has room for `bytes` more
+pub fn check_space(dir: &Path, bytes: u64) -> Result<(), AppErr> {
+    let free = match free_space(dir) {
+        Some(free) => free,
+        None => {
+            debug!("The free space on {} could not be found", dir.display());
+            return Ok(());
+        }
+    };
+    debug!("Expecting to write {} to {}, which has {} free", megabytes(bytes), dir.display(), megabytes(free));
+    if free < bytes + SPARE_BYTES {
+        return Err(AppErr::new(tr!(
+            "not-enough-space",
+            dir = dir.display(),
+            needed = megabytes(bytes + SPARE_BYTES),
+            free = megabytes(free)
+        )));
+    }
+    Ok(())
+}
+
+/// Warns if `dir` is on a network drive, as setting the wallpaper from one may fail
+pub fn warn_if_network_drive(dir: &Path) {
+    if is_network_drive(dir) {
+        warn!("{}", tr!("network-drive", dir = dir.display()));
+    }
+}
+
+/// The bytes free on the volume `dir` is on, from df
+#[cfg(not(windows))]
+fn free_space(dir: &Path) -> Option<u64> {
+    let output = std::process::Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    // Filesystem 1024-blocks Used Available Capacity Mounted on
+    let available = output.lines().nth(1)?.split_whitespace().nth(3)?;
+    available.parse::<u64>().ok().map(|kb| kb * 1024)
+}
+
+#[cfg(windows)]
+fn free_space(dir: &Path) -> Option<u64> {
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+    use winapi::um::winnt::ULARGE_INTEGER;
+
+    let dir = crate::ffi_windows::os_str_to_wchar(dir.as_os_str());
+    let mut free: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    let ok = unsafe { GetDiskFreeSpaceExW(dir.as_ptr(), &mut free, std::ptr::null_mut(), std::ptr::null_mut()) };
+    match ok {
+        0 => None,
+        _ => Some(unsafe { *free.QuadPart() }),
+    }
+}
+
+/// File systems which are mounted from another machine
+#[cfg(not(windows))]
+const NETWORK_FILE_SYSTEMS: [&str; 9] = ["nfs", "nfs4", "cifs", "smbfs", "smb3", "afpfs", "webdav", "fuse.sshfs", "9p"];
+
+/// Whether the mount `dir` is on has a network file system, from the list mount prints.
+/// Linux lists "server:/share on /mnt/share type nfs4 (...)", and macOS
+/// "//user@server/share on /Volumes/share (smbfs, ...)".
+#[cfg(not(windows))]
+fn is_network_drive(dir: &Path) -> bool {
+    let output = match std::process::Command::new("mount").output() {
+        Ok(output) => output,
+        Err(_) => return false,
+    };
+    let output = String::from_utf8_lossy(&output.stdout);
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let mounts = output.lines().filter_map(|line| {
+        let (_, rest) = line.split_once(" on ")?;
+        match rest.split_once(" type ") {
+            Some((mount_point, rest)) => Some((mount_point, rest.split_whitespace().next()?)),
+            None => {
+                let (mount_point, rest) = rest.rsplit_once(" (")?;
+                Some((mount_point, rest.split([',', ')']).next()?))
+            }
+        }
+    });
+    // The deepest mount point containing the directory is the one it is on
+    mounts
+        .filter(|(mount_point, _)| dir.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .is_some_and(|(_, file_system)| NETWORK_FILE_SYSTEMS.contains(&file_system))
+}
+
+#[cfg(windows)]
+fn is_network_drive(dir: &Path) -> bool {
+    use std::path::{Component, Prefix};
+    use winapi::um::fileapi::GetDriveTypeW;
+    use winapi::um::winbase::DRIVE_REMOTE;
+
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let root = match dir.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            // UNC paths such as \\server\share are always on the network
+            Prefix::UNC(..) | Prefix::VerbatimUNC(..) => return true,
+            _ => {
+                let mut root = prefix.as_os_str().to_owned();
+                root.push("\\");
+                root
+            }
+        },
+        _ => return false,
+    };
+    let root = crate::ffi_windows::os_str_to_wchar(&root);
+    unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+}
@@ -0,0 +1,56 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use crate::size::Size;
+
+/// A common screen, which sets the canvas the disk is laid out on, and so the level, margins
+/// and size of the output image
+#[derive(Clone, Copy)]
+pub struct Preset {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+const PRESETS: [Preset; 7] = [
+    Preset { name: "1080p", width: 1920, height: 1080 },
+    Preset { name: "1440p", width: 2560, height: 1440 },
+    Preset { name: "4k", width: 3840, height: 2160 },
+    Preset { name: "ultrawide-1080", width: 2560, height: 1080 },
+    Preset { name: "ultrawide-1440", width: 3440, height: 1440 },
+    Preset { name: "vertical-1080", width: 1080, height: 1920 },
+    Preset { name: "vertical-1440", width: 1440, height: 2560 },
+];
+
+#[derive(Clone)]
+pub struct PresetValueParser;
+
+impl clap::builder::TypedValueParser for PresetValueParser {
+    type Value = Preset;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let value = value.to_string_lossy();
+        match PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(value.trim())) {
+            Some(preset) => Ok(*preset),
+            None => {
+                let names = PRESETS.iter().map(|preset| preset.name).collect::<Vec<_>>();
+                Err(Error::raw(ErrorKind::InvalidValue, format!("Invalid preset, use one of {}", names.join(", "))))
+            }
+        }
+    }
+}
+
+impl Preset {
+    /// The size the output image is scaled down to
+    pub fn size(&self) -> Size {
+        Size {
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl Display for Preset {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{} ({}x{})", self.name, self.width, self.height)
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use chrono::prelude::*;
+
+use crate::satellite::Satellite;
+
+/// Himawari scans the full disk every ten minutes
+pub const FRAME_INTERVAL_MINUTES: i64 = 10;
+
+/// An image product served by a satellite's image server
+#[derive(Clone)]
+pub struct Product {
+    pub satellite: Satellite,
+    /// The name of the product in the URL path, e.g. "D531106"
+    pub name: String,
+    /// Width (and height) in pixels of each image fragment
+    pub tile_width: u32,
+    /// Where frames are downloaded from with --base-url, e.g. a mirror or a local caching
+    /// proxy, instead of the satellite's own server. It must lay frames out the same way below it.
+    pub base_url: Option<String>,
+    /// The levels given with --levels, for a mirror which serves other levels than the
+    /// satellite's server
+    pub levels: Option<Vec<u32>>,
+}
+
+impl Product {
+    /// The URL frames are downloaded from, below which the server lays them out
+    pub fn base_url(&self) -> &str {
+        match self.base_url {
+            Some(ref url) => url,
+            None => self.satellite.server().default_base_url(),
+        }
+    }
+
+    /// The URL of the metadata giving the time of the latest frame
+    pub fn latest_url(&self, cache_buster: u64) -> String {
+        let path = self.satellite.server().latest_path(&self.name);
+        format!("{}/{}?_={}", self.base_url(), path, cache_buster)
+    }
+
+    /// The URL of the tile at (`x`, `y`) of a frame split into `level` tiles a side
+    pub fn tile_url(&self, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> String {
+        let path = self.satellite.server().tile_path(self, level, timestamp, x, y);
+        format!("{}/{}", self.base_url(), path)
+    }
+
+    /// The levels the product is served at, smallest first, unless --levels says otherwise
+    pub fn levels(&self) -> &[u32] {
+        match self.levels {
+            Some(ref levels) => levels,
+            None => self.satellite.server().levels(&self.name),
+        }
+    }
+
+    /// `product`, downloaded from the same server as this one and at the same levels
+    pub fn alongside(&self, product: Product) -> Product {
+        Product {
+            base_url: self.base_url.clone(),
+            levels: self.levels.clone(),
+            ..product
+        }
+    }
+
+    /// The directory the tiles at `level` are saved in, relative to a directory of saved tiles
+    pub fn level_dir(&self, level: u32) -> PathBuf {
+        let mut path = PathBuf::from(self.satellite.server().product_dir(&self.name));
+        path.push(format!("{}d", level));
+        path.push(self.tile_width.to_string());
+        path
+    }
+
+    /// The path of a tile relative to a directory of saved tiles, mirroring its URL on NICT
+    pub fn tile_path(&self, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> PathBuf {
+        let mut path = self.level_dir(level);
+        path.push(timestamp.format("%Y").to_string());
+        path.push(timestamp.format("%m").to_string());
+        path.push(timestamp.format("%d").to_string());
+        path.push(format!("{}_{}_{}.png", timestamp.format("%H%M%S"), x, y));
+        path
+    }
+}
+
+impl Default for Product {
+    fn default() -> Product {
+        Satellite::default().default_product()
+    }
+}
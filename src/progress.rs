@@ -0,0 +1,115 @@
+//! Progress through the tiles of a frame, which can take minutes at levels 16 and 20.
+//!
+//! In a terminal a bar is redrawn on stderr as each tile finishes. Otherwise, as when
+//! running as a scheduled task, a line is logged every 10% instead. Either way a summary
+//! line is logged once the frame is done with.
+
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use log::info;
+
+use crate::console;
+
+/// The width of the bar, in characters
+const BAR_WIDTH: usize = 30;
+
+/// Enough to blank the bar and its counts
+const LINE_WIDTH: usize = 80;
+
+/// How many percent apart the lines logged without a terminal are
+const LOG_STEP_PERCENT: u32 = 10;
+
+#[derive(Default)]
+struct Counts {
+    downloaded: u32,
+    retried: u32,
+    failed: u32,
+    /// The last multiple of LOG_STEP_PERCENT logged
+    logged_percent: u32,
+}
+
+pub struct Progress {
+    total: u32,
+    counts: Mutex<Counts>,
+    started: Instant,
+    bar: bool,
+}
+
+impl Progress {
+    /// Progress through a frame of `total` tiles
+    pub fn new(total: u32) -> Progress {
+        Progress {
+            total,
+            counts: Mutex::new(Counts::default()),
+            started: Instant::now(),
+            bar: console::is_interactive(),
+        }
+    }
+
+    /// Notes that a tile failed and is being retried
+    pub fn retried(&self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.retried += 1;
+        self.show(&mut counts);
+    }
+
+    /// Notes that a tile was fetched, or failed after any retries
+    pub fn finish(&self, succeeded: bool) {
+        let mut counts = self.counts.lock().unwrap();
+        match succeeded {
+            true => counts.downloaded += 1,
+            false => counts.failed += 1,
+        }
+        self.show(&mut counts);
+    }
+
+    fn show(&self, counts: &mut Counts) {
+        let finished = counts.downloaded + counts.failed;
+        let percent = finished * 100 / self.total.max(1);
+        if self.bar {
+            let filled = BAR_WIDTH * finished as usize / self.total.max(1) as usize;
+            // The cursor is left at the start of the line, so that any log line printed
+            // before the next redraw replaces the bar
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(
+                stderr,
+                "[{}{}] {:>3}% {}/{} tiles, {} retried, {} failed\r",
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled),
+                percent,
+                finished,
+                self.total,
+                counts.retried,
+                counts.failed
+            );
+            let _ = stderr.flush();
+        } else if percent >= counts.logged_percent + LOG_STEP_PERCENT && finished < self.total {
+            counts.logged_percent = percent - percent % LOG_STEP_PERCENT;
+            info!(
+                "{}% of tiles fetched ({} of {}, {} retried, {} failed)",
+                percent, finished, self.total, counts.retried, counts.failed
+            );
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let counts = self.counts.lock().unwrap();
+        if self.bar {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "{:width$}\r", "", width = LINE_WIDTH);
+            let _ = stderr.flush();
+        }
+        info!(
+            "Fetched {} of {} tiles in {:.1} s ({} retried, {} failed)",
+            counts.downloaded,
+            self.total,
+            self.started.elapsed().as_secs_f64(),
+            counts.retried,
+            counts.failed
+        );
+    }
+}
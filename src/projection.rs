@@ -0,0 +1,132 @@
+//! Reprojecting the full disk onto a map with `--projection`, so that the image can be used in
+//! mapping tools or layered with other maps.
+//!
+//! The map covers the hemisphere facing the satellite: 90 degrees of longitude either side of
+//! it, and from pole to pole, or to 80 degrees north and south for Mercator, which can't reach
+//! the poles. Near the limb the disk is foreshortened to almost nothing, so the edges of the
+//! map are stretched, and the parts the satellite can't see at all are left empty.
+
+use std::f64::consts::PI;
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::geo;
+use crate::map_layers::Disk;
+
+/// The furthest north or south a Mercator map goes
+const MERCATOR_MAX_LATITUDE: f64 = 80.0;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Projection {
+    /// The disk as the satellite sees it
+    #[default]
+    Disk,
+    /// Latitude and longitude spaced evenly
+    Equirectangular,
+    Mercator,
+}
+
+#[derive(Clone)]
+pub struct ProjectionValueParser;
+
+impl clap::builder::TypedValueParser for ProjectionValueParser {
+    type Value = Projection;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match Projection::try_parse(value.to_string_lossy().as_ref()) {
+            Some(projection) => Ok(projection),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Invalid projection, use disk, equirectangular or mercator")),
+        }
+    }
+}
+
+impl Projection {
+    pub fn try_parse(input: &str) -> Option<Projection> {
+        match input.trim() {
+            "disk" => Some(Projection::Disk),
+            "equirectangular" => Some(Projection::Equirectangular),
+            "mercator" => Some(Projection::Mercator),
+            _ => None,
+        }
+    }
+
+    /// The latitude of the row `v` of the way down a map, from 0 to 1
+    fn latitude(self, v: f64) -> f64 {
+        match self {
+            Projection::Mercator => {
+                let y = mercator_y(MERCATOR_MAX_LATITUDE) * (1.0 - 2.0 * v);
+                y.sinh().atan().to_degrees()
+            }
+            _ => 90.0 - 180.0 * v,
+        }
+    }
+
+    /// The height of a map `width` pixels wide, with square pixels at the equator
+    fn height(self, width: u32) -> u32 {
+        match self {
+            Projection::Mercator => (width as f64 * 2.0 * mercator_y(MERCATOR_MAX_LATITUDE) / PI).round() as u32,
+            _ => width,
+        }
+    }
+}
+
+impl Display for Projection {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            Projection::Disk => "disk",
+            Projection::Equirectangular => "equirectangular",
+            Projection::Mercator => "mercator",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// How far from the equator `latitude` is on a Mercator map, in radians of longitude
+fn mercator_y(latitude: f64) -> f64 {
+    (PI / 4.0 + latitude.to_radians() / 2.0).tan().ln()
+}
+
+/// The colour of `image` at (`x`, `y`), blended between the four nearest pixels
+fn sample(image: &RgbaImage, x: f64, y: f64) -> Rgba<u8> {
+    // Pixel centres are at the halves
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (left, top) = (x.floor(), y.floor());
+    let (fx, fy) = (x - left, y - top);
+    let (max_x, max_y) = (image.width() as i64 - 1, image.height() as i64 - 1);
+    let at = |dx: i64, dy: i64| {
+        let px = (left as i64 + dx).clamp(0, max_x) as u32;
+        let py = (top as i64 + dy).clamp(0, max_y) as u32;
+        image.get_pixel(px, py).0
+    };
+    let (a, b, c, d) = (at(0, 0), at(1, 0), at(0, 1), at(1, 1));
+    Rgba([0, 1, 2, 3].map(|i| {
+        let upper = a[i] as f64 * (1.0 - fx) + b[i] as f64 * fx;
+        let lower = c[i] as f64 * (1.0 - fx) + d[i] as f64 * fx;
+        (upper * (1.0 - fy) + lower * fy).round() as u8
+    }))
+}
+
+/// Reprojects `disk` in `image`, seen from above `satellite_longitude`, onto a map in
+/// `projection` as wide as the disk, filling what the satellite can't see with `fill`. Any
+/// margins around the disk are left off.
+pub fn reproject(image: RgbaImage, disk: Disk, satellite_longitude: f64, projection: Projection, fill: Rgba<u8>) -> RgbaImage {
+    if projection == Projection::Disk {
+        return image;
+    }
+    let width = (disk.size.round() as u32).max(1);
+    let height = projection.height(width).max(1);
+    let mut map = RgbaImage::from_pixel(width, height, fill);
+    map.par_chunks_mut(width as usize * 4).enumerate().for_each(|(y, row)| {
+        let latitude = projection.latitude((y as f64 + 0.5) / height as f64);
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let longitude = satellite_longitude - 90.0 + 180.0 * (x as f64 + 0.5) / width as f64;
+            if let Some((u, v)) = geo::project(latitude, longitude, satellite_longitude) {
+                let colour = sample(&image, disk.left + u * disk.size, disk.top + v * disk.size);
+                pixel.copy_from_slice(&colour.0);
+            }
+        }
+    });
+    map
+}
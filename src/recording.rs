@@ -0,0 +1,112 @@
+//! Recording HTTP responses, to reproduce a run later without the network.
+//!
+//! With `--record <dir>` every response is saved in the directory, and with `--replay <dir>`
+//! requests are answered from it instead. Each response is kept as a `<key>.json` file with
+//! its URL, status and headers, next to a `<key>.body` file with its content.
+
+use std::collections::BTreeMap;
+use std::fs::DirBuilder;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Url};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::AppErr;
+use crate::http::HttpResponse;
+use crate::messages::tr;
+
+/// Whether a client records the responses to its requests, or replays them, and where
+#[derive(Clone)]
+pub enum Recording {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedResponse {
+    url: String,
+    status: u16,
+    headers: BTreeMap<String, String>,
+}
+
+/// Identifies the request for `url` across runs, ignoring the cache buster
+fn key(url: &str) -> String {
+    let url = match Url::parse(url) {
+        Ok(mut parsed) => {
+            let query: Vec<_> = parsed
+                .query_pairs()
+                .filter(|(name, _)| name != "_")
+                .map(|(name, value)| (name.into_owned(), value.into_owned()))
+                .collect();
+            parsed.set_query(None);
+            if !query.is_empty() {
+                parsed.query_pairs_mut().extend_pairs(query);
+            }
+            parsed.to_string()
+        }
+        Err(_) => url.to_string(),
+    };
+    let hash = format!("{:x}", Sha256::digest(url.as_bytes()));
+    hash[..16].to_string()
+}
+
+fn paths(dir: &Path, url: &str) -> (PathBuf, PathBuf) {
+    let key = key(url);
+    (
+        dir.join(format!("{}.json", key)),
+        dir.join(format!("{}.body", key)),
+    )
+}
+
+/// Saves the response to a request for `url` in `dir`
+pub fn record(dir: &Path, url: &str, response: &HttpResponse) -> Result<(), AppErr> {
+    DirBuilder::new().recursive(true).create(dir)?;
+    let (meta_path, body_path) = paths(dir, url);
+    let headers = response
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let meta = RecordedResponse {
+        url: url.to_string(),
+        status: response.status.as_u16(),
+        headers,
+    };
+    std::fs::write(&body_path, &response.body)?;
+    serde_json::to_writer_pretty(std::fs::File::create(&meta_path)?, &meta)?;
+    Ok(())
+}
+
+/// Reads the response to a request for `url` recorded in `dir`
+pub fn replay(dir: &Path, url: &str) -> Result<HttpResponse, AppErr> {
+    let (meta_path, body_path) = paths(dir, url);
+    let meta: RecordedResponse = match std::fs::File::open(&meta_path) {
+        Ok(file) => serde_json::from_reader(file)?,
+        Err(_) => {
+            return Err(AppErr::new(tr!(
+                "replay-missing",
+                url = url,
+                dir = dir.display()
+            )))
+        }
+    };
+    info!("Replaying the recorded response to {}", meta.url);
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in meta.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+    Ok(HttpResponse {
+        status: StatusCode::from_u16(meta.status).unwrap_or(StatusCode::OK),
+        headers,
+        body: std::fs::read(&body_path)?,
+    })
+}
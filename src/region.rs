@@ -0,0 +1,30 @@
+/// A rectangular region of an image, in pixels
+#[derive(Clone)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// A `width` x `height` region centred as closely as possible on (`cx`, `cy`)
+    /// without extending outside an image of `bounds_width` x `bounds_height`
+    pub fn centred_on(
+        cx: u32,
+        cy: u32,
+        width: u32,
+        height: u32,
+        bounds_width: u32,
+        bounds_height: u32,
+    ) -> Region {
+        let width = width.min(bounds_width);
+        let height = height.min(bounds_height);
+        Region {
+            x: cx.saturating_sub(width / 2).min(bounds_width - width),
+            y: cy.saturating_sub(height / 2).min(bounds_height - height),
+            width,
+            height,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! A JSON report of every tile request, written with `--report <path>`.
+//!
+//! Each entry records how a tile was fetched: how many requests it took, the last status
+//! seen, its size and how long it took, so sporadic failures can be traced to a network
+//! or CDN problem afterwards.
+
+use std::fs::DirBuilder;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use chrono::prelude::*;
+use reqwest::StatusCode;
+use serde_derive::Serialize;
+
+use crate::error::AppErr;
+use crate::metrics::Metrics;
+
+#[derive(Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    #[default]
+    Network,
+    /// The on-disk HTTP cache
+    Cache,
+    /// A recording made with --record
+    Replay,
+}
+
+/// How a download went, filled in as it progresses
+#[derive(Default)]
+pub struct Transfer {
+    /// Requests sent, including those which resumed an interrupted body
+    pub attempts: u32,
+    /// The status of the last response
+    pub status: Option<StatusCode>,
+    pub source: Source,
+}
+
+#[derive(Serialize)]
+struct TileRequest {
+    url: String,
+    started: DateTime<Utc>,
+    source: Source,
+    attempts: u32,
+    status: Option<u16>,
+    bytes: Option<usize>,
+    duration_ms: u64,
+    error: Option<String>,
+}
+
+/// The report as it is written
+#[derive(Serialize)]
+struct Written<'a> {
+    written: DateTime<Utc>,
+    requests: &'a [TileRequest],
+}
+
+/// The tile requests of the update in progress, kept by everything taking part in it. Clones
+/// share their requests. The default keeps none.
+#[derive(Clone, Default)]
+pub struct Report {
+    requests: Option<Arc<Mutex<Vec<TileRequest>>>>,
+}
+
+impl Report {
+    /// A report which keeps track of tile requests, to be written once each update ends
+    pub fn new() -> Report {
+        Report {
+            requests: Some(Arc::default()),
+        }
+    }
+
+    /// Runs `download` to fetch the tile at `url`, noting how it went in the report and
+    /// `metrics`, if they are kept. An error comes back as the tile's failure, with the status
+    /// of the last response. `download` fills in the transfer it is given, and hands it back
+    /// with the body.
+    pub async fn track<F, Fut>(&self, metrics: &Metrics, url: &str, download: F) -> Result<Vec<u8>, AppErr>
+    where
+        F: FnOnce(Transfer) -> Fut,
+        Fut: Future<Output = (Transfer, Result<Vec<u8>, AppErr>)>,
+    {
+        let started = Utc::now();
+        let timer = Instant::now();
+        let (transfer, result) = download(Transfer::default()).await;
+        metrics.record_tile(&transfer, result.as_ref().ok().map(|body| body.len()));
+        if let Some(ref requests) = self.requests {
+            let request = TileRequest {
+                url: url.to_string(),
+                started,
+                source: transfer.source,
+                attempts: transfer.attempts,
+                status: transfer.status.map(|status| status.as_u16()),
+                bytes: result.as_ref().ok().map(|body| body.len()),
+                duration_ms: timer.elapsed().as_millis() as u64,
+                error: result.as_ref().err().map(|app_err| app_err.to_string()),
+            };
+            requests.lock().unwrap().push(request);
+        }
+        let status = transfer.status.map(|status| status.as_u16());
+        result.map_err(|app_err| app_err.for_tile(url.to_string(), status, transfer.attempts))
+    }
+
+    /// Writes the requests made since the last report to `path`, then starts afresh
+    pub fn write(&self, path: &Path) -> Result<(), AppErr> {
+        let requests = match self.requests {
+            Some(ref requests) => std::mem::take(&mut *requests.lock().unwrap()),
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            DirBuilder::new().recursive(true).create(dir)?;
+        }
+        let report = Written {
+            written: Utc::now(),
+            requests: &requests,
+        };
+        serde_json::to_writer_pretty(std::fs::File::create(path)?, &report)?;
+        Ok(())
+    }
+}
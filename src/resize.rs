@@ -0,0 +1,182 @@
+use image::imageops::FilterType;
+use image::{Rgba, RgbaImage};
+
+use crate::resize_mode::ResizeMode;
+use crate::size::Size;
+
+/// Resizes `image` to `size` as `mode` says, padding it with `background` if need be
+pub fn resize(image: RgbaImage, size: &Size, mode: ResizeMode, background: Rgba<u8>) -> RgbaImage {
+    match mode {
+        ResizeMode::Fit => fit_within(image, size),
+        ResizeMode::Fill => fill(image, size),
+        ResizeMode::Stretch => stretch(image, size),
+        ResizeMode::Pad => pad(image, size, background),
+    }
+}
+
+/// Scales `image` down to fit within `size`, preserving aspect ratio.
+/// Images which already fit are returned unchanged.
+pub fn fit_within(image: RgbaImage, size: &Size) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    let scale = f64::min(size.width as f64 / w as f64, size.height as f64 / h as f64);
+    if scale >= 1.0 {
+        return image;
+    }
+    let target_w = ((w as f64 * scale).round() as u32).max(1);
+    let target_h = ((h as f64 * scale).round() as u32).max(1);
+
+    // A Lanczos filter over a very large (e.g. supersampled) image needs an intermediate
+    // buffer of 16 bytes per pixel, so first reduce by the largest whole factor with a cheap
+    // box filter and only run the high quality filter over what remains
+    let image = match box_factor(w, h, size, ResizeMode::Fit) {
+        Some(factor) => box_downsample(&image, factor),
+        None => image,
+    };
+
+    image::imageops::resize(&image, target_w, target_h, FilterType::Lanczos3)
+}
+
+/// Scales `image` down to fit within `size`, then centres it on `background` to make it
+/// exactly `size`
+pub fn pad(image: RgbaImage, size: &Size, background: Rgba<u8>) -> RgbaImage {
+    let image = fit_within(image, size);
+    let (w, h) = image.dimensions();
+    if (w, h) == (size.width, size.height) {
+        return image;
+    }
+    let mut canvas = RgbaImage::from_pixel(size.width, size.height, background);
+    let x = size.width.saturating_sub(w) / 2;
+    let y = size.height.saturating_sub(h) / 2;
+    image::imageops::overlay(&mut canvas, &image, x as i64, y as i64);
+    canvas
+}
+
+/// Scales `image` to exactly `size`, stretching or squashing it to the new aspect ratio
+pub fn stretch(image: RgbaImage, size: &Size) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    if (w, h) == (size.width, size.height) {
+        return image;
+    }
+    let image = match box_factor(w, h, size, ResizeMode::Stretch) {
+        Some(factor) => box_downsample(&image, factor),
+        None => image,
+    };
+    image::imageops::resize(&image, size.width, size.height, FilterType::Lanczos3)
+}
+
+/// Crops `image` to the aspect ratio of `size` around its centre, then scales it down to fit
+pub fn fill(image: RgbaImage, size: &Size) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    let aspect = size.width as f64 / size.height as f64;
+    let (crop_w, crop_h) = match (w as f64 / h as f64) > aspect {
+        true => (((h as f64 * aspect).round() as u32).clamp(1, w), h),
+        false => (w, ((w as f64 / aspect).round() as u32).clamp(1, h)),
+    };
+    let image =
+        image::imageops::crop_imm(&image, (w - crop_w) / 2, (h - crop_h) / 2, crop_w, crop_h)
+            .to_image();
+    fit_within(image, size)
+}
+
+/// Averages each `factor` x `factor` block of pixels into one
+fn box_downsample(image: &RgbaImage, factor: u32) -> RgbaImage {
+    let mut reducer = BandReducer::new(image.width(), image.height(), factor);
+    reducer.push(image);
+    reducer.finish()
+}
+
+/// The factor to box filter an image of `width` x `height` by before resizing it to `size`
+/// with `mode`, or None if it needs no more than a high quality filter
+pub fn box_factor(width: u32, height: u32, size: &Size, mode: ResizeMode) -> Option<u32> {
+    let scales = (
+        size.width as f64 / width as f64,
+        size.height as f64 / height as f64,
+    );
+    // Filling or stretching keeps the side which is scaled down least at full size
+    let scale = match mode {
+        ResizeMode::Fit | ResizeMode::Pad => f64::min(scales.0, scales.1),
+        ResizeMode::Fill | ResizeMode::Stretch => f64::max(scales.0, scales.1),
+    };
+    let factor = (1.0 / scale).floor() as u32;
+    (factor >= 2).then_some(factor)
+}
+
+/// Averages each `factor` x `factor` block of pixels into one, as the image arrives in
+/// bands from top to bottom, so that it never has to be held at full size
+pub struct BandReducer {
+    factor: u32,
+    width: u32,
+    output: RgbaImage,
+    /// Rows which don't yet make up a whole output row
+    pending: Vec<u8>,
+    next_row: u32,
+}
+
+impl BandReducer {
+    /// A reducer for an image of `width` x `height`
+    pub fn new(width: u32, height: u32, factor: u32) -> BandReducer {
+        BandReducer {
+            factor,
+            width,
+            output: RgbaImage::new(width / factor, height / factor),
+            pending: Vec::new(),
+            next_row: 0,
+        }
+    }
+
+    /// Adds the next `band` of the image, which must be as wide as the image
+    pub fn push(&mut self, band: &RgbaImage) {
+        assert_eq!(band.width(), self.width, "Bands must span the whole image");
+        self.pending.extend_from_slice(band.as_raw());
+        self.reduce_pending();
+    }
+
+    /// Adds `rows` more rows of `colour`
+    pub fn push_blank(&mut self, rows: u32, colour: Rgba<u8>) {
+        let pixels = (rows * self.width) as usize;
+        self.pending.reserve(pixels * 4);
+        for _ in 0..pixels {
+            self.pending.extend_from_slice(&colour.0);
+        }
+        self.reduce_pending();
+    }
+
+    fn reduce_pending(&mut self) {
+        let factor = self.factor as usize;
+        let row_len = self.width as usize * 4;
+        let area = self.factor * self.factor;
+        let mut sums = vec![[0u32; 4]; self.output.width() as usize];
+        let mut start = 0;
+
+        while self.pending.len() - start >= factor * row_len && self.next_row < self.output.height()
+        {
+            sums.iter_mut().for_each(|sum| *sum = [0; 4]);
+            for row in self.pending[start..start + factor * row_len].chunks_exact(row_len) {
+                for (sum, block) in sums.iter_mut().zip(row.chunks_exact(factor * 4)) {
+                    for pixel in block.chunks_exact(4) {
+                        for c in 0..4 {
+                            sum[c] += pixel[c] as u32;
+                        }
+                    }
+                }
+            }
+            for (x, sum) in sums.iter().enumerate() {
+                let pixel = [
+                    (sum[0] / area) as u8,
+                    (sum[1] / area) as u8,
+                    (sum[2] / area) as u8,
+                    (sum[3] / area) as u8,
+                ];
+                self.output.put_pixel(x as u32, self.next_row, Rgba(pixel));
+            }
+            self.next_row += 1;
+            start += factor * row_len;
+        }
+        self.pending.drain(..start);
+    }
+
+    /// The reduced image. Rows which were never pushed are left transparent.
+    pub fn finish(self) -> RgbaImage {
+        self.output
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// How the output image is made to fit --resize
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Scaled down to fit within the size, keeping its aspect ratio
+    #[default]
+    Fit,
+    /// Cropped to the aspect ratio of the size around its centre, then scaled down to fit
+    Fill,
+    /// Scaled to exactly the size, whatever its aspect ratio
+    Stretch,
+    /// Scaled down to fit within the size, then centred on black to make it exactly that size
+    Pad,
+}
+
+#[derive(Clone)]
+pub struct ResizeModeValueParser;
+
+impl clap::builder::TypedValueParser for ResizeModeValueParser {
+    type Value = ResizeMode;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match value.to_string_lossy().as_ref().trim() {
+            "fit" => Ok(ResizeMode::Fit),
+            "fill" => Ok(ResizeMode::Fill),
+            "stretch" => Ok(ResizeMode::Stretch),
+            "pad" => Ok(ResizeMode::Pad),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid resize mode, use fit, fill, stretch or pad")),
+        }
+    }
+}
+
+impl Display for ResizeMode {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            ResizeMode::Fit => "fit",
+            ResizeMode::Fill => "fill",
+            ResizeMode::Stretch => "stretch",
+            ResizeMode::Pad => "pad",
+        };
+        write!(f, "{}", s)
+    }
+}
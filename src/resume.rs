@@ -0,0 +1,109 @@
+//! Resuming interrupted downloads with HTTP Range requests.
+//!
+//! Bodies are written to a partial file as they arrive, so when the connection drops
+//! the download picks up where it stopped, either straight away or on the next run.
+
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{info, warn};
+use reqwest::header::{HeaderMap, CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+
+use crate::error::AppErr;
+use crate::http::HttpClient;
+use crate::report::Transfer;
+
+const PARTIAL_DIR_NAME: &str = "himawari-desktop-updater-partial";
+
+/// Partial files older than this are assumed to be abandoned, and started again
+const MAX_PARTIAL_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How many times a download is resumed straight away, as long as each attempt makes progress
+const MAX_RESUMES: u32 = 3;
+
+fn partial_path(url: &str) -> PathBuf {
+    let hash = format!("{:x}", Sha256::digest(url.as_bytes()));
+    std::env::temp_dir().join(PARTIAL_DIR_NAME).join(hash)
+}
+
+fn partial_len(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// The first byte of the range in a "bytes 100-199/200" Content-Range header
+fn range_start(response: &reqwest::Response) -> Option<u64> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.strip_prefix("bytes ")?;
+    range.split('-').next()?.parse().ok()
+}
+
+/// Downloads the rest of `url` into the partial file at `path`, returning the response headers
+async fn download_into(client: &HttpClient, url: &str, path: &Path, transfer: &mut Transfer) -> Result<HeaderMap, AppErr> {
+    let (mut response, mut file) = loop {
+        let offset = partial_len(path);
+        let mut request = client.client()?.get(url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+        transfer.attempts += 1;
+        let response = request.send().await?;
+        transfer.status = Some(response.status());
+
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT if range_start(&response) == Some(offset) => {
+                info!("Resuming {} from byte {}", url, offset);
+                let file = OpenOptions::new().append(true).open(path)?;
+                break (response, file);
+            }
+            // The partial body is no use, so start again
+            StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE => {
+                std::fs::remove_file(path)?;
+            }
+            // The server ignored the range and sent the whole body
+            _ => break (response.error_for_status()?, File::create(path)?),
+        }
+    };
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+        client.throttle(chunk.len()).await;
+    }
+    Ok(response.headers().clone())
+}
+
+/// Downloads `url`, resuming from any part of it left over from an interrupted download.
+/// Returns the headers of the response which completed the body, and the body.
+pub async fn download(client: &HttpClient, url: &str, transfer: &mut Transfer) -> Result<(HeaderMap, Vec<u8>), AppErr> {
+    let path = partial_path(url);
+    if let Some(dir) = path.parent() {
+        DirBuilder::new().recursive(true).create(dir)?;
+    }
+    let stale = std::fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > MAX_PARTIAL_AGE);
+    if stale {
+        std::fs::remove_file(&path)?;
+    }
+
+    let mut resumes = 0;
+    let headers = loop {
+        let before = partial_len(&path);
+        match download_into(client, url, &path, transfer).await {
+            Ok(headers) => break headers,
+            Err(app_err) if resumes < MAX_RESUMES && partial_len(&path) > before => {
+                warn!("Download of {} was interrupted, resuming: {}", url, app_err);
+                resumes += 1;
+            }
+            Err(app_err) => return Err(app_err),
+        }
+    };
+
+    let data = std::fs::read(&path)?;
+    std::fs::remove_file(&path)?;
+    Ok((headers, data))
+}
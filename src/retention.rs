@@ -0,0 +1,206 @@
+//! Removing old frames from the output directory, with `--keep-last` and `--keep-days`.
+//!
+//! Only archived frames named after their timestamp are touched. A frame written in several
+//! formats counts once, and goes with its sidecar, missing tiles manifest, thumbnail,
+//! variants and raw copy.
+
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use log::{info, warn};
+
+use crate::archive;
+use crate::checksums;
+use crate::error::AppErr;
+use crate::orientation::ORIENTATIONS;
+
+pub struct RetentionOptions {
+    /// Keep this many of the newest frames
+    pub keep_last: Option<u32>,
+    /// Keep the frames captured within this many days
+    pub keep_days: Option<u32>,
+    /// Only log the frames which would be removed
+    pub dry_run: bool,
+    /// Update the checksum manifest as frames are removed
+    pub checksums: bool,
+}
+
+/// Removes the frames in `output_dir` which `options` don't keep, other than `current`,
+/// returning the paths of the images removed (or which would be, in a dry run)
+pub fn prune(output_dir: &Path, current: &Path, options: &RetentionOptions) -> Result<Vec<PathBuf>, AppErr> {
+    if options.keep_last.is_none() && options.keep_days.is_none() {
+        return Ok(Vec::new());
+    }
+    let frames = archive::list_frames(output_dir)?;
+
+    // Newest first, each frame once however many formats it was written in
+    let mut timestamps: Vec<_> = frames.iter().map(|frame| frame.timestamp).collect();
+    timestamps.dedup();
+    let keep_last = options.keep_last.map_or(usize::MAX, |count| count as usize);
+    let newest_kept = timestamps.get(keep_last.saturating_sub(1)).copied();
+    let oldest_kept = options
+        .keep_days
+        .map(|days| Utc::now() - chrono::Duration::days(days as i64));
+
+    let kept = |timestamp: &DateTime<Utc>| {
+        newest_kept.is_none_or(|newest| *timestamp >= newest)
+            && oldest_kept.is_none_or(|oldest| *timestamp >= oldest)
+    };
+
+    let mut removed = Vec::new();
+    for frame in frames.into_iter().filter(|frame| frame.path != current && !kept(&frame.timestamp)) {
+        if options.dry_run {
+            info!("Would remove {}", frame.path.display());
+            removed.push(frame.path);
+            continue;
+        }
+        info!("Removing {}", frame.path.display());
+        std::fs::remove_file(&frame.path)?;
+        // Not every frame has these
+        let _ = std::fs::remove_file(archive::sidecar_path(&frame.path));
+        let _ = std::fs::remove_file(archive::missing_tiles_path(&frame.path));
+        let thumbnail_path = archive::thumbnail_path(&frame.path);
+        let thumbnail_removed = std::fs::remove_file(&thumbnail_path).is_ok();
+        let variant_paths: Vec<_> = ORIENTATIONS
+            .iter()
+            .map(|&orientation| archive::variant_path(&frame.path, orientation))
+            .filter(|path| std::fs::remove_file(path).is_ok())
+            .collect();
+        let raw_path = archive::raw_path(output_dir, &frame.path);
+        let raw_removed = std::fs::remove_file(&raw_path).is_ok();
+        if options.checksums {
+            let paths = std::iter::once(&frame.path)
+                .chain(raw_removed.then_some(&raw_path))
+                .chain(thumbnail_removed.then_some(&thumbnail_path))
+                .chain(&variant_paths);
+            for path in paths {
+                if let Err(app_err) = checksums::forget(output_dir, path) {
+                    warn!("Failed to update the checksum manifest: {}", app_err);
+                }
+            }
+        }
+        removed.push(frame.path);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::output_format::OutputFormat;
+
+    /// An empty directory for the test `name`
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("himawari-desktop-updater-retention-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes an empty frame captured at `timestamp` to `dir`
+    fn frame(dir: &Path, timestamp: &DateTime<Utc>, output_format: &OutputFormat) -> PathBuf {
+        let path = dir.join(archive::frame_file_name(timestamp, output_format));
+        std::fs::write(&path, b"").unwrap();
+        path
+    }
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.ymd(2022, 11, 1).and_hms(hour, 0, 0)
+    }
+
+    fn options(keep_last: Option<u32>, keep_days: Option<u32>) -> RetentionOptions {
+        RetentionOptions {
+            keep_last,
+            keep_days,
+            dry_run: false,
+            checksums: false,
+        }
+    }
+
+    fn sorted(mut paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn keep_last_counts_frames_once() {
+        let dir = test_dir("keep-last");
+        let paths: Vec<_> = (0..4).map(|hour| frame(&dir, &at(hour), &OutputFormat::PNG)).collect();
+        let jpeg = frame(&dir, &at(2), &OutputFormat::JPEG);
+
+        let removed = prune(&dir, &paths[3], &options(Some(2), None)).unwrap();
+        assert_eq!(sorted(removed), vec![paths[0].clone(), paths[1].clone()]);
+        assert!(!paths[0].exists() && !paths[1].exists());
+        assert!(paths[2].exists() && jpeg.exists() && paths[3].exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn current_frame_is_kept() {
+        let dir = test_dir("current");
+        let paths: Vec<_> = (0..3).map(|hour| frame(&dir, &at(hour), &OutputFormat::PNG)).collect();
+
+        let removed = prune(&dir, &paths[0], &options(Some(1), None)).unwrap();
+        assert_eq!(removed, vec![paths[1].clone()]);
+        assert!(paths[0].exists() && paths[2].exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn keep_days_and_keep_last_both_apply() {
+        let dir = test_dir("keep-days");
+        let now = crate::frame_time::floor(&Utc::now());
+        let old = frame(&dir, &(now - chrono::Duration::days(3)), &OutputFormat::PNG);
+        let recent = frame(&dir, &(now - chrono::Duration::hours(1)), &OutputFormat::PNG);
+        let current = frame(&dir, &now, &OutputFormat::PNG);
+
+        let removed = prune(&dir, &current, &options(Some(3), Some(1))).unwrap();
+        assert_eq!(removed, vec![old.clone()]);
+        assert!(!old.exists() && recent.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn removes_companion_files() {
+        let dir = test_dir("companions");
+        let old = frame(&dir, &at(0), &OutputFormat::PNG);
+        let current = frame(&dir, &at(1), &OutputFormat::PNG);
+        let sidecar = archive::sidecar_path(&old);
+        let thumbnail = archive::thumbnail_path(&old);
+        std::fs::write(&sidecar, "{}").unwrap();
+        std::fs::write(&thumbnail, b"").unwrap();
+        let untouched = dir.join("notes.txt");
+        std::fs::write(&untouched, b"").unwrap();
+
+        prune(&dir, &current, &options(Some(1), None)).unwrap();
+        assert!(!old.exists() && !sidecar.exists() && !thumbnail.exists());
+        assert!(current.exists() && untouched.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn dry_run_removes_nothing() {
+        let dir = test_dir("dry-run");
+        let paths: Vec<_> = (0..3).map(|hour| frame(&dir, &at(hour), &OutputFormat::PNG)).collect();
+        let dry_run = RetentionOptions {
+            dry_run: true,
+            ..options(Some(1), None)
+        };
+
+        let removed = prune(&dir, &paths[2], &dry_run).unwrap();
+        assert_eq!(sorted(removed), vec![paths[0].clone(), paths[1].clone()]);
+        assert!(paths.iter().all(|path| path.exists()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn nothing_removed_without_limits() {
+        let dir = test_dir("no-limits");
+        let paths: Vec<_> = (0..3).map(|hour| frame(&dir, &at(hour), &OutputFormat::PNG)).collect();
+
+        assert!(prune(&dir, &paths[2], &options(None, None)).unwrap().is_empty());
+        assert!(paths.iter().all(|path| path.exists()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
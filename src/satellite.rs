@@ -0,0 +1,288 @@
+//! The satellites whose full disk imagery can be downloaded, and the rules of their servers:
+//! where to find the latest frame, how frames are split into tiles, and where each tile is.
+//!
+//! Himawari comes from NICT. GOES-East and GOES-West come from the RAMMB/CIRA SLIDER tile
+//! server, which serves each frame at zoom levels of 1, 2, 4, 8 or 16 tiles a side.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use chrono::prelude::*;
+use serde_derive::Deserialize;
+
+use crate::error::{AppErr, ErrorKind};
+use crate::geo;
+use crate::product::Product;
+
+pub const HIMAWARI_BASE_URL: &str = "https://himawari8-dl.nict.go.jp/himawari8/img";
+pub const SLIDER_BASE_URL: &str = "https://rammb-slider.cira.colostate.edu/data";
+
+/// Checks that `url`, given with --base-url, can be downloaded from, and returns it without
+/// any trailing slash
+pub fn parse_base_url(url: &str) -> Result<String, AppErr> {
+    let invalid = |reason: &str| AppErr::of_kind(ErrorKind::Config, format!("Invalid base URL {}: {}", url, reason));
+    let parsed = reqwest::Url::parse(url).map_err(|err| invalid(&err.to_string()))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(invalid("use http or https"));
+    }
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Satellite {
+    #[default]
+    Himawari,
+    /// GOES-19, which replaced GOES-16 over the Americas and the Atlantic
+    GoesEast,
+    /// GOES-18, over the Americas and the eastern Pacific
+    GoesWest,
+}
+
+#[derive(Clone)]
+pub struct SatelliteValueParser;
+
+impl clap::builder::TypedValueParser for SatelliteValueParser {
+    type Value = Satellite;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match value.to_string_lossy().trim().to_ascii_lowercase().as_str() {
+            "himawari" | "himawari-8" | "himawari-9" => Ok(Satellite::Himawari),
+            "goes-east" | "goes-16" | "goes-19" => Ok(Satellite::GoesEast),
+            "goes-west" | "goes-17" | "goes-18" => Ok(Satellite::GoesWest),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid satellite, use himawari, goes-east or goes-west")),
+        }
+    }
+}
+
+impl Satellite {
+    pub fn server(self) -> &'static dyn ImageServer {
+        match self {
+            Satellite::Himawari => &Nict,
+            Satellite::GoesEast => &Slider { name: "goes-19" },
+            Satellite::GoesWest => &Slider { name: "goes-18" },
+        }
+    }
+
+    /// The product downloaded unless another is asked for
+    pub fn default_product(self) -> Product {
+        let (name, tile_width) = match self {
+            Satellite::Himawari => ("D531106", 550),
+            Satellite::GoesEast | Satellite::GoesWest => ("geocolor", 678),
+        };
+        Product {
+            satellite: self,
+            name: name.to_string(),
+            tile_width,
+            base_url: None,
+            levels: None,
+        }
+    }
+
+    /// Longitude of the point directly below the satellite
+    pub fn longitude(self) -> f64 {
+        match self {
+            Satellite::Himawari => geo::HIMAWARI_LONGITUDE,
+            Satellite::GoesEast => -75.2,
+            Satellite::GoesWest => -137.0,
+        }
+    }
+
+    /// The name recorded with each frame, e.g. in its EXIF metadata
+    pub fn full_name(self) -> &'static str {
+        match self {
+            Satellite::Himawari => "Himawari",
+            Satellite::GoesEast => "GOES-East",
+            Satellite::GoesWest => "GOES-West",
+        }
+    }
+}
+
+impl Display for Satellite {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            Satellite::Himawari => "himawari",
+            Satellite::GoesEast => "goes-east",
+            Satellite::GoesWest => "goes-west",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Where a satellite's server keeps its frames
+pub trait ImageServer {
+    /// The URL frames are downloaded from unless --base-url says otherwise
+    fn default_base_url(&self) -> &'static str;
+
+    /// The path of the metadata giving the time of the latest frame of `product`, relative
+    /// to the base URL
+    fn latest_path(&self, product: &str) -> String;
+
+    /// Reads the time of the latest frame from the metadata
+    fn parse_latest(&self, body: &[u8]) -> Result<DateTime<Utc>, AppErr>;
+
+    /// Reads the times of every frame the metadata lists, which is only the latest unless the
+    /// server lists more
+    fn parse_times(&self, body: &[u8]) -> Result<Vec<DateTime<Utc>>, AppErr> {
+        Ok(vec![self.parse_latest(body)?])
+    }
+
+    /// The path of the tile at (`x`, `y`) of a frame split into `level` tiles a side,
+    /// relative to the base URL
+    fn tile_path(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> String;
+
+    /// The numbers of tiles a side frames of `product` are served at, smallest first
+    fn levels(&self, product: &str) -> &'static [u32];
+
+    /// Checks that tiles could be named at each of `levels` tiles a side, the levels a mirror
+    /// given with --levels serves
+    fn check_levels(&self, _levels: &[u32]) -> Result<(), AppErr> {
+        Ok(())
+    }
+
+    /// The name of the directory tiles are saved in, which is also the name of its cache
+    fn product_dir(&self, product: &str) -> String;
+}
+
+struct Nict;
+
+#[derive(Deserialize)]
+struct NictLatest {
+    date: String,
+}
+
+impl ImageServer for Nict {
+    fn default_base_url(&self) -> &'static str {
+        HIMAWARI_BASE_URL
+    }
+
+    fn latest_path(&self, product: &str) -> String {
+        format!("{}/latest.json", product)
+    }
+
+    fn parse_latest(&self, body: &[u8]) -> Result<DateTime<Utc>, AppErr> {
+        let latest: NictLatest = serde_json::from_slice(body)?;
+        Ok(Utc.datetime_from_str(&latest.date, "%Y-%m-%d %H:%M:%S")?)
+    }
+
+    fn tile_path(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> String {
+        format!(
+            "{}/{}d/{}/{}_{}_{}.png",
+            product.name,
+            level,
+            product.tile_width,
+            timestamp.format("%Y/%m/%d/%H%M%S"),
+            x,
+            y
+        )
+    }
+
+    fn levels(&self, product: &str) -> &'static [u32] {
+        // The infrared band is only served up to 8 tiles a side
+        match product {
+            "INFRARED_FULL" => &[1, 2, 4, 8],
+            _ => &[1, 2, 4, 8, 16, 20],
+        }
+    }
+
+    fn product_dir(&self, product: &str) -> String {
+        product.to_string()
+    }
+}
+
+struct Slider {
+    /// The name of the satellite on the server, e.g. "goes-19"
+    name: &'static str,
+}
+
+#[derive(Deserialize)]
+struct SliderLatest {
+    /// Scan times as numbers like 20221101120020, newest first
+    timestamps_int: Vec<u64>,
+}
+
+impl ImageServer for Slider {
+    fn default_base_url(&self) -> &'static str {
+        SLIDER_BASE_URL
+    }
+
+    fn latest_path(&self, product: &str) -> String {
+        format!("json/{}/full_disk/{}/latest_times.json", self.name, product)
+    }
+
+    fn parse_latest(&self, body: &[u8]) -> Result<DateTime<Utc>, AppErr> {
+        self.parse_times(body)?
+            .into_iter()
+            .max()
+            .ok_or_else(|| AppErr::of_kind(ErrorKind::Parse, "No frames listed"))
+    }
+
+    fn parse_times(&self, body: &[u8]) -> Result<Vec<DateTime<Utc>>, AppErr> {
+        let latest: SliderLatest = serde_json::from_slice(body)?;
+        latest
+            .timestamps_int
+            .iter()
+            .map(|time| Ok(Utc.datetime_from_str(&time.to_string(), "%Y%m%d%H%M%S")?))
+            .collect()
+    }
+
+    fn tile_path(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> String {
+        // Zoom level n has 2^n tiles a side, named by row then column
+        format!(
+            "imagery/{}/{}---full_disk/{}/{}/{:02}/{:03}_{:03}.png",
+            timestamp.format("%Y/%m/%d"),
+            self.name,
+            product.name,
+            timestamp.format("%Y%m%d%H%M%S"),
+            level.trailing_zeros(),
+            y,
+            x
+        )
+    }
+
+    fn levels(&self, _product: &str) -> &'static [u32] {
+        // Zoom levels 0 to 4
+        &[1, 2, 4, 8, 16]
+    }
+
+    fn check_levels(&self, levels: &[u32]) -> Result<(), AppErr> {
+        // Tiles are named by zoom level, so only come in powers of two a side
+        match levels.iter().find(|level| !level.is_power_of_two()) {
+            Some(level) => Err(AppErr::of_kind(
+                ErrorKind::Config,
+                format!("Invalid level {}: GOES tiles are served at powers of two a side", level),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn product_dir(&self, product: &str) -> String {
+        format!("{}_{}", self.name, product)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slider_levels_are_powers_of_two() {
+        let server = Satellite::GoesEast.server();
+        assert!(server.check_levels(&[1, 2, 4, 8, 16, 32]).is_ok());
+        assert_eq!(server.check_levels(&[4, 20]).err().map(|app_err| app_err.kind()), Some(ErrorKind::Config));
+        assert!(server.check_levels(&[0]).is_err());
+    }
+
+    #[test]
+    fn nict_levels_are_any() {
+        assert!(Satellite::Himawari.server().check_levels(&[4, 20, 40]).is_ok());
+    }
+
+    #[test]
+    fn slider_zoom_follows_level() {
+        let product = Satellite::GoesWest.default_product();
+        let timestamp = Utc.ymd(2022, 11, 1).and_hms(12, 0, 20);
+        assert_eq!(
+            Satellite::GoesWest.server().tile_path(&product, 8, &timestamp, 2, 5),
+            "imagery/2022/11/01/goes-18---full_disk/geocolor/20221101120020/03/005_002.png"
+        );
+    }
+}
@@ -0,0 +1,459 @@
+//! Installing a scheduled task which runs the updater every few minutes: a Task Scheduler
+//! task on Windows, a launchd agent on macOS, and on Linux a systemd user timer, or a crontab
+//! entry without systemd.
+//!
+//! Without arguments the task picks up its settings from the config file, and starts in the
+//! config directory, which is where the log file is written. With arguments, as given to the
+//! install-schedule subcommand, it starts in the directory it was installed from so relative
+//! paths mean the same.
+
+use crate::error::AppErr;
+use crate::messages::tr;
+
+#[cfg(not(target_os = "macos"))]
+const TASK_NAME: &str = "himawari-desktop-updater";
+
+/// The directory the task starts in
+fn working_dir(args: &[String]) -> Result<std::path::PathBuf, AppErr> {
+    let dir = match args.is_empty() {
+        true => crate::config::default_path()
+            .as_deref()
+            .and_then(|path| path.parent())
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_else(std::env::temp_dir),
+        false => std::env::current_dir()?,
+    };
+    std::fs::DirBuilder::new().recursive(true).create(&dir)?;
+    Ok(dir)
+}
+
+/// Installs the task, replacing any installed before, to run with `args` every `interval`
+/// minutes
+#[cfg(windows)]
+pub fn install(interval: u32, args: &[String]) -> Result<(), AppErr> {
+    use std::process::Command;
+
+    let exe = std::env::current_exe()?;
+    let working_dir = working_dir(args)?;
+
+    let arguments = args.iter().map(|arg| quote_arg(arg)).collect::<Vec<_>>().join(" ");
+    let xml = task_xml(
+        &exe.to_string_lossy(),
+        &arguments,
+        &working_dir.to_string_lossy(),
+        interval,
+    );
+    // schtasks expects task definitions to be UTF-16, with a byte order mark
+    let mut bytes = vec![0xFF, 0xFE];
+    bytes.extend(xml.encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+    let xml_path = std::env::temp_dir().join("himawari-desktop-updater-task.xml");
+    std::fs::write(&xml_path, bytes)?;
+
+    let status = Command::new("schtasks")
+        .args(["/Create", "/F", "/TN", TASK_NAME, "/XML"])
+        .arg(&xml_path)
+        .status();
+    let _ = std::fs::remove_file(&xml_path);
+    let status = status?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("schedule-failed", status = status))),
+    }
+}
+
+/// Removes the task, if it is installed
+#[cfg(windows)]
+pub fn uninstall() -> Result<(), AppErr> {
+    use std::process::Command;
+
+    let status = Command::new("schtasks")
+        .args(["/Delete", "/F", "/TN", TASK_NAME])
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("unschedule-failed", status = status))),
+    }
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Quotes `arg` for a Windows command line, where backslashes only escape quotes and the
+/// backslashes before them
+#[cfg(windows)]
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push(c);
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// A task which runs `command` with `arguments` every `interval` minutes while the user is
+/// logged on
+#[cfg(windows)]
+fn task_xml(command: &str, arguments: &str, working_dir: &str, interval: u32) -> String {
+    use chrono::prelude::*;
+
+    let start = Local::now().format("%Y-%m-%dT%H:%M:%S");
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <Triggers>
+    <TimeTrigger>
+      <StartBoundary>{start}</StartBoundary>
+      <Repetition>
+        <Interval>PT{interval}M</Interval>
+      </Repetition>
+      <Enabled>true</Enabled>
+    </TimeTrigger>
+  </Triggers>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <StartWhenAvailable>true</StartWhenAvailable>
+    <ExecutionTimeLimit>PT1H</ExecutionTimeLimit>
+  </Settings>
+  <Actions>
+    <Exec>
+      <Command>{command}</Command>
+      <Arguments>{arguments}</Arguments>
+      <WorkingDirectory>{working_dir}</WorkingDirectory>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+        start = start,
+        interval = interval,
+        command = escape_xml(command),
+        arguments = escape_xml(arguments),
+        working_dir = escape_xml(working_dir),
+    )
+}
+
+/// Marks the crontab entry of the schedule, so it can be replaced and removed
+#[cfg(not(any(windows, target_os = "macos")))]
+const CRONTAB_MARKER: &str = "# himawari-desktop-updater";
+
+/// The variables the updater finds the desktop through, which neither systemd timers nor cron
+/// pass on, so they are kept from the session the schedule is installed in
+#[cfg(not(any(windows, target_os = "macos")))]
+const DESKTOP_VARS: [&str; 5] = [
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XDG_CURRENT_DESKTOP",
+    "DESKTOP_SESSION",
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+/// Installs a systemd user timer, or a crontab entry where systemd isn't running, replacing
+/// any installed before, to run with `args` every `interval` minutes
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn install(interval: u32, args: &[String]) -> Result<(), AppErr> {
+    let exe = std::env::current_exe()?;
+    let working_dir = working_dir(args)?;
+    let command = std::iter::once(exe.to_string_lossy().into_owned())
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>();
+    let env = DESKTOP_VARS
+        .iter()
+        .filter_map(|&name| Some((name, std::env::var(name).ok().filter(|value| !value.is_empty())?)))
+        .collect::<Vec<_>>();
+    let schedule = Schedule {
+        interval,
+        command,
+        working_dir: working_dir.to_string_lossy().into_owned(),
+        env,
+    };
+    match systemd_unit_dir() {
+        Some(ref dir) if has_systemd() => install_timer(dir, &schedule),
+        _ => install_crontab(&schedule),
+    }
+}
+
+/// Removes the timer and the crontab entry, whichever are installed
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn uninstall() -> Result<(), AppErr> {
+    if let Some(dir) = systemd_unit_dir() {
+        let timer = dir.join(format!("{}.timer", TASK_NAME));
+        if timer.exists() {
+            systemctl(&["disable", "--now", &format!("{}.timer", TASK_NAME)])?;
+            std::fs::remove_file(timer)?;
+            std::fs::remove_file(dir.join(format!("{}.service", TASK_NAME)))?;
+            systemctl(&["daemon-reload"])?;
+        }
+    }
+    // crontab may not even be installed
+    if let Ok(crontab) = read_crontab() {
+        if crontab.lines().any(|line| line.ends_with(CRONTAB_MARKER)) {
+            write_crontab(&without_schedule(&crontab))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+struct Schedule {
+    interval: u32,
+    /// The program and its arguments
+    command: Vec<String>,
+    working_dir: String,
+    env: Vec<(&'static str, String)>,
+}
+
+/// Where systemd looks for the user's own units
+#[cfg(not(any(windows, target_os = "macos")))]
+fn systemd_unit_dir() -> Option<std::path::PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty()).map(std::path::PathBuf::from);
+    let config = var("XDG_CONFIG_HOME").or_else(|| var("HOME").map(|home| home.join(".config")))?;
+    Some(config.join("systemd").join("user"))
+}
+
+/// Whether a systemd user instance is running to run the timer
+#[cfg(not(any(windows, target_os = "macos")))]
+fn has_systemd() -> bool {
+    std::process::Command::new("systemctl")
+        .args(["--user", "show-environment"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn systemctl(args: &[&str]) -> Result<(), AppErr> {
+    let status = std::process::Command::new("systemctl").arg("--user").args(args).status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("command-failed", command = "systemctl", status = status))),
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn install_timer(dir: &std::path::Path, schedule: &Schedule) -> Result<(), AppErr> {
+    // Specifiers start with % and variables with $ in unit files, wherever they appear
+    let escape = |text: &str| text.replace('%', "%%").replace('$', "$$");
+    let quote = |text: &str| format!("\"{}\"", escape(text).replace('\\', "\\\\").replace('"', "\\\""));
+
+    let mut service = String::from("[Unit]\nDescription=Updates the wallpaper with the latest photo of the Earth\n\n");
+    service.push_str("[Service]\nType=oneshot\n");
+    service.push_str(&format!("WorkingDirectory={}\n", escape(&schedule.working_dir)));
+    for (name, value) in &schedule.env {
+        service.push_str(&format!("Environment={}\n", quote(&format!("{}={}", name, value))));
+    }
+    let command = schedule.command.iter().map(|word| quote(word)).collect::<Vec<_>>().join(" ");
+    service.push_str(&format!("ExecStart={}\n", command));
+
+    let timer = format!(
+        "[Unit]\nDescription=Runs {name} every {interval} minutes\n\n\
+         [Timer]\nOnBootSec=1min\nOnUnitActiveSec={interval}min\n\n\
+         [Install]\nWantedBy=timers.target\n",
+        name = TASK_NAME,
+        interval = schedule.interval,
+    );
+
+    std::fs::DirBuilder::new().recursive(true).create(dir)?;
+    std::fs::write(dir.join(format!("{}.service", TASK_NAME)), service)?;
+    std::fs::write(dir.join(format!("{}.timer", TASK_NAME)), timer)?;
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", &format!("{}.timer", TASK_NAME)])?;
+    // Restart it, so a changed interval applies from now
+    systemctl(&["restart", &format!("{}.timer", TASK_NAME)])
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn install_crontab(schedule: &Schedule) -> Result<(), AppErr> {
+    // cron can only count minutes within the hour, so longer intervals become whole hours
+    let times = match schedule.interval {
+        minutes if minutes < 60 => format!("*/{} * * * *", minutes),
+        minutes => {
+            let hours = minutes.div_ceil(60);
+            if minutes % 60 != 0 {
+                log::warn!("cron can't run every {} minutes, running every {} hours instead", minutes, hours);
+            }
+            format!("0 */{} * * *", hours)
+        }
+    };
+    // Quoted for sh, with % escaped as cron reads it as a newline
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "'\\''")).replace('%', "\\%");
+    let env = schedule
+        .env
+        .iter()
+        .map(|(name, value)| format!("{}={} ", name, quote(value)))
+        .collect::<String>();
+    let command = schedule.command.iter().map(|word| quote(word)).collect::<Vec<_>>().join(" ");
+    let entry = format!(
+        "{} cd {} && {}{} {}\n",
+        times,
+        quote(&schedule.working_dir),
+        env,
+        command,
+        CRONTAB_MARKER
+    );
+
+    let crontab = read_crontab()?;
+    write_crontab(&(without_schedule(&crontab) + &entry))
+}
+
+/// The user's crontab, which is empty if they have none yet
+#[cfg(not(any(windows, target_os = "macos")))]
+fn read_crontab() -> Result<String, AppErr> {
+    let output = std::process::Command::new("crontab").arg("-l").output().map_err(crontab_error)?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Ok(String::new()),
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn write_crontab(crontab: &str) -> Result<(), AppErr> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("crontab").arg("-").stdin(Stdio::piped()).spawn().map_err(crontab_error)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(crontab.as_bytes())?;
+    }
+    let status = child.wait()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("schedule-failed", status = status))),
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn crontab_error(err: std::io::Error) -> AppErr {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => AppErr::new(tr!("program-not-found", program = "crontab")),
+        _ => err.into(),
+    }
+}
+
+/// `crontab` without the entry of the schedule
+#[cfg(not(any(windows, target_os = "macos")))]
+fn without_schedule(crontab: &str) -> String {
+    crontab
+        .lines()
+        .filter(|line| !line.ends_with(CRONTAB_MARKER))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// The label of the launchd agent, which also names its plist
+#[cfg(target_os = "macos")]
+const AGENT_LABEL: &str = "io.github.deadalusai.himawari-desktop-updater";
+
+/// Where the agent's plist is installed, in the user's own launch agents
+#[cfg(target_os = "macos")]
+fn agent_path() -> Result<std::path::PathBuf, AppErr> {
+    let home = std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .ok_or_else(|| AppErr::new(tr!("no-config-dir")))?;
+    let mut path = std::path::PathBuf::from(home);
+    path.push("Library");
+    path.push("LaunchAgents");
+    path.push(format!("{}.plist", AGENT_LABEL));
+    Ok(path)
+}
+
+/// Installs a launchd agent, replacing any installed before, to run with `args` every
+/// `interval` minutes while the user is logged in
+#[cfg(target_os = "macos")]
+pub fn install(interval: u32, args: &[String]) -> Result<(), AppErr> {
+    let exe = std::env::current_exe()?;
+    let working_dir = working_dir(args)?;
+    let command = std::iter::once(exe.to_string_lossy().into_owned())
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>();
+    let plist = agent_plist(&command, &working_dir.to_string_lossy(), interval);
+
+    let path = agent_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new().recursive(true).create(dir)?;
+    }
+    // An agent which is loaded keeps its old settings until it is unloaded
+    if path.exists() {
+        let _ = launchctl("unload", &path);
+    }
+    std::fs::write(&path, plist)?;
+    launchctl("load", &path)
+}
+
+/// Unloads and removes the agent, if it is installed
+#[cfg(target_os = "macos")]
+pub fn uninstall() -> Result<(), AppErr> {
+    let path = agent_path()?;
+    if path.exists() {
+        launchctl("unload", &path)?;
+        std::fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn launchctl(subcommand: &str, path: &std::path::Path) -> Result<(), AppErr> {
+    let status = std::process::Command::new("launchctl")
+        .args([subcommand, "-w"])
+        .arg(path)
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AppErr::new(tr!("command-failed", command = "launchctl", status = status))),
+    }
+}
+
+/// An agent which runs `command` every `interval` minutes, and as soon as it is loaded
+#[cfg(target_os = "macos")]
+fn agent_plist(command: &[String], working_dir: &str, interval: u32) -> String {
+    let arguments = command
+        .iter()
+        .map(|word| format!("        <string>{}</string>\n", escape_xml(word)))
+        .collect::<String>();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>WorkingDirectory</key>
+    <string>{working_dir}</string>
+    <key>StartInterval</key>
+    <integer>{seconds}</integer>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#,
+        label = AGENT_LABEL,
+        arguments = arguments,
+        working_dir = escape_xml(working_dir),
+        seconds = interval * 60,
+    )
+}
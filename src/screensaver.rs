@@ -0,0 +1,417 @@
+//! Screensaver mode.
+//!
+//! When the binary is copied to a `.scr` file Windows invokes it with one of
+//! the standard screensaver arguments:
+//!
+//! - `/s` to run the screensaver fullscreen
+//! - `/p <HWND>` to render a preview into the given window
+//! - `/c[:HWND]` to show the configuration dialog
+//!
+//! The screensaver cycles through the most recent frames in the archive
+//! directory chosen in the configuration dialog.
+
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
+use std::mem::{size_of, zeroed};
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::ptr::{null, null_mut};
+
+use image::imageops::FilterType;
+use winapi::shared::minwindef::{FALSE, LPARAM, LRESULT, TRUE, UINT, WPARAM};
+use winapi::shared::windef::{HBRUSH, HWND, POINT, RECT};
+use winapi::shared::windowsx::{GET_X_LPARAM, GET_Y_LPARAM};
+use winapi::shared::winerror::{FAILED, SUCCEEDED};
+use winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER;
+use winapi::um::combaseapi::{CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize};
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::objbase::{COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE};
+use winapi::um::shobjidl::{IFileOpenDialog, FOS_PICKFOLDERS};
+use winapi::um::shobjidl_core::{CLSID_FileOpenDialog, IShellItem, SIGDN_FILESYSPATH};
+use winapi::um::wingdi::{
+    GetStockObject, SetDIBitsToDevice, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, BLACK_BRUSH,
+    DIB_RGB_COLORS,
+};
+use winapi::um::winnt::LPWSTR;
+use winapi::um::winuser::*;
+use winapi::Interface;
+use winreg::enums::HKEY_CURRENT_USER;
+use winreg::RegKey;
+
+use crate::archive::list_frames;
+use crate::error::AppErr;
+use crate::ffi_windows::os_str_to_wchar;
+use crate::messages::tr;
+
+/// The number of recent frames to cycle through (one day of captures at 10 minute intervals)
+const RECENT_FRAME_COUNT: usize = 144;
+/// How long each frame stays on screen
+const FRAME_INTERVAL_MS: UINT = 2000;
+const FRAME_TIMER_ID: usize = 1;
+/// How far the mouse may drift before the screensaver exits
+const MOUSE_MOVE_THRESHOLD: i32 = 8;
+
+const REGISTRY_KEY: &str = "Software\\himawari-desktop-updater";
+const REGISTRY_ARCHIVE_DIR: &str = "ScreensaverDir";
+
+pub enum ScreensaverMode {
+    Run,
+    Preview(HWND),
+    Configure(HWND),
+}
+
+/// Recognizes the arguments Windows passes to screensavers
+pub fn parse_args(args: &[String]) -> Option<ScreensaverMode> {
+    let first = args.get(1)?.to_ascii_lowercase();
+    let first = first.strip_prefix('/').or_else(|| first.strip_prefix('-'))?;
+    // The window handle is passed either as "/c:1234" or "/p 1234"
+    let (flag, hwnd) = match first.split_once(':') {
+        Some((flag, hwnd)) => (flag, Some(hwnd)),
+        None => (first, args.get(2).map(|s| s.as_str())),
+    };
+    let hwnd = hwnd
+        .and_then(|s| s.trim().parse::<usize>().ok())
+        .map_or(null_mut(), |n| n as HWND);
+    match flag {
+        "s" => Some(ScreensaverMode::Run),
+        "p" if !hwnd.is_null() => Some(ScreensaverMode::Preview(hwnd)),
+        "c" => Some(ScreensaverMode::Configure(hwnd)),
+        _ => None,
+    }
+}
+
+pub fn run(mode: ScreensaverMode) -> Result<(), AppErr> {
+    match mode {
+        ScreensaverMode::Run => show(None),
+        ScreensaverMode::Preview(parent) => show(Some(parent)),
+        ScreensaverMode::Configure(parent) => match pick_folder(parent)? {
+            Some(dir) => write_archive_dir(&dir),
+            None => Ok(()),
+        },
+    }
+}
+
+fn read_archive_dir() -> Option<PathBuf> {
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    let key = hkcu.open_subkey(REGISTRY_KEY).ok()?;
+    let dir: String = key.get_value(REGISTRY_ARCHIVE_DIR).ok()?;
+    Some(PathBuf::from(dir))
+}
+
+fn write_archive_dir(dir: &Path) -> Result<(), AppErr> {
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    let (key, _) = hkcu.create_subkey(REGISTRY_KEY)?;
+    key.set_value(REGISTRY_ARCHIVE_DIR, &dir.as_os_str())?;
+    Ok(())
+}
+
+fn recent_frames() -> Vec<PathBuf> {
+    let frames = match read_archive_dir() {
+        Some(dir) => list_frames(&dir).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    // Play the frames back in chronological order
+    frames
+        .into_iter()
+        .take(RECENT_FRAME_COUNT)
+        .map(|frame| frame.path)
+        .rev()
+        .collect()
+}
+
+/// A frame scaled to the window and converted to a top-down BGRA bitmap
+struct Bitmap {
+    width: i32,
+    height: i32,
+    bgra: Vec<u8>,
+}
+
+fn load_bitmap(path: &Path, width: u32, height: u32) -> Result<Bitmap, AppErr> {
+    let image = image::open(path)?
+        .resize(width, height, FilterType::Triangle)
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    let mut bgra = image.into_raw();
+    for pixel in bgra.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    Ok(Bitmap {
+        width: width as i32,
+        height: height as i32,
+        bgra,
+    })
+}
+
+struct Screensaver {
+    frames: Vec<PathBuf>,
+    next_frame: usize,
+    current: Option<Bitmap>,
+    preview: bool,
+    cursor_origin: Option<POINT>,
+}
+
+impl Screensaver {
+    fn advance(&mut self, hwnd: HWND) {
+        let rect = client_rect(hwnd);
+        // Skip over frames which fail to load (e.g. pruned since we started)
+        for _ in 0..self.frames.len() {
+            let path = &self.frames[self.next_frame];
+            self.next_frame = (self.next_frame + 1) % self.frames.len();
+            if let Ok(bitmap) = load_bitmap(path, rect.right as u32, rect.bottom as u32) {
+                self.current = Some(bitmap);
+                break;
+            }
+        }
+    }
+
+    /// Returns true if the mouse has moved far enough to dismiss the screensaver
+    fn mouse_moved(&mut self, position: POINT) -> bool {
+        match self.cursor_origin {
+            None => {
+                self.cursor_origin = Some(position);
+                false
+            }
+            Some(origin) => {
+                (position.x - origin.x).abs() > MOUSE_MOVE_THRESHOLD
+                    || (position.y - origin.y).abs() > MOUSE_MOVE_THRESHOLD
+            }
+        }
+    }
+}
+
+thread_local! {
+    static SCREENSAVER: RefCell<Option<Screensaver>> = const { RefCell::new(None) };
+}
+
+fn with_screensaver<T>(f: impl FnOnce(&mut Screensaver) -> T) -> Option<T> {
+    SCREENSAVER.with(|cell| cell.borrow_mut().as_mut().map(f))
+}
+
+fn client_rect(hwnd: HWND) -> RECT {
+    unsafe {
+        let mut rect: RECT = zeroed();
+        GetClientRect(hwnd, &mut rect);
+        rect
+    }
+}
+
+fn show(parent: Option<HWND>) -> Result<(), AppErr> {
+    let screensaver = Screensaver {
+        frames: recent_frames(),
+        next_frame: 0,
+        current: None,
+        preview: parent.is_some(),
+        cursor_origin: None,
+    };
+    SCREENSAVER.with(|cell| *cell.borrow_mut() = Some(screensaver));
+
+    unsafe {
+        let instance = GetModuleHandleW(null());
+        let class_name = os_str_to_wchar(OsStr::new("HimawariScreensaver"));
+        let class = WNDCLASSW {
+            style: CS_HREDRAW | CS_VREDRAW,
+            lpfnWndProc: Some(window_proc),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: instance,
+            hIcon: null_mut(),
+            hCursor: null_mut(),
+            hbrBackground: null_mut(),
+            lpszMenuName: null(),
+            lpszClassName: class_name.as_ptr(),
+        };
+        if RegisterClassW(&class) == 0 {
+            return Err(AppErr::new(tr!("screensaver-class-failed")));
+        }
+
+        let hwnd = match parent {
+            Some(parent) => {
+                let rect = client_rect(parent);
+                CreateWindowExW(
+                    0,
+                    class_name.as_ptr(),
+                    null(),
+                    WS_CHILD | WS_VISIBLE,
+                    0,
+                    0,
+                    rect.right,
+                    rect.bottom,
+                    parent,
+                    null_mut(),
+                    instance,
+                    null_mut(),
+                )
+            }
+            None => CreateWindowExW(
+                WS_EX_TOPMOST,
+                class_name.as_ptr(),
+                null(),
+                WS_POPUP | WS_VISIBLE,
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+                GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                GetSystemMetrics(SM_CYVIRTUALSCREEN),
+                null_mut(),
+                null_mut(),
+                instance,
+                null_mut(),
+            ),
+        };
+        if hwnd.is_null() {
+            return Err(AppErr::new(tr!("screensaver-window-failed")));
+        }
+
+        let mut msg: MSG = zeroed();
+        while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    Ok(())
+}
+
+fn paint(hwnd: HWND) {
+    unsafe {
+        let mut ps: PAINTSTRUCT = zeroed();
+        let hdc = BeginPaint(hwnd, &mut ps);
+        let rect = client_rect(hwnd);
+        FillRect(hdc, &rect, GetStockObject(BLACK_BRUSH as i32) as HBRUSH);
+        with_screensaver(|screensaver| {
+            if let Some(ref bitmap) = screensaver.current {
+                let mut info: BITMAPINFO = zeroed();
+                info.bmiHeader.biSize = size_of::<BITMAPINFOHEADER>() as u32;
+                info.bmiHeader.biWidth = bitmap.width;
+                // Negative height indicates a top-down bitmap
+                info.bmiHeader.biHeight = -bitmap.height;
+                info.bmiHeader.biPlanes = 1;
+                info.bmiHeader.biBitCount = 32;
+                info.bmiHeader.biCompression = BI_RGB;
+                SetDIBitsToDevice(
+                    hdc,
+                    (rect.right - bitmap.width) / 2,
+                    (rect.bottom - bitmap.height) / 2,
+                    bitmap.width as u32,
+                    bitmap.height as u32,
+                    0,
+                    0,
+                    0,
+                    bitmap.height as u32,
+                    bitmap.bgra.as_ptr() as *const _,
+                    &info,
+                    DIB_RGB_COLORS,
+                );
+            }
+        });
+        EndPaint(hwnd, &ps);
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    let preview = with_screensaver(|screensaver| screensaver.preview).unwrap_or(true);
+    match msg {
+        WM_CREATE => {
+            with_screensaver(|screensaver| screensaver.advance(hwnd));
+            SetTimer(hwnd, FRAME_TIMER_ID, FRAME_INTERVAL_MS, None);
+            0
+        }
+        WM_TIMER => {
+            with_screensaver(|screensaver| screensaver.advance(hwnd));
+            InvalidateRect(hwnd, null(), FALSE);
+            0
+        }
+        // The whole window is repainted in WM_PAINT
+        WM_ERASEBKGND => 1,
+        WM_PAINT => {
+            paint(hwnd);
+            0
+        }
+        WM_SETCURSOR if !preview => {
+            SetCursor(null_mut());
+            TRUE as LRESULT
+        }
+        WM_MOUSEMOVE if !preview => {
+            let position = POINT {
+                x: GET_X_LPARAM(lparam),
+                y: GET_Y_LPARAM(lparam),
+            };
+            if with_screensaver(|screensaver| screensaver.mouse_moved(position)).unwrap_or(false) {
+                PostMessageW(hwnd, WM_CLOSE, 0, 0);
+            }
+            0
+        }
+        WM_KEYDOWN | WM_SYSKEYDOWN | WM_LBUTTONDOWN | WM_MBUTTONDOWN | WM_RBUTTONDOWN
+            if !preview =>
+        {
+            PostMessageW(hwnd, WM_CLOSE, 0, 0);
+            0
+        }
+        WM_ACTIVATEAPP if !preview && wparam == 0 => {
+            PostMessageW(hwnd, WM_CLOSE, 0, 0);
+            0
+        }
+        WM_DESTROY => {
+            KillTimer(hwnd, FRAME_TIMER_ID);
+            PostQuitMessage(0);
+            0
+        }
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
+
+/// Shows the shell folder picker, returning None if the user cancelled
+fn pick_folder(parent: HWND) -> Result<Option<PathBuf>, AppErr> {
+    unsafe {
+        CoInitializeEx(null_mut(), COINIT_APARTMENTTHREADED | COINIT_DISABLE_OLE1DDE);
+
+        let mut dialog: *mut IFileOpenDialog = null_mut();
+        let hr = CoCreateInstance(
+            &CLSID_FileOpenDialog,
+            null_mut(),
+            CLSCTX_INPROC_SERVER,
+            &IFileOpenDialog::uuidof(),
+            &mut dialog as *mut *mut IFileOpenDialog as *mut _,
+        );
+        if FAILED(hr) {
+            CoUninitialize();
+            return Err(AppErr::new(tr!("folder-picker-failed")));
+        }
+
+        let mut options = 0;
+        (*dialog).GetOptions(&mut options);
+        (*dialog).SetOptions(options | FOS_PICKFOLDERS);
+        let title = os_str_to_wchar(OsStr::new(&tr!("folder-picker-title")));
+        (*dialog).SetTitle(title.as_ptr());
+
+        let mut path = None;
+        // Show fails with ERROR_CANCELLED if the dialog is dismissed
+        if SUCCEEDED((*dialog).Show(parent)) {
+            let mut item: *mut IShellItem = null_mut();
+            if SUCCEEDED((*dialog).GetResult(&mut item)) {
+                let mut name: LPWSTR = null_mut();
+                if SUCCEEDED((*item).GetDisplayName(SIGDN_FILESYSPATH, &mut name)) {
+                    path = Some(PathBuf::from(wchar_to_os_string(name)));
+                    CoTaskMemFree(name as *mut _);
+                }
+                (*item).Release();
+            }
+        }
+
+        (*dialog).Release();
+        CoUninitialize();
+        Ok(path)
+    }
+}
+
+unsafe fn wchar_to_os_string(wchar: LPWSTR) -> OsString {
+    let mut len = 0;
+    while *wchar.add(len) != 0 {
+        len += 1;
+    }
+    OsString::from_wide(std::slice::from_raw_parts(wchar, len))
+}
@@ -0,0 +1,162 @@
+//! Updating the updater itself from the project's GitHub releases, with `self-update`.
+//!
+//! Each release carries a binary for every platform, named for the OS and architecture, e.g.
+//! `himawari-desktop-updater-windows-x86_64.exe`, and a `SHA256SUMS` manifest of them. The
+//! binary for this platform is downloaded, checked against the manifest, and put in place of
+//! the running executable. Windows won't overwrite an executable which is running, but will
+//! rename it, so there the old one is moved aside and removed by the next update.
+
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use reqwest::header::{ACCEPT, USER_AGENT};
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppErr, ErrorKind};
+use crate::http::HttpClient;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/deadalusai/himawari-desktop-updater/releases/latest";
+
+/// The hosts releases are downloaded from, to look up with another resolver
+pub const HOSTS: [&str; 3] = ["api.github.com", "github.com", "objects.githubusercontent.com"];
+
+/// The name of the manifest of checksums in each release
+const SUMS_ASSET: &str = "SHA256SUMS";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// What `run` did
+pub enum Outcome {
+    UpToDate { version: String },
+    /// A newer version is available, but was only checked for
+    Available { version: String },
+    Updated { version: String },
+}
+
+fn get(client: &HttpClient, url: &str) -> Result<Vec<u8>, AppErr> {
+    let request = client
+        .client()?
+        .get(url)
+        // GitHub refuses API requests without a user agent
+        .header(USER_AGENT, concat!("himawari-desktop-updater/", env!("CARGO_PKG_VERSION")))
+        .header(ACCEPT, "application/vnd.github+json, application/octet-stream");
+    client.block_on(async {
+        let response = request.send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    })
+}
+
+/// The numbers of a version like "v1.2.3", for comparing versions
+fn version_numbers(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+/// The name of the binary for this platform in a release
+fn asset_name() -> String {
+    let os = match std::env::consts::OS {
+        "macos" => "macos",
+        "windows" => "windows",
+        _ => "linux",
+    };
+    format!("himawari-desktop-updater-{}-{}{}", os, std::env::consts::ARCH, std::env::consts::EXE_SUFFIX)
+}
+
+/// The checksum of `name` in a `SHA256SUMS` manifest, as written by `sha256sum`
+fn expected_checksum(manifest: &str, name: &str) -> Option<String> {
+    manifest.lines().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        // A leading '*' marks a file hashed in binary mode
+        (file.trim().trim_start_matches('*') == name).then(|| hash.to_ascii_lowercase())
+    })
+}
+
+/// `<path>.<extension>`, beside the executable
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.to_path_buf().into_os_string();
+    name.push(format!(".{}", extension));
+    PathBuf::from(name)
+}
+
+/// Puts `binary` in place of the executable at `exe`
+fn replace(exe: &Path, binary: &[u8]) -> Result<(), AppErr> {
+    let new_path = sibling(exe, "new");
+    std::fs::write(&new_path, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    // Running executables can be renamed on every platform, but only replaced on Unix
+    let old_path = sibling(exe, "old");
+    #[cfg(windows)]
+    std::fs::rename(exe, &old_path)?;
+    if let Err(err) = std::fs::rename(&new_path, exe) {
+        let _ = std::fs::rename(&old_path, exe);
+        let _ = std::fs::remove_file(&new_path);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Checks the latest release, and unless `check_only` installs it in place of this executable
+/// if it is newer. Downloads are made with `client`.
+pub fn run(client: &HttpClient, check_only: bool) -> Result<Outcome, AppErr> {
+    let current = env!("CARGO_PKG_VERSION");
+    let exe = std::env::current_exe()?;
+    // Left behind by the last update on Windows
+    let _ = std::fs::remove_file(sibling(&exe, "old"));
+
+    info!("Checking for a newer release than {}...", current);
+    let release: Release = serde_json::from_slice(&get(client, RELEASES_URL)?)?;
+    let version = release.tag_name.trim_start_matches('v').to_string();
+    if version_numbers(&release.tag_name) <= version_numbers(current) {
+        return Ok(Outcome::UpToDate { version: current.to_string() });
+    }
+    if check_only {
+        return Ok(Outcome::Available { version });
+    }
+
+    let find = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| AppErr::of_kind(ErrorKind::NoRelease, format!("Release {} has no {}", version, name)))
+    };
+    let name = asset_name();
+    let binary_asset = find(&name)?;
+    let manifest = String::from_utf8_lossy(&get(client, &find(SUMS_ASSET)?.browser_download_url)?).into_owned();
+    let expected = expected_checksum(&manifest, &name)
+        .ok_or_else(|| AppErr::of_kind(ErrorKind::Network, format!("{} of release {} has no entry for {}", SUMS_ASSET, version, name)))?;
+
+    info!("Downloading {}...", binary_asset.browser_download_url);
+    let binary = get(client, &binary_asset.browser_download_url)?;
+    let actual = format!("{:x}", Sha256::digest(&binary));
+    if actual != expected {
+        return Err(AppErr::of_kind(
+            ErrorKind::Network,
+            format!("The checksum of {} is {}, but {} says {}", name, actual, SUMS_ASSET, expected),
+        ));
+    }
+
+    info!("Replacing {}...", exe.display());
+    replace(&exe, &binary).inspect_err(|_| {
+        warn!("The executable may need to be replaced by hand, from {}", binary_asset.browser_download_url);
+    })?;
+    Ok(Outcome::Updated { version })
+}
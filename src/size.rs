@@ -0,0 +1,46 @@
+use std::fmt::Display;
+
+#[derive(Clone)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone)]
+pub struct SizeValueParser;
+
+impl clap::builder::TypedValueParser for SizeValueParser {
+    type Value = Size;
+    fn parse_ref(
+        &self,
+        _cmd: &clap::Command,
+        _arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match Size::try_parse(value.to_string_lossy().as_ref()) {
+            Some(s) => Ok(s),
+            None => Err(Error::raw(ErrorKind::InvalidValue, "Use format WIDTHxHEIGHT")),
+        }
+    }
+}
+
+impl Size {
+    pub fn try_parse(input: &str) -> Option<Size> {
+        let (width, height) = input.trim().split_once(['x', 'X'])?;
+        let width = width.trim().parse::<u32>().ok()?;
+        let height = height.trim().parse::<u32>().ok()?;
+
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        Some(Size { width, height })
+    }
+}
+
+impl Display for Size {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
@@ -0,0 +1,83 @@
+//! State kept between runs in the output directory.
+
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::AppErr;
+
+const STATE_FILE_NAME: &str = ".himawari-state.json";
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct State {
+    /// The frame most recently set as the wallpaper
+    #[serde(default)]
+    pub wallpaper: Option<WallpaperState>,
+    /// The last frame archived with a sequence number
+    #[serde(default)]
+    pub numbered: Option<NumberedState>,
+    /// The frame most recently downloaded and written
+    #[serde(default)]
+    pub last_update: Option<UpdateState>,
+    /// The last update to fail, unless one has succeeded since
+    #[serde(default)]
+    pub last_failure: Option<FailureState>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WallpaperState {
+    pub path: PathBuf,
+    pub timestamp: DateTime<Utc>,
+    /// Whether --heal-wallpaper put this frame back after an update failed, rather than an
+    /// update setting it
+    #[serde(default)]
+    pub stale: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NumberedState {
+    pub number: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateState {
+    pub timestamp: DateTime<Utc>,
+    pub path: PathBuf,
+    /// How many tiles were left black, which --repair can fetch later
+    pub missing_tiles: usize,
+    /// When the update finished
+    pub finished: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FailureState {
+    pub error: String,
+    pub time: DateTime<Utc>,
+    /// How many updates in a row have failed
+    pub count: u32,
+}
+
+impl State {
+    /// Reads the state saved in `output_dir`, or the default state if there is none
+    pub fn load(output_dir: &Path) -> State {
+        std::fs::File::open(output_dir.join(STATE_FILE_NAME))
+            .ok()
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, output_dir: &Path) -> Result<(), AppErr> {
+        let file = std::fs::File::create(output_dir.join(STATE_FILE_NAME))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Whether the frame at `timestamp` was the last one written, to `path`, and is still there
+    pub fn wrote(&self, timestamp: &DateTime<Utc>, path: &Path) -> bool {
+        self.last_update
+            .as_ref()
+            .is_some_and(|update| update.timestamp == *timestamp && update.path == path && path.exists())
+    }
+}
@@ -0,0 +1,98 @@
+//! Storm-following mode: crops the output to a named tropical cyclone.
+//!
+//! Storm positions are read from a feed in the format of the US National Hurricane
+//! Center's `CurrentStorms.json`.
+
+use log::{info, warn};
+use serde_derive::Deserialize;
+
+use crate::error::AppErr;
+use crate::geo;
+use crate::http::HttpClient;
+use crate::region::Region;
+
+pub const DEFAULT_STORM_FEED_URL: &str = "https://www.nhc.noaa.gov/CurrentStorms.json";
+
+#[derive(Clone)]
+pub struct StormOptions {
+    /// The name of the storm to follow, e.g. "Hinnamnor"
+    pub name: String,
+    pub feed_url: String,
+    /// How much of the disk to show: the crop is 1/zoom of the disk wide
+    pub zoom: u32,
+    /// Where the satellite the storm is seen from is, over the equator
+    pub satellite_longitude: f64,
+}
+
+/// Where the disk is on the canvas of a frame: `disk_size` pixels wide at (`disk_x`, `disk_y`)
+/// on a `canvas_width` x `canvas_height` canvas
+pub struct FrameGeometry {
+    pub disk_x: u32,
+    pub disk_y: u32,
+    pub disk_size: u32,
+    pub canvas_width: u32,
+    pub canvas_height: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StormFeed {
+    active_storms: Vec<ActiveStorm>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActiveStorm {
+    name: String,
+    latitude_numeric: f64,
+    longitude_numeric: f64,
+}
+
+/// Looks up the current position (latitude, longitude) of the named storm.
+/// Returns None if the storm is not in the feed.
+pub fn find_storm(client: &HttpClient, options: &StormOptions) -> Result<Option<(f64, f64)>, AppErr> {
+    info!("Downloading storm feed {}...", options.feed_url);
+    let feed: StormFeed = client.download_json(&options.feed_url)?;
+    let storm = feed
+        .active_storms
+        .into_iter()
+        .find(|storm| storm.name.eq_ignore_ascii_case(&options.name));
+    Ok(storm.map(|storm| (storm.latitude_numeric, storm.longitude_numeric)))
+}
+
+/// Finds the region of the canvas of a frame laid out as `geometry` to crop to in order to
+/// follow the storm. The region will have the given aspect ratio (width / height).
+/// Returns None if the storm is not active or not visible.
+pub fn storm_region(
+    client: &HttpClient,
+    options: &StormOptions,
+    geometry: &FrameGeometry,
+    aspect: f64,
+) -> Result<Option<Region>, AppErr> {
+    let (latitude, longitude) = match find_storm(client, options)? {
+        Some(position) => position,
+        None => {
+            warn!("Storm {} is not in the storm feed", options.name);
+            return Ok(None);
+        }
+    };
+    let (u, v) = match geo::project(latitude, longitude, options.satellite_longitude) {
+        Some(position) => position,
+        None => {
+            warn!("Storm {} at {}, {} is not visible", options.name, latitude, longitude);
+            return Ok(None);
+        }
+    };
+    info!("Following storm {} at {}, {}", options.name, latitude, longitude);
+
+    let width = geometry.disk_size / options.zoom;
+    let height = (width as f64 / aspect).round() as u32;
+    Ok(Some(Region::centred_on(
+        geometry.disk_x + (u * geometry.disk_size as f64) as u32,
+        geometry.disk_y + (v * geometry.disk_size as f64) as u32,
+        width.max(1),
+        height.max(1),
+        geometry.canvas_width,
+        geometry.canvas_height,
+    )))
+}
@@ -0,0 +1,40 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// How many times larger than the target size to render before scaling down
+#[derive(Clone)]
+pub struct Supersample(u32);
+
+#[derive(Clone)]
+pub struct SupersampleValueParser;
+
+impl clap::builder::TypedValueParser for SupersampleValueParser {
+    type Value = Supersample;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        let value = value.to_string_lossy();
+        let value = value.trim();
+        let value = value.strip_suffix(['x', 'X']).unwrap_or(value);
+        match value.parse::<u32>() {
+            Ok(n) if (1..=4).contains(&n) => Ok(Supersample(n)),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid supersampling factor, use 1x, 2x, 3x or 4x")),
+        }
+    }
+}
+
+impl Supersample {
+    pub fn to_factor(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Display for Supersample {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        write!(f, "{}x", self.0)
+    }
+}
+
+impl Default for Supersample {
+    fn default() -> Supersample {
+        Supersample(1)
+    }
+}
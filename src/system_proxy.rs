@@ -0,0 +1,200 @@
+//! Following the Windows proxy settings, including automatic detection and PAC scripts.
+//!
+//! reqwest only reads the proxy server from the registry, which isn't enough on networks
+//! that configure proxies through WPAD or an auto-config script, or bypass some hosts.
+//! WinHTTP works out which proxy each host should go through, as browsers would.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::mem::zeroed;
+use std::ptr::null;
+use std::sync::Mutex;
+
+use log::info;
+use reqwest::ClientBuilder;
+use reqwest::{Proxy, Url};
+use winapi::shared::minwindef::{FALSE, TRUE};
+use winapi::um::winbase::GlobalFree;
+use winapi::um::winhttp::*;
+use winapi::um::winnt::LPWSTR;
+
+use crate::ffi_windows::os_str_to_wchar;
+
+/// Environment variables which reqwest reads proxies from, and which take precedence
+const PROXY_VARS: [&str; 6] = [
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+/// The proxy found for each scheme and host, as finding one may mean running a PAC script
+static PROXIES: Mutex<Option<HashMap<String, Option<String>>>> = Mutex::new(None);
+
+/// Sends requests through the proxy the system settings choose for each URL
+pub fn apply(builder: ClientBuilder) -> ClientBuilder {
+    if PROXY_VARS
+        .iter()
+        .any(|name| std::env::var_os(name).is_some())
+    {
+        return builder;
+    }
+    builder.proxy(Proxy::custom(proxy_for))
+}
+
+fn proxy_for(url: &Url) -> Option<String> {
+    let key = format!("{}://{}", url.scheme(), url.host_str()?);
+    let mut proxies = PROXIES.lock().unwrap();
+    let proxies = proxies.get_or_insert_with(HashMap::new);
+    if let Some(proxy) = proxies.get(&key) {
+        return proxy.clone();
+    }
+    let proxy = find_proxy(url);
+    if let Some(ref proxy) = proxy {
+        info!("Using the system proxy {} for {}", proxy, key);
+    }
+    proxies.insert(key, proxy.clone());
+    proxy
+}
+
+/// Takes ownership of a string allocated by WinHTTP
+unsafe fn take_string(string: LPWSTR) -> Option<String> {
+    if string.is_null() {
+        return None;
+    }
+    let mut len = 0;
+    while *string.add(len) != 0 {
+        len += 1;
+    }
+    let value = String::from_utf16_lossy(std::slice::from_raw_parts(string, len));
+    GlobalFree(string as _);
+    Some(value)
+}
+
+fn find_proxy(url: &Url) -> Option<String> {
+    let mut config: WINHTTP_CURRENT_USER_IE_PROXY_CONFIG = unsafe { zeroed() };
+    if unsafe { WinHttpGetIEProxyConfigForCurrentUser(&mut config) } == FALSE {
+        return None;
+    }
+    let auto_config_url = unsafe { take_string(config.lpszAutoConfigUrl) };
+    let proxy = unsafe { take_string(config.lpszProxy) };
+    let bypass = unsafe { take_string(config.lpszProxyBypass) };
+
+    if config.fAutoDetect != FALSE || auto_config_url.is_some() {
+        // Like browsers, fall back on the fixed settings if no script can be found
+        if let Some(proxy) =
+            auto_proxy(url, config.fAutoDetect != FALSE, auto_config_url.as_deref())
+        {
+            return proxy;
+        }
+    }
+    let host = url.host_str()?;
+    if bypass.is_some_and(|bypass| is_bypassed(host, &bypass)) {
+        return None;
+    }
+    choose_proxy(&proxy?, url.scheme())
+}
+
+/// Finds the proxy with WPAD or the auto-config script at `config_url`.
+/// Returns None if neither could be run, or Some(None) to connect directly.
+fn auto_proxy(url: &Url, auto_detect: bool, config_url: Option<&str>) -> Option<Option<String>> {
+    let agent = os_str_to_wchar(OsStr::new("himawari-desktop-updater"));
+    let config_url = config_url.map(|config_url| os_str_to_wchar(OsStr::new(config_url)));
+    let target = os_str_to_wchar(OsStr::new(url.as_str()));
+
+    let mut options: WINHTTP_AUTOPROXY_OPTIONS = unsafe { zeroed() };
+    if auto_detect {
+        options.dwFlags |= WINHTTP_AUTOPROXY_AUTO_DETECT;
+        options.dwAutoDetectFlags = WINHTTP_AUTO_DETECT_TYPE_DHCP | WINHTTP_AUTO_DETECT_TYPE_DNS_A;
+    }
+    if let Some(ref config_url) = config_url {
+        options.dwFlags |= WINHTTP_AUTOPROXY_CONFIG_URL;
+        options.lpszAutoConfigUrl = config_url.as_ptr();
+    }
+    options.fAutoLogonIfChallenged = TRUE;
+
+    unsafe {
+        let session = WinHttpOpen(
+            agent.as_ptr(),
+            WINHTTP_ACCESS_TYPE_NO_PROXY,
+            null(),
+            null(),
+            0,
+        );
+        if session.is_null() {
+            return None;
+        }
+        let mut proxy_info: WINHTTP_PROXY_INFO = zeroed();
+        let found = WinHttpGetProxyForUrl(session, target.as_ptr(), &mut options, &mut proxy_info);
+        WinHttpCloseHandle(session);
+        if found == FALSE {
+            return None;
+        }
+        let proxy = take_string(proxy_info.lpszProxy);
+        take_string(proxy_info.lpszProxyBypass);
+        match proxy_info.dwAccessType {
+            WINHTTP_ACCESS_TYPE_NAMED_PROXY => {
+                Some(proxy.and_then(|proxy| choose_proxy(&proxy, url.scheme())))
+            }
+            _ => Some(None),
+        }
+    }
+}
+
+/// Picks the proxy for `scheme` from a list like "proxy:8080" or "http=proxy:80;https=proxy:443"
+fn choose_proxy(list: &str, scheme: &str) -> Option<String> {
+    let entries = list
+        .split(|c: char| c == ';' || c.is_whitespace())
+        .filter(|entry| !entry.is_empty());
+    let mut chosen = None;
+    for entry in entries {
+        match entry.split_once('=') {
+            Some((entry_scheme, address)) if entry_scheme.eq_ignore_ascii_case(scheme) => {
+                chosen = Some(address);
+                break;
+            }
+            Some(_) => {}
+            None => {
+                chosen.get_or_insert(entry);
+            }
+        }
+    }
+    chosen.map(|address| match address.contains("://") {
+        true => address.to_string(),
+        false => format!("http://{}", address),
+    })
+}
+
+/// True if `host` matches the bypass list, e.g. "<local>;*.example.com;10.*"
+fn is_bypassed(host: &str, bypass: &str) -> bool {
+    bypass
+        .split(|c: char| c == ';' || c == ',' || c.is_whitespace())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| match entry {
+            // Hosts without a domain
+            "<local>" => !host.contains('.'),
+            entry => {
+                let pattern = entry
+                    .split_once("://")
+                    .map_or(entry, |(_, pattern)| pattern);
+                wildcard_match(&pattern.to_ascii_lowercase(), &host.to_ascii_lowercase())
+            }
+        })
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            let text = match text.strip_prefix(prefix) {
+                Some(text) => text,
+                None => return false,
+            };
+            (0..=text.len())
+                .filter(|&i| text.is_char_boundary(i))
+                .any(|i| wildcard_match(rest, &text[i..]))
+        }
+    }
+}
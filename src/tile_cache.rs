@@ -0,0 +1,186 @@
+//! An on-disk cache of tiles, kept between runs.
+//!
+//! A tile never changes once the server has it, so unlike the HTTP cache there is nothing to
+//! expire: a tile is kept until the cache grows past --max-cache-size, and the least
+//! recently used tiles are dropped. A run repeated for the same frame, or retried after
+//! some tiles failed, only downloads the tiles it doesn't have yet.
+//!
+//! Tiles are laid out like --save-tiles, by product, level, time and position, under
+//! `--cache-dir` or a `tiles` directory beside the HTTP cache.
+//!
+//! Tiles of one frame are never the same as those of the next, as clouds move everywhere on
+//! the disk, but tiles which lie wholly in space are black in every frame. With
+//! `--differential` those are taken from an earlier frame in the cache rather than downloaded.
+
+use std::fs::DirBuilder;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Once};
+use std::time::SystemTime;
+
+use chrono::prelude::*;
+use log::{debug, warn};
+
+use crate::error::AppErr;
+use crate::http_cache;
+use crate::product::{Product, FRAME_INTERVAL_MINUTES};
+
+/// How many earlier frames are searched for a copy of a tile in space, a day's worth
+const EARLIER_FRAMES: i64 = 24 * 60 / FRAME_INTERVAL_MINUTES;
+
+/// The cache is trimmed to this size unless --max-cache-size says otherwise
+pub const DEFAULT_MAX_CACHE_BYTES: u64 = 1024 * 1024 * 1024;
+
+#[derive(Clone)]
+pub struct TileCacheOptions {
+    /// Where tiles are kept, if not in the user's cache directory
+    pub dir: Option<PathBuf>,
+    pub max_bytes: u64,
+    /// Reuse tiles in space from earlier frames
+    pub differential: bool,
+}
+
+impl Default for TileCacheOptions {
+    fn default() -> TileCacheOptions {
+        TileCacheOptions {
+            dir: None,
+            max_bytes: DEFAULT_MAX_CACHE_BYTES,
+            differential: false,
+        }
+    }
+}
+
+/// Where tiles are cached unless --cache-dir says otherwise
+pub fn default_dir() -> PathBuf {
+    http_cache::base_dir().join("tiles")
+}
+
+/// The tile cache as `TileCacheOptions` lay it out. Clones share it, and it is pruned once,
+/// before the first tile is put in it.
+#[derive(Clone)]
+pub struct TileCache {
+    options: Arc<TileCacheOptions>,
+    pruned: Arc<Once>,
+}
+
+impl TileCache {
+    pub fn new(options: TileCacheOptions) -> TileCache {
+        TileCache {
+            options: Arc::new(options),
+            pruned: Arc::new(Once::new()),
+        }
+    }
+
+    /// True if tiles in space are reused from earlier frames
+    pub fn is_differential(&self) -> bool {
+        self.options.differential
+    }
+
+    fn dir(&self) -> PathBuf {
+        match self.options.dir {
+            Some(ref dir) => dir.clone(),
+            None => default_dir(),
+        }
+    }
+
+    /// The cached PNG data of the tile at (`x`, `y`) of the frame at `timestamp`, if there is any
+    pub fn get(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> Option<Vec<u8>> {
+        let path = self.dir().join(product.tile_path(level, timestamp, x, y));
+        let data = std::fs::read(&path).ok().filter(|data| !data.is_empty())?;
+        debug!("Using the cached chunk {}", path.display());
+        // Note the use, so pruning drops the least recently used tiles first
+        if let Ok(file) = std::fs::File::options().append(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    /// The cached PNG data of the tile at (`x`, `y`) of the most recent of the frames in the day
+    /// before `timestamp` which has it, for tiles which are the same in every frame
+    pub fn get_earlier(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> Option<Vec<u8>> {
+        (1..=EARLIER_FRAMES)
+            .map(|age| *timestamp - chrono::Duration::minutes(FRAME_INTERVAL_MINUTES * age))
+            .find_map(|earlier| self.get(product, level, &earlier, x, y))
+    }
+
+    /// Keeps the PNG data of a downloaded tile. Failures are only logged.
+    pub fn put(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32, data: &[u8]) {
+        self.pruned.call_once(|| {
+            if let Err(app_err) = self.prune() {
+                warn!("Failed to prune the tile cache: {}", app_err);
+            }
+        });
+
+        let path = self.dir().join(product.tile_path(level, timestamp, x, y));
+        let store = || -> Result<(), AppErr> {
+            if let Some(parent) = path.parent() {
+                DirBuilder::new().recursive(true).create(parent)?;
+            }
+            // Write to one side first, so a tile is never read half written
+            let part_path = path.with_extension("part");
+            std::fs::write(&part_path, data)?;
+            std::fs::rename(&part_path, &path)?;
+            Ok(())
+        };
+        if let Err(app_err) = store() {
+            warn!("Failed to cache the chunk {}: {}", path.display(), app_err);
+        }
+    }
+
+    /// Drops the cached tile at (`x`, `y`) of the frame at `timestamp`, which turned out not to be
+    /// worth keeping
+    pub fn remove(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) {
+        let path = self.dir().join(product.tile_path(level, timestamp, x, y));
+        if std::fs::remove_file(&path).is_ok() {
+            debug!("Removed the cached chunk {}", path.display());
+        }
+    }
+
+    /// How many bytes of tiles are cached
+    pub fn size(&self) -> u64 {
+        let mut files = Vec::new();
+        let _ = list_files(&self.dir(), &mut files);
+        files.iter().map(|(_, len, _)| len).sum()
+    }
+
+    /// Removes the least recently used tiles until the cache fits in the maximum size
+    fn prune(&self) -> Result<(), AppErr> {
+        let dir = self.dir();
+        if !dir.exists() {
+            return Ok(());
+        }
+        let mut files = Vec::new();
+        list_files(&dir, &mut files)?;
+
+        let max_bytes = self.options.max_bytes;
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in files {
+            if total <= max_bytes {
+                break;
+            }
+            std::fs::remove_file(&path)?;
+            total -= len;
+            // Tidy up the directories of frames which are gone entirely
+            for parent in path.ancestors().skip(1).take_while(|parent| *parent != dir) {
+                if std::fs::remove_dir(parent).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every file below `dir`, with when it was last used and its size
+fn list_files(dir: &Path, files: &mut Vec<(SystemTime, u64, PathBuf)>) -> Result<(), AppErr> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = std::fs::metadata(&path)?;
+        if metadata.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+    Ok(())
+}
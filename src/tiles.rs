@@ -0,0 +1,280 @@
+//! Where image fragments come from: the NICT server, or a directory of saved tiles.
+//!
+//! Saved tiles mirror the layout of the server below `/himawari8/img/`, e.g.
+//! `D531106/4d/550/2022/11/01/032000_0_0.png`, so a directory written by `--save-tiles`
+//! or mirrored by another downloader can be composed without touching the network.
+//!
+//! Both kinds of source answer the same two questions, the time of the newest frame and the
+//! data of a tile of it, through [`ImageSource`], so the rest of the updater, down to
+//! compositing, treats them alike. A directory can be given as a path or a `file://` URL.
+
+use std::fs::DirBuilder;
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use futures::future::{self, BoxFuture, FutureExt, LocalBoxFuture};
+use log::debug;
+
+use crate::error::{AppErr, ErrorKind};
+use crate::geo;
+use crate::http::{HttpClient, DEFAULT_CONCURRENCY};
+use crate::http_cache;
+use crate::latest::{self, LatestFrame};
+use crate::messages::tr;
+use crate::product::Product;
+use crate::metrics::Metrics;
+use crate::report::{Report, Source};
+use crate::tile_cache::TileCache;
+
+/// Where the tiles of frames come from. Besides the server and a directory of saved tiles,
+/// other programs can compose frames from tiles of their own by implementing this.
+pub trait ImageSource: Send + Sync {
+    /// The time of the newest frame of `product` with tiles at `level`
+    fn newest(&self, product: &Product, level: u32) -> Result<DateTime<Utc>, AppErr>;
+
+    /// The newest frame of `product`, and whether it has changed since the last update which
+    /// wrote to `output_dir`
+    fn latest(&self, product: &Product, level: u32, _output_dir: &Path) -> Result<LatestFrame, AppErr> {
+        self.newest(product, level).map(LatestFrame::new)
+    }
+
+    /// The time of the frame of `product` nearest to `time`, or the newest if the source can't
+    /// look further back
+    fn nearest(&self, product: &Product, level: u32, _time: &DateTime<Utc>) -> Result<DateTime<Utc>, AppErr> {
+        self.newest(product, level)
+    }
+
+    /// How many tiles are fetched at once
+    fn concurrency(&self) -> usize {
+        DEFAULT_CONCURRENCY
+    }
+
+    /// True if tiles are downloaded, so that failed ones are worth retrying
+    fn is_remote(&self) -> bool {
+        false
+    }
+
+    /// Runs `future`, which fetches tiles, to completion
+    fn block_on(&self, future: LocalBoxFuture<'_, Result<(), AppErr>>) -> Result<(), AppErr> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        runtime.block_on(future)
+    }
+
+    /// Where the tile at (`x`, `y`) of the frame at `timestamp` is read from, for messages
+    fn location(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> String;
+
+    /// Reads the PNG data of the tile at (`x`, `y`) of the frame at `timestamp`
+    fn fetch<'a>(
+        &'a self,
+        product: &'a Product,
+        level: u32,
+        timestamp: &'a DateTime<Utc>,
+        x: u32,
+        y: u32,
+    ) -> BoxFuture<'a, Result<Vec<u8>, AppErr>>;
+
+    /// Drops every copy kept of the tile at (`x`, `y`) of the frame at `timestamp`, such as a
+    /// placeholder, so that the next update fetches it again
+    fn forget(&self, _product: &Product, _level: u32, _timestamp: &DateTime<Utc>, _x: u32, _y: u32) {}
+}
+
+/// Downloads tiles from the server with `client`, reusing those in `cache` and optionally
+/// keeping a copy of each in `save_dir`. Each download is noted in `metrics` and `report`.
+#[derive(Clone)]
+pub struct HttpSource {
+    pub client: HttpClient,
+    pub cache: TileCache,
+    pub save_dir: Option<PathBuf>,
+    pub metrics: Metrics,
+    pub report: Report,
+}
+
+impl ImageSource for HttpSource {
+    /// From the server's latest.json
+    fn newest(&self, product: &Product, _level: u32) -> Result<DateTime<Utc>, AppErr> {
+        latest::newest(&self.client, product)
+    }
+
+    /// From the server's latest.json, requested conditionally on the response cached in
+    /// `output_dir`
+    fn latest(&self, product: &Product, _level: u32, output_dir: &Path) -> Result<LatestFrame, AppErr> {
+        latest::fetch(&self.client, product, output_dir)
+    }
+
+    fn nearest(&self, product: &Product, _level: u32, time: &DateTime<Utc>) -> Result<DateTime<Utc>, AppErr> {
+        latest::nearest(&self.client, product, time)
+    }
+
+    fn concurrency(&self) -> usize {
+        self.client.concurrency()
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+
+    /// On the client's runtime
+    fn block_on(&self, future: LocalBoxFuture<'_, Result<(), AppErr>>) -> Result<(), AppErr> {
+        self.client.block_on(future)
+    }
+
+    fn location(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> String {
+        product.tile_url(level, timestamp, x, y)
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        product: &'a Product,
+        level: u32,
+        timestamp: &'a DateTime<Utc>,
+        x: u32,
+        y: u32,
+    ) -> BoxFuture<'a, Result<Vec<u8>, AppErr>> {
+        let HttpSource {
+            client,
+            cache,
+            save_dir,
+            metrics,
+            report,
+        } = self;
+        async move {
+            let url = &product.tile_url(level, timestamp, x, y);
+            // Recordings keep every response, so only use the tile cache outside of them
+            let caching = client.caches_responses() && client.recording().is_none();
+            let data = report.track(metrics, url, |mut transfer| async move {
+                if let Some(data) = caching.then(|| cache.get(product, level, timestamp, x, y)).flatten() {
+                    transfer.source = Source::Cache;
+                    return (transfer, Ok(data));
+                }
+                // Tiles in space don't change from one frame to the next
+                let unchanging = caching && cache.is_differential() && geo::tile_in_space(level, x, y);
+                if let Some(data) = unchanging.then(|| cache.get_earlier(product, level, timestamp, x, y)).flatten() {
+                    transfer.source = Source::Cache;
+                    return (transfer, Ok(data));
+                }
+                debug!("Downloading chunk {}...", url);
+                let result = client.download_tracked(url, &mut transfer).await;
+                match result {
+                    Ok(ref data) if caching => cache.put(product, level, timestamp, x, y, data),
+                    _ => {}
+                }
+                (transfer, result)
+            })
+            .await?;
+            if let Some(save_dir) = save_dir {
+                let path = save_dir.join(product.tile_path(level, timestamp, x, y));
+                if let Some(parent) = path.parent() {
+                    DirBuilder::new().recursive(true).create(parent)?;
+                }
+                std::fs::write(&path, &data)?;
+            }
+            Ok(data)
+        }
+        .boxed()
+    }
+
+    fn forget(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) {
+        http_cache::remove(&product.tile_url(level, timestamp, x, y));
+        self.cache.remove(product, level, timestamp, x, y);
+        if let Some(ref save_dir) = self.save_dir {
+            let _ = std::fs::remove_file(save_dir.join(product.tile_path(level, timestamp, x, y)));
+        }
+    }
+}
+
+/// Reads previously saved tiles from a directory. They are left alone when forgotten.
+#[derive(Clone)]
+pub struct DirectorySource {
+    pub dir: PathBuf,
+}
+
+impl DirectorySource {
+    /// A source reading the tiles saved in `location`, a directory or a `file://` URL of one.
+    /// A relative path is taken from the current directory.
+    pub fn new(location: &str) -> Result<DirectorySource, AppErr> {
+        let path = match location.strip_prefix("file:") {
+            Some(_) => reqwest::Url::parse(location)
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or_else(|| AppErr::of_kind(ErrorKind::Config, tr!("invalid-tile-url", url = location)))?,
+            None => PathBuf::from(location),
+        };
+        Ok(DirectorySource {
+            dir: std::env::current_dir()?.join(path),
+        })
+    }
+}
+
+impl ImageSource for DirectorySource {
+    /// The newest saved in the directory
+    fn newest(&self, product: &Product, level: u32) -> Result<DateTime<Utc>, AppErr> {
+        latest_timestamp(&self.dir, product, level)
+    }
+
+    /// The tile's path in the directory
+    fn location(&self, product: &Product, level: u32, timestamp: &DateTime<Utc>, x: u32, y: u32) -> String {
+        self.dir.join(product.tile_path(level, timestamp, x, y)).display().to_string()
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        product: &'a Product,
+        level: u32,
+        timestamp: &'a DateTime<Utc>,
+        x: u32,
+        y: u32,
+    ) -> BoxFuture<'a, Result<Vec<u8>, AppErr>> {
+        let path = self.dir.join(product.tile_path(level, timestamp, x, y));
+        debug!("Reading chunk {}...", path.display());
+        let result = std::fs::read(&path).map_err(|err| AppErr::new(format!("{}: {}", path.display(), err)));
+        future::ready(result).boxed()
+    }
+}
+
+/// Sorted names of the subdirectories of `dir` which parse as numbers
+fn numbered_dirs(dir: &Path) -> Vec<(u32, PathBuf)> {
+    let mut dirs: Vec<_> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let number = entry.file_name().to_str()?.parse().ok()?;
+            Some((number, entry.path()))
+        })
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// The newest frame in `dir` with a first tile at `level`, used in place of latest.json
+pub fn latest_timestamp(
+    dir: &Path,
+    product: &Product,
+    level: u32,
+) -> Result<DateTime<Utc>, AppErr> {
+    let level_dir = dir.join(product.level_dir(level));
+
+    for (year, year_dir) in numbered_dirs(&level_dir).into_iter().rev() {
+        for (month, month_dir) in numbered_dirs(&year_dir).into_iter().rev() {
+            for (day, day_dir) in numbered_dirs(&month_dir).into_iter().rev() {
+                let latest = std::fs::read_dir(&day_dir)?
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| {
+                        let name = entry.file_name().into_string().ok()?;
+                        let time = name.strip_suffix("_0_0.png")?;
+                        NaiveTime::parse_from_str(time, "%H%M%S").ok()
+                    })
+                    .max();
+                let date = NaiveDate::from_ymd_opt(year as i32, month, day);
+                if let Some((date, time)) = date.zip(latest) {
+                    return Ok(DateTime::from_utc(date.and_time(time), Utc));
+                }
+            }
+        }
+    }
+    Err(AppErr::new(tr!(
+        "no-saved-tiles",
+        dir = level_dir.display()
+    )))
+}
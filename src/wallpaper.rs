@@ -0,0 +1,117 @@
+//! Setting the desktop wallpaper and lock screen image, the way the current platform does.
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::colour::Colour;
+use crate::error::{AppErr, ErrorKind};
+use crate::monitor::Monitor;
+use crate::orientation::{self, Orientation};
+use crate::size::Size;
+use crate::wallpaper_backend::WallpaperBackend;
+use crate::wallpaper_style::WallpaperStyle;
+
+#[cfg(target_os = "macos")]
+use crate::ffi_macos::{screen_size, set_lockscreen, set_wallpaper};
+#[cfg(not(any(windows, target_os = "macos")))]
+use crate::ffi_unix::{screen_size, set_lockscreen, set_wallpaper};
+#[cfg(windows)]
+use crate::ffi_windows::{screen_size, set_lockscreen, set_wallpaper};
+
+/// Sets the wallpaper with a chosen backend, on Linux, or on chosen monitors, on Windows,
+/// placed in a chosen style on a chosen background colour where it doesn't fill the screen.
+/// The choices which don't apply to the platform are ignored.
+#[derive(Clone, Copy)]
+pub struct WallpaperSetter {
+    #[cfg(not(any(windows, target_os = "macos")))]
+    pub(crate) backend: Option<WallpaperBackend>,
+    #[cfg(windows)]
+    pub(crate) monitor: Monitor,
+    /// Without a style the platform's default is used, or on macOS the desktop's own kept
+    pub(crate) style: Option<WallpaperStyle>,
+    #[cfg(not(target_os = "macos"))]
+    pub(crate) background: Colour,
+}
+
+impl WallpaperSetter {
+    pub fn new(
+        backend: Option<WallpaperBackend>,
+        monitor: Monitor,
+        style: Option<WallpaperStyle>,
+        background: Colour,
+    ) -> WallpaperSetter {
+        #[cfg(any(windows, target_os = "macos"))]
+        let _ = backend;
+        #[cfg(not(windows))]
+        let _ = monitor;
+        #[cfg(target_os = "macos")]
+        let _ = background;
+        WallpaperSetter {
+            #[cfg(not(any(windows, target_os = "macos")))]
+            backend,
+            #[cfg(windows)]
+            monitor,
+            style,
+            #[cfg(not(target_os = "macos"))]
+            background,
+        }
+    }
+
+    pub fn set_wallpaper(&self, image_path: &Path) -> Result<(), AppErr> {
+        set_wallpaper(image_path, self).map_err(|app_err| app_err.with_kind(ErrorKind::Wallpaper))
+    }
+
+    /// Points the desktop slideshow at the images in `dir`, changing every `interval`. Only
+    /// Windows has a slideshow which can be set, so elsewhere the wallpaper is set to `newest`.
+    pub fn set_slideshow(&self, dir: &Path, newest: &Path, interval: Duration) -> Result<(), AppErr> {
+        #[cfg(windows)]
+        {
+            let _ = newest;
+            crate::ffi_windows::set_slideshow(dir, interval, self).map_err(|app_err| app_err.with_kind(ErrorKind::Wallpaper))
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = (dir, interval);
+            self.set_wallpaper(newest)
+        }
+    }
+
+    /// Sets `landscape` as the wallpaper of the monitors wider than they are tall, and
+    /// `portrait` as that of the others. Only Windows can tell its monitors apart, so elsewhere
+    /// the one which suits the screen is set.
+    pub fn set_wallpaper_by_orientation(&self, landscape: &Path, portrait: &Path) -> Result<(), AppErr> {
+        #[cfg(windows)]
+        {
+            crate::ffi_windows::set_wallpaper_by_orientation(landscape, portrait, self)
+                .map_err(|app_err| app_err.with_kind(ErrorKind::Wallpaper))
+        }
+        #[cfg(not(windows))]
+        {
+            let image_path = match self.screen_size() {
+                Ok(size) if Orientation::of(size.width, size.height) == Orientation::Portrait => portrait,
+                _ => landscape,
+            };
+            self.set_wallpaper(image_path)
+        }
+    }
+
+    /// Sets the frame at `image_path` as the wallpaper, or its versions for each orientation
+    /// of monitor, if `variants` has any
+    pub fn set_frame(&self, image_path: &Path, variants: &[Orientation]) -> Result<(), AppErr> {
+        if variants.is_empty() {
+            return self.set_wallpaper(image_path);
+        }
+        let landscape = orientation::image_for(image_path, variants, Orientation::Landscape);
+        let portrait = orientation::image_for(image_path, variants, Orientation::Portrait);
+        self.set_wallpaper_by_orientation(&landscape, &portrait)
+    }
+
+    pub fn set_lockscreen(&self, image_path: &Path) -> Result<(), AppErr> {
+        set_lockscreen(image_path).map_err(|app_err| app_err.with_kind(ErrorKind::Wallpaper))
+    }
+
+    /// The size in pixels of the screen the wallpaper is set on
+    pub fn screen_size(&self) -> Result<Size, AppErr> {
+        screen_size(self)
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// How the wallpaper is set on Linux and other Unix desktops
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WallpaperBackend {
+    /// GNOME, and desktops based on it, through gsettings
+    Gnome,
+    /// KDE Plasma, through a Plasma shell script run by qdbus
+    Kde,
+    /// XFCE, through xfconf-query
+    Xfce,
+    /// The sway compositor, through swaymsg
+    Sway,
+    /// Other wlroots compositors, by running swaybg
+    Swaybg,
+    /// Wayland compositors with the swww daemon, which is started if needed
+    Swww,
+    /// Hyprland, through hyprpaper
+    Hyprpaper,
+}
+
+#[derive(Clone)]
+pub struct WallpaperBackendValueParser;
+
+impl clap::builder::TypedValueParser for WallpaperBackendValueParser {
+    type Value = WallpaperBackend;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match value.to_string_lossy().as_ref().trim() {
+            "gnome" => Ok(WallpaperBackend::Gnome),
+            "kde" => Ok(WallpaperBackend::Kde),
+            "xfce" => Ok(WallpaperBackend::Xfce),
+            "sway" => Ok(WallpaperBackend::Sway),
+            "swaybg" => Ok(WallpaperBackend::Swaybg),
+            "swww" => Ok(WallpaperBackend::Swww),
+            "hyprpaper" => Ok(WallpaperBackend::Hyprpaper),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid wallpaper backend, use gnome, kde, xfce, sway, swaybg, swww or hyprpaper")),
+        }
+    }
+}
+
+impl Display for WallpaperBackend {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            WallpaperBackend::Gnome => "gnome",
+            WallpaperBackend::Kde => "kde",
+            WallpaperBackend::Xfce => "xfce",
+            WallpaperBackend::Sway => "sway",
+            WallpaperBackend::Swaybg => "swaybg",
+            WallpaperBackend::Swww => "swww",
+            WallpaperBackend::Hyprpaper => "hyprpaper",
+        };
+        write!(f, "{}", s)
+    }
+}
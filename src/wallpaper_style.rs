@@ -0,0 +1,48 @@
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+/// How the wallpaper is placed on the screen
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum WallpaperStyle {
+    /// As large as fits on the screen, on the background colour
+    #[default]
+    Fit,
+    /// As small as covers the screen, cropping the edges
+    Fill,
+    /// Stretched to the shape of the screen
+    Stretch,
+    /// At its own size, in the middle of the screen
+    Center,
+    /// Across every monitor, where the desktop can. Others fit the image instead.
+    Span,
+}
+
+#[derive(Clone)]
+pub struct WallpaperStyleValueParser;
+
+impl clap::builder::TypedValueParser for WallpaperStyleValueParser {
+    type Value = WallpaperStyle;
+    fn parse_ref(&self, _cmd: &clap::Command, _arg: Option<&clap::Arg>, value: &std::ffi::OsStr) -> Result<Self::Value, clap::Error> {
+        use clap::error::{Error, ErrorKind};
+        match value.to_string_lossy().as_ref().trim() {
+            "fit" => Ok(WallpaperStyle::Fit),
+            "fill" => Ok(WallpaperStyle::Fill),
+            "stretch" => Ok(WallpaperStyle::Stretch),
+            "center" | "centre" => Ok(WallpaperStyle::Center),
+            "span" => Ok(WallpaperStyle::Span),
+            _ => Err(Error::raw(ErrorKind::InvalidValue, "Invalid wallpaper style, use fit, fill, stretch, center or span")),
+        }
+    }
+}
+
+impl Display for WallpaperStyle {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
+        let s = match *self {
+            WallpaperStyle::Fit => "fit",
+            WallpaperStyle::Fill => "fill",
+            WallpaperStyle::Stretch => "stretch",
+            WallpaperStyle::Center => "center",
+            WallpaperStyle::Span => "span",
+        };
+        write!(f, "{}", s)
+    }
+}
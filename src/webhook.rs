@@ -0,0 +1,52 @@
+//! Posting the outcome of each update to a webhook given with --webhook, so it can be fed into
+//! chat or home automation without a wrapper script.
+//!
+//! The payload is the outcome printed by --json, along with a one line summary as `text` and
+//! `content`, which Slack and Discord webhooks show as the message.
+
+use log::{debug, warn};
+use serde_derive::Serialize;
+
+use crate::error::AppErr;
+use crate::http::{without_password, HttpClient};
+use crate::outcome::Outcome;
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    #[serde(flatten)]
+    outcome: &'a Outcome,
+    text: String,
+    content: String,
+}
+
+/// Posts `outcome` to `url` with `client`. A webhook is a courtesy, so failures are only logged.
+pub fn post(client: &HttpClient, url: &str, outcome: &Outcome) {
+    let summary = summary(outcome);
+    let payload = Payload {
+        outcome,
+        text: summary.clone(),
+        content: summary,
+    };
+    debug!("Posting to webhook {}...", without_password(url));
+    let result = client.block_on(async {
+        let response = client.client()?.post(url).json(&payload).send().await?;
+        response.error_for_status()?;
+        Ok::<_, AppErr>(())
+    });
+    if let Err(app_err) = result {
+        warn!("Failed to post to the webhook {}: {}", without_password(url), app_err);
+    }
+}
+
+fn summary(outcome: &Outcome) -> String {
+    match (outcome.timestamp, &outcome.error) {
+        (_, Some(error)) => format!("Update failed: {}", error),
+        (Some(timestamp), None) if outcome.written => {
+            format!("New frame from {}", timestamp.format("%Y-%m-%d %H:%M UTC"))
+        }
+        (Some(timestamp), None) => {
+            format!("No new frame, the latest is from {}", timestamp.format("%Y-%m-%d %H:%M UTC"))
+        }
+        (None, None) => "Update finished".to_string(),
+    }
+}